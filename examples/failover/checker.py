@@ -10,6 +10,8 @@ logger = logging.getLogger('checker')
 
 
 DB_NAME = 'mydb'
+# The `admin_password` of the server proxies.
+ADMIN_PASSWORD = 'undermoon_admin'
 INIT_SLOTS_CONFIG = {
     'server_proxy1:6001': [[0, 5461]],
     'server_proxy2:6002': [[5462, 10922]],
@@ -74,7 +76,7 @@ def send_all_config(slots_config, nodes_config):
 def send_config(address, slots_config, nodes_config):
     assert address in slots_config
     host, port = address.split(':')
-    client = redis.StrictRedis(host, port, socket_timeout=1)
+    client = redis.StrictRedis(host, port, socket_timeout=1, password=ADMIN_PASSWORD)
 
     setdb = ['UMCTL', 'SETDB', '1', 'FORCE']
     for start, end in slots_config[address]:
@@ -6,18 +6,17 @@ extern crate log;
 extern crate config;
 extern crate env_logger;
 
-use futures::future::select_all;
 use reqwest;
 use std::env;
 use std::error::Error;
 use std::sync::Arc;
 use std::time::Duration;
 use undermoon::coordinator::http_mani_broker::HttpMetaManipulationBroker;
-use undermoon::coordinator::http_meta_broker::HttpMetaBroker;
+use undermoon::coordinator::http_meta_broker::{BrokerAddresses, HttpMetaBroker};
 use undermoon::coordinator::service::{CoordinatorConfig, CoordinatorService};
-use undermoon::protocol::PooledRedisClientFactory;
+use undermoon::protocol::{CredentialTable, PooledRedisClientFactory, TlsClientConfig};
 
-fn gen_conf() -> Vec<CoordinatorConfig> {
+fn gen_conf() -> CoordinatorConfig {
     let mut s = config::Config::new();
     // If config file is specified, load it.
     if let Some(conf_file_path) = env::args().nth(1) {
@@ -30,13 +29,14 @@ fn gen_conf() -> Vec<CoordinatorConfig> {
         .map(|_| ())
         .unwrap_or_else(|e| warn!("failed to read config from env vars: {:?}", e));
 
-    let mut broker_address_list = vec![];
+    let mut broker_addresses = vec![];
 
+    // Multiple broker addresses should be the brokers in the same replication group.
     if let Ok(list) = s.get::<Vec<String>>("broker_address") {
         info!("load multiple broker addresses {:?}", list);
-        broker_address_list = list;
+        broker_addresses = list;
     } else {
-        broker_address_list.push(
+        broker_addresses.push(
             s.get::<String>("broker_address")
                 .unwrap_or_else(|_| "127.0.0.1:7799".to_string()),
         )
@@ -48,31 +48,55 @@ fn gen_conf() -> Vec<CoordinatorConfig> {
         .get::<String>("reporter_id")
         .unwrap_or_else(|_| "127.0.0.1:6699".to_string());
 
-    broker_address_list
-        .into_iter()
-        .map(|broker_address| CoordinatorConfig {
-            broker_address,
-            reporter_id: reporter_id.clone(),
-        })
-        .collect()
+    let non_empty = |field: &str| s.get::<String>(field).ok().filter(|v| !v.is_empty());
+    let credentials = CredentialTable::from_config(
+        non_empty("redis_username"),
+        non_empty("redis_password"),
+        s.get::<Vec<String>>("redis_node_credentials")
+            .unwrap_or_default(),
+    )
+    .unwrap_or_else(|err| {
+        error!("invalid credentials: {}", err);
+        CredentialTable::default()
+    });
+
+    let proxy_tls = if s.get::<bool>("proxy_tls").unwrap_or(false) {
+        let ca_file = s.get::<String>("proxy_tls_ca_file").unwrap_or_default();
+        let skip_verify = s.get::<bool>("proxy_tls_skip_verify").unwrap_or(false);
+        TlsClientConfig::from_ca_file(&ca_file, skip_verify)
+            .map_err(|err| error!("failed to load proxy tls config: {}", err))
+            .ok()
+    } else {
+        None
+    };
+
+    CoordinatorConfig {
+        broker_addresses,
+        reporter_id,
+        credentials,
+        proxy_tls,
+    }
 }
 
 fn gen_service(
     config: CoordinatorConfig,
 ) -> CoordinatorService<HttpMetaBroker, HttpMetaManipulationBroker, PooledRedisClientFactory> {
     let http_client = reqwest::Client::new();
+    let broker_addresses = BrokerAddresses::new(config.broker_addresses.clone());
     let data_broker = Arc::new(HttpMetaBroker::new(
-        config.broker_address.clone(),
+        broker_addresses.clone(),
         http_client.clone(),
     ));
     let mani_broker = Arc::new(HttpMetaManipulationBroker::new(
-        config.broker_address.clone(),
+        broker_addresses,
         http_client,
     ));
 
     let timeout = Duration::new(2, 0);
     let pool_size = 2;
-    let client_factory = PooledRedisClientFactory::new(pool_size, timeout);
+    let client_factory =
+        PooledRedisClientFactory::with_credentials(pool_size, timeout, config.credentials.clone())
+            .with_tls(config.proxy_tls.clone());
 
     CoordinatorService::new(config, data_broker, mani_broker, client_factory)
 }
@@ -80,22 +104,19 @@ fn gen_service(
 fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
-    let configs = gen_conf();
-    let service_num = configs.len();
-    let services = configs.into_iter().map(gen_service);
-    let futs = select_all(services.map(|service| {
-        Box::pin(async move {
-            if let Err(err) = service.run().await {
-                error!("coordinator error {:?}", err);
-            }
-        })
-    }));
+    let config = gen_conf();
+    let service = gen_service(config);
+    let fut = async move {
+        if let Err(err) = service.run().await {
+            error!("coordinator error {:?}", err);
+        }
+    };
 
     let mut runtime = tokio::runtime::Builder::new()
         .threaded_scheduler()
-        .core_threads(service_num)
+        .core_threads(1)
         .enable_all()
         .build()?;
-    runtime.block_on(futs);
+    runtime.block_on(fut);
     Ok(())
 }
@@ -7,6 +7,9 @@ extern crate env_logger;
 use actix_web::{middleware, App, HttpServer};
 use std::env;
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use undermoon::broker::replication::BrokerRole;
 use undermoon::broker::service::{configure_app, MemBrokerConfig, MemBrokerService};
 
 fn gen_conf() -> MemBrokerConfig {
@@ -23,12 +26,33 @@ fn gen_conf() -> MemBrokerConfig {
         .map(|_| ())
         .unwrap_or_else(|e| warn!("failed to read address from env vars {:?}", e));
 
+    let role = s
+        .get::<String>("role")
+        .map_err(|e| e.to_string())
+        .and_then(|role| role.parse::<BrokerRole>())
+        .unwrap_or_else(|e| {
+            warn!("invalid role, use leader by default: {:?}", e);
+            BrokerRole::Leader
+        });
+
+    let replica_addresses = s
+        .get::<Vec<String>>("replica_addresses")
+        .unwrap_or_else(|_| vec![]);
+
     MemBrokerConfig {
         address: s
             .get::<String>("address")
             .unwrap_or_else(|_| "127.0.0.1:7799".to_string()),
         failure_ttl: s.get::<u64>("failure_ttl").unwrap_or_else(|_| 60),
         failure_quorum: s.get::<u64>("failure_quorum").unwrap_or_else(|_| 1),
+        storage_dir: s
+            .get::<String>("storage_dir")
+            .ok()
+            .filter(|dir| !dir.is_empty()),
+        snapshot_interval: s.get::<u64>("snapshot_interval").unwrap_or(60),
+        role,
+        replica_addresses,
+        replication_interval: s.get::<u64>("replication_interval").unwrap_or(5),
     }
 }
 
@@ -38,11 +62,37 @@ async fn main() -> std::io::Result<()> {
 
     let config = gen_conf();
     let address = config.address.clone();
+    let snapshot_interval = Duration::from_secs(config.snapshot_interval);
+    let persistence_enabled = config.storage_dir.is_some();
+    let replication_interval = Duration::from_secs(config.replication_interval);
+    let replication_enabled = !config.replica_addresses.is_empty();
+
+    // Replay the snapshot and the operation log if persistence is enabled.
+    let service = Arc::new(MemBrokerService::new(config)?);
+
+    if persistence_enabled {
+        let service = service.clone();
+        thread::spawn(move || loop {
+            thread::sleep(snapshot_interval);
+            if let Err(err) = service.snapshot() {
+                error!("failed to save snapshot: {:?}", err);
+            }
+        });
+    }
+    if replication_enabled {
+        let service = service.clone();
+        // Periodically replicate the metadata so that the restarted followers can catch up.
+        actix_rt::spawn(async move {
+            loop {
+                service.replicate().await;
+                tokio::time::delay_for(replication_interval).await;
+            }
+        });
+    }
 
-    let service = Arc::new(MemBrokerService::new(config));
     HttpServer::new(move || {
         App::new()
-            .app_data(service.clone())
+            .data(service.clone())
             .configure(configure_app)
             .wrap(middleware::Logger::default())
     })
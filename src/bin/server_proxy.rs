@@ -10,15 +10,19 @@ use arc_swap::ArcSwap;
 use std::env;
 use std::error::Error;
 use std::num::NonZeroUsize;
-use std::sync::atomic::AtomicI64;
+use std::sync::atomic::{AtomicI64, AtomicUsize};
 use std::sync::Arc;
 use std::time::Duration;
 use string_error::into_err;
 use undermoon::common::track::TrackedFutureRegistry;
-use undermoon::protocol::PooledRedisClientFactory;
+use undermoon::protocol::{
+    CredentialTable, PooledRedisClientFactory, TlsClientConfig, TlsError, TlsServerConfig,
+};
+use undermoon::proxy::client::ClientRegistry;
+use undermoon::proxy::command_table::CommandTable;
 use undermoon::proxy::executor::SharedForwardHandler;
 use undermoon::proxy::manager::MetaMap;
-use undermoon::proxy::service::{ServerProxyConfig, ServerProxyService};
+use undermoon::proxy::service::{AdminPassword, ServerProxyConfig, ServerProxyService};
 use undermoon::proxy::slowlog::SlowRequestLogger;
 
 fn gen_conf() -> Result<ServerProxyConfig, &'static str> {
@@ -52,6 +56,25 @@ fn gen_conf() -> Result<ServerProxyConfig, &'static str> {
         NonZeroUsize::new(s.get::<usize>("session_batch_buf").unwrap_or_else(|_| 10))
             .ok_or_else(|| "session_batch_buf")?;
 
+    let backend_credentials = gen_backend_credentials(&s).map_err(|err| {
+        error!("{}", err);
+        "backend_node_credentials"
+    })?;
+    let command_table =
+        CommandTable::from_config(s.get::<Vec<String>>("extra_commands").unwrap_or_default())
+            .map_err(|err| {
+                error!("{}", err);
+                "extra_commands"
+            })?;
+    let tls_server = gen_tls_server(&s).map_err(|err| {
+        error!("failed to load tls certificate: {}", err);
+        "tls_cert_file"
+    })?;
+    let backend_tls = gen_backend_tls(&s).map_err(|err| {
+        error!("failed to load backend tls config: {}", err);
+        "backend_tls_ca_file"
+    })?;
+
     let config = ServerProxyConfig {
         address: address.clone(),
         announce_address: s
@@ -71,6 +94,7 @@ fn gen_conf() -> Result<ServerProxyConfig, &'static str> {
             .get::<usize>("backend_channel_size")
             .unwrap_or_else(|_| 4096),
         backend_conn_num,
+        blocking_pool_size: s.get::<usize>("blocking_pool_size").unwrap_or(16),
         backend_batch_min_time: s
             .get::<usize>("backend_batch_min_time")
             .unwrap_or_else(|_| 20000),
@@ -85,10 +109,62 @@ fn gen_conf() -> Result<ServerProxyConfig, &'static str> {
             .get::<usize>("session_batch_max_time")
             .unwrap_or_else(|_| 400_000),
         session_batch_buf,
+        proto_max_bulk_len: s
+            .get::<usize>("proto_max_bulk_len")
+            .unwrap_or(512 * 1024 * 1024),
+        proto_max_array_len: s.get::<usize>("proto_max_array_len").unwrap_or(1024 * 1024),
+        proto_max_depth: s.get::<usize>("proto_max_depth").unwrap_or(8),
+        backend_credentials,
+        admin_password: s
+            .get::<String>("admin_password")
+            .ok()
+            .filter(|password| !password.is_empty())
+            .map(AdminPassword::new),
+        tls_server,
+        backend_tls,
+        backend_resp3: s.get::<bool>("backend_resp3").unwrap_or(false),
+        command_table: Arc::new(command_table),
+        max_clients: AtomicUsize::new(s.get::<usize>("max_clients").unwrap_or(10000)),
+        client_timeout: s.get::<u64>("client_timeout").unwrap_or(0),
+        output_buffer_hard_limit: s
+            .get::<usize>("output_buffer_hard_limit")
+            .unwrap_or(256 * 1024 * 1024),
+        output_buffer_soft_limit: s
+            .get::<usize>("output_buffer_soft_limit")
+            .unwrap_or(64 * 1024 * 1024),
+        output_buffer_soft_seconds: s.get::<u64>("output_buffer_soft_seconds").unwrap_or(60),
     };
     Ok(config)
 }
 
+fn gen_backend_credentials(s: &config::Config) -> Result<CredentialTable, String> {
+    let non_empty = |field: &str| s.get::<String>(field).ok().filter(|v| !v.is_empty());
+    CredentialTable::from_config(
+        non_empty("backend_username"),
+        non_empty("backend_password"),
+        s.get::<Vec<String>>("backend_node_credentials")
+            .unwrap_or_default(),
+    )
+}
+
+fn gen_tls_server(s: &config::Config) -> Result<Option<TlsServerConfig>, TlsError> {
+    let cert_file = s.get::<String>("tls_cert_file").unwrap_or_default();
+    let key_file = s.get::<String>("tls_key_file").unwrap_or_default();
+    if cert_file.is_empty() || key_file.is_empty() {
+        return Ok(None);
+    }
+    TlsServerConfig::from_pem_files(&cert_file, &key_file).map(Some)
+}
+
+fn gen_backend_tls(s: &config::Config) -> Result<Option<TlsClientConfig>, TlsError> {
+    if !s.get::<bool>("backend_tls").unwrap_or(false) {
+        return Ok(None);
+    }
+    let ca_file = s.get::<String>("backend_tls_ca_file").unwrap_or_default();
+    let skip_verify = s.get::<bool>("backend_tls_skip_verify").unwrap_or(false);
+    TlsClientConfig::from_ca_file(&ca_file, skip_verify).map(Some)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
     let conf = gen_conf().map_err(|field| {
@@ -100,24 +176,41 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let timeout = Duration::new(1, 0);
     let pool_size = 4;
-    let client_factory = PooledRedisClientFactory::new(pool_size, timeout);
+    let client_factory = PooledRedisClientFactory::with_credentials(
+        pool_size,
+        timeout,
+        config.backend_credentials.clone(),
+    )
+    .with_tls(config.backend_tls.clone());
+    // Idle connections for the blocking commands are also kept in a pool.
+    let blocking_client_factory = PooledRedisClientFactory::with_credentials(
+        config.blocking_pool_size,
+        timeout,
+        config.backend_credentials.clone(),
+    )
+    .with_tls(config.backend_tls.clone())
+    .without_cmd_timeout();
 
     let slow_request_logger = Arc::new(SlowRequestLogger::new(config.clone()));
     let meta_map = Arc::new(ArcSwap::new(Arc::new(MetaMap::new())));
     let future_registry = Arc::new(TrackedFutureRegistry::default());
+    let client_registry = Arc::new(ClientRegistry::default());
 
     let forward_handler = SharedForwardHandler::new(
         config.clone(),
         Arc::new(client_factory),
+        Arc::new(blocking_client_factory),
         slow_request_logger.clone(),
         meta_map,
         future_registry.clone(),
+        client_registry.clone(),
     );
     let server = ServerProxyService::new(
         config.clone(),
         forward_handler,
         slow_request_logger,
         future_registry,
+        client_registry,
     );
 
     let mut runtime = tokio::runtime::Builder::new()
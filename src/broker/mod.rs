@@ -1,2 +1,4 @@
+mod persistence;
+pub mod replication;
 pub mod service;
 mod store;
@@ -0,0 +1,390 @@
+use super::store::{MetaStore, MetaStoreError, CHUNK_HALF_NODE_NUM};
+use crate::common::cluster::MigrationTaskMeta;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const SNAPSHOT_TMP_FILE: &str = "snapshot.json.tmp";
+const OP_LOG_FILE: &str = "operations.log";
+
+// All the operations which could change the MetaStore.
+// They need to be deterministic so that replaying them on the same snapshot
+// always results in the same MetaStore.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum MetaStoreOp {
+    AddProxy {
+        proxy_address: String,
+        nodes: [String; CHUNK_HALF_NODE_NUM],
+    },
+    RemoveProxy {
+        proxy_address: String,
+    },
+    AddCluster {
+        cluster_name: String,
+        node_num: usize,
+    },
+    AddSharedCluster {
+        cluster_name: String,
+        host_cluster_name: String,
+    },
+    RemoveCluster {
+        cluster_name: String,
+    },
+    ChangeConfig {
+        cluster_name: String,
+        config: BTreeMap<String, String>,
+    },
+    AutoAddNodes {
+        cluster_name: String,
+        num: Option<usize>,
+    },
+    MigrateSlots {
+        cluster_name: String,
+    },
+    CommitMigration {
+        task: MigrationTaskMeta,
+    },
+    ReplaceFailedProxy {
+        proxy_address: String,
+    },
+    AddFailure {
+        address: String,
+        reporter_id: String,
+        report_time: i64,
+    },
+}
+
+impl MetaStoreOp {
+    pub fn apply(self, store: &mut MetaStore) -> Result<(), MetaStoreError> {
+        match self {
+            MetaStoreOp::AddProxy {
+                proxy_address,
+                nodes,
+            } => store.add_proxy(proxy_address, nodes),
+            MetaStoreOp::RemoveProxy { proxy_address } => store.remove_proxy(proxy_address),
+            MetaStoreOp::AddCluster {
+                cluster_name,
+                node_num,
+            } => store.add_cluster(cluster_name, node_num),
+            MetaStoreOp::AddSharedCluster {
+                cluster_name,
+                host_cluster_name,
+            } => store.add_shared_cluster(cluster_name, host_cluster_name),
+            MetaStoreOp::RemoveCluster { cluster_name } => store.remove_cluster(cluster_name),
+            MetaStoreOp::ChangeConfig {
+                cluster_name,
+                config,
+            } => store.change_config(cluster_name, config),
+            MetaStoreOp::AutoAddNodes { cluster_name, num } => {
+                store.auto_add_nodes(cluster_name, num).map(|_| ())
+            }
+            MetaStoreOp::MigrateSlots { cluster_name } => store.migrate_slots(cluster_name),
+            MetaStoreOp::CommitMigration { task } => store.commit_migration(task),
+            MetaStoreOp::ReplaceFailedProxy { proxy_address } => {
+                store.replace_failed_proxy(proxy_address).map(|_| ())
+            }
+            MetaStoreOp::AddFailure {
+                address,
+                reporter_id,
+                report_time,
+            } => {
+                store.add_failure_with_time(address, reporter_id, report_time);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct OpLogEntry {
+    index: u64,
+    op: MetaStoreOp,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Snapshot {
+    // The index of the last operation included in this snapshot.
+    last_index: u64,
+    store: MetaStore,
+}
+
+// MetaStorage persists the MetaStore with a snapshot file and an append-only operation log.
+// Every operation gets a monotonically increasing index so that the operations
+// already included in the snapshot will be skipped during replaying
+// even if the broker crashes after writing the snapshot but before truncating the log.
+pub struct MetaStorage {
+    dir: PathBuf,
+    op_log: File,
+    last_index: u64,
+    snapshot_index: u64,
+}
+
+impl MetaStorage {
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<(Self, MetaStore)> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let (snapshot_index, mut store) = match Self::load_snapshot(&dir)? {
+            Some(Snapshot { last_index, store }) => (last_index, store),
+            None => (0, MetaStore::default()),
+        };
+
+        let mut last_index = snapshot_index;
+        let op_log_path = dir.join(OP_LOG_FILE);
+        let op_log_data = if op_log_path.exists() {
+            fs::read(&op_log_path)?
+        } else {
+            vec![]
+        };
+
+        let mut valid_len = 0;
+        for line in op_log_data.split_inclusive(|b| *b == b'\n') {
+            // The last entry might be partially written before crashing.
+            if line.last() != Some(&b'\n') {
+                warn!("found partially written operation log entry");
+                break;
+            }
+            let OpLogEntry { index, op } = match serde_json::from_slice(line) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("stop replaying at invalid operation log entry: {:?}", err);
+                    break;
+                }
+            };
+            valid_len += line.len();
+            if index <= last_index {
+                continue;
+            }
+            if let Err(err) = op.apply(&mut store) {
+                debug!("replayed operation {} failed: {:?}", index, err);
+            }
+            last_index = index;
+        }
+
+        let op_log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&op_log_path)?;
+        if valid_len < op_log_data.len() {
+            // Remove the broken entries so that the new entries will not be appended after them.
+            op_log.set_len(valid_len as u64)?;
+            op_log.sync_all()?;
+        }
+
+        info!(
+            "recovered meta data from {:?} with snapshot index {} and last index {}",
+            dir, snapshot_index, last_index
+        );
+
+        let storage = Self {
+            dir,
+            op_log,
+            last_index,
+            snapshot_index,
+        };
+        Ok((storage, store))
+    }
+
+    fn load_snapshot(dir: &Path) -> io::Result<Option<Snapshot>> {
+        let snapshot_path = dir.join(SNAPSHOT_FILE);
+        if !snapshot_path.exists() {
+            return Ok(None);
+        }
+        let reader = BufReader::new(File::open(&snapshot_path)?);
+        let snapshot = serde_json::from_reader(reader)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Some(snapshot))
+    }
+
+    pub fn append(&mut self, op: MetaStoreOp) -> io::Result<()> {
+        let entry = OpLogEntry {
+            index: self.last_index + 1,
+            op,
+        };
+        let mut data = serde_json::to_vec(&entry)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        data.push(b'\n');
+        self.op_log.write_all(&data)?;
+        self.op_log.sync_data()?;
+        self.last_index = entry.index;
+        Ok(())
+    }
+
+    pub fn need_snapshot(&self) -> bool {
+        self.last_index != self.snapshot_index
+    }
+
+    // The caller should make sure the store has applied all the operations
+    // appended to this storage.
+    pub fn snapshot(&mut self, store: MetaStore) -> io::Result<()> {
+        let snapshot = Snapshot {
+            last_index: self.last_index,
+            store,
+        };
+        let data = serde_json::to_vec(&snapshot)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        let tmp_path = self.dir.join(SNAPSHOT_TMP_FILE);
+        {
+            let mut tmp_file = File::create(&tmp_path)?;
+            tmp_file.write_all(&data)?;
+            tmp_file.sync_all()?;
+        }
+        fs::rename(&tmp_path, self.dir.join(SNAPSHOT_FILE))?;
+
+        self.op_log.set_len(0)?;
+        self.op_log.sync_all()?;
+        self.snapshot_index = snapshot.last_index;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn gen_testing_dir(name: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!("undermoon-{}-{}", name, nanos))
+    }
+
+    fn gen_testing_ops() -> Vec<MetaStoreOp> {
+        let mut ops = vec![];
+        for host_index in 1..=4 {
+            for i in 1..=3 {
+                ops.push(MetaStoreOp::AddProxy {
+                    proxy_address: format!("127.0.0.{}:70{:02}", host_index, i),
+                    nodes: [
+                        format!("127.0.0.{}:60{:02}", host_index, i * 2),
+                        format!("127.0.0.{}:60{:02}", host_index, i * 2 + 1),
+                    ],
+                });
+            }
+        }
+        ops.push(MetaStoreOp::AddCluster {
+            cluster_name: "test_db".to_string(),
+            node_num: 4,
+        });
+        ops.push(MetaStoreOp::AutoAddNodes {
+            cluster_name: "test_db".to_string(),
+            num: Some(4),
+        });
+        ops.push(MetaStoreOp::MigrateSlots {
+            cluster_name: "test_db".to_string(),
+        });
+        // The failed operations are also logged.
+        ops.push(MetaStoreOp::RemoveCluster {
+            cluster_name: "not_existed".to_string(),
+        });
+        ops.push(MetaStoreOp::AddFailure {
+            address: "127.0.0.1:7001".to_string(),
+            reporter_id: "reporter".to_string(),
+            report_time: 233,
+        });
+        ops
+    }
+
+    fn apply_and_append(store: &mut MetaStore, storage: &mut MetaStorage, op: MetaStoreOp) {
+        let _ = op.clone().apply(store);
+        storage.append(op).unwrap();
+    }
+
+    fn assert_store_eq(store1: &MetaStore, store2: &MetaStore) {
+        assert_eq!(
+            serde_json::to_value(store1).unwrap(),
+            serde_json::to_value(store2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_replay_op_log() {
+        let dir = gen_testing_dir("replay");
+        let (mut storage, mut store) = MetaStorage::open(&dir).unwrap();
+        for op in gen_testing_ops() {
+            apply_and_append(&mut store, &mut storage, op);
+        }
+        assert!(store.get_cluster_by_name("test_db").is_some());
+        drop(storage);
+
+        let (_storage, recovered_store) = MetaStorage::open(&dir).unwrap();
+        assert_store_eq(&store, &recovered_store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_and_replay() {
+        let dir = gen_testing_dir("snapshot");
+        let (mut storage, mut store) = MetaStorage::open(&dir).unwrap();
+        let mut ops = gen_testing_ops();
+        let rest_ops = ops.split_off(ops.len() / 2);
+        for op in ops {
+            apply_and_append(&mut store, &mut storage, op);
+        }
+        assert!(storage.need_snapshot());
+        storage.snapshot(store.clone()).unwrap();
+        assert!(!storage.need_snapshot());
+        for op in rest_ops {
+            apply_and_append(&mut store, &mut storage, op);
+        }
+        drop(storage);
+
+        let (_storage, recovered_store) = MetaStorage::open(&dir).unwrap();
+        assert_store_eq(&store, &recovered_store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_skip_ops_in_snapshot() {
+        let dir = gen_testing_dir("skip");
+        let (mut storage, mut store) = MetaStorage::open(&dir).unwrap();
+        for op in gen_testing_ops() {
+            apply_and_append(&mut store, &mut storage, op);
+        }
+        // Simulate crashing after writing the snapshot but before truncating the log.
+        let op_log = fs::read(dir.join(OP_LOG_FILE)).unwrap();
+        storage.snapshot(store.clone()).unwrap();
+        drop(storage);
+        fs::write(dir.join(OP_LOG_FILE), op_log).unwrap();
+
+        let (_storage, recovered_store) = MetaStorage::open(&dir).unwrap();
+        assert_store_eq(&store, &recovered_store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_partially_written_op_log() {
+        let dir = gen_testing_dir("partial");
+        let (mut storage, mut store) = MetaStorage::open(&dir).unwrap();
+        for op in gen_testing_ops() {
+            apply_and_append(&mut store, &mut storage, op);
+        }
+        drop(storage);
+        let mut op_log = OpenOptions::new()
+            .append(true)
+            .open(dir.join(OP_LOG_FILE))
+            .unwrap();
+        op_log
+            .write_all(b"{\"index\":10000,\"op\":{\"AddPro")
+            .unwrap();
+
+        let (mut storage, recovered_store) = MetaStorage::open(&dir).unwrap();
+        assert_store_eq(&store, &recovered_store);
+
+        // New entries should still be able to be replayed.
+        let op = MetaStoreOp::RemoveCluster {
+            cluster_name: "test_db".to_string(),
+        };
+        apply_and_append(&mut store, &mut storage, op);
+        drop(storage);
+        let (_storage, recovered_store) = MetaStorage::open(&dir).unwrap();
+        assert!(recovered_store.get_cluster_by_name("test_db").is_none());
+        assert_store_eq(&store, &recovered_store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
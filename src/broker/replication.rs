@@ -0,0 +1,260 @@
+use super::store::MetaStore;
+use futures::future::join_all;
+use reqwest;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BrokerRole {
+    Leader,
+    Follower,
+}
+
+impl FromStr for BrokerRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "leader" => Ok(BrokerRole::Leader),
+            "follower" => Ok(BrokerRole::Follower),
+            _ => Err(format!("invalid broker role {}", s)),
+        }
+    }
+}
+
+impl Serialize for BrokerRole {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            BrokerRole::Leader => serializer.serialize_str("leader"),
+            BrokerRole::Follower => serializer.serialize_str("follower"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BrokerRole {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        BrokerRole::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+// The term is bumped on every promotion so that the stale leader
+// will step down once it sees a larger term.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReplicationState {
+    pub role: BrokerRole,
+    pub term: u64,
+    pub leader_address: Option<String>,
+}
+
+impl ReplicationState {
+    pub fn new(role: BrokerRole, address: String) -> Self {
+        let leader_address = match role {
+            BrokerRole::Leader => Some(address),
+            BrokerRole::Follower => None,
+        };
+        Self {
+            role,
+            term: 0,
+            leader_address,
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.role == BrokerRole::Leader
+    }
+
+    // Returns whether the replicated store is newer than the current one,
+    // or the current state if the payload is rejected.
+    pub fn accept(
+        &mut self,
+        payload: &ReplicationPayload,
+        store: &MetaStore,
+    ) -> Result<bool, ReplicationState> {
+        if payload.term < self.term || (payload.term == self.term && self.is_leader()) {
+            return Err(self.clone());
+        }
+        let newer =
+            payload.term > self.term || payload.store.get_global_epoch() > store.get_global_epoch();
+        self.role = BrokerRole::Follower;
+        self.term = payload.term;
+        self.leader_address = Some(payload.leader_address.clone());
+        Ok(newer)
+    }
+
+    pub fn promote(&mut self, address: String) {
+        self.role = BrokerRole::Leader;
+        self.term += 1;
+        self.leader_address = Some(address);
+    }
+
+    pub fn step_down(&mut self, other: ReplicationState) {
+        if other.term <= self.term {
+            return;
+        }
+        self.role = BrokerRole::Follower;
+        self.term = other.term;
+        self.leader_address = other.leader_address;
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ReplicationPayload {
+    pub term: u64,
+    pub leader_address: String,
+    pub store: MetaStore,
+}
+
+pub struct MetaReplicator {
+    replica_addresses: Vec<String>,
+    client: reqwest::Client,
+}
+
+impl MetaReplicator {
+    pub fn new(replica_addresses: Vec<String>, client: reqwest::Client) -> Self {
+        Self {
+            replica_addresses,
+            client,
+        }
+    }
+
+    pub fn has_replicas(&self) -> bool {
+        !self.replica_addresses.is_empty()
+    }
+
+    // Returns the number of the replicas accepting the payload
+    // and the replication state with the largest term from the replicas rejecting it.
+    pub async fn replicate(
+        &self,
+        payload: &ReplicationPayload,
+    ) -> (usize, Option<ReplicationState>) {
+        let futs = self
+            .replica_addresses
+            .iter()
+            .map(|address| self.replicate_to(address, payload));
+        let results = join_all(futs).await;
+        let acked = results.iter().filter(|res| res.is_ok()).count();
+        let rejected = results
+            .into_iter()
+            .filter_map(|res| res.err().flatten())
+            .max_by_key(|state| state.term);
+        (acked, rejected)
+    }
+
+    // The number of the replicas needed so that the leader and them form the majority.
+    pub fn get_quorum(&self) -> usize {
+        let group_size = self.replica_addresses.len() + 1;
+        group_size / 2
+    }
+
+    // Err(None) means the replica could not be reached.
+    async fn replicate_to(
+        &self,
+        address: &str,
+        payload: &ReplicationPayload,
+    ) -> Result<(), Option<ReplicationState>> {
+        let url = format!("http://{}/api/replication/metadata", address);
+        let response = match self.client.put(&url).json(payload).send().await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!("failed to replicate metadata to {}: {:?}", address, err);
+                return Err(None);
+            }
+        };
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        if status.as_u16() != 409 {
+            warn!("failed to replicate metadata to {}: {:?}", address, status);
+            return Err(None);
+        }
+        match response.json::<ReplicationState>().await {
+            Ok(state) => {
+                warn!("replica {} rejected the metadata: {:?}", address, state);
+                Err(Some(state))
+            }
+            Err(err) => {
+                warn!("invalid replication reply from {}: {:?}", address, err);
+                Err(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_payload(term: u64, epoch: u64) -> ReplicationPayload {
+        let mut store = MetaStore::default();
+        for _ in 0..epoch {
+            store.bump_global_epoch();
+        }
+        ReplicationPayload {
+            term,
+            leader_address: "127.0.0.1:7799".to_string(),
+            store,
+        }
+    }
+
+    #[test]
+    fn test_follower_accept() {
+        let mut state = ReplicationState::new(BrokerRole::Follower, "127.0.0.1:17799".to_string());
+        let store = MetaStore::default();
+        assert!(state.accept(&gen_payload(0, 1), &store).unwrap());
+        assert_eq!(state.leader_address, Some("127.0.0.1:7799".to_string()));
+        assert!(!state.accept(&gen_payload(0, 0), &store).unwrap());
+        assert!(state.accept(&gen_payload(1, 0), &store).unwrap());
+        assert_eq!(state.term, 1);
+        assert!(state.accept(&gen_payload(0, 10), &store).is_err());
+        assert_eq!(state.term, 1);
+    }
+
+    #[test]
+    fn test_leader_step_down() {
+        let address = "127.0.0.1:17799".to_string();
+        let mut state = ReplicationState::new(BrokerRole::Leader, address.clone());
+        let store = MetaStore::default();
+        assert!(state.accept(&gen_payload(0, 1), &store).is_err());
+        assert!(state.is_leader());
+
+        assert!(state.accept(&gen_payload(1, 0), &store).unwrap());
+        assert!(!state.is_leader());
+
+        state.promote(address.clone());
+        assert!(state.is_leader());
+        assert_eq!(state.term, 2);
+        assert_eq!(state.leader_address, Some(address));
+
+        let other = ReplicationState {
+            role: BrokerRole::Leader,
+            term: 3,
+            leader_address: Some("127.0.0.1:7799".to_string()),
+        };
+        state.step_down(other);
+        assert!(!state.is_leader());
+        assert_eq!(state.term, 3);
+    }
+
+    #[test]
+    fn test_replication_quorum() {
+        let gen_replicator = |replica_num: usize| {
+            let addresses = (0..replica_num)
+                .map(|i| format!("127.0.0.1:{}", 7000 + i))
+                .collect();
+            MetaReplicator::new(addresses, reqwest::Client::new())
+        };
+        assert_eq!(gen_replicator(1).get_quorum(), 1);
+        assert_eq!(gen_replicator(2).get_quorum(), 1);
+        assert_eq!(gen_replicator(3).get_quorum(), 2);
+        assert_eq!(gen_replicator(4).get_quorum(), 2);
+    }
+}
@@ -1,3 +1,5 @@
+use super::persistence::{MetaStorage, MetaStoreOp};
+use super::replication::{BrokerRole, MetaReplicator, ReplicationPayload, ReplicationState};
 use super::store::{MetaStore, MetaStoreError, CHUNK_HALF_NODE_NUM};
 use crate::common::cluster::{Cluster, DBName, MigrationTaskMeta, Node, Proxy};
 use crate::common::version::UNDERMOON_VERSION;
@@ -5,14 +7,24 @@ use crate::coordinator::http_meta_broker::{
     ClusterNamesPayload, ClusterPayload, FailuresPayload, ProxyAddressesPayload, ProxyPayload,
 };
 use actix_http::ResponseBuilder;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
 use actix_web::{error, http, web, HttpRequest, HttpResponse, Responder};
 use chrono;
+use chrono::Utc;
+use futures::Future;
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::sync::{Arc, RwLock};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+const REPLICATION_API_PREFIX: &str = "/api/replication";
 
 pub fn configure_app(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
+            .wrap_fn(replicate_writes)
             .route("/version", web::get().to(get_version))
             .route("/metadata", web::get().to(get_all_metadata))
 
@@ -40,7 +52,12 @@ pub fn configure_app(cfg: &mut web::ServiceConfig) {
 
             // Additional api
             .route("/clusters", web::post().to(add_cluster))
+            .route("/clusters/shared", web::post().to(add_shared_cluster))
             .route("/clusters/meta/{cluster_name}", web::delete().to(remove_cluster))
+            .route(
+                "/clusters/config/{cluster_name}",
+                web::patch().to(change_config),
+            )
             .route(
                 "/clusters/nodes/{cluster_name}",
                 web::post().to(auto_add_nodes),
@@ -55,28 +72,215 @@ pub fn configure_app(cfg: &mut web::ServiceConfig) {
             .route(
                 "/proxies/nodes/{proxy_address}",
                 web::delete().to(remove_proxy),
-            ),
+            )
+
+            // Replication api
+            .route("/replication/state", web::get().to(get_replication_state))
+            .route("/replication/metadata", web::put().to(receive_replication))
+            .route("/replication/promote", web::post().to(promote)),
     );
 }
 
+type ServiceFuture = Pin<Box<dyn Future<Output = Result<ServiceResponse, error::Error>>>>;
+
+// Followers redirect the write requests to the leader,
+// and the leader replicates the metadata to followers after the write requests.
+// The write requests fail with 503 if the majority of the followers don't get the metadata,
+// though the leader will still keep replicating the changes periodically.
+fn replicate_writes<S>(req: ServiceRequest, srv: &mut S) -> ServiceFuture
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = error::Error>,
+    S::Future: 'static,
+{
+    let is_write =
+        req.method() != http::Method::GET && !req.path().starts_with(REPLICATION_API_PREFIX);
+    let service = match req.app_data::<Arc<MemBrokerService>>() {
+        Some(service) if is_write => service,
+        _ => return Box::pin(srv.call(req)),
+    };
+
+    let state = service.get_replication_state();
+    if !state.is_leader() {
+        let response = match state.leader_address {
+            Some(leader_address) => {
+                let path = req
+                    .uri()
+                    .path_and_query()
+                    .map(|p| p.as_str())
+                    .unwrap_or_else(|| req.path());
+                let location = format!("http://{}{}", leader_address, path);
+                HttpResponse::TemporaryRedirect()
+                    .header(http::header::LOCATION, location)
+                    .finish()
+            }
+            None => HttpResponse::ServiceUnavailable().body("leader not found"),
+        };
+        return Box::pin(futures::future::ok(req.into_response(response)));
+    }
+
+    let fut = srv.call(req);
+    Box::pin(async move {
+        let response = fut.await?;
+        if response.status().is_success() && !service.replicate().await {
+            let unavailable =
+                HttpResponse::ServiceUnavailable().body("failed to replicate metadata");
+            return Ok(response.into_response(unavailable));
+        }
+        Ok(response)
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct MemBrokerConfig {
     pub address: String,
     pub failure_ttl: u64, // in seconds
     pub failure_quorum: u64,
+    // None will disable persistence.
+    pub storage_dir: Option<String>,
+    pub snapshot_interval: u64, // in seconds
+    pub role: BrokerRole,
+    // Other brokers in the same replication group.
+    pub replica_addresses: Vec<String>,
+    pub replication_interval: u64, // in seconds
 }
 
 pub struct MemBrokerService {
     config: MemBrokerConfig,
     store: Arc<RwLock<MetaStore>>,
+    storage: Option<Mutex<MetaStorage>>,
+    replication: Mutex<ReplicationState>,
+    replicator: MetaReplicator,
 }
 
 impl MemBrokerService {
-    pub fn new(config: MemBrokerConfig) -> Self {
-        Self {
+    pub fn new(config: MemBrokerConfig) -> io::Result<Self> {
+        let (storage, store) = match config.storage_dir.as_ref() {
+            Some(dir) => {
+                let (storage, store) = MetaStorage::open(dir)?;
+                (Some(Mutex::new(storage)), store)
+            }
+            None => (None, MetaStore::default()),
+        };
+        let replication = ReplicationState::new(config.role, config.address.clone());
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(1))
+            .build()
+            .expect("MemBrokerService::new: failed to create http client");
+        let replicator = MetaReplicator::new(config.replica_addresses.clone(), client);
+        Ok(Self {
             config,
-            store: Arc::new(RwLock::new(MetaStore::default())),
+            store: Arc::new(RwLock::new(store)),
+            storage,
+            replication: Mutex::new(replication),
+            replicator,
+        })
+    }
+
+    pub fn get_replication_state(&self) -> ReplicationState {
+        self.replication
+            .lock()
+            .expect("MemBrokerService::get_replication_state")
+            .clone()
+    }
+
+    pub fn receive_replication(
+        &self,
+        payload: ReplicationPayload,
+    ) -> Result<ReplicationState, ReplicationState> {
+        let mut replication = self
+            .replication
+            .lock()
+            .expect("MemBrokerService::receive_replication");
+        let mut store = self
+            .store
+            .write()
+            .expect("MemBrokerService::receive_replication: lock store");
+        if replication.accept(&payload, &store)? {
+            *store = payload.store;
+            if let Some(storage) = self.storage.as_ref() {
+                let res = storage
+                    .lock()
+                    .expect("MemBrokerService::receive_replication: lock storage")
+                    .snapshot(store.clone());
+                if let Err(err) = res {
+                    error!("failed to save replicated metadata: {:?}", err);
+                }
+            }
+        }
+        Ok(replication.clone())
+    }
+
+    pub fn promote(&self) -> ReplicationState {
+        let mut replication = self.replication.lock().expect("MemBrokerService::promote");
+        replication.promote(self.config.address.clone());
+        info!("promoted to leader: {:?}", replication);
+        replication.clone()
+    }
+
+    // Returns whether the metadata is replicated to the majority of the followers.
+    pub async fn replicate(&self) -> bool {
+        if !self.replicator.has_replicas() {
+            return true;
         }
+        let state = self.get_replication_state();
+        if !state.is_leader() {
+            return false;
+        }
+        let payload = ReplicationPayload {
+            term: state.term,
+            leader_address: self.config.address.clone(),
+            store: self.get_all_data(),
+        };
+        let (acked, rejected) = self.replicator.replicate(&payload).await;
+        if let Some(other) = rejected {
+            let mut replication = self
+                .replication
+                .lock()
+                .expect("MemBrokerService::replicate");
+            replication.step_down(other);
+            if !replication.is_leader() {
+                warn!("step down to follower: {:?}", replication);
+                return false;
+            }
+        }
+        acked >= self.replicator.get_quorum()
+    }
+
+    // Operations are persisted before being applied
+    // so that the store will not be changed if we fail to persist the operation.
+    // The failed operations are also persisted since they will fail again in the replay.
+    fn update_store<T, F>(&self, op: MetaStoreOp, f: F) -> Result<T, MetaStoreError>
+    where
+        F: FnOnce(&mut MetaStore) -> Result<T, MetaStoreError>,
+    {
+        let mut store = self.store.write().expect("MemBrokerService::update_store");
+        if let Some(storage) = self.storage.as_ref() {
+            storage
+                .lock()
+                .expect("MemBrokerService::update_store: lock storage")
+                .append(op)
+                .map_err(|err| {
+                    error!("failed to persist meta store operation: {:?}", err);
+                    MetaStoreError::PersistenceError
+                })?;
+        }
+        f(&mut store)
+    }
+
+    pub fn snapshot(&self) -> io::Result<()> {
+        let storage = match self.storage.as_ref() {
+            Some(storage) => storage,
+            None => return Ok(()),
+        };
+        // Hold the read lock so that no operation could be appended during the snapshot.
+        let store = self.store.read().expect("MemBrokerService::snapshot");
+        let mut storage = storage
+            .lock()
+            .expect("MemBrokerService::snapshot: lock storage");
+        if !storage.need_snapshot() {
+            return Ok(());
+        }
+        storage.snapshot(store.clone())
     }
 
     pub fn get_all_data(&self) -> MetaStore {
@@ -119,45 +323,74 @@ impl MemBrokerService {
             proxy_address,
             nodes,
         } = host_resource;
-        self.store
-            .write()
-            .expect("MemBrokerService::add_hosts")
-            .add_proxy(proxy_address, nodes)
+        let op = MetaStoreOp::AddProxy {
+            proxy_address: proxy_address.clone(),
+            nodes: nodes.clone(),
+        };
+        self.update_store(op, |store| store.add_proxy(proxy_address, nodes))
     }
 
     pub fn add_cluster(&self, cluster_name: String, node_num: usize) -> Result<(), MetaStoreError> {
-        self.store
-            .write()
-            .expect("MemBrokerService::add_cluster")
-            .add_cluster(cluster_name, node_num)
+        let op = MetaStoreOp::AddCluster {
+            cluster_name: cluster_name.clone(),
+            node_num,
+        };
+        self.update_store(op, |store| store.add_cluster(cluster_name, node_num))
+    }
+
+    pub fn add_shared_cluster(
+        &self,
+        cluster_name: String,
+        host_cluster_name: String,
+    ) -> Result<(), MetaStoreError> {
+        let op = MetaStoreOp::AddSharedCluster {
+            cluster_name: cluster_name.clone(),
+            host_cluster_name: host_cluster_name.clone(),
+        };
+        self.update_store(op, |store| {
+            store.add_shared_cluster(cluster_name, host_cluster_name)
+        })
     }
 
     pub fn remove_cluster(&self, cluster_name: String) -> Result<(), MetaStoreError> {
-        self.store
-            .write()
-            .expect("MemBrokerService::remove_cluster")
-            .remove_cluster(cluster_name)
+        let op = MetaStoreOp::RemoveCluster {
+            cluster_name: cluster_name.clone(),
+        };
+        self.update_store(op, |store| store.remove_cluster(cluster_name))
+    }
+
+    pub fn change_config(
+        &self,
+        cluster_name: String,
+        config: BTreeMap<String, String>,
+    ) -> Result<(), MetaStoreError> {
+        let op = MetaStoreOp::ChangeConfig {
+            cluster_name: cluster_name.clone(),
+            config: config.clone(),
+        };
+        self.update_store(op, |store| store.change_config(cluster_name, config))
     }
 
     pub fn auto_add_node(&self, cluster_name: String) -> Result<Vec<Node>, MetaStoreError> {
-        self.store
-            .write()
-            .expect("MemBrokerService::auto_add_node")
-            .auto_add_nodes(cluster_name, None)
+        let op = MetaStoreOp::AutoAddNodes {
+            cluster_name: cluster_name.clone(),
+            num: None,
+        };
+        self.update_store(op, |store| store.auto_add_nodes(cluster_name, None))
     }
 
     pub fn remove_proxy(&self, proxy_address: String) -> Result<(), MetaStoreError> {
-        self.store
-            .write()
-            .expect("MemBrokerService::remove_proxy")
-            .remove_proxy(proxy_address)
+        let op = MetaStoreOp::RemoveProxy {
+            proxy_address: proxy_address.clone(),
+        };
+        self.update_store(op, |store| store.remove_proxy(proxy_address))
     }
 
     pub fn migrate_slots(&self, cluster_name: String) -> Result<(), MetaStoreError> {
-        self.store
-            .write()
-            .expect("MemBrokerService::migrate_slots")
-            .migrate_slots(cluster_name)
+        let op = MetaStoreOp::MigrateSlots {
+            cluster_name: cluster_name.clone(),
+        };
+        self.update_store(op, |store| store.migrate_slots(cluster_name))
     }
 
     pub fn get_failures(&self) -> Vec<String> {
@@ -169,28 +402,32 @@ impl MemBrokerService {
             .get_failures(failure_ttl, failure_quorum)
     }
 
-    pub fn add_failure(&self, address: String, reporter_id: String) {
-        self.store
-            .write()
-            .expect("MemBrokerService::add_failure")
-            .add_failure(address, reporter_id)
+    pub fn add_failure(&self, address: String, reporter_id: String) -> Result<(), MetaStoreError> {
+        let report_time = Utc::now().timestamp();
+        let op = MetaStoreOp::AddFailure {
+            address: address.clone(),
+            reporter_id: reporter_id.clone(),
+            report_time,
+        };
+        self.update_store(op, |store| {
+            store.add_failure_with_time(address, reporter_id, report_time);
+            Ok(())
+        })
     }
 
     pub fn commit_migration(&self, task: MigrationTaskMeta) -> Result<(), MetaStoreError> {
-        self.store
-            .write()
-            .expect("MemBrokerService::commit_migration")
-            .commit_migration(task)
+        let op = MetaStoreOp::CommitMigration { task: task.clone() };
+        self.update_store(op, |store| store.commit_migration(task))
     }
 
     pub fn replace_failed_node(
         &self,
         failed_proxy_address: String,
     ) -> Result<Proxy, MetaStoreError> {
-        self.store
-            .write()
-            .expect("MemBrokerService::replace_failed_node")
-            .replace_failed_proxy(failed_proxy_address)
+        let op = MetaStoreOp::ReplaceFailedProxy {
+            proxy_address: failed_proxy_address.clone(),
+        };
+        self.update_store(op, |store| store.replace_failed_proxy(failed_proxy_address))
     }
 }
 
@@ -264,6 +501,24 @@ async fn add_cluster(
     state.add_cluster(cluster_name, node_number).map(|()| "")
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct CreateSharedClusterPayload {
+    cluster_name: String,
+    host_cluster_name: String,
+}
+
+async fn add_shared_cluster(
+    (payload, state): (web::Json<CreateSharedClusterPayload>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
+    let CreateSharedClusterPayload {
+        cluster_name,
+        host_cluster_name,
+    } = payload.into_inner();
+    state
+        .add_shared_cluster(cluster_name, host_cluster_name)
+        .map(|()| "")
+}
+
 async fn remove_cluster(
     (path, state): (web::Path<(String,)>, ServiceState),
 ) -> Result<&'static str, MetaStoreError> {
@@ -271,6 +526,19 @@ async fn remove_cluster(
     state.remove_cluster(cluster_name).map(|()| "")
 }
 
+async fn change_config(
+    (path, payload, state): (
+        web::Path<(String,)>,
+        web::Json<BTreeMap<String, String>>,
+        ServiceState,
+    ),
+) -> Result<&'static str, MetaStoreError> {
+    let cluster_name = path.into_inner().0;
+    state
+        .change_config(cluster_name, payload.into_inner())
+        .map(|()| "")
+}
+
 async fn auto_add_nodes(
     (path, state): (web::Path<(String,)>, ServiceState),
 ) -> Result<web::Json<Vec<Node>>, MetaStoreError> {
@@ -292,10 +560,13 @@ async fn migrate_slots(
     state.migrate_slots(cluster_name).map(|()| "")
 }
 
-async fn add_failure((path, state): (web::Path<(String, String)>, ServiceState)) -> &'static str {
+async fn add_failure(
+    (path, state): (web::Path<(String, String)>, ServiceState),
+) -> Result<&'static str, MetaStoreError> {
     let (server_proxy_address, reporter_id) = path.into_inner();
-    state.add_failure(server_proxy_address, reporter_id);
-    ""
+    state
+        .add_failure(server_proxy_address, reporter_id)
+        .map(|()| "")
 }
 
 async fn commit_migration(
@@ -304,6 +575,25 @@ async fn commit_migration(
     state.commit_migration(task.into_inner()).map(|()| "")
 }
 
+async fn get_replication_state(state: ServiceState) -> impl Responder {
+    web::Json(state.get_replication_state())
+}
+
+async fn receive_replication(
+    (payload, state): (web::Json<ReplicationPayload>, ServiceState),
+) -> HttpResponse {
+    match state.receive_replication(payload.into_inner()) {
+        Ok(replication) => HttpResponse::Ok().json(replication),
+        Err(replication) => HttpResponse::Conflict().json(replication),
+    }
+}
+
+async fn promote(state: ServiceState) -> impl Responder {
+    let replication = state.promote();
+    state.replicate().await;
+    web::Json(replication)
+}
+
 async fn replace_failed_node(
     (path, state): (web::Path<(String,)>, ServiceState),
 ) -> Result<web::Json<Proxy>, MetaStoreError> {
@@ -315,6 +605,7 @@ impl error::ResponseError for MetaStoreError {
     fn status_code(&self) -> http::StatusCode {
         match self {
             MetaStoreError::NoAvailableResource => http::StatusCode::CONFLICT,
+            MetaStoreError::PersistenceError => http::StatusCode::INTERNAL_SERVER_ERROR,
             _ => http::StatusCode::BAD_REQUEST,
         }
     }
@@ -323,3 +614,112 @@ impl error::ResponseError for MetaStoreError {
         ResponseBuilder::new(self.status_code()).body(self.description().to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    fn gen_service(role: BrokerRole) -> Arc<MemBrokerService> {
+        gen_service_with_replicas(role, vec![])
+    }
+
+    fn gen_service_with_replicas(
+        role: BrokerRole,
+        replica_addresses: Vec<String>,
+    ) -> Arc<MemBrokerService> {
+        let config = MemBrokerConfig {
+            address: "127.0.0.1:17799".to_string(),
+            failure_ttl: 60,
+            failure_quorum: 1,
+            storage_dir: None,
+            snapshot_interval: 3600,
+            role,
+            replica_addresses,
+            replication_interval: 10,
+        };
+        Arc::new(MemBrokerService::new(config).unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn test_follower_redirects_writes() {
+        let service = gen_service(BrokerRole::Follower);
+        let mut app =
+            test::init_service(App::new().data(service.clone()).configure(configure_app)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/clusters/nodes/mydb?count=4")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+
+        let payload = ReplicationPayload {
+            term: 1,
+            leader_address: "127.0.0.1:7799".to_string(),
+            store: MetaStore::default(),
+        };
+        assert!(service.receive_replication(payload).is_ok());
+
+        let req = test::TestRequest::post()
+            .uri("/api/clusters/nodes/mydb?count=4")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(
+            resp.headers().get(http::header::LOCATION).unwrap(),
+            "http://127.0.0.1:7799/api/clusters/nodes/mydb?count=4"
+        );
+
+        // The read requests and the replication requests are served by the follower.
+        let req = test::TestRequest::get().uri("/api/version").to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let req = test::TestRequest::post()
+            .uri("/api/replication/promote")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        assert!(service.get_replication_state().is_leader());
+    }
+
+    #[actix_rt::test]
+    async fn test_leader_serves_writes() {
+        let service = gen_service(BrokerRole::Leader);
+        let mut app =
+            test::init_service(App::new().data(service.clone()).configure(configure_app)).await;
+
+        let req = test::TestRequest::delete()
+            .uri("/api/clusters/meta/mydb")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_ne!(resp.status(), http::StatusCode::TEMPORARY_REDIRECT);
+        assert!(resp.headers().get(http::header::LOCATION).is_none());
+    }
+
+    #[actix_rt::test]
+    async fn test_leader_fails_writes_without_replicas() {
+        // Nothing listens on these ports.
+        let replica_addresses = vec!["127.0.0.1:1".to_string(), "127.0.0.1:2".to_string()];
+        let service = gen_service_with_replicas(BrokerRole::Leader, replica_addresses);
+        let mut app =
+            test::init_service(App::new().data(service.clone()).configure(configure_app)).await;
+
+        let req = test::TestRequest::put()
+            .uri("/api/proxies/nodes")
+            .set_json(&ProxyResource {
+                proxy_address: "127.0.0.1:7000".to_string(),
+                nodes: ["127.0.0.1:6000".to_string(), "127.0.0.1:6001".to_string()],
+            })
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+
+        // The failed requests are not replicated.
+        let req = test::TestRequest::delete()
+            .uri("/api/clusters/meta/mydb")
+            .to_request();
+        let resp = test::call_service(&mut app, req).await;
+        assert_eq!(resp.status(), http::StatusCode::BAD_REQUEST);
+    }
+}
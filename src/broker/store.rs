@@ -8,7 +8,7 @@ use crate::common::utils::SLOT_NUM;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use itertools::Itertools;
 use std::cmp::min;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::num::NonZeroUsize;
@@ -122,6 +122,10 @@ struct ClusterStore {
     name: DBName,
     chunks: Vec<ChunkStore>,
     config: ClusterConfig,
+    // The cluster whose chunks are shared with this cluster.
+    // The chunks of this cluster are left empty when it's set.
+    #[serde(default)]
+    host: Option<DBName>,
 }
 
 #[derive(Debug)]
@@ -133,9 +137,11 @@ struct MigrationSlots {
 #[derive(Clone, Deserialize, Serialize)]
 pub struct MetaStore {
     global_epoch: u64,
-    cluster: Option<ClusterStore>,
+    clusters: HashMap<DBName, ClusterStore>,
     // proxy_address => nodes and cluster_name
-    all_proxies: HashMap<String, ProxyResource>,
+    // Use BTreeMap to make the resource allocation deterministic
+    // so that replaying the operation log results in the same state.
+    all_proxies: BTreeMap<String, ProxyResource>,
     // proxy addresses
     failed_proxies: HashSet<String>,
     // failed_proxy_address => reporter_id => time,
@@ -146,8 +152,8 @@ impl Default for MetaStore {
     fn default() -> Self {
         Self {
             global_epoch: 0,
-            cluster: None,
-            all_proxies: HashMap::new(),
+            clusters: HashMap::new(),
+            all_proxies: BTreeMap::new(),
             failed_proxies: HashSet::new(),
             failures: HashMap::new(),
         }
@@ -169,39 +175,32 @@ impl MetaStore {
     }
 
     pub fn get_proxy_by_address(&self, address: &str) -> Option<Proxy> {
-        let all_nodes = &self.all_proxies;
-        let cluster_opt = self.get_cluster();
-
-        let node_resource = all_nodes.get(address)?;
-
-        let cluster = match cluster_opt {
-            Some(cluster) => cluster,
-            None => {
-                return Some(Proxy::new(
-                    address.to_string(),
-                    self.global_epoch,
-                    vec![],
-                    node_resource.node_addresses.to_vec(),
-                    vec![],
-                    HashMap::new(),
-                ));
-            }
+        let node_resource = self.all_proxies.get(address)?;
+        // The clusters sharing the nodes all reside in the same proxies.
+        let clusters: Vec<Cluster> = match self.get_cluster_store_by_proxy(address) {
+            Some(cluster_store) => self
+                .get_sharing_cluster_stores(&cluster_store.name)
+                .into_iter()
+                .map(|cluster_store| self.cluster_store_to_cluster(cluster_store))
+                .collect(),
+            None => vec![],
         };
 
-        let cluster_name = cluster.get_name().clone();
         let epoch = self.global_epoch;
-        let nodes: Vec<Node> = cluster
-            .get_nodes()
-            .iter()
-            .filter(|node| node.get_proxy_address() == address)
-            .cloned()
-            .collect();
-
-        let (peers, free_nodes) = if nodes.is_empty() {
-            let free_nodes = node_resource.node_addresses.to_vec();
-            (vec![], free_nodes)
-        } else {
-            let peers = cluster
+        let mut clusters_config = HashMap::new();
+        let mut nodes: Vec<Node> = vec![];
+        let mut peers: Vec<PeerProxy> = vec![];
+        for cluster in clusters.iter() {
+            let cluster_name = cluster.get_name().clone();
+            clusters_config.insert(cluster_name.clone(), cluster.get_config().clone());
+            nodes.extend(
+                cluster
+                    .get_nodes()
+                    .iter()
+                    .filter(|node| node.get_proxy_address() == address)
+                    .cloned(),
+            );
+            let cluster_peers = cluster
                 .get_nodes()
                 .iter()
                 .filter(|n| n.get_role() == Role::Master && n.get_proxy_address() != address)
@@ -217,8 +216,15 @@ impl MetaStore {
                         slots,
                     }
                 })
-                .collect();
-            (peers, vec![])
+                .collect::<Vec<_>>();
+            peers.extend(cluster_peers);
+        }
+
+        let free_nodes = if nodes.is_empty() {
+            peers.clear();
+            node_resource.node_addresses.to_vec()
+        } else {
+            vec![]
         };
         let proxy = Proxy::new(
             address.to_string(),
@@ -226,34 +232,71 @@ impl MetaStore {
             nodes,
             free_nodes,
             peers,
-            HashMap::new(),
+            clusters_config,
         );
         Some(proxy)
     }
 
     pub fn get_cluster_names(&self) -> Vec<DBName> {
-        match &self.cluster {
-            Some(cluster_store) => vec![cluster_store.name.clone()],
-            None => vec![],
-        }
+        let mut names: Vec<DBName> = self.clusters.keys().cloned().collect();
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        names
     }
 
     pub fn get_cluster_by_name(&self, db_name: &str) -> Option<Cluster> {
         let db_name = DBName::from(&db_name).ok()?;
-        let cluster_store = self.cluster.as_ref()?;
-        if cluster_store.name != db_name {
-            return None;
-        }
+        let cluster_store = self.clusters.get(&db_name)?;
+        Some(self.cluster_store_to_cluster(cluster_store))
+    }
+
+    fn get_cluster_store_by_proxy(&self, proxy_address: &str) -> Option<&ClusterStore> {
+        self.clusters.values().find(|cluster_store| {
+            cluster_store.chunks.iter().any(|chunk| {
+                chunk
+                    .proxy_addresses
+                    .iter()
+                    .any(|address| address == proxy_address)
+            })
+        })
+    }
+
+    // Returns the host cluster and all the clusters sharing its nodes.
+    fn get_sharing_cluster_stores(&self, host_name: &DBName) -> Vec<&ClusterStore> {
+        let mut cluster_stores: Vec<&ClusterStore> = self
+            .clusters
+            .values()
+            .filter(|cluster_store| {
+                &cluster_store.name == host_name || cluster_store.host.as_ref() == Some(host_name)
+            })
+            .collect();
+        cluster_stores.sort_by(|a, b| a.name.as_str().cmp(b.name.as_str()));
+        cluster_stores
+    }
+
+    fn is_sharing_nodes(&self, db_name: &DBName) -> bool {
+        self.clusters.values().any(|cluster_store| {
+            (&cluster_store.name == db_name && cluster_store.host.is_some())
+                || cluster_store.host.as_ref() == Some(db_name)
+        })
+    }
 
-        self.get_cluster()
+    fn get_cluster_name_by_proxy(&self, proxy_address: &str) -> Option<DBName> {
+        self.get_cluster_store_by_proxy(proxy_address)
+            .map(|cluster_store| cluster_store.name.clone())
     }
 
-    fn get_cluster(&self) -> Option<Cluster> {
-        let cluster_store = self.cluster.as_ref()?;
+    fn cluster_store_to_cluster(&self, cluster_store: &ClusterStore) -> Cluster {
         let cluster_name = cluster_store.name.clone();
+        let chunks = match cluster_store
+            .host
+            .as_ref()
+            .and_then(|host_name| self.clusters.get(host_name))
+        {
+            Some(host_store) => &host_store.chunks,
+            None => &cluster_store.chunks,
+        };
 
-        let nodes = cluster_store
-            .chunks
+        let nodes = chunks
             .iter()
             .map(|chunk| {
                 let mut nodes = vec![];
@@ -284,9 +327,7 @@ impl MetaStore {
                         slots.append(&mut first_slots);
                         let slot_ranges: Vec<_> = chunk.migrating_slots[0]
                             .iter()
-                            .map(|slot_range_store| {
-                                slot_range_store.to_slot_range(&cluster_store.chunks)
-                            })
+                            .map(|slot_range_store| slot_range_store.to_slot_range(chunks))
                             .collect();
                         slots.extend(slot_ranges);
                     }
@@ -298,9 +339,7 @@ impl MetaStore {
                         slots.append(&mut second_slots);
                         let slot_ranges: Vec<_> = chunk.migrating_slots[1]
                             .iter()
-                            .map(|slot_range_store| {
-                                slot_range_store.to_slot_range(&cluster_store.chunks)
-                            })
+                            .map(|slot_range_store| slot_range_store.to_slot_range(chunks))
                             .collect();
                         slots.extend(slot_ranges);
                     }
@@ -346,22 +385,30 @@ impl MetaStore {
             .flatten()
             .collect();
 
-        let cluster = Cluster::new(
+        Cluster::new(
             cluster_store.name.clone(),
             self.global_epoch,
             nodes,
             cluster_store.config.clone(),
-        );
-        Some(cluster)
+        )
     }
 
     pub fn add_failure(&mut self, address: String, reporter_id: String) {
         let now = Utc::now();
+        self.add_failure_with_time(address, reporter_id, now.timestamp())
+    }
+
+    pub fn add_failure_with_time(
+        &mut self,
+        address: String,
+        reporter_id: String,
+        report_time: i64,
+    ) {
         self.bump_global_epoch();
         self.failures
             .entry(address)
             .or_insert_with(HashMap::new)
-            .insert(reporter_id, now.timestamp());
+            .insert(reporter_id, report_time);
     }
 
     pub fn get_failures(
@@ -412,8 +459,8 @@ impl MetaStore {
 
     pub fn add_cluster(&mut self, db_name: String, node_num: usize) -> Result<(), MetaStoreError> {
         let db_name = DBName::from(&db_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
-        if self.cluster.is_some() {
-            return Err(MetaStoreError::OnlySupportOneCluster);
+        if self.clusters.contains_key(&db_name) {
+            return Err(MetaStoreError::AlreadyExisted);
         }
 
         if node_num % 4 != 0 {
@@ -426,12 +473,74 @@ impl MetaStore {
         let chunk_stores = Self::proxy_resource_to_chunk_store(proxy_resource_arr, true);
 
         let cluster_store = ClusterStore {
-            name: db_name,
+            name: db_name.clone(),
             chunks: chunk_stores,
             config: ClusterConfig::default(),
+            host: None,
         };
 
-        self.cluster = Some(cluster_store);
+        self.clusters.insert(db_name, cluster_store);
+        self.bump_global_epoch();
+        Ok(())
+    }
+
+    // The new cluster shares the nodes of the host cluster
+    // and uses `<cluster_name>:` as its key prefix.
+    pub fn add_shared_cluster(
+        &mut self,
+        db_name: String,
+        host_name: String,
+    ) -> Result<(), MetaStoreError> {
+        let db_name = DBName::from(&db_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
+        let host_name = DBName::from(&host_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
+        if self.clusters.contains_key(&db_name) {
+            return Err(MetaStoreError::AlreadyExisted);
+        }
+
+        let host = self
+            .clusters
+            .get(&host_name)
+            .ok_or(MetaStoreError::ClusterNotFound)?;
+        if host.host.is_some() {
+            return Err(MetaStoreError::NotSupported);
+        }
+        // The added nodes without any slots are also waiting for the migration.
+        let running_migration = host.chunks.iter().any(|chunk| {
+            chunk.migrating_slots.iter().any(|slots| !slots.is_empty())
+                || chunk.stable_slots.iter().any(|slots| slots.is_none())
+        });
+        if running_migration {
+            return Err(MetaStoreError::MigrationRunning);
+        }
+
+        let key_prefix = format!("{}:", db_name);
+        let mut prefixes = vec![];
+        for cluster_store in self.get_sharing_cluster_stores(&host_name).into_iter() {
+            match cluster_store.config.key_prefix.as_ref() {
+                Some(prefix) => prefixes.push(prefix.as_str()),
+                // Otherwise the keys of the host cluster could conflict with the others.
+                None => return Err(MetaStoreError::InvalidConfig),
+            }
+        }
+        let conflicted = prefixes
+            .iter()
+            .any(|prefix| prefix.starts_with(&key_prefix) || key_prefix.starts_with(prefix));
+        if conflicted {
+            return Err(MetaStoreError::InvalidConfig);
+        }
+
+        let config = ClusterConfig {
+            key_prefix: Some(key_prefix),
+            ..Default::default()
+        };
+        let cluster_store = ClusterStore {
+            name: db_name.clone(),
+            chunks: vec![],
+            config,
+            host: Some(host_name),
+        };
+
+        self.clusters.insert(db_name, cluster_store);
         self.bump_global_epoch();
         Ok(())
     }
@@ -491,14 +600,45 @@ impl MetaStore {
     pub fn remove_cluster(&mut self, db_name: String) -> Result<(), MetaStoreError> {
         let db_name = DBName::from(&db_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
 
-        match &self.cluster {
-            None => return Err(MetaStoreError::ClusterNotFound),
-            Some(cluster) if cluster.name != db_name => {
-                return Err(MetaStoreError::ClusterNotFound);
-            }
-            _ => (),
+        let has_sharing_clusters = self
+            .clusters
+            .values()
+            .any(|cluster_store| cluster_store.host.as_ref() == Some(&db_name));
+        if has_sharing_clusters {
+            return Err(MetaStoreError::InUse);
+        }
+
+        if self.clusters.remove(&db_name).is_none() {
+            return Err(MetaStoreError::ClusterNotFound);
         }
-        self.cluster = None;
+
+        self.bump_global_epoch();
+        Ok(())
+    }
+
+    // All the fields are validated before any of them gets changed.
+    pub fn change_config(
+        &mut self,
+        db_name: String,
+        config: BTreeMap<String, String>,
+    ) -> Result<(), MetaStoreError> {
+        let db_name = DBName::from(&db_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
+        // The key prefixes keep the keys of the clusters sharing nodes apart.
+        if config.contains_key("key_prefix") && self.is_sharing_nodes(&db_name) {
+            return Err(MetaStoreError::InvalidConfig);
+        }
+        let cluster = self
+            .clusters
+            .get_mut(&db_name)
+            .ok_or(MetaStoreError::ClusterNotFound)?;
+
+        let mut cluster_config = cluster.config.clone();
+        for (field, value) in config.iter() {
+            cluster_config
+                .set_field(field, value)
+                .map_err(|_| MetaStoreError::InvalidConfig)?;
+        }
+        cluster.config = cluster_config;
 
         self.bump_global_epoch();
         Ok(())
@@ -511,15 +651,13 @@ impl MetaStore {
     ) -> Result<Vec<Node>, MetaStoreError> {
         let db_name = DBName::from(&db_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
 
-        let existing_node_num = match self.cluster.as_ref() {
+        let existing_node_num = match self.clusters.get(&db_name) {
             None => return Err(MetaStoreError::ClusterNotFound),
-            Some(cluster) => {
-                if cluster.name != db_name {
-                    return Err(MetaStoreError::ClusterNotFound);
-                }
-                cluster.chunks.len() * 4
-            }
+            Some(cluster) => cluster.chunks.len() * 4,
         };
+        if self.is_sharing_nodes(&db_name) {
+            return Err(MetaStoreError::NotSupported);
+        }
 
         let num = match num {
             None => existing_node_num,
@@ -534,14 +672,16 @@ impl MetaStore {
         let proxy_resource_arr = self.consume_proxy(proxy_num)?;
         let mut chunks = Self::proxy_resource_to_chunk_store(proxy_resource_arr, false);
 
-        match self.cluster {
+        match self.clusters.get_mut(&db_name) {
             None => return Err(MetaStoreError::ClusterNotFound),
-            Some(ref mut cluster) => {
+            Some(cluster) => {
                 cluster.chunks.append(&mut chunks);
             }
         }
 
-        let cluster = self.get_cluster().expect("auto_add_nodes");
+        let cluster = self
+            .get_cluster_by_name(db_name.as_str())
+            .expect("auto_add_nodes");
         let nodes = cluster.get_nodes();
         let new_nodes = nodes
             .get((nodes.len() - num)..)
@@ -553,14 +693,8 @@ impl MetaStore {
     }
 
     pub fn remove_proxy(&mut self, proxy_address: String) -> Result<(), MetaStoreError> {
-        if let Some(cluster) = self.get_cluster() {
-            if cluster
-                .get_nodes()
-                .iter()
-                .any(|node| node.get_proxy_address() == proxy_address)
-            {
-                return Err(MetaStoreError::InUse);
-            }
+        if self.get_cluster_store_by_proxy(&proxy_address).is_some() {
+            return Err(MetaStoreError::InUse);
         }
 
         self.all_proxies.remove(&proxy_address);
@@ -572,17 +706,16 @@ impl MetaStore {
 
     pub fn migrate_slots(&mut self, db_name: String) -> Result<(), MetaStoreError> {
         let db_name = DBName::from(&db_name).map_err(|_| MetaStoreError::InvalidClusterName)?;
+        // The proxies can't migrate the keys of the clusters sharing nodes.
+        if self.is_sharing_nodes(&db_name) {
+            return Err(MetaStoreError::NotSupported);
+        }
         let new_epoch = self.global_epoch + 1;
 
         {
-            let cluster = match self.cluster.as_mut() {
+            let cluster = match self.clusters.get_mut(&db_name) {
                 None => return Err(MetaStoreError::ClusterNotFound),
-                Some(cluster) => {
-                    if db_name != cluster.name {
-                        return Err(MetaStoreError::ClusterNotFound);
-                    }
-                    cluster
-                }
+                Some(cluster) => cluster,
             };
 
             let running_migration = cluster
@@ -739,8 +872,8 @@ impl MetaStore {
 
     pub fn commit_migration(&mut self, task: MigrationTaskMeta) -> Result<(), MetaStoreError> {
         let cluster = self
-            .cluster
-            .as_mut()
+            .clusters
+            .get_mut(&task.db_name)
             .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
         let task_epoch = match &task.slot_range.tag {
             SlotRangeTag::None => return Err(MetaStoreError::InvalidMigrationTask),
@@ -857,17 +990,12 @@ impl MetaStore {
         let failed_proxies = self.failed_proxies.clone();
         let failures = self.failures.clone();
         let occupied_proxies = self
-            .cluster
-            .as_ref()
-            .map(|cluster| {
-                cluster
-                    .chunks
-                    .iter()
-                    .map(|chunk| chunk.proxy_addresses.to_vec())
-                    .flatten()
-                    .collect::<HashSet<String>>()
-            })
-            .unwrap_or_else(HashSet::new);
+            .clusters
+            .values()
+            .flat_map(|cluster| cluster.chunks.iter())
+            .map(|chunk| chunk.proxy_addresses.to_vec())
+            .flatten()
+            .collect::<HashSet<String>>();
 
         let mut free_proxies = vec![];
         for proxy_resource in self.all_proxies.values() {
@@ -886,8 +1014,8 @@ impl MetaStore {
         free_proxies
     }
 
-    fn build_link_table(&self) -> HashMap<String, HashMap<String, usize>> {
-        let mut link_table: HashMap<String, HashMap<String, usize>> = HashMap::new();
+    fn build_link_table(&self) -> BTreeMap<String, BTreeMap<String, usize>> {
+        let mut link_table: BTreeMap<String, BTreeMap<String, usize>> = BTreeMap::new();
         for proxy_resource in self.all_proxies.values() {
             let first = proxy_resource.proxy_address.clone();
             let first_host = first
@@ -907,18 +1035,18 @@ impl MetaStore {
                 }
                 link_table
                     .entry(first_host.clone())
-                    .or_insert_with(HashMap::new)
+                    .or_insert_with(BTreeMap::new)
                     .entry(second_host.clone())
                     .or_insert(0);
                 link_table
                     .entry(second_host.clone())
-                    .or_insert_with(HashMap::new)
+                    .or_insert_with(BTreeMap::new)
                     .entry(first_host.clone())
                     .or_insert(0);
             }
         }
 
-        if let Some(cluster) = self.cluster.as_ref() {
+        for cluster in self.clusters.values() {
             for chunk in cluster.chunks.iter() {
                 let first = chunk.proxy_addresses[0].clone();
                 let second = chunk.proxy_addresses[1].clone();
@@ -934,13 +1062,13 @@ impl MetaStore {
                     .to_string();
                 let linked_num = link_table
                     .entry(first_host.clone())
-                    .or_insert_with(HashMap::new)
+                    .or_insert_with(BTreeMap::new)
                     .entry(second_host.clone())
                     .or_insert(0);
                 *linked_num += 1;
                 let linked_num = link_table
                     .entry(second_host)
-                    .or_insert_with(HashMap::new)
+                    .or_insert_with(BTreeMap::new)
                     .entry(first_host)
                     .or_insert(0);
                 *linked_num += 1;
@@ -954,7 +1082,7 @@ impl MetaStore {
         proxy_num: NonZeroUsize,
     ) -> Result<Vec<[ProxyResource; CHUNK_HALF_NODE_NUM]>, MetaStoreError> {
         // host => proxies
-        let mut host_proxies: HashMap<String, Vec<ProxySlot>> = HashMap::new();
+        let mut host_proxies: BTreeMap<String, Vec<ProxySlot>> = BTreeMap::new();
         for proxy_address in self.get_free_proxies().into_iter() {
             let host = proxy_address
                 .split(':')
@@ -991,9 +1119,9 @@ impl MetaStore {
     }
 
     fn remove_redundant_chunks(
-        mut host_proxies: HashMap<String, Vec<ProxySlot>>,
+        mut host_proxies: BTreeMap<String, Vec<ProxySlot>>,
         expected_num: NonZeroUsize,
-    ) -> Result<HashMap<String, Vec<ProxySlot>>, MetaStoreError> {
+    ) -> Result<BTreeMap<String, Vec<ProxySlot>>, MetaStoreError> {
         let mut free_proxy_num: usize = host_proxies.values().map(|proxies| proxies.len()).sum();
         let mut max_proxy_num = host_proxies
             .values()
@@ -1020,8 +1148,8 @@ impl MetaStore {
     }
 
     fn allocate_chunk(
-        mut host_proxies: HashMap<String, Vec<ProxySlot>>,
-        mut link_table: HashMap<String, HashMap<String, usize>>,
+        mut host_proxies: BTreeMap<String, Vec<ProxySlot>>,
+        mut link_table: BTreeMap<String, BTreeMap<String, usize>>,
         expected_num: NonZeroUsize,
     ) -> Result<Vec<[String; CHUNK_HALF_NODE_NUM]>, MetaStoreError> {
         let max_proxy_num = host_proxies
@@ -1102,28 +1230,21 @@ impl MetaStore {
             return Err(MetaStoreError::HostNotFound);
         }
 
-        let not_in_use = self
-            .get_cluster()
-            .and_then(|cluster| {
-                cluster
-                    .get_nodes()
-                    .iter()
-                    .find(|node| node.get_proxy_address() == failed_proxy_address)
-                    .map(|_| ())
-            })
-            .is_none();
-        if not_in_use {
-            self.failed_proxies.insert(failed_proxy_address);
-            return Err(MetaStoreError::NotInUse);
-        }
+        let db_name = match self.get_cluster_name_by_proxy(&failed_proxy_address) {
+            Some(db_name) => db_name,
+            None => {
+                self.failed_proxies.insert(failed_proxy_address);
+                return Err(MetaStoreError::NotInUse);
+            }
+        };
 
-        self.takeover_master(failed_proxy_address.clone())?;
+        self.takeover_master(&db_name, failed_proxy_address.clone())?;
 
         let proxy_resource = self.consume_new_proxy(failed_proxy_address.clone())?;
         {
             let cluster = self
-                .cluster
-                .as_mut()
+                .clusters
+                .get_mut(&db_name)
                 .expect("replace_failed_proxy: get cluster");
             for chunk in cluster.chunks.iter_mut() {
                 if chunk.proxy_addresses[0] == failed_proxy_address {
@@ -1147,12 +1268,16 @@ impl MetaStore {
             .expect("replace_failed_proxy"))
     }
 
-    fn takeover_master(&mut self, failed_proxy_address: String) -> Result<(), MetaStoreError> {
+    fn takeover_master(
+        &mut self,
+        db_name: &DBName,
+        failed_proxy_address: String,
+    ) -> Result<(), MetaStoreError> {
         self.bump_global_epoch();
 
         let cluster = self
-            .cluster
-            .as_mut()
+            .clusters
+            .get_mut(db_name)
             .ok_or_else(|| MetaStoreError::ClusterNotFound)?;
         for chunk in cluster.chunks.iter_mut() {
             if chunk.proxy_addresses[0] == failed_proxy_address {
@@ -1231,12 +1356,13 @@ pub enum MetaStoreError {
     HostNotFound,
     InvalidNodeNum,
     InvalidClusterName,
+    InvalidConfig,
     InvalidMigrationTask,
     InvalidProxyAddress,
     MigrationTaskNotFound,
-    OnlySupportOneCluster,
     MigrationRunning,
     NotSupported,
+    PersistenceError,
 }
 
 impl fmt::Display for MetaStoreError {
@@ -1254,6 +1380,7 @@ impl Error for MetaStoreError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use matches::assert_matches;
 
     fn add_testing_proxies(store: &mut MetaStore, host_num: usize, proxy_per_host: usize) {
         for host_index in 1..=host_num {
@@ -1299,6 +1426,42 @@ mod tests {
         store.remove_proxy(proxy_address.to_string()).unwrap();
     }
 
+    #[test]
+    fn test_change_config() {
+        let mut store = MetaStore::default();
+        add_testing_proxies(&mut store, 4, 3);
+        let db_name = "test_db".to_string();
+        store.add_cluster(db_name.clone(), 4).unwrap();
+        let epoch = store.get_global_epoch();
+
+        let mut config = BTreeMap::new();
+        config.insert("password".to_string(), "secret".to_string());
+        config.insert("enable_flushdb".to_string(), "true".to_string());
+        store.change_config(db_name.clone(), config).unwrap();
+        assert!(store.get_global_epoch() > epoch);
+
+        let cluster = store.get_cluster_by_name(&db_name).unwrap();
+        assert_eq!(cluster.get_config().password.as_deref(), Some("secret"));
+        assert!(cluster.get_config().enable_flushdb);
+
+        let proxy_address = cluster.get_nodes()[0].get_proxy_address().to_string();
+        let proxy = store.get_proxy_by_address(&proxy_address).unwrap();
+        let config = proxy.get_clusters_config().get(cluster.get_name()).unwrap();
+        assert_eq!(config.password.as_deref(), Some("secret"));
+
+        // Invalid config should not change anything.
+        let mut config = BTreeMap::new();
+        config.insert("password".to_string(), "another".to_string());
+        config.insert("enable_flushdb".to_string(), "yes".to_string());
+        assert!(store.change_config(db_name.clone(), config).is_err());
+        let cluster = store.get_cluster_by_name(&db_name).unwrap();
+        assert_eq!(cluster.get_config().password.as_deref(), Some("secret"));
+
+        assert!(store
+            .change_config("not_exist".to_string(), BTreeMap::new())
+            .is_err());
+    }
+
     #[test]
     fn test_add_and_remove_cluster() {
         let mut store = MetaStore::default();
@@ -1343,7 +1506,7 @@ mod tests {
             .sum();
         assert_eq!(free_node_num, original_free_node_num - 4);
 
-        let r = store.add_cluster("another_db".to_string(), 4);
+        let r = store.add_cluster(db_name.clone(), 4);
         assert!(r.is_err());
         let epoch3 = store.get_global_epoch();
         assert_eq!(epoch2, epoch3);
@@ -1507,6 +1670,167 @@ mod tests {
         assert!(epoch6 < epoch7);
     }
 
+    #[test]
+    fn test_multiple_clusters() {
+        let mut store = MetaStore::default();
+        const ALL_PROXIES: usize = 4 * 3;
+        add_testing_proxies(&mut store, 4, 3);
+
+        let db_name1 = "test_db1".to_string();
+        let db_name2 = "test_db2".to_string();
+        store.add_cluster(db_name1.clone(), 4).unwrap();
+        store.add_cluster(db_name2.clone(), 8).unwrap();
+        assert_eq!(store.get_free_proxies().len(), ALL_PROXIES - 2 - 4);
+
+        let names: Vec<String> = store
+            .get_cluster_names()
+            .into_iter()
+            .map(|db_name| db_name.to_string())
+            .collect();
+        assert_eq!(names, vec![db_name1.clone(), db_name2.clone()]);
+
+        let cluster1 = store.get_cluster_by_name(&db_name1).unwrap();
+        let cluster2 = store.get_cluster_by_name(&db_name2).unwrap();
+        check_cluster_slots(cluster1.clone(), 4);
+        check_cluster_slots(cluster2.clone(), 8);
+
+        let proxies1: HashSet<String> = cluster1
+            .get_nodes()
+            .iter()
+            .map(|node| node.get_proxy_address().to_string())
+            .collect();
+        let proxies2: HashSet<String> = cluster2
+            .get_nodes()
+            .iter()
+            .map(|node| node.get_proxy_address().to_string())
+            .collect();
+        assert!(proxies1.is_disjoint(&proxies2));
+
+        for proxy_address in proxies2.iter() {
+            let proxy = store.get_proxy_by_address(proxy_address).unwrap();
+            assert_eq!(proxy.get_nodes().len(), 2);
+            for node in proxy.get_nodes() {
+                assert_eq!(node.get_cluster_name().as_str(), db_name2);
+            }
+            for peer in proxy.get_peers() {
+                assert_eq!(peer.cluster_name.as_str(), db_name2);
+                assert!(proxies2.contains(&peer.proxy_address));
+            }
+        }
+
+        let proxy_address = proxies1.iter().next().unwrap().clone();
+        assert!(store.remove_proxy(proxy_address).is_err());
+
+        test_scaling_cluster(&mut store, &db_name1, ALL_PROXIES - 4, 4);
+        let cluster2_after = store.get_cluster_by_name(&db_name2).unwrap();
+        assert_eq!(cluster2.get_nodes(), cluster2_after.get_nodes());
+
+        let failed_proxy_address = cluster2
+            .get_nodes()
+            .get(0)
+            .unwrap()
+            .get_proxy_address()
+            .to_string();
+        let new_proxy = store
+            .replace_failed_proxy(failed_proxy_address.clone())
+            .unwrap();
+        for node in new_proxy.get_nodes() {
+            assert_eq!(node.get_cluster_name().as_str(), db_name2);
+        }
+        let cluster1 = store.get_cluster_by_name(&db_name1).unwrap();
+        check_cluster_slots(cluster1, 8);
+
+        store.remove_cluster(db_name1.clone()).unwrap();
+        assert!(store.get_cluster_by_name(&db_name1).is_none());
+        assert!(store.get_cluster_by_name(&db_name2).is_some());
+        assert_eq!(store.get_free_proxies().len(), ALL_PROXIES - 4 - 1);
+    }
+
+    #[test]
+    fn test_shared_cluster() {
+        let mut store = MetaStore::default();
+        const ALL_PROXIES: usize = 4 * 3;
+        add_testing_proxies(&mut store, 4, 3);
+
+        let host_name = "test_db".to_string();
+        let tenant_name = "tenant1".to_string();
+        store.add_cluster(host_name.clone(), 4).unwrap();
+
+        // The host cluster needs a key prefix.
+        let err = store
+            .add_shared_cluster(tenant_name.clone(), host_name.clone())
+            .unwrap_err();
+        assert_matches!(err, MetaStoreError::InvalidConfig);
+        let mut config = BTreeMap::new();
+        config.insert("key_prefix".to_string(), "tenant1".to_string());
+        store.change_config(host_name.clone(), config).unwrap();
+        let err = store
+            .add_shared_cluster(tenant_name.clone(), host_name.clone())
+            .unwrap_err();
+        assert_matches!(err, MetaStoreError::InvalidConfig);
+        let mut config = BTreeMap::new();
+        config.insert("key_prefix".to_string(), "host:".to_string());
+        store.change_config(host_name.clone(), config).unwrap();
+
+        let err = store
+            .add_shared_cluster(tenant_name.clone(), "not_existed".to_string())
+            .unwrap_err();
+        assert_matches!(err, MetaStoreError::ClusterNotFound);
+
+        let epoch = store.get_global_epoch();
+        store
+            .add_shared_cluster(tenant_name.clone(), host_name.clone())
+            .unwrap();
+        assert!(store.get_global_epoch() > epoch);
+        assert_eq!(store.get_free_proxies().len(), ALL_PROXIES - 2);
+        let err = store
+            .add_shared_cluster("tenant2".to_string(), tenant_name.clone())
+            .unwrap_err();
+        assert_matches!(err, MetaStoreError::NotSupported);
+
+        let host = store.get_cluster_by_name(&host_name).unwrap();
+        let tenant = store.get_cluster_by_name(&tenant_name).unwrap();
+        check_cluster_slots(tenant.clone(), 4);
+        assert_eq!(tenant.get_config().key_prefix.as_deref(), Some("tenant1:"));
+        for (host_node, tenant_node) in host.get_nodes().iter().zip(tenant.get_nodes()) {
+            assert_eq!(host_node.get_address(), tenant_node.get_address());
+            assert_eq!(host_node.get_slots(), tenant_node.get_slots());
+            assert_eq!(tenant_node.get_cluster_name().as_str(), tenant_name);
+        }
+
+        let proxy_address = host.get_nodes()[0].get_proxy_address().to_string();
+        let proxy = store.get_proxy_by_address(&proxy_address).unwrap();
+        assert_eq!(proxy.get_nodes().len(), 4);
+        assert_eq!(proxy.get_peers().len(), 2);
+        assert!(proxy.get_free_nodes().is_empty());
+        assert_eq!(proxy.get_clusters_config().len(), 2);
+
+        let mut config = BTreeMap::new();
+        config.insert("key_prefix".to_string(), "".to_string());
+        let err = store.change_config(host_name.clone(), config).unwrap_err();
+        assert_matches!(err, MetaStoreError::InvalidConfig);
+        let err = store.migrate_slots(host_name.clone()).unwrap_err();
+        assert_matches!(err, MetaStoreError::NotSupported);
+        let err = store
+            .auto_add_nodes(tenant_name.clone(), Some(4))
+            .unwrap_err();
+        assert_matches!(err, MetaStoreError::NotSupported);
+        let err = store.remove_cluster(host_name.clone()).unwrap_err();
+        assert_matches!(err, MetaStoreError::InUse);
+
+        let new_proxy = store.replace_failed_proxy(proxy_address.clone()).unwrap();
+        assert_eq!(new_proxy.get_nodes().len(), 4);
+        let tenant = store.get_cluster_by_name(&tenant_name).unwrap();
+        assert!(tenant
+            .get_nodes()
+            .iter()
+            .all(|node| node.get_proxy_address() != proxy_address));
+
+        store.remove_cluster(tenant_name.clone()).unwrap();
+        store.remove_cluster(host_name).unwrap();
+        assert_eq!(store.get_free_proxies().len(), ALL_PROXIES - 1);
+    }
+
     const DB_NAME: &'static str = "test_db";
 
     fn test_migration_helper(
@@ -1542,7 +1866,16 @@ mod tests {
     }
 
     fn test_scaling(store: &mut MetaStore, all_proxy_num: usize, added_node_num: usize) {
-        let db_name = DB_NAME.to_string();
+        test_scaling_cluster(store, DB_NAME, all_proxy_num, added_node_num)
+    }
+
+    fn test_scaling_cluster(
+        store: &mut MetaStore,
+        db_name: &str,
+        free_proxy_num: usize,
+        added_node_num: usize,
+    ) {
+        let db_name = db_name.to_string();
         let start_node_num = store
             .get_cluster_by_name(&db_name)
             .unwrap()
@@ -1560,7 +1893,7 @@ mod tests {
         assert_eq!(cluster.get_nodes().len(), start_node_num + added_node_num);
         assert_eq!(
             store.get_free_proxies().len(),
-            all_proxy_num - start_node_num / 2 - added_node_num / 2
+            free_proxy_num - start_node_num / 2 - added_node_num / 2
         );
 
         store.migrate_slots(db_name.clone()).unwrap();
@@ -647,6 +647,9 @@ impl Cluster {
     pub fn get_epoch(&self) -> u64 {
         self.epoch
     }
+    pub fn get_config(&self) -> &ClusterConfig {
+        &self.config
+    }
     pub fn into_nodes(self) -> Vec<Node> {
         self.nodes
     }
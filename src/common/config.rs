@@ -1,6 +1,6 @@
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU64, Ordering};
 
@@ -10,6 +10,34 @@ pub struct ClusterConfig {
     pub compression_strategy: CompressionStrategy,
     #[serde(default)]
     pub migration_config: MigrationConfig,
+    // FLUSHDB will remove the data of all the local nodes of the cluster.
+    #[serde(default)]
+    pub enable_flushdb: bool,
+    // Route the read-only commands to replicas without READONLY.
+    #[serde(default)]
+    pub default_readonly: bool,
+    #[serde(default)]
+    pub replica_read_policy: ReplicaReadPolicy,
+    // Clients need to AUTH once either of the passwords is set.
+    #[serde(default)]
+    pub password: Option<String>,
+    // Only allows the read-only commands.
+    #[serde(default)]
+    pub readonly_password: Option<String>,
+    // Disabled besides the default disabled commands of the server proxy.
+    #[serde(default)]
+    pub disabled_commands: BTreeSet<String>,
+    // original name => new name. The original name will be disabled.
+    #[serde(default)]
+    pub renamed_commands: BTreeMap<String, String>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    // The max number of clients of this cluster on each server proxy. 0 means unlimited.
+    #[serde(default)]
+    pub max_clients: u64,
+    // Prepended to all the keys in the backend so that the clusters could share the same nodes.
+    #[serde(default)]
+    pub key_prefix: Option<String>,
 }
 
 impl Default for ClusterConfig {
@@ -17,6 +45,16 @@ impl Default for ClusterConfig {
         Self {
             compression_strategy: CompressionStrategy::default(),
             migration_config: MigrationConfig::default(),
+            enable_flushdb: false,
+            default_readonly: false,
+            replica_read_policy: ReplicaReadPolicy::default(),
+            password: None,
+            readonly_password: None,
+            disabled_commands: BTreeSet::new(),
+            renamed_commands: BTreeMap::new(),
+            rate_limit: RateLimitConfig::default(),
+            max_clients: 0,
+            key_prefix: None,
         }
     }
 }
@@ -30,6 +68,58 @@ impl ClusterConfig {
                     CompressionStrategy::from_str(&value).map_err(|_| ConfigError::InvalidValue)?;
                 self.compression_strategy = strategy;
             }
+            "enable_flushdb" => {
+                let enabled = value
+                    .parse::<bool>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.enable_flushdb = enabled;
+            }
+            "default_readonly" => {
+                let readonly = value
+                    .parse::<bool>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.default_readonly = readonly;
+            }
+            "replica_read_policy" => {
+                let policy =
+                    ReplicaReadPolicy::from_str(value).map_err(|_| ConfigError::InvalidValue)?;
+                self.replica_read_policy = policy;
+            }
+            "max_clients" => {
+                let max_clients = value
+                    .parse::<u64>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.max_clients = max_clients;
+            }
+            // An empty value removes the prefix.
+            "key_prefix" => {
+                self.key_prefix = Some(value.to_string()).filter(|prefix| !prefix.is_empty())
+            }
+            // An empty value removes the password.
+            "password" => self.password = Self::parse_password(value),
+            "readonly_password" => self.readonly_password = Self::parse_password(value),
+            // In the format of `cmd1,cmd2`.
+            "disabled_commands" => {
+                self.disabled_commands = value
+                    .split(',')
+                    .map(|cmd| cmd.trim().to_uppercase())
+                    .filter(|cmd| !cmd.is_empty())
+                    .collect();
+            }
+            // In the format of `original1:new1,original2:new2`.
+            "renamed_commands" => {
+                let mut renamed_commands = BTreeMap::new();
+                for pair in value.split(',').filter(|pair| !pair.trim().is_empty()) {
+                    let mut it = pair.splitn(2, ':').map(|cmd| cmd.trim().to_uppercase());
+                    match (it.next(), it.next()) {
+                        (Some(original), Some(new)) if !original.is_empty() && !new.is_empty() => {
+                            renamed_commands.insert(original, new);
+                        }
+                        _ => return Err(ConfigError::InvalidValue),
+                    }
+                }
+                self.renamed_commands = renamed_commands;
+            }
             _ => {
                 if field.starts_with("migration_") {
                     let f = field
@@ -37,6 +127,12 @@ impl ClusterConfig {
                         .nth(1)
                         .ok_or_else(|| ConfigError::FieldNotFound)?;
                     return self.migration_config.set_field(f, value);
+                } else if field.starts_with("rate_limit_") {
+                    let f = field
+                        .splitn(3, '_')
+                        .nth(2)
+                        .ok_or_else(|| ConfigError::FieldNotFound)?;
+                    return self.rate_limit.set_field(f, value);
                 } else {
                     return Err(ConfigError::FieldNotFound);
                 }
@@ -45,12 +141,43 @@ impl ClusterConfig {
         Ok(())
     }
 
+    fn parse_password(value: &str) -> Option<String> {
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_string())
+        }
+    }
+
+    pub fn requires_auth(&self) -> bool {
+        self.password.is_some() || self.readonly_password.is_some()
+    }
+
+    // Returns None if the password is wrong,
+    // otherwise returns whether it only has the read-only access.
+    pub fn check_password(&self, password: &[u8]) -> Option<bool> {
+        if self.password.as_deref().map(str::as_bytes) == Some(password) {
+            Some(false)
+        } else if self.readonly_password.as_deref().map(str::as_bytes) == Some(password) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
     pub fn to_str_map(&self) -> HashMap<String, String> {
-        vec![
+        let mut map: HashMap<String, String> = vec![
             (
                 "compression_strategy",
                 self.compression_strategy.to_str().to_string(),
             ),
+            ("enable_flushdb", self.enable_flushdb.to_string()),
+            ("default_readonly", self.default_readonly.to_string()),
+            (
+                "replica_read_policy",
+                self.replica_read_policy.to_str().to_string(),
+            ),
+            ("max_clients", self.max_clients.to_string()),
             (
                 "migration_max_migration_time",
                 self.migration_config.max_migration_time.to_string(),
@@ -75,10 +202,45 @@ impl ClusterConfig {
                 "migration_scan_count",
                 self.migration_config.scan_count.to_string(),
             ),
+            (
+                "rate_limit_max_ops_per_sec",
+                self.rate_limit.max_ops_per_sec.to_string(),
+            ),
+            (
+                "rate_limit_max_bytes_per_sec",
+                self.rate_limit.max_bytes_per_sec.to_string(),
+            ),
+            (
+                "rate_limit_max_delay",
+                self.rate_limit.max_delay.to_string(),
+            ),
         ]
         .into_iter()
         .map(|(k, v)| (k.to_string(), v))
-        .collect()
+        .collect();
+        // The unset fields are omitted so that no empty argument is sent.
+        if let Some(password) = self.password.as_ref() {
+            map.insert("password".to_string(), password.clone());
+        }
+        if let Some(password) = self.readonly_password.as_ref() {
+            map.insert("readonly_password".to_string(), password.clone());
+        }
+        if let Some(key_prefix) = self.key_prefix.as_ref() {
+            map.insert("key_prefix".to_string(), key_prefix.clone());
+        }
+        if !self.disabled_commands.is_empty() {
+            let cmds: Vec<&str> = self.disabled_commands.iter().map(String::as_str).collect();
+            map.insert("disabled_commands".to_string(), cmds.join(","));
+        }
+        if !self.renamed_commands.is_empty() {
+            let pairs: Vec<String> = self
+                .renamed_commands
+                .iter()
+                .map(|(original, new)| format!("{}:{}", original, new))
+                .collect();
+            map.insert("renamed_commands".to_string(), pairs.join(","));
+        }
+        map
     }
 }
 
@@ -145,6 +307,65 @@ impl<'de> Deserialize<'de> for CompressionStrategy {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReplicaReadPolicy {
+    Random,
+    RoundRobin,
+    LowestLatency,
+}
+
+impl Default for ReplicaReadPolicy {
+    fn default() -> Self {
+        ReplicaReadPolicy::Random
+    }
+}
+
+pub struct InvalidReplicaReadPolicyStr;
+
+impl FromStr for ReplicaReadPolicy {
+    type Err = InvalidReplicaReadPolicyStr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_lowercase();
+        match lowercase.as_str() {
+            "random" => Ok(Self::Random),
+            "round_robin" => Ok(Self::RoundRobin),
+            "lowest_latency" => Ok(Self::LowestLatency),
+            _ => Err(InvalidReplicaReadPolicyStr),
+        }
+    }
+}
+
+impl ReplicaReadPolicy {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Random => "random",
+            Self::RoundRobin => "round_robin",
+            Self::LowestLatency => "lowest_latency",
+        }
+    }
+}
+
+impl Serialize for ReplicaReadPolicy {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.to_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ReplicaReadPolicy {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s)
+            .map_err(|_| D::Error::custom(format!("invalid replica read policy {}", s)))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct MigrationConfig {
     pub max_migration_time: u64,
@@ -214,6 +435,37 @@ impl Default for MigrationConfig {
     }
 }
 
+// Token bucket rate limiting for each cluster. 0 means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub max_ops_per_sec: u64,
+    #[serde(default)]
+    pub max_bytes_per_sec: u64,
+    // In milliseconds. The requests which need to wait longer will be rejected.
+    #[serde(default)]
+    pub max_delay: u64,
+}
+
+impl RateLimitConfig {
+    fn set_field(&mut self, field: &str, value: &str) -> Result<(), ConfigError> {
+        let v = value
+            .parse::<u64>()
+            .map_err(|_| ConfigError::InvalidValue)?;
+        match field.to_lowercase().as_str() {
+            "max_ops_per_sec" => self.max_ops_per_sec = v,
+            "max_bytes_per_sec" => self.max_bytes_per_sec = v,
+            "max_delay" => self.max_delay = v,
+            _ => return Err(ConfigError::FieldNotFound),
+        }
+        Ok(())
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_ops_per_sec == 0 && self.max_bytes_per_sec == 0
+    }
+}
+
 pub struct AtomicMigrationConfig {
     max_migration_time: AtomicU64,
     max_blocking_time: AtomicU64,
@@ -290,5 +542,148 @@ mod tests {
             .set_field("migration_delete_count", "666")
             .expect("test_config_set_field");
         assert_eq!(cluster_config.migration_config.delete_count, 666);
+
+        assert!(!cluster_config.enable_flushdb);
+        cluster_config
+            .set_field("enable_flushdb", "true")
+            .expect("test_config_set_field");
+        assert!(cluster_config.enable_flushdb);
+        assert!(cluster_config.set_field("enable_flushdb", "yes").is_err());
+
+        cluster_config
+            .set_field("max_clients", "100")
+            .expect("test_config_set_field");
+        assert_eq!(cluster_config.max_clients, 100);
+        assert!(cluster_config.set_field("max_clients", "-1").is_err());
+
+        cluster_config
+            .set_field("replica_read_policy", "lowest_latency")
+            .expect("test_config_set_field");
+        assert_eq!(
+            cluster_config.replica_read_policy,
+            ReplicaReadPolicy::LowestLatency
+        );
+        assert!(cluster_config
+            .set_field("replica_read_policy", "nearest")
+            .is_err());
+    }
+
+    #[test]
+    fn test_cluster_password() {
+        let mut cluster_config = ClusterConfig::default();
+        assert!(!cluster_config.requires_auth());
+        assert!(!cluster_config.to_str_map().contains_key("password"));
+
+        cluster_config
+            .set_field("password", "secret")
+            .expect("test_cluster_password");
+        cluster_config
+            .set_field("readonly_password", "readonly_secret")
+            .expect("test_cluster_password");
+        assert!(cluster_config.requires_auth());
+        assert_eq!(cluster_config.check_password(b"secret"), Some(false));
+        assert_eq!(
+            cluster_config.check_password(b"readonly_secret"),
+            Some(true)
+        );
+        assert_eq!(cluster_config.check_password(b"wrong"), None);
+        let map = cluster_config.to_str_map();
+        assert_eq!(map.get("password").map(String::as_str), Some("secret"));
+
+        cluster_config
+            .set_field("password", "")
+            .expect("test_cluster_password");
+        assert_eq!(cluster_config.check_password(b""), None);
+        assert!(cluster_config.requires_auth());
+    }
+
+    #[test]
+    fn test_key_prefix_config() {
+        let mut cluster_config = ClusterConfig::default();
+        assert!(!cluster_config.to_str_map().contains_key("key_prefix"));
+
+        cluster_config
+            .set_field("key_prefix", "tenant1:")
+            .expect("test_key_prefix_config");
+        assert_eq!(cluster_config.key_prefix.as_deref(), Some("tenant1:"));
+        let map = cluster_config.to_str_map();
+        assert_eq!(map.get("key_prefix").map(String::as_str), Some("tenant1:"));
+
+        cluster_config
+            .set_field("key_prefix", "")
+            .expect("test_key_prefix_config");
+        assert!(cluster_config.key_prefix.is_none());
+    }
+
+    #[test]
+    fn test_cluster_commands() {
+        let mut cluster_config = ClusterConfig::default();
+        cluster_config
+            .set_field("disabled_commands", "keys, flushdb,")
+            .expect("test_cluster_commands");
+        cluster_config
+            .set_field("renamed_commands", "flushall:my_flushall,config:my_config")
+            .expect("test_cluster_commands");
+        assert_eq!(
+            cluster_config.disabled_commands.iter().collect::<Vec<_>>(),
+            vec!["FLUSHDB", "KEYS"]
+        );
+        assert_eq!(
+            cluster_config
+                .renamed_commands
+                .get("FLUSHALL")
+                .map(String::as_str),
+            Some("MY_FLUSHALL")
+        );
+
+        let map = cluster_config.to_str_map();
+        let mut another_config = ClusterConfig::default();
+        for (field, value) in map.iter() {
+            another_config
+                .set_field(field, value)
+                .expect("test_cluster_commands");
+        }
+        assert_eq!(another_config, cluster_config);
+
+        assert!(cluster_config
+            .set_field("renamed_commands", "flushall")
+            .is_err());
+        assert!(cluster_config
+            .set_field("renamed_commands", "flushall:")
+            .is_err());
+        cluster_config
+            .set_field("disabled_commands", "")
+            .expect("test_cluster_commands");
+        assert!(cluster_config.disabled_commands.is_empty());
+    }
+
+    #[test]
+    fn test_rate_limit_config() {
+        let mut cluster_config = ClusterConfig::default();
+        assert!(cluster_config.rate_limit.is_unlimited());
+        cluster_config
+            .set_field("rate_limit_max_ops_per_sec", "1000")
+            .expect("test_rate_limit_config");
+        cluster_config
+            .set_field("RATE_LIMIT_MAX_DELAY", "50")
+            .expect("test_rate_limit_config");
+        assert_eq!(cluster_config.rate_limit.max_ops_per_sec, 1000);
+        assert_eq!(cluster_config.rate_limit.max_bytes_per_sec, 0);
+        assert_eq!(cluster_config.rate_limit.max_delay, 50);
+        assert!(!cluster_config.rate_limit.is_unlimited());
+
+        assert!(cluster_config
+            .set_field("rate_limit_max_bytes_per_sec", "-1")
+            .is_err());
+        assert!(cluster_config.set_field("rate_limit_max_qps", "1").is_err());
+
+        let map = cluster_config.to_str_map();
+        let mut another_config = ClusterConfig::default();
+        for (field, value) in map.iter() {
+            another_config
+                .set_field(field, value)
+                .expect("test_rate_limit_config");
+        }
+        assert_eq!(another_config, cluster_config);
     }
 }
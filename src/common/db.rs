@@ -1,4 +1,4 @@
-use super::cluster::SlotRange;
+use super::cluster::{SlotRange, SlotRangeTag};
 use super::utils::{has_flags, CmdParseError};
 use crate::common::cluster::DBName;
 use crate::common::config::ClusterConfig;
@@ -93,6 +93,33 @@ impl ProxyDBMeta {
         &self.clusters_config
     }
 
+    // The slot migration and the deletion of the migrated keys scan all the keys of the backend,
+    // so they are not allowed for the clusters with key prefix or sharing the same nodes.
+    // Returns the first cluster violating it.
+    pub fn find_shared_node_migration(&self) -> Option<DBName> {
+        let mut node_db_num: HashMap<&str, usize> = HashMap::new();
+        for nodes in self.local.get_map().values() {
+            for address in nodes.keys() {
+                *node_db_num.entry(address.as_str()).or_insert(0) += 1;
+            }
+        }
+        self.local.get_map().iter().find_map(|(db_name, nodes)| {
+            let has_key_prefix = self.clusters_config.get(db_name).key_prefix.is_some();
+            let violated = nodes.iter().any(|(address, slot_ranges)| {
+                let shared = node_db_num.get(address.as_str()).cloned().unwrap_or(0) > 1;
+                (has_key_prefix || shared)
+                    && slot_ranges
+                        .iter()
+                        .any(|slot_range| slot_range.tag != SlotRangeTag::None)
+            });
+            if violated {
+                Some(db_name.clone())
+            } else {
+                None
+            }
+        })
+    }
+
     pub fn from_resp<T: AsRef<[u8]>>(
         resp: &Resp<T>,
     ) -> Result<(Self, Result<(), ParseExtendedMetaError>), CmdParseError> {
@@ -596,6 +623,27 @@ mod tests {
             "mydb",
             "migration_scan_count",
             "16",
+            "mydb",
+            "rate_limit_max_ops_per_sec",
+            "0",
+            "mydb",
+            "rate_limit_max_bytes_per_sec",
+            "0",
+            "mydb",
+            "rate_limit_max_delay",
+            "0",
+            "mydb",
+            "max_clients",
+            "0",
+            "mydb",
+            "enable_flushdb",
+            "false",
+            "mydb",
+            "default_readonly",
+            "false",
+            "mydb",
+            "replica_read_policy",
+            "random",
             "otherdb",
             "compression_strategy",
             "disabled",
@@ -617,12 +665,74 @@ mod tests {
             "otherdb",
             "migration_scan_count",
             "16",
+            "otherdb",
+            "rate_limit_max_ops_per_sec",
+            "0",
+            "otherdb",
+            "rate_limit_max_bytes_per_sec",
+            "0",
+            "otherdb",
+            "rate_limit_max_delay",
+            "0",
+            "otherdb",
+            "max_clients",
+            "0",
+            "otherdb",
+            "enable_flushdb",
+            "false",
+            "otherdb",
+            "default_readonly",
+            "false",
+            "otherdb",
+            "replica_read_policy",
+            "random",
         ];
         result_args.sort();
         full_args.sort();
         assert_eq!(result_args, full_args);
     }
 
+    fn gen_migration_meta(extra_args: &[&str]) -> ProxyDBMeta {
+        let mut arguments = vec![
+            "233",
+            "FORCE",
+            "dbname",
+            "127.0.0.1:7000",
+            "1",
+            "0-1000",
+            "dbname",
+            "127.0.0.1:7001",
+            "MIGRATING",
+            "1",
+            "1001-2000",
+            "233",
+            "127.0.0.1:6001",
+            "127.0.0.1:7001",
+            "127.0.0.2:6001",
+            "127.0.0.2:7001",
+        ];
+        arguments.extend_from_slice(extra_args);
+        let mut it = arguments.into_iter().map(|s| s.to_string()).peekable();
+        let (db_meta, _) = ProxyDBMeta::parse(&mut it).expect("gen_migration_meta");
+        db_meta
+    }
+
+    #[test]
+    fn test_find_shared_node_migration() {
+        let db_name = DBName::from("dbname").unwrap();
+        assert!(gen_migration_meta(&[])
+            .find_shared_node_migration()
+            .is_none());
+        // Another cluster without migration on a different node.
+        let meta = gen_migration_meta(&["other", "127.0.0.1:7002", "1", "0-16383"]);
+        assert!(meta.find_shared_node_migration().is_none());
+
+        let meta = gen_migration_meta(&["CONFIG", "dbname", "key_prefix", "t1:"]);
+        assert_eq!(meta.find_shared_node_migration(), Some(db_name.clone()));
+        let meta = gen_migration_meta(&["other", "127.0.0.1:7001", "1", "0-16383"]);
+        assert_eq!(meta.find_shared_node_migration(), Some(db_name));
+    }
+
     #[test]
     fn test_to_map() {
         let arguments = vec![
@@ -791,6 +901,27 @@ mod tests {
             "dbname",
             "migration_scan_count",
             "16",
+            "dbname",
+            "rate_limit_max_ops_per_sec",
+            "0",
+            "dbname",
+            "rate_limit_max_bytes_per_sec",
+            "0",
+            "dbname",
+            "rate_limit_max_delay",
+            "0",
+            "dbname",
+            "max_clients",
+            "0",
+            "dbname",
+            "enable_flushdb",
+            "false",
+            "dbname",
+            "default_readonly",
+            "false",
+            "dbname",
+            "replica_read_policy",
+            "random",
         ]
         .into_iter()
         .map(|s| s.to_string());
@@ -4,6 +4,7 @@ use crc16::{State, XMODEM};
 use futures::{stream, Stream};
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub trait ThreadSafe: Send + Sync + 'static {}
 
@@ -123,6 +124,15 @@ pub fn get_slot(key: &[u8]) -> usize {
     State::<XMODEM>::calculate(get_hash_tag(key)) as usize % SLOT_NUM
 }
 
+// Good enough for picking one of the nodes without introducing a random generator.
+pub fn random_index(len: usize) -> usize {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as usize)
+        .unwrap_or(0);
+    nanos % len
+}
+
 pub fn pretty_print_bytes(data: &[u8]) -> String {
     match str::from_utf8(data) {
         Ok(s) => s.to_string(),
@@ -1,4 +1,5 @@
 use super::broker::{MetaManipulationBroker, MetaManipulationBrokerError};
+use super::http_meta_broker::BrokerAddresses;
 use crate::common::cluster::{MigrationTaskMeta, Proxy};
 use futures::Future;
 use reqwest;
@@ -6,14 +7,14 @@ use std::pin::Pin;
 
 #[derive(Clone)]
 pub struct HttpMetaManipulationBroker {
-    broker_address: String,
+    broker_addresses: BrokerAddresses,
     client: reqwest::Client,
 }
 
 impl HttpMetaManipulationBroker {
-    pub fn new(broker_address: String, client: reqwest::Client) -> Self {
+    pub fn new(broker_addresses: BrokerAddresses, client: reqwest::Client) -> Self {
         HttpMetaManipulationBroker {
-            broker_address,
+            broker_addresses,
             client,
         }
     }
@@ -24,14 +25,20 @@ impl HttpMetaManipulationBroker {
         &self,
         failed_proxy_address: String,
     ) -> Result<Proxy, MetaManipulationBrokerError> {
-        let url = format!(
-            "http://{}/api/proxies/failover/{}",
-            self.broker_address, failed_proxy_address
-        );
-        let response = self.client.post(&url).send().await.map_err(|e| {
-            error!("Failed to replace proxy {:?}", e);
-            MetaManipulationBrokerError::InvalidReply
-        })?;
+        let response = self
+            .broker_addresses
+            .send_write(|address| {
+                let url = format!(
+                    "http://{}/api/proxies/failover/{}",
+                    address, failed_proxy_address
+                );
+                self.client.post(&url)
+            })
+            .await
+            .map_err(|e| {
+                error!("Failed to replace proxy {:?}", e);
+                MetaManipulationBrokerError::InvalidReply
+            })?;
 
         let status = response.status();
 
@@ -63,13 +70,12 @@ impl HttpMetaManipulationBroker {
         &self,
         meta: MigrationTaskMeta,
     ) -> Result<(), MetaManipulationBrokerError> {
-        let url = format!("http://{}/api/clusters/migrations", self.broker_address);
-
         let response = self
-            .client
-            .put(&url)
-            .json(&meta)
-            .send()
+            .broker_addresses
+            .send_write(|address| {
+                let url = format!("http://{}/api/clusters/migrations", address);
+                self.client.put(&url).json(&meta)
+            })
             .await
             .map_err(|e| {
                 error!("Failed to commit migration {:?}", e);
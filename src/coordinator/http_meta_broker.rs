@@ -4,18 +4,117 @@ use crate::common::utils::vec_result_to_stream;
 use futures::{Future, FutureExt, Stream};
 use reqwest;
 use serde_derive::Deserialize;
+use std::error::Error;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Broker addresses of the same replication group.
+// Requests will be sent to the current address,
+// and will fail over to the next one on connection errors or server errors.
+// The write requests are not idempotent,
+// so they only fail over when they could not have been handled by the broker.
+#[derive(Clone)]
+pub struct BrokerAddresses {
+    addresses: Arc<Vec<String>>,
+    curr: Arc<AtomicUsize>,
+}
+
+impl BrokerAddresses {
+    pub fn new(addresses: Vec<String>) -> Self {
+        assert!(!addresses.is_empty(), "empty broker addresses");
+        Self {
+            addresses: Arc::new(addresses),
+            curr: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn get(&self) -> (usize, &str) {
+        let index = self.curr.load(Ordering::SeqCst) % self.addresses.len();
+        (index, &self.addresses[index])
+    }
+
+    fn fail_over(&self, failed_index: usize) {
+        let next = (failed_index + 1) % self.addresses.len();
+        // Other requests might have already moved to the next address.
+        let _ = self
+            .curr
+            .compare_exchange(failed_index, next, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    pub async fn send<F>(&self, build_request: F) -> Result<reqwest::Response, reqwest::Error>
+    where
+        F: Fn(&str) -> reqwest::RequestBuilder,
+    {
+        self.send_with(build_request, |res| match res {
+            Ok(response) => response.status().is_server_error(),
+            Err(_) => true,
+        })
+        .await
+    }
+
+    // Only fail over when the broker could not be connected
+    // or it's a follower without leader.
+    pub async fn send_write<F>(&self, build_request: F) -> Result<reqwest::Response, reqwest::Error>
+    where
+        F: Fn(&str) -> reqwest::RequestBuilder,
+    {
+        self.send_with(build_request, |res| match res {
+            Ok(response) => response.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE,
+            Err(err) => is_connect_error(err),
+        })
+        .await
+    }
+
+    async fn send_with<F, P>(
+        &self,
+        build_request: F,
+        should_fail_over: P,
+    ) -> Result<reqwest::Response, reqwest::Error>
+    where
+        F: Fn(&str) -> reqwest::RequestBuilder,
+        P: Fn(&Result<reqwest::Response, reqwest::Error>) -> bool,
+    {
+        let mut last_result = None;
+        for _ in 0..self.addresses.len() {
+            let (index, address) = self.get();
+            let res = build_request(address).send().await;
+            if !should_fail_over(&res) {
+                return res;
+            }
+            match res.as_ref() {
+                Ok(response) => warn!(
+                    "broker {} replied {:?}, try next broker",
+                    address,
+                    response.status()
+                ),
+                Err(err) => warn!("failed to connect to broker {}: {:?}", address, err),
+            }
+            last_result = Some(res);
+            self.fail_over(index);
+        }
+        last_result.expect("BrokerAddresses::send: empty addresses")
+    }
+}
+
+// reqwest 0.10 does not tell whether the request failed before being sent.
+fn is_connect_error(err: &reqwest::Error) -> bool {
+    err.source()
+        .and_then(|source| source.downcast_ref::<hyper::Error>())
+        .map(hyper::Error::is_connect)
+        .unwrap_or(false)
+}
 
 #[derive(Clone)]
 pub struct HttpMetaBroker {
-    broker_address: String,
+    broker_addresses: BrokerAddresses,
     client: reqwest::Client,
 }
 
 impl HttpMetaBroker {
-    pub fn new(broker_address: String, client: reqwest::Client) -> Self {
+    pub fn new(broker_addresses: BrokerAddresses, client: reqwest::Client) -> Self {
         HttpMetaBroker {
-            broker_address,
+            broker_addresses,
             client,
         }
     }
@@ -23,11 +122,17 @@ impl HttpMetaBroker {
 
 impl HttpMetaBroker {
     async fn get_cluster_names_impl(&self) -> Result<Vec<DBName>, MetaDataBrokerError> {
-        let url = format!("http://{}/api/clusters/names", self.broker_address);
-        let response = self.client.get(&url).send().await.map_err(|e| {
-            error!("failed to get cluster names {:?}", e);
-            MetaDataBrokerError::InvalidReply
-        })?;
+        let response = self
+            .broker_addresses
+            .send(|address| {
+                let url = format!("http://{}/api/clusters/names", address);
+                self.client.get(&url)
+            })
+            .await
+            .map_err(|e| {
+                error!("failed to get cluster names {:?}", e);
+                MetaDataBrokerError::InvalidReply
+            })?;
         let ClusterNamesPayload { names } = response.json().await.map_err(|e| {
             error!("failed to get cluster names from json {:?}", e);
             MetaDataBrokerError::InvalidReply
@@ -36,11 +141,17 @@ impl HttpMetaBroker {
     }
 
     async fn get_cluster_impl(&self, name: DBName) -> Result<Option<Cluster>, MetaDataBrokerError> {
-        let url = format!("http://{}/api/clusters/meta/{}", self.broker_address, name);
-        let response = self.client.get(&url).send().await.map_err(|e| {
-            error!("failed to get cluster {:?}", e);
-            MetaDataBrokerError::InvalidReply
-        })?;
+        let response = self
+            .broker_addresses
+            .send(|address| {
+                let url = format!("http://{}/api/clusters/meta/{}", address, name);
+                self.client.get(&url)
+            })
+            .await
+            .map_err(|e| {
+                error!("failed to get cluster {:?}", e);
+                MetaDataBrokerError::InvalidReply
+            })?;
         let ClusterPayload { cluster } = response.json().await.map_err(|e| {
             error!("failed to get cluster from json {:?}", e);
             MetaDataBrokerError::InvalidReply
@@ -49,11 +160,17 @@ impl HttpMetaBroker {
     }
 
     async fn get_host_addresses_impl(&self) -> Result<Vec<String>, MetaDataBrokerError> {
-        let url = format!("http://{}/api/proxies/addresses", self.broker_address);
-        let response = self.client.get(&url).send().await.map_err(|e| {
-            error!("failed to get host addresses {:?}", e);
-            MetaDataBrokerError::InvalidReply
-        })?;
+        let response = self
+            .broker_addresses
+            .send(|address| {
+                let url = format!("http://{}/api/proxies/addresses", address);
+                self.client.get(&url)
+            })
+            .await
+            .map_err(|e| {
+                error!("failed to get host addresses {:?}", e);
+                MetaDataBrokerError::InvalidReply
+            })?;
         let ProxyAddressesPayload { addresses } = response.json().await.map_err(|e| {
             error!("failed to get host adddresses from json {:?}", e);
             MetaDataBrokerError::InvalidReply
@@ -62,14 +179,17 @@ impl HttpMetaBroker {
     }
 
     async fn get_host_impl(&self, address: String) -> Result<Option<Proxy>, MetaDataBrokerError> {
-        let url = format!(
-            "http://{}/api/proxies/meta/{}",
-            self.broker_address, address
-        );
-        let response = self.client.get(&url).send().await.map_err(|e| {
-            error!("failed to get host {:?}", e);
-            MetaDataBrokerError::InvalidReply
-        })?;
+        let response = self
+            .broker_addresses
+            .send(|broker_address| {
+                let url = format!("http://{}/api/proxies/meta/{}", broker_address, address);
+                self.client.get(&url)
+            })
+            .await
+            .map_err(|e| {
+                error!("failed to get host {:?}", e);
+                MetaDataBrokerError::InvalidReply
+            })?;
         let ProxyPayload { host } = response.json().await.map_err(move |e| {
             error!("failed to get host {} from json {:?}", address, e);
             MetaDataBrokerError::InvalidReply
@@ -82,14 +202,20 @@ impl HttpMetaBroker {
         address: String,
         reporter_id: String,
     ) -> Result<(), MetaDataBrokerError> {
-        let url = format!(
-            "http://{}/api/failures/{}/{}",
-            self.broker_address, address, reporter_id
-        );
-        let response = self.client.post(&url).send().await.map_err(|e| {
-            error!("failed to add failures {:?}", e);
-            MetaDataBrokerError::InvalidReply
-        })?;
+        let response = self
+            .broker_addresses
+            .send_write(|broker_address| {
+                let url = format!(
+                    "http://{}/api/failures/{}/{}",
+                    broker_address, address, reporter_id
+                );
+                self.client.post(&url)
+            })
+            .await
+            .map_err(|e| {
+                error!("failed to add failures {:?}", e);
+                MetaDataBrokerError::InvalidReply
+            })?;
         let status = response.status();
         if status.is_success() {
             Ok(())
@@ -109,11 +235,17 @@ impl HttpMetaBroker {
     }
 
     async fn get_failures_impl(&self) -> Result<Vec<String>, MetaDataBrokerError> {
-        let url = format!("http://{}/api/failures", self.broker_address);
-        let response = self.client.get(&url).send().await.map_err(|e| {
-            error!("Failed to get failures {:?}", e);
-            MetaDataBrokerError::InvalidReply
-        })?;
+        let response = self
+            .broker_addresses
+            .send(|address| {
+                let url = format!("http://{}/api/failures", address);
+                self.client.get(&url)
+            })
+            .await
+            .map_err(|e| {
+                error!("Failed to get failures {:?}", e);
+                MetaDataBrokerError::InvalidReply
+            })?;
         let FailuresPayload { addresses } = response.json().await.map_err(|e| {
             error!("Failed to get cluster names from json {:?}", e);
             MetaDataBrokerError::InvalidReply
@@ -201,3 +333,126 @@ pub struct ProxyPayload {
 pub struct FailuresPayload {
     pub addresses: Vec<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::prelude::*;
+
+    // Replies the same status to all the requests.
+    async fn run_http_server(status: &'static str) -> String {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            loop {
+                let (mut sock, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(_) => return,
+                };
+                let mut buf = [0; 1024];
+                let _ = sock.read(&mut buf).await;
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\n\r\n", status);
+                let _ = sock.write_all(response.as_bytes()).await;
+            }
+        });
+        address
+    }
+
+    async fn gen_closed_address() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    async fn send_version_request(
+        addresses: &BrokerAddresses,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        addresses
+            .send(|address| client.get(&format!("http://{}/api/version", address)))
+            .await
+    }
+
+    async fn send_write_request(
+        addresses: &BrokerAddresses,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        addresses
+            .send_write(|address| client.post(&format!("http://{}/api/failures/a/b", address)))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_fail_over_on_connect_error() {
+        let closed_address = gen_closed_address().await;
+        let address = run_http_server("200 OK").await;
+        let addresses = BrokerAddresses::new(vec![closed_address, address.clone()]);
+
+        let response = send_version_request(&addresses).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(addresses.get(), (1, address.as_str()));
+
+        // The later requests go to the new address directly.
+        let response = send_version_request(&addresses).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(addresses.get().0, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fail_over_on_server_error() {
+        let failed_address = run_http_server("503 Service Unavailable").await;
+        let address = run_http_server("200 OK").await;
+        let addresses = BrokerAddresses::new(vec![failed_address, address]);
+
+        let response = send_version_request(&addresses).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(addresses.get().0, 1);
+    }
+
+    #[tokio::test]
+    async fn test_all_addresses_failed() {
+        let addresses =
+            BrokerAddresses::new(vec![gen_closed_address().await, gen_closed_address().await]);
+        assert!(send_version_request(&addresses).await.is_err());
+        // Every address has been tried once.
+        assert_eq!(addresses.get().0, 0);
+
+        let failed_address = run_http_server("500 Internal Server Error").await;
+        let addresses = BrokerAddresses::new(vec![gen_closed_address().await, failed_address]);
+        let response = send_version_request(&addresses).await.unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_fail_over() {
+        let closed_address = gen_closed_address().await;
+        let unavailable_address = run_http_server("503 Service Unavailable").await;
+        let address = run_http_server("200 OK").await;
+        let addresses = BrokerAddresses::new(vec![closed_address, unavailable_address, address]);
+        let response = send_write_request(&addresses).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(addresses.get().0, 2);
+    }
+
+    #[tokio::test]
+    async fn test_no_write_fail_over_on_server_error() {
+        // The write request might have been applied by the broker.
+        let failed_address = run_http_server("500 Internal Server Error").await;
+        let address = run_http_server("200 OK").await;
+        let addresses = BrokerAddresses::new(vec![failed_address, address]);
+        let response = send_write_request(&addresses).await.unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(addresses.get().0, 0);
+
+        // The read requests still fail over.
+        let response = send_version_request(&addresses).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(addresses.get().0, 1);
+    }
+}
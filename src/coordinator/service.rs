@@ -12,7 +12,7 @@ use super::migration::{BrokerMigrationCommitter, MigrationStateRespChecker};
 use super::recover::{BrokerProxyFailureRetriever, ReplaceNodeHandler};
 use super::sync::{BrokerMetaRetriever, ProxyMetaRespSender};
 use crate::common::utils::ThreadSafe;
-use crate::protocol::RedisClientFactory;
+use crate::protocol::{CredentialTable, RedisClientFactory, TlsClientConfig};
 use futures::future::select_all;
 use futures::{Future, StreamExt};
 use futures_timer::Delay;
@@ -22,8 +22,13 @@ use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct CoordinatorConfig {
-    pub broker_address: String,
+    // Brokers in the same replication group.
+    pub broker_addresses: Vec<String>,
     pub reporter_id: String,
+    // For connecting to the server proxies.
+    pub credentials: CredentialTable,
+    // Set when the server proxies enable TLS on their client ports.
+    pub proxy_tls: Option<TlsClientConfig>,
 }
 
 pub struct CoordinatorService<
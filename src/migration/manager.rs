@@ -258,6 +258,16 @@ where
             .join("\r\n")
     }
 
+    pub fn contains_slot(&self, db_name: &DBName, slot: usize) -> bool {
+        match self.task_map.get(db_name) {
+            Some(tasks) => tasks.values().any(|record| match record {
+                Either::Left(migrating_task) => migrating_task.contains_slot(slot),
+                Either::Right(importing_task) => importing_task.contains_slot(slot),
+            }),
+            None => false,
+        }
+    }
+
     pub fn send(&self, mut cmd_task: T) -> Result<(), DBSendError<BlockingHintTask<T>>> {
         cmd_task.log_event(TaskEvent::SentToMigrationDB);
         self.send_to_db(cmd_task)
@@ -0,0 +1,248 @@
+use super::client::RedisClientError;
+use super::codec::RespCodec;
+use super::packet::new_simple_packet_codec;
+use super::resp::{BinSafeStr, Resp, RespVec};
+use super::tls::MaybeTlsStream;
+use super::EncodeError;
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::fmt;
+use tokio_util::codec::Decoder;
+
+#[derive(Clone, PartialEq)]
+pub struct Credential {
+    // Only supported by the ACL of redis 6.
+    pub username: Option<String>,
+    pub password: String,
+}
+
+// Should not print the password to the log.
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Credential {{ username: {:?}, password: ** }}",
+            self.username
+        )
+    }
+}
+
+impl Credential {
+    pub fn new(username: Option<String>, password: String) -> Self {
+        Self { username, password }
+    }
+
+    // HELLO requires the username. "default" is the one used by AUTH without the username.
+    pub fn to_hello_auth_args(&self) -> Vec<BinSafeStr> {
+        let username = self.username.as_deref().unwrap_or("default");
+        vec![
+            b"AUTH".to_vec(),
+            username.as_bytes().to_vec(),
+            self.password.clone().into_bytes(),
+        ]
+    }
+
+    pub fn to_auth_command(&self) -> Vec<BinSafeStr> {
+        let mut cmd = vec![b"AUTH".to_vec()];
+        if let Some(username) = self.username.as_ref() {
+            cmd.push(username.clone().into_bytes());
+        }
+        cmd.push(self.password.clone().into_bytes());
+        cmd
+    }
+}
+
+// The credential of a node will override the default one.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialTable {
+    default: Option<Credential>,
+    nodes: HashMap<String, Credential>,
+}
+
+impl CredentialTable {
+    pub fn new(default: Option<Credential>, nodes: HashMap<String, Credential>) -> Self {
+        Self { default, nodes }
+    }
+
+    // Each node credential is in the format of `<address> [<username>] <password>`.
+    pub fn from_config(
+        username: Option<String>,
+        password: Option<String>,
+        node_credentials: Vec<String>,
+    ) -> Result<Self, String> {
+        let default = password.map(|password| Credential::new(username, password));
+        let mut nodes = HashMap::new();
+        for node_credential in node_credentials.iter() {
+            let segs: Vec<&str> = node_credential.split_whitespace().collect();
+            let (address, credential) = match segs.as_slice() {
+                [address, password] => (address, Credential::new(None, password.to_string())),
+                [address, username, password] => (
+                    address,
+                    Credential::new(Some(username.to_string()), password.to_string()),
+                ),
+                _ => return Err(format!("invalid node credential: {}", node_credential)),
+            };
+            nodes.insert(address.to_string(), credential);
+        }
+        Ok(Self::new(default, nodes))
+    }
+
+    pub fn get(&self, address: &str) -> Option<&Credential> {
+        self.nodes.get(address).or(self.default.as_ref())
+    }
+}
+
+// Sends AUTH right after the connection is established.
+pub async fn auth_conn(
+    sock: MaybeTlsStream,
+    credential: &Credential,
+) -> Result<MaybeTlsStream, RedisClientError> {
+    init_conn(sock, credential.to_auth_command()).await
+}
+
+// Switches to RESP3 by HELLO right after the connection is established,
+// which also authenticates the connection.
+pub async fn hello_conn(
+    sock: MaybeTlsStream,
+    credential: Option<&Credential>,
+) -> Result<MaybeTlsStream, RedisClientError> {
+    let mut cmd = vec![b"HELLO".to_vec(), b"3".to_vec()];
+    if let Some(credential) = credential {
+        cmd.extend(credential.to_hello_auth_args());
+    }
+    init_conn(sock, cmd).await
+}
+
+async fn init_conn(
+    sock: MaybeTlsStream,
+    cmd: Vec<BinSafeStr>,
+) -> Result<MaybeTlsStream, RedisClientError> {
+    let (encoder, decoder) = new_simple_packet_codec::<Vec<BinSafeStr>, RespVec>();
+    let mut frame = RespCodec::new(encoder, decoder).framed(sock);
+
+    frame.send(cmd).await.map_err(|err| match err {
+        EncodeError::Io(err) => RedisClientError::Io(err),
+        EncodeError::NotReady(_) => RedisClientError::InvalidState,
+    })?;
+
+    match frame.next().await {
+        Some(Ok(Resp::Error(err))) => {
+            let err = String::from_utf8_lossy(&err).to_string();
+            error!("failed to auth: {}", err);
+            Err(RedisClientError::AuthFailed)
+        }
+        Some(Ok(_)) => Ok(frame.into_inner()),
+        Some(Err(err)) => {
+            warn!("invalid AUTH reply: {:?}", err);
+            Err(RedisClientError::InvalidReply)
+        }
+        None => Err(RedisClientError::Closed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    async fn run_auth(reply: &'static [u8]) -> Result<MaybeTlsStream, RedisClientError> {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 64];
+            let n = sock.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"*2\r\n$4\r\nAUTH\r\n$4\r\npass\r\n".as_ref());
+            sock.write_all(reply).await.unwrap();
+        });
+        let sock = MaybeTlsStream::Plain(TcpStream::connect(address).await.unwrap());
+        auth_conn(sock, &Credential::new(None, "pass".to_string())).await
+    }
+
+    #[tokio::test]
+    async fn test_auth_conn() {
+        assert!(run_auth(b"+OK\r\n").await.is_ok());
+        let res = run_auth(b"-WRONGPASS invalid password\r\n").await;
+        assert_matches!(res, Err(RedisClientError::AuthFailed));
+    }
+
+    async fn run_hello(
+        credential: Option<Credential>,
+        expected: &'static [u8],
+        reply: &'static [u8],
+    ) -> Result<MaybeTlsStream, RedisClientError> {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 128];
+            let n = sock.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], expected);
+            sock.write_all(reply).await.unwrap();
+        });
+        let sock = MaybeTlsStream::Plain(TcpStream::connect(address).await.unwrap());
+        hello_conn(sock, credential.as_ref()).await
+    }
+
+    #[tokio::test]
+    async fn test_hello_conn() {
+        let hello_reply = b"%2\r\n$6\r\nserver\r\n$5\r\nredis\r\n$5\r\nproto\r\n:3\r\n";
+        let res = run_hello(None, b"*2\r\n$5\r\nHELLO\r\n$1\r\n3\r\n", hello_reply).await;
+        assert!(res.is_ok());
+        let res = run_hello(
+            Some(Credential::new(None, "pass".to_string())),
+            b"*5\r\n$5\r\nHELLO\r\n$1\r\n3\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n$4\r\npass\r\n",
+            hello_reply,
+        )
+        .await;
+        assert!(res.is_ok());
+        let res = run_hello(
+            Some(Credential::new(
+                Some("user".to_string()),
+                "pass".to_string(),
+            )),
+            b"*5\r\n$5\r\nHELLO\r\n$1\r\n3\r\n$4\r\nAUTH\r\n$4\r\nuser\r\n$4\r\npass\r\n",
+            b"-WRONGPASS invalid username-password pair\r\n",
+        )
+        .await;
+        assert_matches!(res, Err(RedisClientError::AuthFailed));
+    }
+
+    #[test]
+    fn test_credential_table() {
+        let node_credentials = vec![
+            "127.0.0.1:6379 pass1".to_string(),
+            "127.0.0.1:6380 user2 pass2".to_string(),
+        ];
+        let table = CredentialTable::from_config(
+            Some("user".to_string()),
+            Some("pass".to_string()),
+            node_credentials,
+        )
+        .unwrap();
+
+        let credential = table.get("127.0.0.1:6379").unwrap();
+        assert_eq!(
+            credential.to_auth_command(),
+            vec![b"AUTH".to_vec(), b"pass1".to_vec()]
+        );
+        let credential = table.get("127.0.0.1:6380").unwrap();
+        assert_eq!(
+            credential.to_auth_command(),
+            vec![b"AUTH".to_vec(), b"user2".to_vec(), b"pass2".to_vec()]
+        );
+        let credential = table.get("127.0.0.1:6381").unwrap();
+        assert_eq!(
+            credential.to_auth_command(),
+            vec![b"AUTH".to_vec(), b"user".to_vec(), b"pass".to_vec()]
+        );
+
+        let table = CredentialTable::from_config(None, None, vec![]).unwrap();
+        assert!(table.get("127.0.0.1:6379").is_none());
+
+        let res = CredentialTable::from_config(None, None, vec!["127.0.0.1:6379".to_string()]);
+        assert!(res.is_err());
+    }
+}
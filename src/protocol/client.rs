@@ -1,4 +1,6 @@
+use super::auth::{auth_conn, CredentialTable};
 use super::resp::{BinSafeStr, RespVec};
+use super::tls::{connect_stream, MaybeTlsStream, TlsClientConfig};
 use crate::common::utils::{resolve_first_address, ThreadSafe};
 use crate::protocol::{
     new_optional_multi_packet_codec, EncodeError, OptionalMulti, OptionalMultiPacketDecoder,
@@ -16,7 +18,6 @@ use std::io;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::TcpStream;
 use tokio::time;
 use tokio_util::codec::{Decoder, Framed};
 
@@ -124,10 +125,10 @@ where
 
 #[derive(Debug)]
 struct RedisClientConnection {
-    sock: TcpStream,
+    sock: MaybeTlsStream,
 }
 
-impl From<RedisClientConnection> for TcpStream {
+impl From<RedisClientConnection> for MaybeTlsStream {
     fn from(conn: RedisClientConnection) -> Self {
         conn.sock
     }
@@ -174,18 +175,19 @@ type ClientCodec =
     RespCodec<OptionalMultiPacketEncoder<Vec<BinSafeStr>>, OptionalMultiPacketDecoder<RespVec>>;
 
 struct RedisClientConnectionHandle {
-    frame: Framed<TcpStream, ClientCodec>,
+    frame: Framed<MaybeTlsStream, ClientCodec>,
     reclaim_sender: Arc<crossbeam_channel::Sender<RedisClientConnection>>,
 }
 
 pub struct PooledRedisClient {
     conn_handle: Option<RedisClientConnectionHandle>,
-    timeout: Duration,
+    // None is only used for the blocking commands which have their own timeout.
+    timeout: Option<Duration>,
     err: bool,
 }
 
 impl PooledRedisClient {
-    fn new(conn_handle: RedisClientConnectionHandle, timeout: Duration) -> Self {
+    fn new(conn_handle: RedisClientConnectionHandle, timeout: Option<Duration>) -> Self {
         Self {
             conn_handle: Some(conn_handle),
             timeout,
@@ -223,19 +225,23 @@ impl PooledRedisClient {
         &mut self,
         command: OptionalMulti<Vec<BinSafeStr>>,
     ) -> Result<OptionalMulti<RespVec>, RedisClientError> {
+        // The connection should not go back to the pool
+        // if the future gets dropped before the reply comes back.
+        self.err = true;
         let timeout = self.timeout;
         let exec_cut = self.execute_cmd(command);
-        let r = match time::timeout(timeout, exec_cut).await {
-            Err(err) => {
-                warn!("redis client timeout: {:?}", err);
-                Err(RedisClientError::Timeout)
-            }
-            Ok(Err(err)) => Err(err),
-            Ok(Ok(resp)) => Ok(resp),
+        let r = match timeout {
+            None => exec_cut.await,
+            Some(timeout) => match time::timeout(timeout, exec_cut).await {
+                Err(err) => {
+                    warn!("redis client timeout: {:?}", err);
+                    Err(RedisClientError::Timeout)
+                }
+                Ok(Err(err)) => Err(err),
+                Ok(Ok(resp)) => Ok(resp),
+            },
         };
-        if r.is_err() {
-            self.err = true;
-        }
+        self.err = r.is_err();
         r
     }
 }
@@ -277,17 +283,42 @@ pub struct PooledRedisClientFactory {
     // TODO: need to cleanup unused pools.
     pool_map: DashMap<String, Pool<RedisClientConnection>>,
     timeout: Duration,
+    cmd_timeout: Option<Duration>,
+    credentials: CredentialTable,
+    tls: Option<TlsClientConfig>,
 }
 
 impl PooledRedisClientFactory {
     pub fn new(capacity: usize, timeout: Duration) -> Self {
+        Self::with_credentials(capacity, timeout, CredentialTable::default())
+    }
+
+    pub fn with_credentials(
+        capacity: usize,
+        timeout: Duration,
+        credentials: CredentialTable,
+    ) -> Self {
         Self {
             capacity,
             pool_map: DashMap::new(),
             timeout,
+            cmd_timeout: Some(timeout),
+            credentials,
+            tls: None,
         }
     }
 
+    pub fn with_tls(mut self, tls: Option<TlsClientConfig>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    // The timeout still applies to creating connections.
+    pub fn without_cmd_timeout(mut self) -> Self {
+        self.cmd_timeout = None;
+        self
+    }
+
     async fn create_conn(
         &self,
         address: String,
@@ -296,10 +327,14 @@ impl PooledRedisClientFactory {
             Some(address) => address,
             None => return Err(RedisClientError::InvalidAddress),
         };
-        let sock = match TcpStream::connect(&sock_address).await {
+        let sock = match connect_stream(&address, sock_address, self.tls.as_ref()).await {
             Ok(conn) => conn,
             Err(io_err) => return Err(RedisClientError::Io(io_err)),
         };
+        let sock = match self.credentials.get(&address) {
+            Some(credential) => auth_conn(sock, credential).await?,
+            None => sock,
+        };
         Ok(RedisClientConnection { sock })
     }
 
@@ -338,7 +373,7 @@ impl PooledRedisClientFactory {
                     frame: ClientCodec::new(encoder, decoder).framed(item.into()),
                     reclaim_sender,
                 };
-                return Ok(PooledRedisClient::new(conn_handle, self.timeout));
+                return Ok(PooledRedisClient::new(conn_handle, self.cmd_timeout));
             }
             Either::Right(reclaim_sender) => reclaim_sender,
         };
@@ -361,7 +396,7 @@ impl PooledRedisClientFactory {
                     frame: ClientCodec::new(encoder, decoder).framed(conn.into()),
                     reclaim_sender,
                 };
-                Ok(PooledRedisClient::new(conn_handle, timeout))
+                Ok(PooledRedisClient::new(conn_handle, self.cmd_timeout))
             }
         }
     }
@@ -385,6 +420,7 @@ pub enum RedisClientError {
     InvalidReply,
     InvalidAddress,
     InitError,
+    AuthFailed,
     Closed,
     Done,
     Canceled,
@@ -1,5 +1,6 @@
 use super::decoder::DecodeError;
 use super::encoder::EncodeError;
+use super::stateless::ParseLimits;
 use crate::protocol::packet::{PacketDecoder, PacketEncoder};
 use bytes::BytesMut;
 use tokio_util::codec::{Decoder, Encoder};
@@ -7,11 +8,20 @@ use tokio_util::codec::{Decoder, Encoder};
 pub struct RespCodec<E: PacketEncoder, D: PacketDecoder> {
     encoder: E,
     decoder: D,
+    limits: ParseLimits,
 }
 
 impl<E: PacketEncoder, D: PacketDecoder> RespCodec<E, D> {
     pub fn new(encoder: E, decoder: D) -> Self {
-        Self { encoder, decoder }
+        Self::with_limits(encoder, decoder, ParseLimits::default())
+    }
+
+    pub fn with_limits(encoder: E, decoder: D, limits: ParseLimits) -> Self {
+        Self {
+            encoder,
+            decoder,
+            limits,
+        }
     }
 }
 
@@ -20,7 +30,7 @@ impl<E: PacketEncoder, D: PacketDecoder> Decoder for RespCodec<E, D> {
     type Error = DecodeError;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        self.decoder.decode(buf)
+        self.decoder.decode(buf, &self.limits)
     }
 }
 
@@ -5,6 +5,8 @@ use std::io;
 #[derive(Debug)]
 pub enum DecodeError {
     InvalidProtocol,
+    ExceedLimit(&'static str),
+    InvalidRequest(&'static str),
     Io(io::Error),
 }
 
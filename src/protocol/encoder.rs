@@ -16,6 +16,11 @@ pub fn resp_to_buf(buf: &mut Vec<u8>, resp: &RespVec) -> io::Result<usize> {
     encode_resp(buf, resp)
 }
 
+// The length of the encoded resp without allocating the buffer.
+pub fn get_resp_size(resp: &RespVec) -> usize {
+    encode_resp(&mut io::sink(), resp).unwrap_or(0)
+}
+
 pub fn encode_resp<W, T: AsRef<[u8]>>(writer: &mut W, resp: &Resp<T>) -> io::Result<usize>
 where
     W: io::Write,
@@ -26,7 +31,32 @@ where
         Resp::Integer(s) => encode_simple_element(writer, b":", s),
         Resp::Bulk(bulk) => encode_bulk_str(writer, bulk),
         Resp::Arr(array) => encode_array(writer, array),
+        Resp::Null => writer.write(b"_\r\n"),
+        Resp::Double(s) => encode_simple_element(writer, b",", s),
+        Resp::Boolean(s) => encode_simple_element(writer, b"#", s),
+        Resp::BigNumber(s) => encode_simple_element(writer, b"(", s),
+        Resp::BlobError(s) => encode_blob(writer, b"!", s),
+        Resp::Verbatim(s) => encode_blob(writer, b"=", s),
+        Resp::Map(elements) => encode_aggregate(writer, b"%", elements.len() / 2, elements),
+        Resp::Set(elements) => encode_aggregate(writer, b"~", elements.len(), elements),
+        Resp::Push(elements) => encode_aggregate(writer, b">", elements.len(), elements),
+    }
+}
+
+fn encode_aggregate<W, T: AsRef<[u8]>>(
+    writer: &mut W,
+    prefix: &[u8],
+    len: usize,
+    elements: &[Resp<T>],
+) -> io::Result<usize>
+where
+    W: io::Write,
+{
+    let mut l = encode_simple_element(writer, prefix, &len.to_string().into_bytes())?;
+    for element in elements {
+        l += encode_resp(writer, element)?;
     }
+    Ok(l)
 }
 
 fn encode_array<W, T: AsRef<[u8]>>(writer: &mut W, array: &Array<T>) -> io::Result<usize>
@@ -35,13 +65,7 @@ where
 {
     match *array {
         Array::Nil => writer.write(b"*-1\r\n"),
-        Array::Arr(ref arr) => {
-            let mut l = encode_simple_element(writer, b"*", &arr.len().to_string().into_bytes())?;
-            for element in arr {
-                l += encode_resp(writer, element)?;
-            }
-            Ok(l)
-        }
+        Array::Arr(ref arr) => encode_aggregate(writer, b"*", arr.len(), arr),
     }
 }
 
@@ -51,15 +75,21 @@ where
 {
     match *bulk_str {
         BulkStr::Nil => writer.write(b"$-1\r\n"),
-        BulkStr::Str(ref s) => Ok(encode_simple_element(
-            writer,
-            b"$",
-            &s.as_ref().len().to_string().into_bytes(),
-        )? + writer.write(s.as_ref())?
-            + writer.write(b"\r\n")?),
+        BulkStr::Str(ref s) => encode_blob(writer, b"$", s),
     }
 }
 
+fn encode_blob<W, T: AsRef<[u8]>>(writer: &mut W, prefix: &[u8], s: T) -> io::Result<usize>
+where
+    W: io::Write,
+{
+    Ok(
+        encode_simple_element(writer, prefix, &s.as_ref().len().to_string().into_bytes())?
+            + writer.write(s.as_ref())?
+            + writer.write(b"\r\n")?,
+    )
+}
+
 fn encode_simple_element<W, T: AsRef<[u8]>>(
     writer: &mut W,
     prefix: &[u8],
@@ -105,3 +135,24 @@ impl<T> From<io::Error> for EncodeError<T> {
         EncodeError::Io(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_resp3() {
+        let resp: RespVec = Resp::Map(vec![
+            Resp::Simple(b"key".to_vec()),
+            Resp::Set(vec![Resp::Null, Resp::BigNumber(b"123".to_vec())]),
+            Resp::Verbatim(b"txt:a".to_vec()),
+            Resp::Push(vec![Resp::BlobError(b"ERR".to_vec())]),
+        ]);
+        let mut buf = vec![];
+        let l = resp_to_buf(&mut buf, &resp).expect("test_encode_resp3");
+        let expected = b"%2\r\n+key\r\n~2\r\n_\r\n(123\r\n=5\r\ntxt:a\r\n>1\r\n!3\r\nERR\r\n";
+        assert_eq!(l, expected.len());
+        assert_eq!(buf, expected.to_vec());
+        assert_eq!(get_resp_size(&resp), expected.len());
+    }
+}
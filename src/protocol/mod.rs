@@ -1,3 +1,4 @@
+mod auth;
 mod client;
 mod codec;
 mod decoder;
@@ -6,14 +7,16 @@ mod fp;
 mod packet;
 mod resp;
 mod stateless;
+mod tls;
 
+pub use self::auth::{auth_conn, hello_conn, Credential, CredentialTable};
 pub use self::client::{
     DummyRedisClientFactory, MockRedisClient, PooledRedisClient, PooledRedisClientFactory,
     RedisClient, RedisClientError, RedisClientFactory,
 };
 pub use self::codec::RespCodec;
 pub use self::decoder::DecodeError;
-pub use self::encoder::{encode_resp, resp_to_buf, EncodeError};
+pub use self::encoder::{encode_resp, get_resp_size, resp_to_buf, EncodeError};
 pub use self::fp::{RFunctor, VFunctor};
 pub use self::packet::{
     new_optional_multi_packet_codec, new_simple_packet_codec, DecodedPacket, EncodedPacket,
@@ -23,5 +26,7 @@ pub use self::packet::{
 pub use self::resp::{
     Array, ArrayBytes, ArrayIndex, ArraySlice, ArrayVec, BinSafeStr, BulkStr, BulkStrBytes,
     BulkStrIndex, BulkStrSlice, BulkStrVec, IndexedResp, Resp, RespBytes, RespIndex, RespSlice,
-    RespVec,
+    RespVec, RespVersion,
 };
+pub use self::stateless::ParseLimits;
+pub use self::tls::{connect_stream, MaybeTlsStream, TlsClientConfig, TlsError, TlsServerConfig};
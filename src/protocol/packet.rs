@@ -1,8 +1,8 @@
 use super::decoder::DecodeError;
-use super::encoder::{command_to_buf, encode_resp};
+use super::encoder::{command_to_buf, encode_resp, get_resp_size};
 use super::fp::{RFunctor, VFunctor};
-use super::resp::{BinSafeStr, IndexedResp, Resp, RespSlice, RespVec};
-use super::stateless::{parse_indexed_resp, ParseError};
+use super::resp::{Array, BinSafeStr, IndexedResp, Resp, RespSlice, RespVec};
+use super::stateless::{parse_indexed_resp, ParseError, ParseLimits};
 use crate::common::utils::{
     change_bulk_array_element, change_bulk_str, get_command_element, ThreadSafe,
 };
@@ -30,7 +30,11 @@ pub trait EncodedPacket {
 pub trait DecodedPacket {
     type Hint;
 
-    fn decode(buf: &mut BytesMut, hint: Self::Hint) -> Result<Option<Self>, DecodeError>
+    fn decode(
+        buf: &mut BytesMut,
+        hint: Self::Hint,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self>, DecodeError>
     where
         Self: Sized;
 }
@@ -83,7 +87,11 @@ pub trait PacketEncoder {
 pub trait PacketDecoder {
     type Pkt: DecodedPacket;
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Pkt>, DecodeError>
+    fn decode(
+        &mut self,
+        buf: &mut BytesMut,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self::Pkt>, DecodeError>
     where
         Self: Sized;
 }
@@ -106,6 +114,14 @@ impl RespPacket {
         }
     }
 
+    // The length of the encoded packet.
+    pub fn get_size(&self) -> usize {
+        match self {
+            Self::Indexed(indexed_resp) => indexed_resp.get_data().len(),
+            Self::Data(resp) => get_resp_size(resp),
+        }
+    }
+
     pub fn get_array_element(&self, index: usize) -> Option<&[u8]> {
         match self {
             Self::Indexed(indexed_resp) => indexed_resp.get_array_element(index),
@@ -113,6 +129,14 @@ impl RespPacket {
         }
     }
 
+    pub fn get_array_len(&self) -> Option<usize> {
+        match self {
+            Self::Indexed(indexed_resp) => indexed_resp.get_array_len(),
+            Self::Data(Resp::Arr(Array::Arr(resps))) => Some(resps.len()),
+            Self::Data(_) => None,
+        }
+    }
+
     pub fn get_command_name(&self) -> Option<&str> {
         let element = self.get_array_element(0)?;
         str::from_utf8(element).ok()
@@ -132,6 +156,13 @@ impl RespPacket {
         }
     }
 
+    pub fn is_resp2(&self) -> bool {
+        match self {
+            Self::Indexed(indexed_resp) => indexed_resp.is_resp2(),
+            Self::Data(resp) => resp.is_resp2(),
+        }
+    }
+
     pub fn change_bulk_array_element(&mut self, index: usize, data: Vec<u8>) -> bool {
         let mut resp = match self {
             Self::Indexed(indexed_resp) => indexed_resp.to_resp_vec(),
@@ -219,11 +250,15 @@ impl<T: AsRef<[u8]>> EncodedPacket for Resp<T> {
 impl DecodedPacket for RespVec {
     type Hint = ();
 
-    fn decode(buf: &mut BytesMut, _hint: Self::Hint) -> Result<Option<Self>, DecodeError>
+    fn decode(
+        buf: &mut BytesMut,
+        _hint: Self::Hint,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self>, DecodeError>
     where
         Self: Sized,
     {
-        let item = IndexedResp::decode(buf, ())?;
+        let item = IndexedResp::decode(buf, (), limits)?;
         match item {
             Some(resp) => Ok(Some(resp.to_resp_vec())),
             None => Ok(None),
@@ -234,15 +269,21 @@ impl DecodedPacket for RespVec {
 impl DecodedPacket for IndexedResp {
     type Hint = ();
 
-    fn decode(buf: &mut BytesMut, _hint: Self::Hint) -> Result<Option<Self>, DecodeError>
+    fn decode(
+        buf: &mut BytesMut,
+        _hint: Self::Hint,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self>, DecodeError>
     where
         Self: Sized,
     {
-        match parse_indexed_resp(buf) {
+        match parse_indexed_resp(buf, limits) {
             Ok(r) => Ok(Some(r)),
             Err(e) => match e {
                 ParseError::NotEnoughData => Ok(None),
                 ParseError::InvalidProtocol => Err(DecodeError::InvalidProtocol),
+                ParseError::ExceedLimit(reason) => Err(DecodeError::ExceedLimit(reason)),
+                ParseError::InvalidRequest(reason) => Err(DecodeError::InvalidRequest(reason)),
                 ParseError::UnexpectedErr => {
                     error!("Unexpected error");
                     Err(DecodeError::InvalidProtocol)
@@ -280,11 +321,15 @@ impl EncodedPacket for RespPacket {
 impl DecodedPacket for RespPacket {
     type Hint = ();
 
-    fn decode(buf: &mut BytesMut, _hint: Self::Hint) -> Result<Option<Self>, DecodeError>
+    fn decode(
+        buf: &mut BytesMut,
+        _hint: Self::Hint,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self>, DecodeError>
     where
         Self: Sized,
     {
-        Ok(IndexedResp::decode(buf, ())?.map(RespPacket::Indexed))
+        Ok(IndexedResp::decode(buf, (), limits)?.map(RespPacket::Indexed))
     }
 }
 
@@ -304,11 +349,15 @@ impl<T: EncodedPacket> EncodedPacket for Box<T> {
 impl<T: DecodedPacket> DecodedPacket for Box<T> {
     type Hint = T::Hint;
 
-    fn decode(buf: &mut BytesMut, hint: Self::Hint) -> Result<Option<Self>, DecodeError>
+    fn decode(
+        buf: &mut BytesMut,
+        hint: Self::Hint,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self>, DecodeError>
     where
         Self: Sized,
     {
-        Ok(T::decode(buf, hint)?.map(Box::new))
+        Ok(T::decode(buf, hint, limits)?.map(Box::new))
     }
 }
 
@@ -349,11 +398,15 @@ impl<T: DecodedPacket<Hint = ()>> Default for SimplePacketDecoder<T> {
 impl<T: DecodedPacket<Hint = ()>> PacketDecoder for SimplePacketDecoder<T> {
     type Pkt = T;
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Pkt>, DecodeError>
+    fn decode(
+        &mut self,
+        buf: &mut BytesMut,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self::Pkt>, DecodeError>
     where
         Self: Sized,
     {
-        Self::Pkt::decode(buf, ())
+        Self::Pkt::decode(buf, (), limits)
     }
 }
 
@@ -428,13 +481,17 @@ impl<T: EncodedPacket> EncodedPacket for OptionalMulti<T> {
 impl<T: DecodedPacket> DecodedPacket for OptionalMulti<T> {
     type Hint = OptionalMultiHint<T::Hint>;
 
-    fn decode(buf: &mut BytesMut, hint: Self::Hint) -> Result<Option<Self>, DecodeError>
+    fn decode(
+        buf: &mut BytesMut,
+        hint: Self::Hint,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self>, DecodeError>
     where
         Self: Sized,
     {
         let hints = match hint {
             OptionalMultiHint::Single(hint) => {
-                return match T::decode(buf, hint)? {
+                return match T::decode(buf, hint, limits)? {
                     Some(p) => Ok(Some(OptionalMulti::Single(p))),
                     None => Ok(None),
                 };
@@ -449,7 +506,7 @@ impl<T: DecodedPacket> DecodedPacket for OptionalMulti<T> {
         let mut packets = vec![];
 
         for hint in hints {
-            let packet = match T::decode(buf, hint)? {
+            let packet = match T::decode(buf, hint, limits)? {
                 Some(p) => p,
                 None => return Ok(None),
             };
@@ -546,7 +603,11 @@ impl<D: DecodedPacket<Hint = ()>> OptionalMultiPacketDecoder<D> {
 impl<D: DecodedPacket<Hint = ()>> PacketDecoder for OptionalMultiPacketDecoder<D> {
     type Pkt = OptionalMulti<D>;
 
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Pkt>, DecodeError>
+    fn decode(
+        &mut self,
+        buf: &mut BytesMut,
+        limits: &ParseLimits,
+    ) -> Result<Option<Self::Pkt>, DecodeError>
     where
         Self: Sized,
     {
@@ -571,7 +632,7 @@ impl<D: DecodedPacket<Hint = ()>> PacketDecoder for OptionalMultiPacketDecoder<D
         }
 
         loop {
-            let packet = match D::decode(buf, ())? {
+            let packet = match D::decode(buf, (), limits)? {
                 Some(p) => p,
                 None => return Ok(None),
             };
@@ -612,7 +673,7 @@ mod tests {
             .encode(cmd, |data| buf.extend_from_slice(data))
             .expect("test_single_packet encode");
         assert_eq!(buf.as_ref(), b"*1\r\n$5\r\nPING1\r\n");
-        let res = decoder.decode(&mut buf);
+        let res = decoder.decode(&mut buf, &ParseLimits::default());
         assert!(decoder.curr_hint.is_none());
         assert!(decoder.buf.is_empty());
         assert!(decoder.state.consume().is_none());
@@ -636,7 +697,7 @@ mod tests {
             .encode(cmd, |data| buf.extend_from_slice(data))
             .expect("test_multi_packet encode");
         assert_eq!(buf.as_ref(), b"*1\r\n$5\r\nPING1\r\n*1\r\n$5\r\nPING2\r\n");
-        let res = decoder.decode(&mut buf);
+        let res = decoder.decode(&mut buf, &ParseLimits::default());
         assert!(decoder.curr_hint.is_none());
         assert!(decoder.buf.is_empty());
         assert!(decoder.state.consume().is_none());
@@ -660,7 +721,7 @@ mod tests {
             .encode(cmd, |data| buf.extend_from_slice(data))
             .expect("test_empty_multi_packet encode");
         assert_eq!(buf.as_ref(), b"");
-        let res = decoder.decode(&mut buf);
+        let res = decoder.decode(&mut buf, &ParseLimits::default());
         assert!(decoder.curr_hint.is_none());
         assert!(decoder.buf.is_empty());
         assert!(decoder.state.consume().is_none());
@@ -17,6 +17,29 @@ pub type BulkStrSlice<'a> = BulkStr<&'a [u8]>;
 pub type ArraySlice<'a> = Array<&'a [u8]>;
 pub type RespSlice<'a> = Resp<&'a [u8]>;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RespVersion {
+    Resp2,
+    Resp3,
+}
+
+impl RespVersion {
+    pub fn from_protover(protover: &[u8]) -> Option<Self> {
+        match protover {
+            b"2" => Some(RespVersion::Resp2),
+            b"3" => Some(RespVersion::Resp3),
+            _ => None,
+        }
+    }
+
+    pub fn to_protover(self) -> usize {
+        match self {
+            RespVersion::Resp2 => 2,
+            RespVersion::Resp3 => 3,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct DataIndex(pub usize, pub usize);
 
@@ -71,6 +94,10 @@ impl IndexedResp {
     pub fn get_data(&self) -> &[u8] {
         &self.data
     }
+
+    pub fn is_resp2(&self) -> bool {
+        self.resp.is_resp2()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -92,6 +119,61 @@ pub enum Resp<T> {
     Bulk(BulkStr<T>),
     Integer(T),
     Arr(Array<T>),
+    // The following types are only available in RESP3.
+    Null,
+    Double(T),
+    Boolean(T),
+    BigNumber(T),
+    BlobError(T),
+    // Includes the format prefix such as `txt:`.
+    Verbatim(T),
+    // Keys and values are flattened just like the RESP2 reply of HGETALL.
+    Map(Vec<Resp<T>>),
+    Set(Vec<Resp<T>>),
+    Push(Vec<Resp<T>>),
+}
+
+impl<T> Resp<T> {
+    pub fn is_resp2(&self) -> bool {
+        match self {
+            Self::Error(_) | Self::Simple(_) | Self::Bulk(_) | Self::Integer(_) => true,
+            Self::Arr(Array::Arr(arr)) => arr.iter().all(|resp| resp.is_resp2()),
+            Self::Arr(Array::Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl RespVec {
+    // Converts the RESP3 types to their RESP2 counterparts
+    // for the clients not negotiating RESP3 by HELLO.
+    pub fn into_resp2(self) -> RespVec {
+        match self {
+            Self::Error(_) | Self::Simple(_) | Self::Bulk(_) | Self::Integer(_) => self,
+            Self::Arr(Array::Nil) => Resp::Arr(Array::Nil),
+            Self::Arr(Array::Arr(arr)) => Resp::Arr(Array::Arr(
+                arr.into_iter().map(|resp| resp.into_resp2()).collect(),
+            )),
+            Self::Null => Resp::Bulk(BulkStr::Nil),
+            Self::Double(s) | Self::BigNumber(s) => Resp::Bulk(BulkStr::Str(s)),
+            Self::Boolean(s) => {
+                let n = if s.as_slice() == b"t" { b"1" } else { b"0" };
+                Resp::Integer(n.to_vec())
+            }
+            Self::BlobError(s) => Resp::Error(
+                s.into_iter()
+                    .map(|b| if b == b'\r' || b == b'\n' { b' ' } else { b })
+                    .collect(),
+            ),
+            Self::Verbatim(s) => {
+                let content = s.get(4..).map(|c| c.to_vec()).unwrap_or_default();
+                Resp::Bulk(BulkStr::Str(content))
+            }
+            Self::Map(arr) | Self::Set(arr) | Self::Push(arr) => Resp::Arr(Array::Arr(
+                arr.into_iter().map(|resp| resp.into_resp2()).collect(),
+            )),
+        }
+    }
 }
 
 impl<A, B> Plug<A> for BulkStr<B> {
@@ -221,6 +303,15 @@ impl<A> VFunctor for Resp<A> {
             Self::Bulk(bulk_str) => Resp::Bulk(bulk_str.map(f)),
             Self::Integer(t) => Resp::Integer(f(t)),
             Self::Arr(arr) => Resp::Arr(arr.map(f)),
+            Self::Null => Resp::Null,
+            Self::Double(t) => Resp::Double(f(t)),
+            Self::Boolean(t) => Resp::Boolean(f(t)),
+            Self::BigNumber(t) => Resp::BigNumber(f(t)),
+            Self::BlobError(t) => Resp::BlobError(f(t)),
+            Self::Verbatim(t) => Resp::Verbatim(f(t)),
+            Self::Map(t) => Resp::Map(t.into_iter().map(move |e| e.map(f)).collect()),
+            Self::Set(t) => Resp::Set(t.into_iter().map(move |e| e.map(f)).collect()),
+            Self::Push(t) => Resp::Push(t.into_iter().map(move |e| e.map(f)).collect()),
         }
     }
 }
@@ -233,6 +324,15 @@ impl<'a, A> RFunctor<'a> for Resp<A> {
             Self::Bulk(ref bulk_str) => Resp::Bulk(bulk_str.as_ref()),
             Self::Integer(ref t) => Resp::Integer(t),
             Self::Arr(ref arr) => Resp::Arr(arr.as_ref()),
+            Self::Null => Resp::Null,
+            Self::Double(ref t) => Resp::Double(t),
+            Self::Boolean(ref t) => Resp::Boolean(t),
+            Self::BigNumber(ref t) => Resp::BigNumber(t),
+            Self::BlobError(ref t) => Resp::BlobError(t),
+            Self::Verbatim(ref t) => Resp::Verbatim(t),
+            Self::Map(ref t) => Resp::Map(t.iter().map(|e| e.as_ref()).collect()),
+            Self::Set(ref t) => Resp::Set(t.iter().map(|e| e.as_ref()).collect()),
+            Self::Push(ref t) => Resp::Push(t.iter().map(|e| e.as_ref()).collect()),
         }
     }
 
@@ -243,6 +343,15 @@ impl<'a, A> RFunctor<'a> for Resp<A> {
             Self::Bulk(ref mut bulk_str) => Resp::Bulk(bulk_str.as_mut()),
             Self::Integer(ref mut t) => Resp::Integer(t),
             Self::Arr(ref mut arr) => Resp::Arr(arr.as_mut()),
+            Self::Null => Resp::Null,
+            Self::Double(ref mut t) => Resp::Double(t),
+            Self::Boolean(ref mut t) => Resp::Boolean(t),
+            Self::BigNumber(ref mut t) => Resp::BigNumber(t),
+            Self::BlobError(ref mut t) => Resp::BlobError(t),
+            Self::Verbatim(ref mut t) => Resp::Verbatim(t),
+            Self::Map(ref mut t) => Resp::Map(t.iter_mut().map(|e| e.as_mut()).collect()),
+            Self::Set(ref mut t) => Resp::Set(t.iter_mut().map(|e| e.as_mut()).collect()),
+            Self::Push(ref mut t) => Resp::Push(t.iter_mut().map(|e| e.as_mut()).collect()),
         }
     }
 
@@ -256,6 +365,17 @@ impl<'a, A> RFunctor<'a> for Resp<A> {
             Self::Bulk(ref mut bulk_str) => bulk_str.map_in_place(f),
             Self::Integer(ref mut t) => f(t),
             Self::Arr(ref mut arr) => arr.map_in_place(f),
+            Self::Null => (),
+            Self::Double(ref mut t) => f(t),
+            Self::Boolean(ref mut t) => f(t),
+            Self::BigNumber(ref mut t) => f(t),
+            Self::BlobError(ref mut t) => f(t),
+            Self::Verbatim(ref mut t) => f(t),
+            Self::Map(ref mut arr) | Self::Set(ref mut arr) | Self::Push(ref mut arr) => {
+                for resp in arr.iter_mut() {
+                    resp.map_in_place(f)
+                }
+            }
         }
     }
 }
@@ -311,3 +431,37 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_into_resp2() {
+        let resp: RespVec = Resp::Map(vec![
+            Resp::Simple(b"a".to_vec()),
+            Resp::Boolean(b"t".to_vec()),
+            Resp::Simple(b"b".to_vec()),
+            Resp::Set(vec![Resp::Double(b"1.5".to_vec()), Resp::Null]),
+            Resp::Simple(b"c".to_vec()),
+            Resp::Verbatim(b"txt:hello".to_vec()),
+        ]);
+        assert!(!resp.is_resp2());
+        let resp = resp.into_resp2();
+        assert!(resp.is_resp2());
+        assert_eq!(
+            resp,
+            Resp::Arr(Array::Arr(vec![
+                Resp::Simple(b"a".to_vec()),
+                Resp::Integer(b"1".to_vec()),
+                Resp::Simple(b"b".to_vec()),
+                Resp::Arr(Array::Arr(vec![
+                    Resp::Bulk(BulkStr::Str(b"1.5".to_vec())),
+                    Resp::Bulk(BulkStr::Nil),
+                ])),
+                Resp::Simple(b"c".to_vec()),
+                Resp::Bulk(BulkStr::Str(b"hello".to_vec())),
+            ]))
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use super::decoder::LF;
+use super::encoder::command_to_buf;
 use super::resp::{AdvanceIndex, ArrayIndex, BulkStrIndex, DataIndex, IndexedResp, RespIndex};
 use btoi::btoi;
-use bytes::BytesMut;
+use bytes::{Buf, Bytes, BytesMut};
 use memchr::memchr;
 use std::error::Error;
 use std::fmt;
@@ -11,6 +12,8 @@ pub enum ParseError {
     InvalidProtocol,
     NotEnoughData,
     UnexpectedErr,
+    ExceedLimit(&'static str),
+    InvalidRequest(&'static str),
 }
 
 impl fmt::Display for ParseError {
@@ -29,13 +32,216 @@ impl Error for ParseError {
     }
 }
 
-pub fn parse_indexed_resp(buf: &mut BytesMut) -> Result<IndexedResp, ParseError> {
-    let (resp, consumed) = parse_resp(&buf)?;
-    let data = buf.split_to(consumed).freeze();
-    Ok(IndexedResp::new(resp, data))
+// Same as redis, the inline command without a line feed should not exceed 64KB.
+// So do the lines of RESP such as the length headers and the simple strings.
+const MAX_INLINE_COMMAND_LENGTH: usize = 64 * 1024;
+
+const TOO_BIG_BULK_COUNT: &str = "too big bulk count string";
+const TOO_BIG_MBULK_COUNT: &str = "too big mbulk count string";
+const TOO_BIG_LINE: &str = "too big line";
+
+// The replies of redis are nested much less than this.
+// It only prevents the stack overflow caused by a broken backend.
+const DEFAULT_MAX_DEPTH: usize = 128;
+
+// Limits for the untrusted input from the clients
+// so that the oversized requests will be rejected before being buffered.
+// The default one is used for the replies from the backend redis,
+// which could be large but should not be nested too deep.
+#[derive(Debug, Clone)]
+pub struct ParseLimits {
+    pub max_bulk_len: usize,
+    pub max_array_len: usize,
+    // The top level array has depth 1.
+    pub max_depth: usize,
+    // Only the requests from the clients could be inline commands.
+    pub inline_commands: bool,
 }
 
-pub fn parse_resp(buf: &[u8]) -> Result<(RespIndex, usize), ParseError> {
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_bulk_len: usize::MAX,
+            max_array_len: usize::MAX,
+            max_depth: DEFAULT_MAX_DEPTH,
+            inline_commands: false,
+        }
+    }
+}
+
+pub fn parse_indexed_resp(
+    buf: &mut BytesMut,
+    limits: &ParseLimits,
+) -> Result<IndexedResp, ParseError> {
+    loop {
+        let prefix = *buf.first().ok_or(ParseError::NotEnoughData)?;
+        if is_resp_prefix(prefix) {
+            let (resp, consumed) = parse_resp(&buf, limits)?;
+            let data = buf.split_to(consumed).freeze();
+            return Ok(IndexedResp::new(resp, data));
+        }
+        if !limits.inline_commands {
+            return Err(ParseError::InvalidProtocol);
+        }
+
+        let (args, consumed) = parse_inline_command(buf)?;
+        buf.advance(consumed);
+        // Empty lines are simply skipped just like redis.
+        if args.is_empty() {
+            continue;
+        }
+
+        // Convert it to the normal RESP array so that it can be forwarded as usual.
+        let mut data = Vec::new();
+        command_to_buf(&mut data, args).map_err(|_| ParseError::UnexpectedErr)?;
+        let (resp, _) = parse_resp(&data, limits)?;
+        return Ok(IndexedResp::new(resp, Bytes::from(data)));
+    }
+}
+
+fn is_resp_prefix(prefix: u8) -> bool {
+    b"$+:-*_,#(!=%~>".contains(&prefix)
+}
+
+// Parses the telnet style commands like `GET somekey\r\n`.
+fn parse_inline_command(buf: &[u8]) -> Result<(Vec<Vec<u8>>, usize), ParseError> {
+    let lf_index = match memchr(LF, buf) {
+        Some(lf_index) => lf_index,
+        None if buf.len() > MAX_INLINE_COMMAND_LENGTH => {
+            return Err(ParseError::ExceedLimit("too big inline request"))
+        }
+        None => return Err(ParseError::NotEnoughData),
+    };
+    if lf_index > MAX_INLINE_COMMAND_LENGTH {
+        return Err(ParseError::ExceedLimit("too big inline request"));
+    }
+    let mut line = &buf[..lf_index];
+    if line.last() == Some(&b'\r') {
+        line = &line[..line.len() - 1];
+    }
+    let args = split_inline_args(line)?;
+    Ok((args, lf_index + 1))
+}
+
+const UNBALANCED_QUOTES: ParseError = ParseError::InvalidRequest("unbalanced quotes in request");
+
+// Same as `sdssplitargs` in redis, the arguments could be quoted
+// so that they can contain spaces and escaped characters like "\x00".
+fn split_inline_args(line: &[u8]) -> Result<Vec<Vec<u8>>, ParseError> {
+    let mut args = vec![];
+    let mut i = 0;
+    loop {
+        while i < line.len() && is_space(line[i]) {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+        let mut arg = vec![];
+        while i < line.len() && !is_space(line[i]) {
+            let consumed = match line[i] {
+                b'"' => parse_double_quoted(&line[i + 1..], &mut arg)?,
+                b'\'' => parse_single_quoted(&line[i + 1..], &mut arg)?,
+                b => {
+                    arg.push(b);
+                    0
+                }
+            };
+            i += 1 + consumed;
+        }
+        args.push(arg);
+    }
+}
+
+fn is_space(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c)
+}
+
+// Returns the length of the quoted argument including the closing quote.
+fn parse_double_quoted(buf: &[u8], arg: &mut Vec<u8>) -> Result<usize, ParseError> {
+    let mut i = 0;
+    loop {
+        match buf.get(i) {
+            None => return Err(UNBALANCED_QUOTES),
+            Some(b'"') => {
+                check_closing_quote(buf, i)?;
+                return Ok(i + 1);
+            }
+            Some(b'\\') if i + 1 < buf.len() => {
+                if let Some(b) = parse_hex_escape(&buf[i + 1..]) {
+                    arg.push(b);
+                    i += 4;
+                    continue;
+                }
+                let b = match buf[i + 1] {
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'b' => 0x08,
+                    b'a' => 0x07,
+                    b => b,
+                };
+                arg.push(b);
+                i += 2;
+            }
+            Some(b) => {
+                arg.push(*b);
+                i += 1;
+            }
+        }
+    }
+}
+
+// Only the single quote could be escaped inside single quotes.
+fn parse_single_quoted(buf: &[u8], arg: &mut Vec<u8>) -> Result<usize, ParseError> {
+    let mut i = 0;
+    loop {
+        match buf.get(i) {
+            None => return Err(UNBALANCED_QUOTES),
+            Some(b'\\') if buf.get(i + 1) == Some(&b'\'') => {
+                arg.push(b'\'');
+                i += 2;
+            }
+            Some(b'\'') => {
+                check_closing_quote(buf, i)?;
+                return Ok(i + 1);
+            }
+            Some(b) => {
+                arg.push(*b);
+                i += 1;
+            }
+        }
+    }
+}
+
+// The closing quote must be followed by a space or nothing at all.
+fn check_closing_quote(buf: &[u8], quote_index: usize) -> Result<(), ParseError> {
+    match buf.get(quote_index + 1) {
+        Some(b) if !is_space(*b) => Err(UNBALANCED_QUOTES),
+        _ => Ok(()),
+    }
+}
+
+fn parse_hex_escape(buf: &[u8]) -> Option<u8> {
+    match buf {
+        [b'x', high, low, ..] => {
+            let high = (*high as char).to_digit(16)?;
+            let low = (*low as char).to_digit(16)?;
+            Some((high * 16 + low) as u8)
+        }
+        _ => None,
+    }
+}
+
+pub fn parse_resp(buf: &[u8], limits: &ParseLimits) -> Result<(RespIndex, usize), ParseError> {
+    parse_nested_resp(buf, limits, 0)
+}
+
+fn parse_nested_resp(
+    buf: &[u8],
+    limits: &ParseLimits,
+    depth: usize,
+) -> Result<(RespIndex, usize), ParseError> {
     if buf.is_empty() {
         return Err(ParseError::NotEnoughData);
     }
@@ -45,30 +251,83 @@ pub fn parse_resp(buf: &[u8]) -> Result<(RespIndex, usize), ParseError> {
 
     match prefix {
         b'$' => {
-            let (mut v, consumed) = parse_bulk_str(next_buf)?;
+            let (mut v, consumed) = parse_bulk_str(next_buf, limits)?;
             v.advance(1);
             Ok((RespIndex::Bulk(v), 1 + consumed))
         }
         b'+' => {
-            let (mut v, consumed) = parse_line(next_buf)?;
+            let (mut v, consumed) = parse_line(next_buf, TOO_BIG_LINE)?;
             v.advance(1);
             Ok((RespIndex::Simple(v), 1 + consumed))
         }
         b':' => {
-            let (mut v, consumed) = parse_line(next_buf)?;
+            let (mut v, consumed) = parse_line(next_buf, TOO_BIG_LINE)?;
             v.advance(1);
             Ok((RespIndex::Integer(v), 1 + consumed))
         }
         b'-' => {
-            let (mut v, consumed) = parse_line(next_buf)?;
+            let (mut v, consumed) = parse_line(next_buf, TOO_BIG_LINE)?;
             v.advance(1);
             Ok((RespIndex::Error(v), 1 + consumed))
         }
         b'*' => {
-            let (mut v, consumed) = parse_array(next_buf)?;
+            let (mut v, consumed) = parse_array(next_buf, limits, depth)?;
             v.advance(1);
             Ok((RespIndex::Arr(v), 1 + consumed))
         }
+        b'_' => {
+            let (v, consumed) = parse_line(next_buf, TOO_BIG_LINE)?;
+            if v.0 != v.1 {
+                return Err(ParseError::InvalidProtocol);
+            }
+            Ok((RespIndex::Null, 1 + consumed))
+        }
+        b',' | b'#' | b'(' => {
+            let (mut v, consumed) = parse_line(next_buf, TOO_BIG_LINE)?;
+            v.advance(1);
+            let resp = match prefix {
+                b',' => RespIndex::Double(v),
+                b'#' => RespIndex::Boolean(v),
+                _ => RespIndex::BigNumber(v),
+            };
+            Ok((resp, 1 + consumed))
+        }
+        b'!' | b'=' => {
+            let (v, consumed) = parse_bulk_str(next_buf, limits)?;
+            let mut v = match v {
+                BulkStrIndex::Str(v) => v,
+                BulkStrIndex::Nil => return Err(ParseError::InvalidProtocol),
+            };
+            v.advance(1);
+            let resp = match prefix {
+                b'!' => RespIndex::BlobError(v),
+                _ => RespIndex::Verbatim(v),
+            };
+            Ok((resp, 1 + consumed))
+        }
+        b'%' | b'~' | b'>' => {
+            let (len, consumed) = parse_len(next_buf, TOO_BIG_MBULK_COUNT)?;
+            if len < 0 {
+                return Err(ParseError::InvalidProtocol);
+            }
+            // Map contains both the keys and values.
+            let element_num = if prefix == b'%' {
+                len.checked_mul(2).ok_or(ParseError::InvalidProtocol)?
+            } else {
+                len
+            } as usize;
+            let (mut elements, consumed) =
+                parse_elements(next_buf, consumed, element_num, limits, depth)?;
+            for element in elements.iter_mut() {
+                element.advance(1);
+            }
+            let resp = match prefix {
+                b'%' => RespIndex::Map(elements),
+                b'~' => RespIndex::Set(elements),
+                _ => RespIndex::Push(elements),
+            };
+            Ok((resp, 1 + consumed))
+        }
         prefix => {
             debug!("invalid prefix {:?}", prefix);
             Err(ParseError::InvalidProtocol)
@@ -76,35 +335,61 @@ pub fn parse_resp(buf: &[u8]) -> Result<(RespIndex, usize), ParseError> {
     }
 }
 
-fn parse_array(buf: &[u8]) -> Result<(ArrayIndex, usize), ParseError> {
-    let (len, mut consumed) = parse_len(buf)?;
+fn parse_array(
+    buf: &[u8],
+    limits: &ParseLimits,
+    depth: usize,
+) -> Result<(ArrayIndex, usize), ParseError> {
+    let (len, consumed) = parse_len(buf, TOO_BIG_MBULK_COUNT)?;
     if len < 0 {
         return Ok((ArrayIndex::Nil, consumed));
     }
 
-    let array_size = len as usize;
-    let mut array = Vec::with_capacity(array_size);
+    let (array, consumed) = parse_elements(buf, consumed, len as usize, limits, depth)?;
+    Ok((ArrayIndex::Arr(array), consumed))
+}
+
+fn parse_elements(
+    buf: &[u8],
+    mut consumed: usize,
+    element_num: usize,
+    limits: &ParseLimits,
+    depth: usize,
+) -> Result<(Vec<RespIndex>, usize), ParseError> {
+    if element_num > limits.max_array_len {
+        return Err(ParseError::ExceedLimit("invalid multibulk length"));
+    }
+    if depth >= limits.max_depth {
+        return Err(ParseError::ExceedLimit("too deeply nested multibulk"));
+    }
 
-    for _ in 0..array_size {
+    // The length is not trusted before the elements are received.
+    // Each element takes at least 3 bytes.
+    let mut array = Vec::with_capacity(element_num.min(buf.len() / 3));
+
+    for _ in 0..element_num {
         let next_buf = buf
             .get(consumed..)
             .ok_or_else(|| ParseError::InvalidProtocol)?;
-        let (mut v, element_consumed) = parse_resp(next_buf)?;
+        let (mut v, element_consumed) = parse_nested_resp(next_buf, limits, depth + 1)?;
         v.advance(consumed);
         consumed += element_consumed;
         array.push(v);
     }
 
-    Ok((ArrayIndex::Arr(array), consumed))
+    Ok((array, consumed))
 }
 
-fn parse_bulk_str(buf: &[u8]) -> Result<(BulkStrIndex, usize), ParseError> {
-    let (len, consumed) = parse_len(buf)?;
+fn parse_bulk_str(buf: &[u8], limits: &ParseLimits) -> Result<(BulkStrIndex, usize), ParseError> {
+    let (len, consumed) = parse_len(buf, TOO_BIG_BULK_COUNT)?;
     if len < 0 {
         return Ok((BulkStrIndex::Nil, consumed));
     }
 
     let content_size = len as usize;
+    if content_size > limits.max_bulk_len {
+        return Err(ParseError::ExceedLimit("invalid bulk length"));
+    }
     if buf.len() < consumed + content_size + 2 {
         return Err(ParseError::NotEnoughData);
     }
@@ -113,8 +398,8 @@ fn parse_bulk_str(buf: &[u8]) -> Result<(BulkStrIndex, usize), ParseError> {
     Ok((BulkStrIndex::Str(s), consumed + content_size + 2))
 }
 
-fn parse_len(buf: &[u8]) -> Result<(i64, usize), ParseError> {
-    let (data_index, consumed) = parse_line(buf)?;
+fn parse_len(buf: &[u8], reason: &'static str) -> Result<(i64, usize), ParseError> {
+    let (data_index, consumed) = parse_line(buf, reason)?;
     let next_buf = buf
         .get(data_index.to_range())
         .ok_or_else(|| ParseError::UnexpectedErr)?;
@@ -123,8 +408,18 @@ fn parse_len(buf: &[u8]) -> Result<(i64, usize), ParseError> {
     Ok((len, consumed))
 }
 
-fn parse_line(buf: &[u8]) -> Result<(DataIndex, usize), ParseError> {
-    let lf_index = memchr(LF, &buf).ok_or_else(|| ParseError::NotEnoughData)?;
+// The reason is returned when the line exceeds the limit.
+fn parse_line(buf: &[u8], reason: &'static str) -> Result<(DataIndex, usize), ParseError> {
+    let lf_index = match memchr(LF, &buf) {
+        Some(lf_index) => lf_index,
+        None if buf.len() > MAX_INLINE_COMMAND_LENGTH => {
+            return Err(ParseError::ExceedLimit(reason))
+        }
+        None => return Err(ParseError::NotEnoughData),
+    };
+    if lf_index > MAX_INLINE_COMMAND_LENGTH {
+        return Err(ParseError::ExceedLimit(reason));
+    }
     if lf_index == 0 {
         return Err(ParseError::InvalidProtocol);
     }
@@ -139,46 +434,60 @@ fn parse_line(buf: &[u8]) -> Result<(DataIndex, usize), ParseError> {
 mod tests {
     use super::*;
     use crate::protocol::resp::{ArraySlice, BulkStrSlice, RespSlice};
+    use matches::assert_matches;
 
     #[test]
     fn test_parse_len_bytes() {
-        let r = parse_len(b"233\r\n");
+        let r = parse_len(b"233\r\n", TOO_BIG_BULK_COUNT);
         assert!(r.is_ok());
         let (len, s) = r.expect("test_parse_len");
         assert_eq!(s, 5);
         assert_eq!(len, 233);
 
-        let r = parse_len(b"-233\r\n");
+        let r = parse_len(b"-233\r\n", TOO_BIG_BULK_COUNT);
         assert!(r.is_ok());
         let (len, s) = r.expect("test_parse_len");
         assert_eq!(s, 6);
         assert_eq!(len, -233);
 
-        let r = parse_len(b"2a3\r\n");
+        let r = parse_len(b"2a3\r\n", TOO_BIG_BULK_COUNT);
         assert!(r.is_err());
+
+        let data = vec![b'1'; MAX_INLINE_COMMAND_LENGTH + 1];
+        assert_matches!(
+            parse_len(&data, TOO_BIG_BULK_COUNT),
+            Err(ParseError::ExceedLimit(TOO_BIG_BULK_COUNT))
+        );
     }
 
     #[test]
     fn test_parse_line_bytes() {
         let data = b"233\r\n";
-        let r = parse_line(data);
+        let r = parse_line(data, TOO_BIG_LINE);
         assert!(r.is_ok());
         let (b, l) = r.expect("test_parse_line");
         assert_eq!(l, 5);
         assert_eq!(&data[b.to_range()], b"233");
 
         let data = b"\r\n";
-        let r = parse_line(data);
+        let r = parse_line(data, TOO_BIG_LINE);
         assert!(r.is_ok());
         let (b, l) = r.expect("test_parse_line");
         assert_eq!(l, 2);
         assert_eq!(&data[b.to_range()], b"".as_ref());
+
+        let mut data = vec![b'a'; MAX_INLINE_COMMAND_LENGTH];
+        data.extend_from_slice(b"\r\n");
+        assert_matches!(
+            parse_line(&data, TOO_BIG_LINE),
+            Err(ParseError::ExceedLimit(TOO_BIG_LINE))
+        );
     }
 
     #[test]
     fn test_parse_bulk_str_bytes() {
         let data = b"2\r\nab\r\n";
-        let r = parse_bulk_str(data);
+        let r = parse_bulk_str(data, &ParseLimits::default());
         assert!(r.is_ok());
         let (content, s) = r.expect("test_parse_bulk_str");
         assert_eq!(s, 7);
@@ -187,22 +496,22 @@ mod tests {
             content.try_to_range().map(|r| &data[r])
         );
 
-        let r = parse_bulk_str(b"-1\r\n");
+        let r = parse_bulk_str(b"-1\r\n", &ParseLimits::default());
         assert!(r.is_ok());
         let (content, s) = r.expect("test_parse_bulk_str");
         assert_eq!(s, 4);
         assert_eq!(BulkStrIndex::Nil, content);
 
-        let r = parse_bulk_str(b"2a3\r\nab\r\n");
+        let r = parse_bulk_str(b"2a3\r\nab\r\n", &ParseLimits::default());
         assert!(r.is_err());
 
-        let r = parse_bulk_str(b"0\r\n\r\n");
+        let r = parse_bulk_str(b"0\r\n\r\n", &ParseLimits::default());
         assert!(r.is_ok());
         let (content, s) = r.expect("test_parse_bulk_str");
         assert_eq!(s, 5);
         assert_eq!(Some(b"".as_ref()), content.try_to_range().map(|r| &data[r]));
 
-        let r = parse_bulk_str(b"1\r\na\r\n");
+        let r = parse_bulk_str(b"1\r\na\r\n", &ParseLimits::default());
         assert!(r.is_ok());
         let (content, s) = r.expect("test_parse_bulk_str");
         assert_eq!(s, 6);
@@ -211,18 +520,18 @@ mod tests {
             content.try_to_range().map(|r| &data[r])
         );
 
-        let r = parse_bulk_str(b"2\r\na\r\n");
+        let r = parse_bulk_str(b"2\r\na\r\n", &ParseLimits::default());
         assert!(r.is_err());
 
         // TODO: Support this check
-        // let r = parse_bulk_str("2\r\nabc\r\n".as_bytes());
+        // let r = parse_bulk_str("2\r\nabc\r\n".as_bytes(), &ParseLimits::default());
         // assert!(r.is_err());
     }
 
     #[test]
     fn test_parse_array_bytes() {
         let data = b"2\r\n$1\r\na\r\n$2\r\nbc\r\n";
-        let r = parse_array(data);
+        let r = parse_array(data, &ParseLimits::default(), 0);
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_array");
         assert_eq!(s, 18);
@@ -235,63 +544,63 @@ mod tests {
             arr
         );
 
-        let r = parse_array(b"-1\r\n");
+        let r = parse_array(b"-1\r\n", &ParseLimits::default(), 0);
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_array");
         assert_eq!(s, 4);
         assert_eq!(ArrayIndex::Nil, a);
 
-        let r = parse_array(b"0\r\n");
+        let r = parse_array(b"0\r\n", &ParseLimits::default(), 0);
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_array");
         assert_eq!(s, 3);
         assert_eq!(ArrayIndex::Arr(vec![]), a);
 
-        let r = parse_array(b"1\r\n$2\r\na\r\n");
+        let r = parse_array(b"1\r\n$2\r\na\r\n", &ParseLimits::default(), 0);
         assert!(r.is_err());
 
         // TODO: Support this check
-        // let r = parse_array("1\r\n$2\r\nabc\r\n".as_bytes());
+        // let r = parse_array("1\r\n$2\r\nabc\r\n".as_bytes(), &ParseLimits::default(), 0);
         // assert!(r.is_err());
     }
 
     #[test]
     fn test_parse_resp_bytes() {
-        let r = parse_resp(b"*-1\r\n");
+        let r = parse_resp(b"*-1\r\n", &ParseLimits::default());
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_resp");
         assert_eq!(s, 5);
         assert_eq!(RespIndex::Arr(ArrayIndex::Nil), a);
 
-        let r = parse_resp(b"*0\r\n");
+        let r = parse_resp(b"*0\r\n", &ParseLimits::default());
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_resp");
         assert_eq!(s, 4);
         assert_eq!(RespIndex::Arr(ArrayIndex::Arr(vec![])), a);
 
         let data = b"-abc\r\n";
-        let r = parse_resp(data);
+        let r = parse_resp(data, &ParseLimits::default());
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_resp");
         assert_eq!(s, 6);
         assert_eq!(RespSlice::Error(b"abc"), a.map_to_slice(data));
 
         let data = b":233\r\n";
-        let r = parse_resp(data);
+        let r = parse_resp(data, &ParseLimits::default());
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_resp");
         assert_eq!(s, 6);
         assert_eq!(RespSlice::Integer(b"233"), a.map_to_slice(data));
 
         let data = b"+233\r\n";
-        let r = parse_resp(data);
+        let r = parse_resp(data, &ParseLimits::default());
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_resp");
         assert_eq!(s, 6);
         assert_eq!(RespSlice::Simple(b"233"), a.map_to_slice(data));
 
         let data = b"$3\r\nfoo\r\n";
-        let r = parse_resp(data);
+        let r = parse_resp(data, &ParseLimits::default());
         assert!(r.is_ok());
         let (a, s) = r.expect("test_parse_resp");
         assert_eq!(s, 9);
@@ -300,4 +609,285 @@ mod tests {
             a.map_to_slice(data),
         );
     }
+
+    #[test]
+    fn test_parse_resp3_simple_types() {
+        let r = parse_resp(b"_\r\n", &ParseLimits::default());
+        let (a, s) = r.expect("test_parse_resp3_simple_types");
+        assert_eq!(s, 3);
+        assert_eq!(RespIndex::Null, a);
+
+        assert!(parse_resp(b"_a\r\n", &ParseLimits::default()).is_err());
+
+        let data = b",3.14\r\n";
+        let (a, s) =
+            parse_resp(data, &ParseLimits::default()).expect("test_parse_resp3_simple_types");
+        assert_eq!(s, 7);
+        assert_eq!(RespSlice::Double(b"3.14"), a.map_to_slice(data));
+
+        let data = b"#t\r\n";
+        let (a, s) =
+            parse_resp(data, &ParseLimits::default()).expect("test_parse_resp3_simple_types");
+        assert_eq!(s, 4);
+        assert_eq!(RespSlice::Boolean(b"t"), a.map_to_slice(data));
+
+        let data = b"(3492890328409238509324850943850943825024385\r\n";
+        let (a, s) =
+            parse_resp(data, &ParseLimits::default()).expect("test_parse_resp3_simple_types");
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::BigNumber(b"3492890328409238509324850943850943825024385"),
+            a.map_to_slice(data)
+        );
+
+        let data = b"!10\r\nSYNTAX err\r\n";
+        let (a, s) =
+            parse_resp(data, &ParseLimits::default()).expect("test_parse_resp3_simple_types");
+        assert_eq!(s, data.len());
+        assert_eq!(RespSlice::BlobError(b"SYNTAX err"), a.map_to_slice(data));
+
+        let data = b"=15\r\ntxt:Some string\r\n";
+        let (a, s) =
+            parse_resp(data, &ParseLimits::default()).expect("test_parse_resp3_simple_types");
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::Verbatim(b"txt:Some string"),
+            a.map_to_slice(data)
+        );
+
+        assert!(parse_resp(b"=-1\r\n", &ParseLimits::default()).is_err());
+    }
+
+    #[test]
+    fn test_parse_resp3_aggregate_types() {
+        let data = b"%2\r\n+first\r\n:1\r\n+second\r\n#f\r\n";
+        let (a, s) =
+            parse_resp(data, &ParseLimits::default()).expect("test_parse_resp3_aggregate_types");
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::Map(vec![
+                RespSlice::Simple(b"first"),
+                RespSlice::Integer(b"1"),
+                RespSlice::Simple(b"second"),
+                RespSlice::Boolean(b"f"),
+            ]),
+            a.map_to_slice(data)
+        );
+
+        let data = b"~2\r\n$1\r\na\r\n_\r\n";
+        let (a, s) =
+            parse_resp(data, &ParseLimits::default()).expect("test_parse_resp3_aggregate_types");
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::Set(vec![
+                RespSlice::Bulk(BulkStrSlice::Str(b"a")),
+                RespSlice::Null
+            ]),
+            a.map_to_slice(data)
+        );
+
+        let data = b">2\r\n+message\r\n*1\r\n,1.5\r\n";
+        let (a, s) =
+            parse_resp(data, &ParseLimits::default()).expect("test_parse_resp3_aggregate_types");
+        assert_eq!(s, data.len());
+        assert_eq!(
+            RespSlice::Push(vec![
+                RespSlice::Simple(b"message"),
+                RespSlice::Arr(ArraySlice::Arr(vec![RespSlice::Double(b"1.5")])),
+            ]),
+            a.map_to_slice(data)
+        );
+
+        assert_matches!(
+            parse_resp(b"%1\r\n+key\r\n", &ParseLimits::default()),
+            Err(ParseError::NotEnoughData)
+        );
+        assert_matches!(
+            parse_resp(b"%9223372036854775807\r\n", &ParseLimits::default()),
+            Err(ParseError::InvalidProtocol)
+        );
+    }
+
+    fn inline_limits() -> ParseLimits {
+        ParseLimits {
+            inline_commands: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_inline_command() {
+        let mut buf = BytesMut::from(b"GET  somekey\r\nPING\n".as_ref());
+        let resp =
+            parse_indexed_resp(&mut buf, &inline_limits()).expect("test_parse_inline_command");
+        assert_eq!(resp.get_array_len(), Some(2));
+        assert_eq!(resp.get_array_element(0), Some(b"GET".as_ref()));
+        assert_eq!(resp.get_array_element(1), Some(b"somekey".as_ref()));
+        assert_eq!(resp.get_data(), b"*2\r\n$3\r\nGET\r\n$7\r\nsomekey\r\n");
+
+        let resp =
+            parse_indexed_resp(&mut buf, &inline_limits()).expect("test_parse_inline_command");
+        assert_eq!(resp.get_array_len(), Some(1));
+        assert_eq!(resp.get_array_element(0), Some(b"PING".as_ref()));
+        assert!(buf.is_empty());
+
+        let mut buf = BytesMut::from(b"\r\n\r\n*1\r\n$4\r\nPING\r\n".as_ref());
+        let resp =
+            parse_indexed_resp(&mut buf, &inline_limits()).expect("test_parse_inline_command");
+        assert_eq!(resp.get_array_element(0), Some(b"PING".as_ref()));
+        assert!(buf.is_empty());
+
+        let mut buf = BytesMut::from(b"GET some".as_ref());
+        assert_matches!(
+            parse_indexed_resp(&mut buf, &inline_limits()),
+            Err(ParseError::NotEnoughData)
+        );
+        assert_eq!(buf.len(), 8);
+
+        let mut buf = BytesMut::from(vec![b'a'; MAX_INLINE_COMMAND_LENGTH + 1].as_slice());
+        assert_matches!(
+            parse_indexed_resp(&mut buf, &inline_limits()),
+            Err(ParseError::ExceedLimit(_))
+        );
+
+        // The replies from the backend could not be inline.
+        for data in &[
+            b"GET somekey\r\n".as_ref(),
+            b"|1\r\n+key\r\n+value\r\n+OK\r\n",
+        ] {
+            let mut buf = BytesMut::from(*data);
+            assert_matches!(
+                parse_indexed_resp(&mut buf, &ParseLimits::default()),
+                Err(ParseError::InvalidProtocol)
+            );
+        }
+    }
+
+    fn split(line: &[u8]) -> Vec<Vec<u8>> {
+        split_inline_args(line).expect("split_inline_args")
+    }
+
+    #[test]
+    fn test_split_quoted_inline_args() {
+        assert_eq!(
+            split(b"SET \"a b\" 'c d'"),
+            vec![b"SET".to_vec(), b"a b".to_vec(), b"c d".to_vec()]
+        );
+        assert_eq!(
+            split(b"SET k \"\""),
+            vec![b"SET".to_vec(), b"k".to_vec(), vec![]]
+        );
+        assert_eq!(
+            split(b"SET k \"\\x00\\xfF\\n\\r\\t\\b\\a\\\"\\\\\\q\""),
+            vec![
+                b"SET".to_vec(),
+                b"k".to_vec(),
+                b"\x00\xff\n\r\t\x08\x07\"\\q".to_vec()
+            ]
+        );
+        // Invalid hex escapes are kept as the plain characters.
+        assert_eq!(split(b"\"\\xzz\""), vec![b"xzz".to_vec()]);
+        assert_eq!(
+            split(b"'it\\'s' '\\n'"),
+            vec![b"it's".to_vec(), b"\\n".to_vec()]
+        );
+        // The quotes could start in the middle of an argument.
+        assert_eq!(split(b"a\"b c\" d"), vec![b"ab c".to_vec(), b"d".to_vec()]);
+    }
+
+    #[test]
+    fn test_split_unbalanced_quotes() {
+        for line in &[
+            b"GET \"k".as_ref(),
+            b"GET 'k",
+            b"GET \"k\\\"",
+            b"GET 'k\\'",
+            b"GET \"k\"v",
+            b"GET 'k'v",
+        ] {
+            assert_matches!(
+                split_inline_args(line),
+                Err(ParseError::InvalidRequest("unbalanced quotes in request"))
+            );
+        }
+
+        let mut buf = BytesMut::from(b"SET \"a b\" c\r\nGET \"a\r\n".as_ref());
+        let resp =
+            parse_indexed_resp(&mut buf, &inline_limits()).expect("test_split_unbalanced_quotes");
+        assert_eq!(resp.get_array_element(1), Some(b"a b".as_ref()));
+        assert_matches!(
+            parse_indexed_resp(&mut buf, &inline_limits()),
+            Err(ParseError::InvalidRequest(_))
+        );
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = ParseLimits {
+            max_bulk_len: 3,
+            max_array_len: 2,
+            max_depth: 2,
+            inline_commands: true,
+        };
+
+        let data = b"*2\r\n$3\r\nabc\r\n*1\r\n:1\r\n";
+        let (_, s) = parse_resp(data, &limits).expect("test_parse_limits");
+        assert_eq!(s, data.len());
+
+        // Should fail before the whole bulk string is received.
+        assert_matches!(
+            parse_resp(b"$4\r\nab", &limits),
+            Err(ParseError::ExceedLimit(_))
+        );
+        assert_matches!(
+            parse_resp(b"*3\r\n", &limits),
+            Err(ParseError::ExceedLimit(_))
+        );
+        assert_matches!(
+            parse_resp(b"%2\r\n", &limits),
+            Err(ParseError::ExceedLimit(_))
+        );
+        assert_matches!(
+            parse_resp(b"*1\r\n*1\r\n*1\r\n", &limits),
+            Err(ParseError::ExceedLimit(_))
+        );
+        // The headers could not be too long even without the line feed.
+        let mut data = b"*".to_vec();
+        data.extend(vec![b'0'; MAX_INLINE_COMMAND_LENGTH + 1]);
+        assert_matches!(
+            parse_resp(&data, &limits),
+            Err(ParseError::ExceedLimit(TOO_BIG_MBULK_COUNT))
+        );
+        let mut data = b"+".to_vec();
+        data.extend(vec![b'a'; MAX_INLINE_COMMAND_LENGTH + 1]);
+        assert_matches!(
+            parse_resp(&data, &limits),
+            Err(ParseError::ExceedLimit(TOO_BIG_LINE))
+        );
+
+        let mut buf = BytesMut::from(b"GET abcd\r\n".as_ref());
+        assert_matches!(
+            parse_indexed_resp(&mut buf, &limits),
+            Err(ParseError::ExceedLimit(_))
+        );
+    }
+
+    #[test]
+    fn test_parse_default_limits() {
+        let limits = ParseLimits::default();
+        // Should not allocate for the elements not received yet.
+        assert_matches!(
+            parse_resp(b"*9223372036854775807\r\n:1\r\n", &limits),
+            Err(ParseError::NotEnoughData)
+        );
+        let nested = b"*1\r\n".repeat(DEFAULT_MAX_DEPTH + 1);
+        assert_matches!(
+            parse_resp(&nested, &limits),
+            Err(ParseError::ExceedLimit(_))
+        );
+        let mut nested = b"*1\r\n".repeat(DEFAULT_MAX_DEPTH);
+        nested.extend_from_slice(b":1\r\n");
+        let (_, s) = parse_resp(&nested, &limits).expect("test_parse_default_limits");
+        assert_eq!(s, nested.len());
+    }
 }
@@ -0,0 +1,287 @@
+use native_tls::{Certificate, Identity};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_tls::{TlsAcceptor, TlsConnector, TlsStream};
+
+#[derive(Debug)]
+pub enum MaybeTlsStream {
+    Plain(TcpStream),
+    Tls(TlsStream<TcpStream>),
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(sock) => Pin::new(sock).poll_read(cx, buf),
+            MaybeTlsStream::Tls(sock) => Pin::new(sock).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(sock) => Pin::new(sock).poll_write(cx, buf),
+            MaybeTlsStream::Tls(sock) => Pin::new(sock).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(sock) => Pin::new(sock).poll_flush(cx),
+            MaybeTlsStream::Tls(sock) => Pin::new(sock).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(sock) => Pin::new(sock).poll_shutdown(cx),
+            MaybeTlsStream::Tls(sock) => Pin::new(sock).poll_shutdown(cx),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    Io(io::Error),
+    Tls(native_tls::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for TlsError {
+    fn description(&self) -> &str {
+        "tls error"
+    }
+
+    fn cause(&self) -> Option<&dyn Error> {
+        match self {
+            TlsError::Io(err) => Some(err),
+            TlsError::Tls(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for TlsError {
+    fn from(err: io::Error) -> Self {
+        TlsError::Io(err)
+    }
+}
+
+impl From<native_tls::Error> for TlsError {
+    fn from(err: native_tls::Error) -> Self {
+        TlsError::Tls(err)
+    }
+}
+
+fn tls_to_io_error(err: native_tls::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+// Used for accepting the connections from the clients.
+#[derive(Debug, Clone)]
+pub struct TlsServerConfig {
+    acceptor: TlsAcceptor,
+}
+
+impl TlsServerConfig {
+    // The key should be in PKCS #8 PEM format.
+    pub fn from_pem(cert: &[u8], key: &[u8]) -> Result<Self, TlsError> {
+        let identity = Identity::from_pkcs8(cert, key)?;
+        let acceptor = native_tls::TlsAcceptor::new(identity)?;
+        Ok(Self {
+            acceptor: acceptor.into(),
+        })
+    }
+
+    pub fn from_pem_files(cert_file: &str, key_file: &str) -> Result<Self, TlsError> {
+        let cert = fs::read(cert_file)?;
+        let key = fs::read(key_file)?;
+        Self::from_pem(&cert, &key)
+    }
+
+    pub async fn accept(&self, sock: TcpStream) -> io::Result<MaybeTlsStream> {
+        let sock = self.acceptor.accept(sock).await.map_err(tls_to_io_error)?;
+        Ok(MaybeTlsStream::Tls(sock))
+    }
+}
+
+// Used for connecting to the redis nodes and the peer server proxies.
+#[derive(Debug, Clone)]
+pub struct TlsClientConfig {
+    connector: TlsConnector,
+}
+
+impl TlsClientConfig {
+    pub fn new(ca_cert: Option<&[u8]>, skip_verify: bool) -> Result<Self, TlsError> {
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(ca_cert) = ca_cert {
+            builder.add_root_certificate(Certificate::from_pem(ca_cert)?);
+        }
+        if skip_verify {
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        let connector = builder.build()?;
+        Ok(Self {
+            connector: connector.into(),
+        })
+    }
+
+    // Empty `ca_file` means only the system root certificates are trusted.
+    pub fn from_ca_file(ca_file: &str, skip_verify: bool) -> Result<Self, TlsError> {
+        if ca_file.is_empty() {
+            return Self::new(None, skip_verify);
+        }
+        let ca_cert = fs::read(ca_file)?;
+        Self::new(Some(&ca_cert), skip_verify)
+    }
+
+    // `address` is in the format of `<host>:<port>` and the host is used to verify the certificate.
+    pub async fn connect(&self, address: &str, sock: TcpStream) -> io::Result<MaybeTlsStream> {
+        let sock = self
+            .connector
+            .connect(get_host(address), sock)
+            .await
+            .map_err(tls_to_io_error)?;
+        Ok(MaybeTlsStream::Tls(sock))
+    }
+}
+
+fn get_host(address: &str) -> &str {
+    let host = match address.rfind(':') {
+        Some(i) => address.get(..i).unwrap_or(address),
+        None => address,
+    };
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+pub async fn connect_stream(
+    address: &str,
+    sock_address: SocketAddr,
+    tls: Option<&TlsClientConfig>,
+) -> io::Result<MaybeTlsStream> {
+    let sock = TcpStream::connect(sock_address).await?;
+    match tls {
+        Some(tls) => tls.connect(address, sock).await,
+        None => Ok(MaybeTlsStream::Plain(sock)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::extension::SubjectAlternativeName;
+    use openssl::x509::{X509NameBuilder, X509};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn gen_self_signed_cert() -> (Vec<u8>, Vec<u8>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let san = SubjectAlternativeName::new()
+            .dns("localhost")
+            .ip("127.0.0.1")
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+        let cert = builder.build().to_pem().unwrap();
+        let key = key.private_key_to_pem_pkcs8().unwrap();
+        (cert, key)
+    }
+
+    async fn run_echo(
+        server_config: TlsServerConfig,
+        client_config: TlsClientConfig,
+        address: &'static str,
+    ) -> io::Result<Vec<u8>> {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sock_address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (sock, _) = listener.accept().await.unwrap();
+            if let Ok(mut sock) = server_config.accept(sock).await {
+                let mut buf = [0; 64];
+                let n = sock.read(&mut buf).await.unwrap();
+                sock.write_all(&buf[..n]).await.unwrap();
+            }
+        });
+
+        let mut sock = connect_stream(address, sock_address, Some(&client_config)).await?;
+        sock.write_all(b"+PING\r\n").await?;
+        let mut buf = [0; 64];
+        let n = sock.read(&mut buf).await?;
+        Ok(buf[..n].to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_tls_stream() {
+        let (cert, key) = gen_self_signed_cert();
+        let server_config = TlsServerConfig::from_pem(&cert, &key).unwrap();
+
+        let client_config = TlsClientConfig::new(Some(&cert), false).unwrap();
+        let reply = run_echo(server_config.clone(), client_config, "localhost:6379").await;
+        assert_eq!(reply.unwrap(), b"+PING\r\n".to_vec());
+
+        let client_config = TlsClientConfig::new(Some(&cert), false).unwrap();
+        let reply = run_echo(server_config.clone(), client_config, "127.0.0.1:6379").await;
+        assert_eq!(reply.unwrap(), b"+PING\r\n".to_vec());
+
+        // The self-signed certificate is not trusted.
+        let client_config = TlsClientConfig::new(None, false).unwrap();
+        let reply = run_echo(server_config.clone(), client_config, "localhost:6379").await;
+        assert!(reply.is_err());
+
+        let client_config = TlsClientConfig::new(None, true).unwrap();
+        let reply = run_echo(server_config, client_config, "localhost:6379").await;
+        assert_eq!(reply.unwrap(), b"+PING\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_get_host() {
+        assert_eq!(get_host("localhost:6379"), "localhost");
+        assert_eq!(get_host("127.0.0.1:6379"), "127.0.0.1");
+        assert_eq!(get_host("[::1]:6379"), "::1");
+        assert_eq!(get_host("localhost"), "localhost");
+    }
+}
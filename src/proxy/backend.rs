@@ -5,8 +5,9 @@ use crate::common::batch::TryChunksTimeoutStreamExt;
 use crate::common::track::TrackedFutureRegistry;
 use crate::common::utils::{gen_moved, get_slot, resolve_first_address, ThreadSafe};
 use crate::protocol::{
-    new_simple_packet_codec, DecodeError, EncodeError, EncodedPacket, FromResp, MonoPacket,
-    OptionalMulti, Packet, Resp, RespCodec, RespVec,
+    auth_conn, connect_stream, hello_conn, new_simple_packet_codec, Credential, DecodeError,
+    EncodeError, EncodedPacket, FromResp, MonoPacket, OptionalMulti, Packet, RedisClientError,
+    Resp, RespCodec, RespVec, TlsClientConfig,
 };
 use futures::channel::mpsc;
 use futures::{select, stream, Future, FutureExt, Sink, SinkExt, Stream, StreamExt, TryStreamExt};
@@ -25,7 +26,6 @@ use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use tokio;
-use tokio::net::TcpStream;
 use tokio_util::codec::Decoder;
 
 pub type BackendResult<T> = Result<T, BackendError>;
@@ -290,11 +290,13 @@ impl<H: CmdTaskResultHandler> BackendNode<H> {
     {
         let (tx, rx) = mpsc::unbounded();
         let conn_failed = Arc::new(AtomicBool::new(true));
+        let credential = config.backend_credentials.get(&address).cloned();
         let handle_backend_fut = handle_backend(
             handler,
             rx,
             conn_failed.clone(),
             address,
+            credential,
             config.backend_batch_min_time,
             config.backend_batch_max_time,
             config.backend_batch_buf,
@@ -328,15 +330,36 @@ pub trait ConnFactory: ThreadSafe {
 
     fn create_conn(
         &self,
-        addr: SocketAddr,
+        address: String,
+        sock_address: SocketAddr,
+        credential: Option<Credential>,
     ) -> Pin<Box<dyn Future<Output = CreateConnResult<Self::Pkt>> + Send>>;
 }
 
-pub struct DefaultConnFactory<P>(PhantomData<P>);
+pub struct DefaultConnFactory<P> {
+    tls: Option<TlsClientConfig>,
+    // Switches the connections to RESP3 by HELLO.
+    resp3: bool,
+    phantom: PhantomData<P>,
+}
+
+impl<P> DefaultConnFactory<P> {
+    pub fn new(tls: Option<TlsClientConfig>) -> Self {
+        Self {
+            tls,
+            resp3: false,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn with_resp3(self, resp3: bool) -> Self {
+        Self { resp3, ..self }
+    }
+}
 
 impl<P> Default for DefaultConnFactory<P> {
     fn default() -> Self {
-        Self(PhantomData)
+        Self::new(None)
     }
 }
 
@@ -345,17 +368,31 @@ impl<P: MonoPacket> ConnFactory for DefaultConnFactory<P> {
 
     fn create_conn(
         &self,
-        addr: SocketAddr,
+        address: String,
+        sock_address: SocketAddr,
+        credential: Option<Credential>,
     ) -> Pin<Box<dyn Future<Output = CreateConnResult<Self::Pkt>> + Send>> {
-        Box::pin(create_conn(addr))
+        Box::pin(create_conn(
+            address,
+            sock_address,
+            credential,
+            self.tls.clone(),
+            self.resp3,
+        ))
     }
 }
 
-async fn create_conn<T>(address: SocketAddr) -> CreateConnResult<T>
+async fn create_conn<T>(
+    address: String,
+    sock_address: SocketAddr,
+    credential: Option<Credential>,
+    tls: Option<TlsClientConfig>,
+    resp3: bool,
+) -> CreateConnResult<T>
 where
     T: MonoPacket,
 {
-    let socket = match TcpStream::connect(address).await {
+    let socket = match connect_stream(&address, sock_address, tls.as_ref()).await {
         Ok(socket) => socket,
         Err(err) => {
             error!("failed to connect: {:?}", err);
@@ -363,6 +400,17 @@ where
         }
     };
 
+    let res = match credential {
+        _ if resp3 => hello_conn(socket, credential.as_ref()).await,
+        Some(credential) => auth_conn(socket, &credential).await,
+        None => Ok(socket),
+    };
+    let socket = res.map_err(|err| match err {
+        RedisClientError::Io(err) => BackendError::Io(err),
+        RedisClientError::AuthFailed => BackendError::AuthFailed,
+        _ => BackendError::InvalidProtocol,
+    })?;
+
     let (encoder, decoder) = new_simple_packet_codec::<T, T>();
 
     let frame = RespCodec::new(encoder, decoder).framed(socket);
@@ -376,6 +424,14 @@ where
             error!("backend: invalid protocol");
             BackendError::InvalidProtocol
         }
+        DecodeError::ExceedLimit(reason) => {
+            error!("backend: exceed protocol limit: {}", reason);
+            BackendError::InvalidProtocol
+        }
+        DecodeError::InvalidRequest(reason) => {
+            error!("backend: invalid reply: {}", reason);
+            BackendError::InvalidProtocol
+        }
         DecodeError::Io(e) => {
             error!("backend: io error: {:?}", e);
             BackendError::Io(e)
@@ -398,6 +454,7 @@ pub async fn handle_backend<H, F>(
     task_receiver: mpsc::UnboundedReceiver<H::Task>,
     conn_failed: Arc<AtomicBool>,
     address: String,
+    credential: Option<Credential>,
     backend_batch_min_time: usize,
     backend_batch_max_time: usize,
     backend_batch_buf: NonZeroUsize,
@@ -426,7 +483,10 @@ where
 
     loop {
         conn_failed.store(true, Ordering::SeqCst);
-        let (writer, reader) = match conn_factory.create_conn(sock_address).await {
+        let (writer, reader) = match conn_factory
+            .create_conn(address.clone(), sock_address, credential.clone())
+            .await
+        {
             Ok(conn) => conn,
             Err(err) => {
                 error!("failed to connect: {:?}", err);
@@ -580,6 +640,7 @@ pub enum BackendError {
     NodeNotFound,
     InvalidProtocol,
     InvalidAddress,
+    AuthFailed,
     Canceled,
     InvalidState,
 }
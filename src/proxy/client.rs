@@ -0,0 +1,346 @@
+use super::session::ClientState;
+use crate::common::cluster::DBName;
+use btoi::btou;
+use futures::channel::oneshot;
+use std::collections::{BTreeMap, HashMap};
+use std::str;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+struct ClientEntry {
+    db: Arc<RwLock<DBName>>,
+    client: Arc<RwLock<ClientState>>,
+    // The session stops once this gets triggered.
+    kill_sender: oneshot::Sender<()>,
+}
+
+impl ClientEntry {
+    fn get_db_name(&self) -> DBName {
+        self.db.read().expect("ClientEntry::get_db_name").clone()
+    }
+}
+
+// All the sessions of the server proxy for CLIENT LIST and CLIENT KILL.
+#[derive(Default)]
+pub struct ClientRegistry {
+    clients: RwLock<BTreeMap<usize, ClientEntry>>,
+    // Rejected by `max_clients` of the server proxy.
+    rejected_connections: AtomicU64,
+    // Rejected by `max_clients` of the clusters on AUTH.
+    rejected_cluster_clients: AtomicU64,
+    // Closed by `client_timeout`.
+    timed_out_connections: AtomicU64,
+}
+
+impl ClientRegistry {
+    // Returns the receiver which gets notified when the client is killed.
+    pub fn register(
+        &self,
+        db: Arc<RwLock<DBName>>,
+        client: Arc<RwLock<ClientState>>,
+    ) -> oneshot::Receiver<()> {
+        let (kill_sender, kill_receiver) = oneshot::channel();
+        let id = client.read().expect("ClientRegistry::register").get_id();
+        let entry = ClientEntry {
+            db,
+            client,
+            kill_sender,
+        };
+        self.clients
+            .write()
+            .expect("ClientRegistry::register")
+            .insert(id, entry);
+        kill_receiver
+    }
+
+    pub fn unregister(&self, id: usize) {
+        self.clients
+            .write()
+            .expect("ClientRegistry::unregister")
+            .remove(&id);
+    }
+
+    pub fn get_client_num(&self) -> usize {
+        self.clients
+            .read()
+            .expect("ClientRegistry::get_client_num")
+            .len()
+    }
+
+    pub fn get_db_client_num(&self, db_name: &DBName) -> usize {
+        self.clients
+            .read()
+            .expect("ClientRegistry::get_db_client_num")
+            .values()
+            .filter(|entry| entry.get_db_name() == *db_name)
+            .count()
+    }
+
+    pub fn on_connection_rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn on_cluster_client_rejected(&self) {
+        self.rejected_cluster_clients
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn on_connection_timeout(&self) {
+        self.timed_out_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn info(&self) -> String {
+        let mut db_clients: HashMap<DBName, usize> = HashMap::new();
+        let client_num = {
+            let clients = self.clients.read().expect("ClientRegistry::info");
+            for entry in clients.values() {
+                *db_clients.entry(entry.get_db_name()).or_default() += 1;
+            }
+            clients.len()
+        };
+        let mut db_lines: Vec<String> = db_clients
+            .into_iter()
+            .map(|(db_name, num)| format!("{}:clients={}", db_name, num))
+            .collect();
+        db_lines.sort();
+
+        let mut lines = vec![
+            format!("connected_clients:{}", client_num),
+            format!(
+                "rejected_connections:{}",
+                self.rejected_connections.load(Ordering::Relaxed)
+            ),
+            format!(
+                "rejected_cluster_clients:{}",
+                self.rejected_cluster_clients.load(Ordering::Relaxed)
+            ),
+            format!(
+                "timed_out_connections:{}",
+                self.timed_out_connections.load(Ordering::Relaxed)
+            ),
+        ];
+        lines.extend(db_lines);
+        lines.join("\r\n")
+    }
+
+    pub fn get_client_list(&self, filter: &ClientFilter) -> Vec<String> {
+        self.clients
+            .read()
+            .expect("ClientRegistry::get_client_list")
+            .values()
+            .filter_map(|entry| {
+                let db_name = entry.get_db_name();
+                let client = entry
+                    .client
+                    .read()
+                    .expect("ClientRegistry::get_client_list");
+                if filter.matches(&db_name, &client) {
+                    Some(format_client_info(&db_name, &client))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // Returns the number of the killed clients.
+    pub fn kill(&self, filter: &ClientFilter) -> usize {
+        let mut clients = self.clients.write().expect("ClientRegistry::kill");
+        let ids: Vec<usize> = clients
+            .iter()
+            .filter(|(_, entry)| {
+                let db_name = entry.get_db_name();
+                let client = entry.client.read().expect("ClientRegistry::kill");
+                filter.matches(&db_name, &client)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids.iter() {
+            if let Some(entry) = clients.remove(id) {
+                // The session could have already been closed.
+                let _ = entry.kill_sender.send(());
+            }
+        }
+        ids.len()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ClientFilter {
+    pub id: Option<usize>,
+    pub address: Option<String>,
+    pub db: Option<DBName>,
+    // SKIPME
+    pub skip_id: Option<usize>,
+}
+
+impl ClientFilter {
+    // Parses the `<filter> <value>` pairs of CLIENT KILL.
+    // `current_id` is skipped unless `SKIPME no` is specified.
+    pub fn parse(args: &[&[u8]], current_id: Option<usize>) -> Result<Self, String> {
+        if args.len() & 1 != 0 {
+            return Err("ERR syntax error".to_string());
+        }
+        let mut filter = ClientFilter {
+            skip_id: current_id,
+            ..Default::default()
+        };
+        for pair in args.chunks(2) {
+            let (name, value) = (pair[0], pair[1]);
+            let value_str = str::from_utf8(value).map_err(|_| "ERR syntax error".to_string())?;
+            match name.to_ascii_uppercase().as_slice() {
+                b"ID" => {
+                    let id = btou::<usize>(value)
+                        .map_err(|_| "ERR client-id should be greater than 0".to_string())?;
+                    filter.id = Some(id);
+                }
+                b"ADDR" => filter.address = Some(value_str.to_string()),
+                b"DB" => {
+                    let db = DBName::from(value_str)
+                        .map_err(|_| format!("ERR invalid database name {}", value_str))?;
+                    filter.db = Some(db);
+                }
+                b"SKIPME" => match value_str.to_ascii_lowercase().as_str() {
+                    "yes" => filter.skip_id = current_id,
+                    "no" => filter.skip_id = None,
+                    _ => return Err("ERR syntax error".to_string()),
+                },
+                _ => return Err("ERR syntax error".to_string()),
+            }
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, db_name: &DBName, client: &ClientState) -> bool {
+        let id = client.get_id();
+        self.skip_id != Some(id)
+            && self.id.iter().all(|filter_id| *filter_id == id)
+            && self
+                .address
+                .iter()
+                .all(|address| address == client.get_address())
+            && self.db.iter().all(|db| db == db_name)
+    }
+}
+
+pub fn format_client_info(db_name: &DBName, client: &ClientState) -> String {
+    let name = client
+        .get_name()
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .unwrap_or_default();
+    format!(
+        "id={} addr={} name={} db={} age={} idle={} tot-cmds={} pending-replies={}",
+        client.get_id(),
+        client.get_address(),
+        name,
+        db_name,
+        client.get_age().as_secs(),
+        client.get_idle().as_secs(),
+        client.get_cmd_count(),
+        client.get_pending_replies(),
+    )
+}
+
+// Like redis, the client names can't contain spaces, newlines or special characters.
+pub fn is_valid_client_name(name: &[u8]) -> bool {
+    name.iter().all(|b| (b'!'..=b'~').contains(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_client(
+        id: usize,
+        address: &str,
+        db: &str,
+    ) -> (Arc<RwLock<DBName>>, Arc<RwLock<ClientState>>) {
+        let db = Arc::new(RwLock::new(DBName::from(db).unwrap()));
+        let client = Arc::new(RwLock::new(ClientState::new(id, address.to_string())));
+        (db, client)
+    }
+
+    #[test]
+    fn test_parse_client_filter() {
+        let args: Vec<&[u8]> = vec![b"id", b"3", b"DB", b"mydb", b"skipme", b"no"];
+        let filter = ClientFilter::parse(&args, Some(1)).unwrap();
+        assert_eq!(filter.id, Some(3));
+        assert_eq!(filter.db, Some(DBName::from("mydb").unwrap()));
+        assert_eq!(filter.skip_id, None);
+
+        let args: Vec<&[u8]> = vec![b"addr", b"127.0.0.1:6000"];
+        let filter = ClientFilter::parse(&args, Some(1)).unwrap();
+        assert_eq!(filter.address.as_deref(), Some("127.0.0.1:6000"));
+        assert_eq!(filter.skip_id, Some(1));
+
+        let args: Vec<&[u8]> = vec![b"id"];
+        assert!(ClientFilter::parse(&args, None).is_err());
+        let args: Vec<&[u8]> = vec![b"id", b"invalid"];
+        assert!(ClientFilter::parse(&args, None).is_err());
+        let args: Vec<&[u8]> = vec![b"type", b"normal"];
+        assert!(ClientFilter::parse(&args, None).is_err());
+    }
+
+    #[test]
+    fn test_kill_clients() {
+        let registry = ClientRegistry::default();
+        let (db1, client1) = gen_client(1, "127.0.0.1:6001", "mydb");
+        let (db2, client2) = gen_client(2, "127.0.0.1:6002", "mydb");
+        let (db3, client3) = gen_client(3, "127.0.0.1:6003", "otherdb");
+        let mut receiver1 = registry.register(db1, client1);
+        let mut receiver2 = registry.register(db2, client2);
+        let mut receiver3 = registry.register(db3, client3);
+        assert_eq!(registry.get_client_list(&ClientFilter::default()).len(), 3);
+
+        let filter = ClientFilter {
+            db: Some(DBName::from("mydb").unwrap()),
+            skip_id: Some(1),
+            ..Default::default()
+        };
+        let list = registry.get_client_list(&filter);
+        assert_eq!(list.len(), 1);
+        assert!(list[0].starts_with("id=2 addr=127.0.0.1:6002 name= db=mydb "));
+
+        assert_eq!(registry.kill(&filter), 1);
+        assert_eq!(receiver2.try_recv(), Ok(Some(())));
+        assert_eq!(receiver1.try_recv(), Ok(None));
+        assert_eq!(receiver3.try_recv(), Ok(None));
+        assert_eq!(registry.kill(&filter), 0);
+
+        registry.unregister(3);
+        assert!(receiver3.try_recv().is_err());
+        assert_eq!(registry.get_client_list(&ClientFilter::default()).len(), 1);
+    }
+
+    #[test]
+    fn test_client_stats() {
+        let registry = ClientRegistry::default();
+        let (db1, client1) = gen_client(1, "127.0.0.1:6001", "mydb");
+        let (db2, client2) = gen_client(2, "127.0.0.1:6002", "mydb");
+        let (db3, client3) = gen_client(3, "127.0.0.1:6003", "otherdb");
+        let _receiver1 = registry.register(db1, client1);
+        let _receiver2 = registry.register(db2, client2);
+        let _receiver3 = registry.register(db3.clone(), client3);
+        assert_eq!(registry.get_client_num(), 3);
+        assert_eq!(
+            registry.get_db_client_num(&DBName::from("mydb").unwrap()),
+            2
+        );
+
+        *db3.write().unwrap() = DBName::from("mydb").unwrap();
+        registry.on_connection_rejected();
+        registry.on_connection_timeout();
+        assert_eq!(
+            registry.info(),
+            "connected_clients:3\r\nrejected_connections:1\r\nrejected_cluster_clients:0\r\ntimed_out_connections:1\r\nmydb:clients=3"
+        );
+    }
+
+    #[test]
+    fn test_client_name() {
+        assert!(is_valid_client_name(b"my-client"));
+        assert!(is_valid_client_name(b""));
+        assert!(!is_valid_client_name(b"my client"));
+        assert!(!is_valid_client_name(b"client\n"));
+    }
+}
@@ -1,5 +1,7 @@
+use super::command_table::{get_builtin_command_info, CommandInfo, CommandTable, KeySpec};
+use super::key_prefix::add_key_prefix;
 use super::slowlog::Slowlog;
-use crate::common::utils::byte_to_uppercase;
+use crate::common::utils::{byte_to_uppercase, get_slot};
 use crate::protocol::{RespPacket, RespSlice, RespVec};
 use arrayvec::ArrayVec;
 use futures::channel::oneshot;
@@ -13,6 +15,7 @@ use std::io;
 use std::pin::Pin;
 use std::result::Result;
 use std::str;
+use std::time::Duration;
 
 const MAX_COMMAND_NAME_LENGTH: usize = 64;
 
@@ -30,6 +33,19 @@ pub enum CmdType {
     Cluster,
     Config,
     Command,
+    Hello,
+    Multi,
+    Exec,
+    Discard,
+    Watch,
+    Unwatch,
+    Subscribe,
+    PSubscribe,
+    Unsubscribe,
+    PUnsubscribe,
+    ReadOnly,
+    ReadWrite,
+    Client,
 }
 
 impl CmdType {
@@ -55,6 +71,19 @@ impl CmdType {
             b"CLUSTER" => CmdType::Cluster,
             b"CONFIG" => CmdType::Config,
             b"COMMAND" => CmdType::Command,
+            b"HELLO" => CmdType::Hello,
+            b"MULTI" => CmdType::Multi,
+            b"EXEC" => CmdType::Exec,
+            b"DISCARD" => CmdType::Discard,
+            b"WATCH" => CmdType::Watch,
+            b"UNWATCH" => CmdType::Unwatch,
+            b"SUBSCRIBE" => CmdType::Subscribe,
+            b"PSUBSCRIBE" => CmdType::PSubscribe,
+            b"UNSUBSCRIBE" => CmdType::Unsubscribe,
+            b"PUNSUBSCRIBE" => CmdType::PUnsubscribe,
+            b"READONLY" => CmdType::ReadOnly,
+            b"READWRITE" => CmdType::ReadWrite,
+            b"CLIENT" => CmdType::Client,
             _ => CmdType::Others,
         }
     }
@@ -99,6 +128,12 @@ pub enum DataCmdType {
     EVALSHA,
     DEL,
     EXISTS,
+    SCAN,
+    DBSIZE,
+    KEYS,
+    FLUSHDB,
+    RANDOMKEY,
+    SCRIPT,
     Others,
 }
 
@@ -146,6 +181,12 @@ impl DataCmdType {
             b"EVALSHA" => DataCmdType::EVALSHA,
             b"DEL" => DataCmdType::DEL,
             b"EXISTS" => DataCmdType::EXISTS,
+            b"SCAN" => DataCmdType::SCAN,
+            b"DBSIZE" => DataCmdType::DBSIZE,
+            b"KEYS" => DataCmdType::KEYS,
+            b"FLUSHDB" => DataCmdType::FLUSHDB,
+            b"RANDOMKEY" => DataCmdType::RANDOMKEY,
+            b"SCRIPT" => DataCmdType::SCRIPT,
             _ => DataCmdType::Others,
         }
     }
@@ -160,24 +201,63 @@ impl DataCmdType {
     }
 }
 
+#[derive(Debug)]
+struct TimeoutOutOfRange;
+
 #[derive(Debug)]
 pub struct Command {
     request: Box<RespPacket>,
     cmd_type: CmdType,
     data_cmd_type: DataCmdType,
+    info: Option<&'static CommandInfo>,
+    key_index: Option<usize>,
 }
 
 impl Command {
+    // Only looks up the built-in commands.
     pub fn new(request: Box<RespPacket>) -> Self {
+        let info = request
+            .get_array_element(0)
+            .and_then(get_builtin_command_info);
+        Self::with_info(request, info)
+    }
+
+    pub fn new_with_table(request: Box<RespPacket>, table: &CommandTable) -> Self {
+        let info = request
+            .get_array_element(0)
+            .and_then(|cmd_name| table.get(cmd_name));
+        Self::with_info(request, info)
+    }
+
+    fn with_info(request: Box<RespPacket>, info: Option<&'static CommandInfo>) -> Self {
         let cmd_type = CmdType::from_packet(&request);
         let data_cmd_type = DataCmdType::from_packet(&request);
+        let key_index = match info {
+            // The backend redis are not in cluster mode and only deliver the messages locally,
+            // so PUBLISH is routed by the channel to where its subscribers are.
+            Some(info) if info.name == "PUBLISH" => Some(1),
+            Some(info) => info.get_first_key_index(request.get_array_len().unwrap_or(0), |i| {
+                request.get_array_element(i)
+            }),
+            // Unknown commands are assumed to have the key at the first argument.
+            None => Some(1),
+        };
         Command {
             request,
             cmd_type,
             data_cmd_type,
+            info,
+            key_index,
         }
     }
 
+    // Replaces the command name and looks up the command again.
+    pub fn rename(self, cmd_name: Vec<u8>, table: &CommandTable) -> Self {
+        let mut request = self.request;
+        request.change_bulk_array_element(0, cmd_name);
+        Self::new_with_table(request, table)
+    }
+
     pub fn into_packet(self) -> Box<RespPacket> {
         self.request
     }
@@ -194,6 +274,14 @@ impl Command {
         self.request.get_array_element(index)
     }
 
+    // The total length of all the elements.
+    pub fn get_data_size(&self) -> usize {
+        (0..self.get_argc())
+            .filter_map(|i| self.get_command_element(i))
+            .map(<[u8]>::len)
+            .sum()
+    }
+
     pub fn get_command_name(&self) -> Option<&str> {
         self.request.get_command_name()
     }
@@ -210,12 +298,159 @@ impl Command {
         self.data_cmd_type
     }
 
+    pub fn get_command_info(&self) -> Option<&'static CommandInfo> {
+        self.info
+    }
+
+    pub fn get_argc(&self) -> usize {
+        self.request.get_array_len().unwrap_or(0)
+    }
+
+    pub fn check_arity(&self) -> bool {
+        match self.info {
+            Some(info) => info.check_arity(self.get_argc()),
+            None => true,
+        }
+    }
+
+    pub fn check_numkeys(&self) -> bool {
+        match self.info {
+            Some(info) => info.check_numkeys(self.get_argc(), |i| self.get_command_element(i)),
+            None => true,
+        }
+    }
+
     pub fn get_key(&self) -> Option<&[u8]> {
-        match self.data_cmd_type {
-            DataCmdType::EVAL | DataCmdType::EVALSHA => self.get_command_element(3),
-            _ => self.get_command_element(1),
+        self.key_index
+            .and_then(|index| self.get_command_element(index))
+    }
+
+    // Redis Cluster rejects the commands with keys in different slots.
+    pub fn is_cross_slot(&self) -> bool {
+        let info = match self.info {
+            Some(info) => info,
+            None => return false,
+        };
+        let mut first_slot = None;
+        let mut cross_slot = false;
+        info.visit_key_indexes(
+            self.get_argc(),
+            |i| self.get_command_element(i),
+            |i| {
+                let slot = match self.get_command_element(i) {
+                    Some(key) => get_slot(key),
+                    None => return true,
+                };
+                match first_slot {
+                    None => first_slot = Some(slot),
+                    Some(first_slot) if first_slot != slot => cross_slot = true,
+                    Some(_) => (),
+                }
+                !cross_slot
+            },
+        );
+        cross_slot
+    }
+
+    pub fn is_readonly(&self) -> bool {
+        self.info.map(|info| info.is_readonly()).unwrap_or(false)
+    }
+
+    // Blocking commands should not stall the pipelined backend connections.
+    pub fn is_blocking(&self) -> bool {
+        match self.info {
+            Some(info) if info.is_blocking() => true,
+            // XREAD and XREADGROUP only block with the BLOCK option.
+            Some(info) if info.key_spec == KeySpec::Streams => {
+                self.get_stream_option_index(b"BLOCK").is_some()
+            }
+            _ => false,
+        }
+    }
+
+    // None means blocking forever.
+    pub fn get_blocking_timeout(&self) -> Option<Duration> {
+        self.parse_blocking_timeout().ok().flatten()
+    }
+
+    pub fn check_blocking_timeout(&self) -> bool {
+        self.parse_blocking_timeout().is_ok()
+    }
+
+    // Same as redis, the timeout in milliseconds needs to fit in i64.
+    // The invalid timeouts are left to the backend.
+    fn parse_blocking_timeout(&self) -> Result<Option<Duration>, TimeoutOutOfRange> {
+        let info = match self.info {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+        if info.key_spec == KeySpec::Streams {
+            let ms = self
+                .get_stream_option_index(b"BLOCK")
+                .and_then(|index| self.get_command_element(index + 1))
+                .and_then(|ms| str::from_utf8(ms).ok())
+                .and_then(|ms| ms.parse::<u64>().ok());
+            return match ms {
+                Some(ms) if ms > i64::MAX as u64 => Err(TimeoutOutOfRange),
+                Some(ms) if ms > 0 => Ok(Some(Duration::from_millis(ms))),
+                _ => Ok(None),
+            };
+        }
+        // The timeout in seconds is the last argument and could be a float since redis 6.
+        let secs = self
+            .get_argc()
+            .checked_sub(1)
+            .and_then(|index| self.get_command_element(index))
+            .and_then(|secs| str::from_utf8(secs).ok())
+            .and_then(|secs| secs.parse::<f64>().ok());
+        match secs {
+            Some(secs) if secs * 1000.0 > i64::MAX as f64 => Err(TimeoutOutOfRange),
+            Some(secs) if secs > 0.0 => Ok(Some(Duration::from_secs_f64(secs))),
+            _ => Ok(None),
+        }
+    }
+
+    // The options of XREAD and XREADGROUP are all before `STREAMS`.
+    fn get_stream_option_index(&self, option: &[u8]) -> Option<usize> {
+        (1..self.get_argc())
+            .map(|i| (i, self.get_command_element(i).unwrap_or(b"")))
+            .take_while(|(_, element)| !element.eq_ignore_ascii_case(b"STREAMS"))
+            .find(|(_, element)| element.eq_ignore_ascii_case(option))
+            .map(|(i, _)| i)
+    }
+
+    fn get_key_indexes(&self) -> Vec<usize> {
+        match self.info {
+            Some(info) => info.get_key_indexes(self.get_argc(), |i| self.get_command_element(i)),
+            None => vec![1],
         }
     }
+
+    pub fn get_keys(&self) -> Vec<&[u8]> {
+        self.get_key_indexes()
+            .into_iter()
+            .filter_map(|i| self.get_command_element(i))
+            .collect()
+    }
+
+    // The packet sent to the backend when the cluster has a key prefix.
+    pub fn get_packet_with_key_prefix(&self, key_prefix: &[u8]) -> RespPacket {
+        let mut packet = self.get_packet();
+        for i in self.get_key_indexes() {
+            if let Some(key) = self.get_command_element(i) {
+                packet.change_bulk_array_element(i, add_key_prefix(key_prefix, key));
+            }
+        }
+        // The channels are namespaced in the same way as the keys.
+        if let Some(info) = self.info {
+            if info.name == "PUBLISH" {
+                if let Some(channel) = self.get_command_element(1) {
+                    packet.change_bulk_array_element(1, add_key_prefix(key_prefix, channel));
+                }
+            }
+        }
+        packet
+    }
 }
 
 pub struct TaskReply {
@@ -362,11 +597,13 @@ impl Error for CommandError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::{Array, BulkStr, Resp};
 
     #[test]
     fn test_parse_cmd_type() {
         assert_eq!(CmdType::from_cmd_name(b"pInG"), CmdType::Ping);
         assert_eq!(CmdType::from_cmd_name(b"get"), CmdType::Others);
+        assert_eq!(CmdType::from_cmd_name(b"hello"), CmdType::Hello);
     }
 
     #[test]
@@ -376,4 +613,123 @@ mod tests {
         assert_eq!(DataCmdType::from_cmd_name(b"eVaL"), DataCmdType::EVAL);
         assert_eq!(DataCmdType::from_cmd_name(b"HMGET"), DataCmdType::Others);
     }
+
+    fn gen_cmd(elements: &[&str]) -> Command {
+        let elements = elements
+            .iter()
+            .map(|e| Resp::Bulk(BulkStr::Str(e.as_bytes().to_vec())))
+            .collect();
+        let packet = RespPacket::from_resp_vec(Resp::Arr(Array::Arr(elements)));
+        Command::new(Box::new(packet))
+    }
+
+    #[test]
+    fn test_get_key() {
+        let cmd = gen_cmd(&["GET", "a"]);
+        assert_eq!(cmd.get_key(), Some(b"a".as_ref()));
+        assert!(cmd.check_arity());
+
+        let cmd = gen_cmd(&["EVAL", "script", "1", "a", "arg"]);
+        assert_eq!(cmd.get_key(), Some(b"a".as_ref()));
+
+        let cmd = gen_cmd(&["XREAD", "STREAMS", "a", "b", "0", "0"]);
+        assert_eq!(cmd.get_key(), Some(b"a".as_ref()));
+        assert_eq!(cmd.get_keys(), vec![b"a".as_ref(), b"b".as_ref()]);
+
+        let cmd = gen_cmd(&["PING"]);
+        assert!(cmd.get_key().is_none());
+
+        let cmd = gen_cmd(&["GET"]);
+        assert!(!cmd.check_arity());
+
+        let cmd = gen_cmd(&["RENAME", "a", "b"]);
+        assert!(cmd.is_cross_slot());
+        let cmd = gen_cmd(&["RENAME", "{a}1", "{a}2"]);
+        assert!(!cmd.is_cross_slot());
+        let cmd = gen_cmd(&["EVAL", "script", "2", "a", "b"]);
+        assert!(cmd.is_cross_slot());
+        let cmd = gen_cmd(&["BITOP", "AND", "{a}1", "{a}2", "{a}3"]);
+        assert!(!cmd.is_cross_slot());
+        assert!(!gen_cmd(&["GET", "a"]).is_cross_slot());
+
+        // Unknown commands
+        let cmd = gen_cmd(&["JSON.GET", "a", "path"]);
+        assert_eq!(cmd.get_key(), Some(b"a".as_ref()));
+        assert!(cmd.check_arity());
+
+        let cmd = gen_cmd(&["PUBLISH", "channel", "message"]);
+        assert_eq!(cmd.get_key(), Some(b"channel".as_ref()));
+        assert!(cmd.get_keys().is_empty());
+    }
+
+    #[test]
+    fn test_packet_with_key_prefix() {
+        let cmd = gen_cmd(&["EVAL", "script", "2", "{a}1", "{a}2", "arg"]);
+        let packet = cmd.get_packet_with_key_prefix(b"t1:");
+        let elements: Vec<&[u8]> = (0..6).filter_map(|i| packet.get_array_element(i)).collect();
+        assert_eq!(
+            elements,
+            vec![
+                b"EVAL".as_ref(),
+                b"script",
+                b"2",
+                b"t1:{a}1",
+                b"t1:{a}2",
+                b"arg"
+            ]
+        );
+        // The slot is still calculated from the original keys.
+        assert_eq!(cmd.get_key(), Some(b"{a}1".as_ref()));
+
+        let cmd = gen_cmd(&["PUBLISH", "channel", "message"]);
+        let packet = cmd.get_packet_with_key_prefix(b"t1:");
+        assert_eq!(packet.get_array_element(1), Some(b"t1:channel".as_ref()));
+        assert_eq!(packet.get_array_element(2), Some(b"message".as_ref()));
+    }
+
+    #[test]
+    fn test_blocking_cmd() {
+        let cmd = gen_cmd(&["BLPOP", "a", "b", "1.5"]);
+        assert!(cmd.is_blocking());
+        assert_eq!(
+            cmd.get_blocking_timeout(),
+            Some(Duration::from_millis(1500))
+        );
+        let cmd = gen_cmd(&["BRPOPLPUSH", "a", "b", "0"]);
+        assert!(cmd.is_blocking());
+        assert!(cmd.get_blocking_timeout().is_none());
+
+        let cmd = gen_cmd(&["XREAD", "COUNT", "2", "BLOCK", "200", "STREAMS", "a", "0"]);
+        assert!(cmd.is_blocking());
+        assert_eq!(cmd.get_blocking_timeout(), Some(Duration::from_millis(200)));
+        let cmd = gen_cmd(&["XREAD", "block", "0", "STREAMS", "a", "0"]);
+        assert!(cmd.is_blocking());
+        assert!(cmd.get_blocking_timeout().is_none());
+        assert!(cmd.check_blocking_timeout());
+
+        let cmd = gen_cmd(&["BLPOP", "a", "1e30"]);
+        assert!(!cmd.check_blocking_timeout());
+        assert!(cmd.get_blocking_timeout().is_none());
+        let cmd = gen_cmd(&["BLPOP", "a", "inf"]);
+        assert!(!cmd.check_blocking_timeout());
+        let cmd = gen_cmd(&["BLPOP", "a", "-1"]);
+        assert!(cmd.check_blocking_timeout());
+        assert!(cmd.get_blocking_timeout().is_none());
+        let cmd = gen_cmd(&[
+            "XREAD",
+            "BLOCK",
+            "18446744073709551615",
+            "STREAMS",
+            "a",
+            "0",
+        ]);
+        assert!(!cmd.check_blocking_timeout());
+        assert!(cmd.get_blocking_timeout().is_none());
+        // `BLOCK` here is a stream key.
+        let cmd = gen_cmd(&["XREAD", "STREAMS", "BLOCK", "0"]);
+        assert!(!cmd.is_blocking());
+
+        assert!(!gen_cmd(&["LPOP", "a"]).is_blocking());
+        assert!(!gen_cmd(&["JSON.GET", "a", "path"]).is_blocking());
+    }
 }
@@ -0,0 +1,803 @@
+use crate::common::config::ClusterConfig;
+use crate::common::utils::{byte_to_uppercase, bytes_ascii_case_insensitive_eq};
+use crate::protocol::{Array, BulkStr, Resp, RespVec};
+use arrayvec::ArrayVec;
+use btoi::btou;
+use std::collections::HashMap;
+use std::str;
+
+const MAX_COMMAND_NAME_LENGTH: usize = 64;
+
+pub const CMD_FLAG_WRITE: u8 = 1;
+pub const CMD_FLAG_READONLY: u8 = 1 << 1;
+pub const CMD_FLAG_ADMIN: u8 = 1 << 2;
+pub const CMD_FLAG_BLOCKING: u8 = 1 << 3;
+
+const NONE: u8 = 0;
+const WRITE: u8 = CMD_FLAG_WRITE;
+const READONLY: u8 = CMD_FLAG_READONLY;
+const ADMIN: u8 = CMD_FLAG_ADMIN;
+const BLOCKING: u8 = CMD_FLAG_BLOCKING;
+
+const FLAG_NAMES: [(u8, &str); 4] = [
+    (CMD_FLAG_WRITE, "write"),
+    (CMD_FLAG_READONLY, "readonly"),
+    (CMD_FLAG_ADMIN, "admin"),
+    (CMD_FLAG_BLOCKING, "blocking"),
+];
+
+// Besides the keys described by `first_key`, `last_key` and `step`,
+// some commands have keys that can only be found by looking into the arguments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeySpec {
+    Range,
+    // The argument at this index is the number of the keys following it,
+    // e.g. EVAL and ZUNIONSTORE.
+    NumKeys(usize),
+    // The first half of the arguments after `STREAMS`, e.g. XREAD.
+    Streams,
+    // The argument after `STORE` or `STOREDIST`, e.g. SORT and GEORADIUS.
+    Store,
+    // The key is at the index when the sub-command matches, e.g. MEMORY USAGE.
+    SubCommand(&'static str, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandInfo {
+    pub name: &'static str,
+    // Negative arity means at least -arity arguments including the command name.
+    pub arity: i64,
+    pub flags: u8,
+    pub first_key: usize,
+    // Negative index counts from the end.
+    pub last_key: i64,
+    pub step: usize,
+    pub key_spec: KeySpec,
+}
+
+const fn cmd(
+    name: &'static str,
+    arity: i64,
+    flags: u8,
+    first_key: usize,
+    last_key: i64,
+    step: usize,
+) -> CommandInfo {
+    movable(
+        name,
+        arity,
+        flags,
+        first_key,
+        last_key,
+        step,
+        KeySpec::Range,
+    )
+}
+
+const fn movable(
+    name: &'static str,
+    arity: i64,
+    flags: u8,
+    first_key: usize,
+    last_key: i64,
+    step: usize,
+    key_spec: KeySpec,
+) -> CommandInfo {
+    CommandInfo {
+        name,
+        arity,
+        flags,
+        first_key,
+        last_key,
+        step,
+        key_spec,
+    }
+}
+
+impl CommandInfo {
+    pub fn is_write(&self) -> bool {
+        self.flags & CMD_FLAG_WRITE != 0
+    }
+
+    pub fn is_readonly(&self) -> bool {
+        self.flags & CMD_FLAG_READONLY != 0
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.flags & CMD_FLAG_ADMIN != 0
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.flags & CMD_FLAG_BLOCKING != 0
+    }
+
+    pub fn check_arity(&self, argc: usize) -> bool {
+        if self.arity >= 0 {
+            argc as i64 == self.arity
+        } else {
+            argc as i64 >= -self.arity
+        }
+    }
+
+    // Returns false if the `numkeys` of commands like EVAL is larger than the rest arguments.
+    pub fn check_numkeys<'a, G>(&self, argc: usize, get_element: G) -> bool
+    where
+        G: Fn(usize) -> Option<&'a [u8]>,
+    {
+        let numkeys_index = match self.key_spec {
+            KeySpec::NumKeys(numkeys_index) => numkeys_index,
+            _ => return true,
+        };
+        // The invalid numbers are left to redis.
+        match get_element(numkeys_index).and_then(|n| btou::<usize>(n).ok()) {
+            Some(numkeys) => numkeys <= argc.saturating_sub(numkeys_index + 1),
+            None => true,
+        }
+    }
+
+    // Calls `f` with the index of each key until it returns false.
+    pub fn visit_key_indexes<'a, G, F>(&self, argc: usize, get_element: G, mut f: F)
+    where
+        G: Fn(usize) -> Option<&'a [u8]>,
+        F: FnMut(usize) -> bool,
+    {
+        if !self.visit_range(argc, &mut f) {
+            return;
+        }
+
+        match self.key_spec {
+            KeySpec::Range => (),
+            KeySpec::NumKeys(numkeys_index) => {
+                let numkeys = match get_element(numkeys_index).and_then(|n| btou::<usize>(n).ok()) {
+                    Some(numkeys) => numkeys,
+                    None => return,
+                };
+                let end = match (numkeys_index + 1).checked_add(numkeys) {
+                    Some(end) => end.min(argc),
+                    None => return,
+                };
+                for i in (numkeys_index + 1)..end {
+                    if !f(i) {
+                        return;
+                    }
+                }
+            }
+            KeySpec::Streams => {
+                let streams_index = match (1..argc).find(|i| {
+                    get_element(*i)
+                        .map(|e| bytes_ascii_case_insensitive_eq(e, b"STREAMS"))
+                        .unwrap_or(false)
+                }) {
+                    Some(i) => i,
+                    None => return,
+                };
+                // Each key has an ID after all the keys.
+                let rest = argc - streams_index - 1;
+                let key_num = rest / 2;
+                if key_num * 2 != rest {
+                    return;
+                }
+                for i in (streams_index + 1)..(streams_index + 1 + key_num) {
+                    if !f(i) {
+                        return;
+                    }
+                }
+            }
+            KeySpec::Store => {
+                let store_index = (self.first_key + 1..argc).find(|i| {
+                    get_element(*i)
+                        .map(|e| {
+                            bytes_ascii_case_insensitive_eq(e, b"STORE")
+                                || bytes_ascii_case_insensitive_eq(e, b"STOREDIST")
+                        })
+                        .unwrap_or(false)
+                });
+                if let Some(i) = store_index {
+                    if i + 1 < argc {
+                        f(i + 1);
+                    }
+                }
+            }
+            KeySpec::SubCommand(sub_command, key_index) => {
+                let matched = get_element(1)
+                    .map(|e| bytes_ascii_case_insensitive_eq(e, sub_command.as_bytes()))
+                    .unwrap_or(false);
+                if matched && key_index < argc {
+                    f(key_index);
+                }
+            }
+        }
+    }
+
+    fn visit_range<F>(&self, argc: usize, f: &mut F) -> bool
+    where
+        F: FnMut(usize) -> bool,
+    {
+        if self.first_key == 0 || self.step == 0 {
+            return true;
+        }
+        let last_key = if self.last_key < 0 {
+            argc as i64 + self.last_key
+        } else {
+            self.last_key
+        };
+        let mut i = self.first_key;
+        while (i as i64) <= last_key && i < argc {
+            if !f(i) {
+                return false;
+            }
+            i += self.step;
+        }
+        true
+    }
+
+    pub fn get_key_indexes<'a, G>(&self, argc: usize, get_element: G) -> Vec<usize>
+    where
+        G: Fn(usize) -> Option<&'a [u8]>,
+    {
+        let mut indexes = vec![];
+        self.visit_key_indexes(argc, get_element, |i| {
+            indexes.push(i);
+            true
+        });
+        indexes
+    }
+
+    pub fn get_first_key_index<'a, G>(&self, argc: usize, get_element: G) -> Option<usize>
+    where
+        G: Fn(usize) -> Option<&'a [u8]>,
+    {
+        let mut index = None;
+        self.visit_key_indexes(argc, get_element, |i| {
+            index = Some(i);
+            false
+        });
+        index
+    }
+
+    // Same as the reply of the `COMMAND INFO` of redis.
+    pub fn to_resp(&self) -> RespVec {
+        let mut flags: Vec<RespVec> = FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.flags & flag != 0)
+            .map(|(_, name)| Resp::Simple(name.as_bytes().to_vec()))
+            .collect();
+        if self.key_spec != KeySpec::Range {
+            flags.push(Resp::Simple(b"movablekeys".to_vec()));
+        }
+        Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(self.name.to_lowercase().into_bytes())),
+            Resp::Integer(self.arity.to_string().into_bytes()),
+            Resp::Arr(Array::Arr(flags)),
+            Resp::Integer(self.first_key.to_string().into_bytes()),
+            Resp::Integer(self.last_key.to_string().into_bytes()),
+            Resp::Integer(self.step.to_string().into_bytes()),
+        ]))
+    }
+}
+
+// Sorted by name for binary search.
+const COMMAND_TABLE: &[CommandInfo] = &[
+    cmd("APPEND", 3, WRITE, 1, 1, 1),
+    cmd("AUTH", -2, NONE, 0, 0, 0),
+    cmd("BGREWRITEAOF", 1, ADMIN, 0, 0, 0),
+    cmd("BGSAVE", -1, ADMIN, 0, 0, 0),
+    cmd("BITCOUNT", -2, READONLY, 1, 1, 1),
+    cmd("BITFIELD", -2, WRITE, 1, 1, 1),
+    cmd("BITOP", -4, WRITE, 2, -1, 1),
+    cmd("BITPOS", -3, READONLY, 1, 1, 1),
+    cmd("BLPOP", -3, WRITE | BLOCKING, 1, -2, 1),
+    cmd("BRPOP", -3, WRITE | BLOCKING, 1, -2, 1),
+    cmd("BRPOPLPUSH", 4, WRITE | BLOCKING, 1, 2, 1),
+    cmd("BZPOPMAX", -3, WRITE | BLOCKING, 1, -2, 1),
+    cmd("BZPOPMIN", -3, WRITE | BLOCKING, 1, -2, 1),
+    cmd("CLIENT", -2, ADMIN, 0, 0, 0),
+    cmd("CLUSTER", -2, ADMIN, 0, 0, 0),
+    cmd("COMMAND", -1, NONE, 0, 0, 0),
+    cmd("CONFIG", -2, ADMIN, 0, 0, 0),
+    cmd("DBSIZE", 1, READONLY, 0, 0, 0),
+    cmd("DEBUG", -2, ADMIN, 0, 0, 0),
+    cmd("DECR", 2, WRITE, 1, 1, 1),
+    cmd("DECRBY", 3, WRITE, 1, 1, 1),
+    cmd("DEL", -2, WRITE, 1, -1, 1),
+    cmd("DISCARD", 1, NONE, 0, 0, 0),
+    cmd("DUMP", 2, READONLY, 1, 1, 1),
+    cmd("ECHO", 2, NONE, 0, 0, 0),
+    movable("EVAL", -3, NONE, 0, 0, 0, KeySpec::NumKeys(2)),
+    movable("EVALSHA", -3, NONE, 0, 0, 0, KeySpec::NumKeys(2)),
+    cmd("EXEC", 1, NONE, 0, 0, 0),
+    cmd("EXISTS", -2, READONLY, 1, -1, 1),
+    cmd("EXPIRE", 3, WRITE, 1, 1, 1),
+    cmd("EXPIREAT", 3, WRITE, 1, 1, 1),
+    cmd("FLUSHALL", -1, WRITE, 0, 0, 0),
+    cmd("FLUSHDB", -1, WRITE, 0, 0, 0),
+    cmd("GEOADD", -5, WRITE, 1, 1, 1),
+    cmd("GEODIST", -4, READONLY, 1, 1, 1),
+    cmd("GEOHASH", -2, READONLY, 1, 1, 1),
+    cmd("GEOPOS", -2, READONLY, 1, 1, 1),
+    movable("GEORADIUS", -6, WRITE, 1, 1, 1, KeySpec::Store),
+    movable("GEORADIUSBYMEMBER", -5, WRITE, 1, 1, 1, KeySpec::Store),
+    cmd("GEORADIUSBYMEMBER_RO", -5, READONLY, 1, 1, 1),
+    cmd("GEORADIUS_RO", -6, READONLY, 1, 1, 1),
+    cmd("GET", 2, READONLY, 1, 1, 1),
+    cmd("GETBIT", 3, READONLY, 1, 1, 1),
+    cmd("GETRANGE", 4, READONLY, 1, 1, 1),
+    cmd("GETSET", 3, WRITE, 1, 1, 1),
+    cmd("HDEL", -3, WRITE, 1, 1, 1),
+    cmd("HELLO", -1, NONE, 0, 0, 0),
+    cmd("HEXISTS", 3, READONLY, 1, 1, 1),
+    cmd("HGET", 3, READONLY, 1, 1, 1),
+    cmd("HGETALL", 2, READONLY, 1, 1, 1),
+    cmd("HINCRBY", 4, WRITE, 1, 1, 1),
+    cmd("HINCRBYFLOAT", 4, WRITE, 1, 1, 1),
+    cmd("HKEYS", 2, READONLY, 1, 1, 1),
+    cmd("HLEN", 2, READONLY, 1, 1, 1),
+    cmd("HMGET", -3, READONLY, 1, 1, 1),
+    cmd("HMSET", -4, WRITE, 1, 1, 1),
+    cmd("HSCAN", -3, READONLY, 1, 1, 1),
+    cmd("HSET", -4, WRITE, 1, 1, 1),
+    cmd("HSETNX", 4, WRITE, 1, 1, 1),
+    cmd("HSTRLEN", 3, READONLY, 1, 1, 1),
+    cmd("HVALS", 2, READONLY, 1, 1, 1),
+    cmd("INCR", 2, WRITE, 1, 1, 1),
+    cmd("INCRBY", 3, WRITE, 1, 1, 1),
+    cmd("INCRBYFLOAT", 3, WRITE, 1, 1, 1),
+    cmd("INFO", -1, NONE, 0, 0, 0),
+    cmd("KEYS", 2, READONLY, 0, 0, 0),
+    cmd("LASTSAVE", 1, NONE, 0, 0, 0),
+    cmd("LINDEX", 3, READONLY, 1, 1, 1),
+    cmd("LINSERT", 5, WRITE, 1, 1, 1),
+    cmd("LLEN", 2, READONLY, 1, 1, 1),
+    cmd("LPOP", -2, WRITE, 1, 1, 1),
+    cmd("LPOS", -3, READONLY, 1, 1, 1),
+    cmd("LPUSH", -3, WRITE, 1, 1, 1),
+    cmd("LPUSHX", -3, WRITE, 1, 1, 1),
+    cmd("LRANGE", 4, READONLY, 1, 1, 1),
+    cmd("LREM", 4, WRITE, 1, 1, 1),
+    cmd("LSET", 4, WRITE, 1, 1, 1),
+    cmd("LTRIM", 4, WRITE, 1, 1, 1),
+    movable(
+        "MEMORY",
+        -2,
+        READONLY,
+        0,
+        0,
+        0,
+        KeySpec::SubCommand("USAGE", 2),
+    ),
+    cmd("MGET", -2, READONLY, 1, -1, 1),
+    cmd("MIGRATE", -6, WRITE, 0, 0, 0),
+    cmd("MONITOR", 1, ADMIN, 0, 0, 0),
+    cmd("MOVE", 3, WRITE, 1, 1, 1),
+    cmd("MSET", -3, WRITE, 1, -1, 2),
+    cmd("MSETNX", -3, WRITE, 1, -1, 2),
+    cmd("MULTI", 1, NONE, 0, 0, 0),
+    cmd("OBJECT", -2, READONLY, 2, 2, 1),
+    cmd("PERSIST", 2, WRITE, 1, 1, 1),
+    cmd("PEXPIRE", 3, WRITE, 1, 1, 1),
+    cmd("PEXPIREAT", 3, WRITE, 1, 1, 1),
+    cmd("PFADD", -2, WRITE, 1, 1, 1),
+    cmd("PFCOUNT", -2, READONLY, 1, -1, 1),
+    cmd("PFMERGE", -2, WRITE, 1, -1, 1),
+    cmd("PING", -1, NONE, 0, 0, 0),
+    cmd("PSETEX", 4, WRITE, 1, 1, 1),
+    cmd("PSUBSCRIBE", -2, NONE, 0, 0, 0),
+    cmd("PTTL", 2, READONLY, 1, 1, 1),
+    cmd("PUBLISH", 3, NONE, 0, 0, 0),
+    cmd("PUBSUB", -2, NONE, 0, 0, 0),
+    cmd("PUNSUBSCRIBE", -1, NONE, 0, 0, 0),
+    cmd("QUIT", 1, NONE, 0, 0, 0),
+    cmd("RANDOMKEY", 1, READONLY, 0, 0, 0),
+    cmd("READONLY", 1, NONE, 0, 0, 0),
+    cmd("READWRITE", 1, NONE, 0, 0, 0),
+    cmd("RENAME", 3, WRITE, 1, 2, 1),
+    cmd("RENAMENX", 3, WRITE, 1, 2, 1),
+    cmd("REPLICAOF", 3, ADMIN, 0, 0, 0),
+    cmd("RESTORE", -4, WRITE, 1, 1, 1),
+    cmd("ROLE", 1, NONE, 0, 0, 0),
+    cmd("RPOP", -2, WRITE, 1, 1, 1),
+    cmd("RPOPLPUSH", 3, WRITE, 1, 2, 1),
+    cmd("RPUSH", -3, WRITE, 1, 1, 1),
+    cmd("RPUSHX", -3, WRITE, 1, 1, 1),
+    cmd("SADD", -3, WRITE, 1, 1, 1),
+    cmd("SAVE", 1, ADMIN, 0, 0, 0),
+    cmd("SCAN", -2, READONLY, 0, 0, 0),
+    cmd("SCARD", 2, READONLY, 1, 1, 1),
+    cmd("SCRIPT", -2, NONE, 0, 0, 0),
+    cmd("SDIFF", -2, READONLY, 1, -1, 1),
+    cmd("SDIFFSTORE", -3, WRITE, 1, -1, 1),
+    cmd("SELECT", 2, NONE, 0, 0, 0),
+    cmd("SET", -3, WRITE, 1, 1, 1),
+    cmd("SETBIT", 4, WRITE, 1, 1, 1),
+    cmd("SETEX", 4, WRITE, 1, 1, 1),
+    cmd("SETNX", 3, WRITE, 1, 1, 1),
+    cmd("SETRANGE", 4, WRITE, 1, 1, 1),
+    cmd("SHUTDOWN", -1, ADMIN, 0, 0, 0),
+    cmd("SINTER", -2, READONLY, 1, -1, 1),
+    cmd("SINTERSTORE", -3, WRITE, 1, -1, 1),
+    cmd("SISMEMBER", 3, READONLY, 1, 1, 1),
+    cmd("SLAVEOF", 3, ADMIN, 0, 0, 0),
+    cmd("SLOWLOG", -2, ADMIN, 0, 0, 0),
+    cmd("SMEMBERS", 2, READONLY, 1, 1, 1),
+    cmd("SMOVE", 4, WRITE, 1, 2, 1),
+    movable("SORT", -2, WRITE, 1, 1, 1, KeySpec::Store),
+    cmd("SPOP", -2, WRITE, 1, 1, 1),
+    cmd("SRANDMEMBER", -2, READONLY, 1, 1, 1),
+    cmd("SREM", -3, WRITE, 1, 1, 1),
+    cmd("SSCAN", -3, READONLY, 1, 1, 1),
+    cmd("STRLEN", 2, READONLY, 1, 1, 1),
+    cmd("SUBSCRIBE", -2, NONE, 0, 0, 0),
+    cmd("SUNION", -2, READONLY, 1, -1, 1),
+    cmd("SUNIONSTORE", -3, WRITE, 1, -1, 1),
+    cmd("SWAPDB", 3, WRITE, 0, 0, 0),
+    cmd("TIME", 1, NONE, 0, 0, 0),
+    cmd("TOUCH", -2, READONLY, 1, -1, 1),
+    cmd("TTL", 2, READONLY, 1, 1, 1),
+    cmd("TYPE", 2, READONLY, 1, 1, 1),
+    cmd("UMCTL", -2, ADMIN, 0, 0, 0),
+    cmd("UNLINK", -2, WRITE, 1, -1, 1),
+    cmd("UNSUBSCRIBE", -1, NONE, 0, 0, 0),
+    cmd("UNWATCH", 1, NONE, 0, 0, 0),
+    cmd("WAIT", 3, NONE, 0, 0, 0),
+    cmd("WATCH", -2, NONE, 1, -1, 1),
+    cmd("XACK", -4, WRITE, 1, 1, 1),
+    cmd("XADD", -5, WRITE, 1, 1, 1),
+    cmd("XCLAIM", -6, WRITE, 1, 1, 1),
+    cmd("XDEL", -3, WRITE, 1, 1, 1),
+    cmd("XGROUP", -2, WRITE, 2, 2, 1),
+    cmd("XINFO", -2, READONLY, 2, 2, 1),
+    cmd("XLEN", 2, READONLY, 1, 1, 1),
+    cmd("XPENDING", -3, READONLY, 1, 1, 1),
+    cmd("XRANGE", -4, READONLY, 1, 1, 1),
+    movable("XREAD", -4, READONLY, 0, 0, 0, KeySpec::Streams),
+    movable("XREADGROUP", -7, WRITE, 0, 0, 0, KeySpec::Streams),
+    cmd("XREVRANGE", -4, READONLY, 1, 1, 1),
+    cmd("XSETID", 3, WRITE, 1, 1, 1),
+    cmd("XTRIM", -2, WRITE, 1, 1, 1),
+    cmd("ZADD", -4, WRITE, 1, 1, 1),
+    cmd("ZCARD", 2, READONLY, 1, 1, 1),
+    cmd("ZCOUNT", 4, READONLY, 1, 1, 1),
+    cmd("ZINCRBY", 4, WRITE, 1, 1, 1),
+    movable("ZINTERSTORE", -4, WRITE, 1, 1, 1, KeySpec::NumKeys(2)),
+    cmd("ZLEXCOUNT", 4, READONLY, 1, 1, 1),
+    cmd("ZPOPMAX", -2, WRITE, 1, 1, 1),
+    cmd("ZPOPMIN", -2, WRITE, 1, 1, 1),
+    cmd("ZRANGE", -4, READONLY, 1, 1, 1),
+    cmd("ZRANGEBYLEX", -4, READONLY, 1, 1, 1),
+    cmd("ZRANGEBYSCORE", -4, READONLY, 1, 1, 1),
+    cmd("ZRANK", 3, READONLY, 1, 1, 1),
+    cmd("ZREM", -3, WRITE, 1, 1, 1),
+    cmd("ZREMRANGEBYLEX", 4, WRITE, 1, 1, 1),
+    cmd("ZREMRANGEBYRANK", 4, WRITE, 1, 1, 1),
+    cmd("ZREMRANGEBYSCORE", 4, WRITE, 1, 1, 1),
+    cmd("ZREVRANGE", -4, READONLY, 1, 1, 1),
+    cmd("ZREVRANGEBYLEX", -4, READONLY, 1, 1, 1),
+    cmd("ZREVRANGEBYSCORE", -4, READONLY, 1, 1, 1),
+    cmd("ZREVRANK", 3, READONLY, 1, 1, 1),
+    cmd("ZSCAN", -3, READONLY, 1, 1, 1),
+    cmd("ZSCORE", 3, READONLY, 1, 1, 1),
+    movable("ZUNIONSTORE", -4, WRITE, 1, 1, 1, KeySpec::NumKeys(2)),
+];
+
+// These commands affect the whole backend redis shared by all the clusters
+// so they're always disabled, even if they're renamed in the cluster config.
+const DEFAULT_DISABLED_COMMANDS: [&str; 16] = [
+    "ACL",
+    "BGREWRITEAOF",
+    "BGSAVE",
+    "DEBUG",
+    "FLUSHALL",
+    "MIGRATE",
+    "MODULE",
+    "MONITOR",
+    "PSYNC",
+    "REPLCONF",
+    "REPLICAOF",
+    "SAVE",
+    "SHUTDOWN",
+    "SLAVEOF",
+    "SWAPDB",
+    "SYNC",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum CommandResolution {
+    Original,
+    // The command is renamed from the original name.
+    Renamed(String),
+    Disabled,
+}
+
+pub fn resolve_command(cmd_name: &[u8], config: Option<&ClusterConfig>) -> CommandResolution {
+    let cmd_name = match to_uppercase_name(cmd_name) {
+        Some(cmd_name) => cmd_name,
+        None => return CommandResolution::Original,
+    };
+    let cmd_name = match str::from_utf8(&cmd_name) {
+        Ok(cmd_name) => cmd_name,
+        Err(_) => return CommandResolution::Original,
+    };
+    let config = match config {
+        Some(config) => config,
+        None if is_disabled(cmd_name, None) => return CommandResolution::Disabled,
+        None => return CommandResolution::Original,
+    };
+    let renamed = config
+        .renamed_commands
+        .iter()
+        .find(|(_, new_name)| *new_name == cmd_name);
+    // The deny lists are checked against the original name so renaming can't enable them.
+    match renamed {
+        Some((original, _)) if is_disabled(original, Some(config)) => CommandResolution::Disabled,
+        Some((original, _)) => CommandResolution::Renamed(original.clone()),
+        None if config.renamed_commands.contains_key(cmd_name)
+            || is_disabled(cmd_name, Some(config)) =>
+        {
+            CommandResolution::Disabled
+        }
+        None => CommandResolution::Original,
+    }
+}
+
+fn is_disabled(cmd_name: &str, config: Option<&ClusterConfig>) -> bool {
+    DEFAULT_DISABLED_COMMANDS.contains(&cmd_name)
+        || config
+            .map(|config| config.disabled_commands.contains(cmd_name))
+            .unwrap_or(false)
+}
+
+fn to_uppercase_name(cmd_name: &[u8]) -> Option<ArrayVec<[u8; MAX_COMMAND_NAME_LENGTH]>> {
+    let mut stack_cmd_name = ArrayVec::<[u8; MAX_COMMAND_NAME_LENGTH]>::new();
+    for b in cmd_name {
+        stack_cmd_name.try_push(byte_to_uppercase(*b)).ok()?;
+    }
+    Some(stack_cmd_name)
+}
+
+pub fn get_builtin_command_info(cmd_name: &[u8]) -> Option<&'static CommandInfo> {
+    let cmd_name = to_uppercase_name(cmd_name)?;
+    let cmd_name: &[u8] = &cmd_name;
+    COMMAND_TABLE
+        .binary_search_by(|info| info.name.as_bytes().cmp(cmd_name))
+        .ok()
+        .and_then(|i| COMMAND_TABLE.get(i))
+}
+
+fn parse_flags(flags: &str) -> Result<u8, String> {
+    if flags == "-" {
+        return Ok(NONE);
+    }
+    let mut res = NONE;
+    for flag in flags.split(',') {
+        let bit = FLAG_NAMES
+            .iter()
+            .find(|(_, name)| *name == flag.to_lowercase())
+            .map(|(bit, _)| *bit)
+            .ok_or(format!("invalid command flag: {}", flag))?;
+        res |= bit;
+    }
+    Ok(res)
+}
+
+// The built-in commands can be extended or overridden by config, e.g. for module commands.
+#[derive(Debug, Default)]
+pub struct CommandTable {
+    extra: HashMap<String, &'static CommandInfo>,
+}
+
+impl CommandTable {
+    // Each command is in the format of `<name> <arity> <flags> <first_key> <last_key> <step>`.
+    // The flags are separated by `,` or just `-` for no flag.
+    pub fn from_config(extra_commands: Vec<String>) -> Result<Self, String> {
+        let mut extra = HashMap::new();
+        for extra_command in extra_commands.iter() {
+            let segs: Vec<&str> = extra_command.split_whitespace().collect();
+            let (name, arity, flags, first_key, last_key, step) = match segs.as_slice() {
+                [name, arity, flags, first_key, last_key, step] => {
+                    (name, arity, flags, first_key, last_key, step)
+                }
+                _ => return Err(format!("invalid command: {}", extra_command)),
+            };
+            let err = || format!("invalid command: {}", extra_command);
+            let name = name.to_uppercase();
+            if name.len() > MAX_COMMAND_NAME_LENGTH {
+                return Err(err());
+            }
+            let info = CommandInfo {
+                // The table lives as long as the process so it's fine to leak it.
+                name: Box::leak(name.clone().into_boxed_str()),
+                arity: arity.parse().map_err(|_| err())?,
+                flags: parse_flags(flags)?,
+                first_key: first_key.parse().map_err(|_| err())?,
+                last_key: last_key.parse().map_err(|_| err())?,
+                step: step.parse().map_err(|_| err())?,
+                key_spec: KeySpec::Range,
+            };
+            extra.insert(name, &*Box::leak(Box::new(info)));
+        }
+        Ok(Self { extra })
+    }
+
+    pub fn get(&self, cmd_name: &[u8]) -> Option<&'static CommandInfo> {
+        if !self.extra.is_empty() {
+            let name = to_uppercase_name(cmd_name)?;
+            if let Some(info) = str::from_utf8(&name).ok().and_then(|n| self.extra.get(n)) {
+                return Some(info);
+            }
+        }
+        get_builtin_command_info(cmd_name)
+    }
+
+    pub fn get_all(&self) -> Vec<&'static CommandInfo> {
+        let builtin = COMMAND_TABLE
+            .iter()
+            .filter(|info| !self.extra.contains_key(info.name));
+        builtin.chain(self.extra.values().cloned()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_keys(table: &CommandTable, cmd: &[&str]) -> Vec<String> {
+        let info = table.get(cmd[0].as_bytes()).unwrap();
+        info.get_key_indexes(cmd.len(), |i| cmd.get(i).map(|e| e.as_bytes()))
+            .into_iter()
+            .map(|i| cmd[i].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_command() {
+        assert_eq!(resolve_command(b"GET", None), CommandResolution::Original);
+        assert_eq!(
+            resolve_command(b"FLUSHALL", None),
+            CommandResolution::Disabled
+        );
+
+        let mut config = ClusterConfig::default();
+        config.disabled_commands.insert("KEYS".to_string());
+        config
+            .renamed_commands
+            .insert("FLUSHALL".to_string(), "MY_FLUSHALL".to_string());
+        config
+            .renamed_commands
+            .insert("CONFIG".to_string(), "MY_CONFIG".to_string());
+        config.disabled_commands.insert("SORT".to_string());
+        config
+            .renamed_commands
+            .insert("SORT".to_string(), "MY_SORT".to_string());
+        let config = Some(&config);
+        assert_eq!(resolve_command(b"GET", config), CommandResolution::Original);
+        assert_eq!(
+            resolve_command(b"KEYS", config),
+            CommandResolution::Disabled
+        );
+        assert_eq!(
+            resolve_command(b"DEBUG", config),
+            CommandResolution::Disabled
+        );
+        assert_eq!(
+            resolve_command(b"FLUSHALL", config),
+            CommandResolution::Disabled
+        );
+        // Renaming does not enable the disabled commands.
+        assert_eq!(
+            resolve_command(b"my_flushall", config),
+            CommandResolution::Disabled
+        );
+        assert_eq!(
+            resolve_command(b"my_sort", config),
+            CommandResolution::Disabled
+        );
+        assert_eq!(
+            resolve_command(b"SORT", config),
+            CommandResolution::Disabled
+        );
+        assert_eq!(
+            resolve_command(b"CONFIG", config),
+            CommandResolution::Disabled
+        );
+        assert_eq!(
+            resolve_command(b"MY_CONFIG", config),
+            CommandResolution::Renamed("CONFIG".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_numkeys() {
+        let table = CommandTable::default();
+        let check = |cmd: &[&str]| {
+            let info = table.get(cmd[0].as_bytes()).unwrap();
+            info.check_numkeys(cmd.len(), |i| cmd.get(i).map(|e| e.as_bytes()))
+        };
+        assert!(check(&["EVAL", "script", "2", "a", "b"]));
+        assert!(check(&["EVAL", "script", "0"]));
+        assert!(check(&["EVAL", "script", "not_number", "a"]));
+        assert!(check(&["GET", "a"]));
+        assert!(!check(&["EVAL", "script", "3", "a", "b"]));
+        assert!(!check(&["EVAL", "script", "18446744073709551615", "a"]));
+        assert!(!check(&["ZUNIONSTORE", "d", "3", "a", "b"]));
+    }
+
+    #[test]
+    fn test_command_table_sorted() {
+        for pair in COMMAND_TABLE.windows(2) {
+            if let [a, b] = pair {
+                assert!(a.name < b.name, "{} {}", a.name, b.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_key_indexes() {
+        let table = CommandTable::default();
+        assert_eq!(get_keys(&table, &["get", "a"]), vec!["a"]);
+        assert!(get_keys(&table, &["ping"]).is_empty());
+        assert_eq!(
+            get_keys(&table, &["MSET", "a", "1", "b", "2"]),
+            vec!["a", "b"]
+        );
+        assert_eq!(get_keys(&table, &["BLPOP", "a", "b", "0"]), vec!["a", "b"]);
+        assert_eq!(
+            get_keys(&table, &["EVAL", "script", "2", "a", "b", "arg"]),
+            vec!["a", "b"]
+        );
+        assert!(get_keys(&table, &["EVAL", "script", "0", "arg"]).is_empty());
+        assert!(get_keys(&table, &["EVAL", "script", "18446744073709551615", "a"]).is_empty());
+        assert_eq!(
+            get_keys(
+                &table,
+                &["ZUNIONSTORE", "d", "2", "a", "b", "WEIGHTS", "1", "2"]
+            ),
+            vec!["d", "a", "b"]
+        );
+        assert_eq!(
+            get_keys(
+                &table,
+                &["XREAD", "COUNT", "2", "STREAMS", "a", "b", "0", "0"]
+            ),
+            vec!["a", "b"]
+        );
+        assert_eq!(get_keys(&table, &["OBJECT", "ENCODING", "a"]), vec!["a"]);
+        assert_eq!(get_keys(&table, &["MEMORY", "USAGE", "a"]), vec!["a"]);
+        assert!(get_keys(&table, &["MEMORY", "STATS"]).is_empty());
+        assert_eq!(
+            get_keys(
+                &table,
+                &["GEORADIUS", "a", "0", "0", "1", "km", "STORE", "b"]
+            ),
+            vec!["a", "b"]
+        );
+        assert_eq!(get_keys(&table, &["SORT", "a"]), vec!["a"]);
+    }
+
+    #[test]
+    fn test_check_arity() {
+        let get = get_builtin_command_info(b"get").unwrap();
+        assert!(get.check_arity(2));
+        assert!(!get.check_arity(3));
+        let mget = get_builtin_command_info(b"mget").unwrap();
+        assert!(!mget.check_arity(1));
+        assert!(mget.check_arity(3));
+        assert!(get_builtin_command_info(b"notexist").is_none());
+    }
+
+    #[test]
+    fn test_extra_commands() {
+        let table = CommandTable::from_config(vec![
+            "JSON.GET -2 readonly 1 1 1".to_string(),
+            "GET 2 readonly,write 1 1 1".to_string(),
+        ])
+        .unwrap();
+        let info = table.get(b"json.get").unwrap();
+        assert!(info.is_readonly());
+        assert_eq!(get_keys(&table, &["JSON.GET", "a", "path"]), vec!["a"]);
+        assert!(table.get(b"get").unwrap().is_write());
+        assert_eq!(table.get_all().len(), COMMAND_TABLE.len() + 1);
+
+        assert!(CommandTable::from_config(vec!["JSON.GET -2".to_string()]).is_err());
+        assert!(CommandTable::from_config(vec!["JSON.GET -2 unknown 1 1 1".to_string()]).is_err());
+    }
+}
@@ -20,6 +20,10 @@ impl CmdCompressor {
         Self { meta_map }
     }
 
+    pub fn is_enabled(&self, dbname: &DBName) -> bool {
+        get_strategy(dbname, &self.meta_map) != CompressionStrategy::Disabled
+    }
+
     pub fn try_compressing_cmd_ctx(&self, cmd_ctx: &mut CmdCtx) -> Result<(), CompressionError> {
         let strategy = get_strategy(&cmd_ctx.get_db_name(), &self.meta_map);
 
@@ -19,6 +19,7 @@ pub const DEFAULT_DB: &str = "admin";
 pub enum DBError {
     OldEpoch,
     TryAgain,
+    SharedNodeMigration(DBName),
 }
 
 impl fmt::Display for DBError {
@@ -42,6 +43,18 @@ pub trait DBTag {
     fn set_db_name(&mut self, db: DBName);
 }
 
+// Where the slot of a database is served.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlotLocation {
+    // The address of the backend redis.
+    Local(String),
+    // The address of the peer server proxy.
+    Remote(String),
+    Migrating,
+    NotCovered,
+    DBNotFound,
+}
+
 pub struct DatabaseMap<S: CmdTaskSender>
 where
     <S as CmdTaskSender>::Task: DBTag,
@@ -144,6 +157,28 @@ where
         }
     }
 
+    pub fn locate_slot(&self, db_name: &DBName, slot: usize) -> SlotLocation {
+        let local_db = self.local_dbs.get(db_name);
+        if let Some(address) = local_db.and_then(|db| db.local_db.slot_map.get(slot)) {
+            return SlotLocation::Local(address.to_string());
+        }
+        match self.remote_dbs.get(db_name) {
+            Some(remote_db) => match remote_db.slot_map.get(slot) {
+                Some(address) => SlotLocation::Remote(address.to_string()),
+                None => SlotLocation::NotCovered,
+            },
+            None if local_db.is_some() => SlotLocation::NotCovered,
+            None => SlotLocation::DBNotFound,
+        }
+    }
+
+    pub fn get_local_nodes(&self, db_name: &DBName) -> Vec<String> {
+        match self.local_dbs.get(db_name) {
+            Some(db) => db.slot_ranges.keys().cloned().collect(),
+            None => vec![],
+        }
+    }
+
     pub fn get_dbs(&self) -> Vec<DBName> {
         self.local_dbs.keys().cloned().collect()
     }
@@ -1,29 +1,62 @@
-use super::backend::{CmdTask, CmdTaskFactory};
-use super::command::{CmdReplyReceiver, CmdType, DataCmdType, TaskResult};
+use super::backend::{CmdTask, CmdTaskFactory, CmdTaskResult};
+use super::client::{format_client_info, is_valid_client_name, ClientFilter, ClientRegistry};
+use super::command::{CmdReplyReceiver, CmdType, Command, CommandError, DataCmdType, TaskResult};
+use super::command_table::CommandResolution;
 use super::compress::{CmdCompressor, CompressionError};
-use super::database::{DBError, DBTag};
+use super::database::{DBError, DBTag, SlotLocation};
+use super::key_prefix::{
+    add_subscriber_prefix, check_prefixed_cmd, gen_prefix_pattern, strip_key_prefix,
+};
 use super::manager::{MetaManager, SharedMetaMap};
+use super::pubsub::{SubscriberConn, Subscription};
+use super::rate_limit::RateLimitExceeded;
+use super::scan::{ScanCursor, MAX_SCAN_NODE_NUM};
+use super::script::ScriptCache;
 use super::service::ServerProxyConfig;
-use super::session::{CmdCtx, CmdCtxFactory, CmdCtxHandler, CmdReplyFuture};
+use super::session::{ClientAuth, CmdCtx, CmdCtxFactory, CmdCtxHandler, CmdReplyFuture};
 use super::slowlog::{slowlogs_to_resp, SlowRequestLogger};
+use super::transaction::QueuedTransaction;
 use crate::common::cluster::DBName;
 use crate::common::db::ProxyDBMeta;
 use crate::common::track::TrackedFutureRegistry;
 use crate::common::utils::{
-    str_ascii_case_insensitive_eq, NOT_READY_FOR_SWITCHING_REPLY, OK_REPLY, OLD_EPOCH_REPLY,
-    TRY_AGAIN_REPLY,
+    gen_moved, get_slot, random_index, str_ascii_case_insensitive_eq,
+    NOT_READY_FOR_SWITCHING_REPLY, OK_REPLY, OLD_EPOCH_REPLY, TRY_AGAIN_REPLY,
 };
 use crate::common::version::UNDERMOON_VERSION;
 use crate::migration::manager::SwitchError;
 use crate::migration::task::parse_switch_command;
 use crate::migration::task::MgrSubCmd;
-use crate::protocol::{Array, BulkStr, RedisClientFactory, Resp, RespVec};
+use crate::protocol::{
+    Array, BinSafeStr, BulkStr, RedisClient, RedisClientError, RedisClientFactory, Resp, RespVec,
+    RespVersion,
+};
 use crate::replication::replicator::ReplicatorMeta;
 use atoi::atoi;
 use btoi::btou;
 use futures::future;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
 use std::str;
 use std::sync::{self, Arc};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+#[derive(Debug, Hash, PartialEq, Eq)]
+enum KeyGroup {
+    Node(String),
+    Slot(usize),
+}
+
+type SubCmdFuture = Pin<Box<dyn Future<Output = CmdTaskResult> + Send + 'static>>;
+
+// The extra time to wait for the reply of a blocking command after its own timeout.
+const BLOCKING_CMD_TIMEOUT_MARGIN: Duration = Duration::from_secs(1);
+
+const INVALID_NUMKEYS_REPLY: &[u8] = b"ERR Number of keys can't be greater than number of args";
+const TIMEOUT_OUT_OF_RANGE_REPLY: &[u8] = b"ERR timeout is out of range";
 
 pub struct SharedForwardHandler<F: RedisClientFactory> {
     handler: sync::Arc<ForwardHandler<F>>,
@@ -41,17 +74,21 @@ impl<F: RedisClientFactory> SharedForwardHandler<F> {
     pub fn new(
         config: Arc<ServerProxyConfig>,
         client_factory: Arc<F>,
+        blocking_client_factory: Arc<F>,
         slow_request_logger: Arc<SlowRequestLogger>,
         meta_map: SharedMetaMap,
         future_registry: Arc<TrackedFutureRegistry>,
+        client_registry: Arc<ClientRegistry>,
     ) -> Self {
         Self {
             handler: sync::Arc::new(ForwardHandler::new(
                 config,
                 client_factory,
+                blocking_client_factory,
                 slow_request_logger,
                 meta_map,
                 future_registry,
+                client_registry,
             )),
         }
     }
@@ -66,62 +103,363 @@ impl<F: RedisClientFactory> CmdCtxHandler for SharedForwardHandler<F> {
 pub struct ForwardHandler<F: RedisClientFactory> {
     config: Arc<ServerProxyConfig>,
     manager: MetaManager<F>,
+    // For the dedicated connections of the transactions.
+    client_factory: Arc<F>,
+    // Blocking commands run on their own connections without the command timeout.
+    blocking_client_factory: Arc<F>,
     slow_request_logger: Arc<SlowRequestLogger>,
     compressor: CmdCompressor,
+    script_cache: ScriptCache,
     future_registry: Arc<TrackedFutureRegistry>,
+    client_registry: Arc<ClientRegistry>,
 }
 
 impl<F: RedisClientFactory> ForwardHandler<F> {
     pub fn new(
         config: Arc<ServerProxyConfig>,
         client_factory: Arc<F>,
+        blocking_client_factory: Arc<F>,
         slow_request_logger: Arc<SlowRequestLogger>,
         meta_map: SharedMetaMap,
         future_registry: Arc<TrackedFutureRegistry>,
+        client_registry: Arc<ClientRegistry>,
     ) -> Self {
         Self {
             config: config.clone(),
             manager: MetaManager::new(
                 config,
-                client_factory,
+                client_factory.clone(),
                 meta_map.clone(),
                 future_registry.clone(),
             ),
+            client_factory,
+            blocking_client_factory,
             slow_request_logger,
             compressor: CmdCompressor::new(meta_map),
+            script_cache: ScriptCache::default(),
             future_registry,
+            client_registry,
         }
     }
 }
 
 impl<F: RedisClientFactory> ForwardHandler<F> {
+    // AUTH [username] password
     fn handle_auth(&self, mut cmd_ctx: CmdCtx) {
-        let key = cmd_ctx.get_key();
-        let db = match key {
-            None => {
+        let cmd = cmd_ctx.get_cmd();
+        let (username, password) = match cmd.get_argc() {
+            2 => (None, cmd.get_command_element(1)),
+            3 => (cmd.get_command_element(1), cmd.get_command_element(2)),
+            _ => {
                 return cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    String::from("Missing database name").into_bytes(),
+                    b"ERR wrong number of arguments for 'auth' command".to_vec(),
                 )))
             }
-            Some(db_name) => match str::from_utf8(&db_name) {
-                Ok(db) => db.to_string(),
-                Err(_) => {
+        };
+        let username = username.map(|name| name.to_vec());
+        let password = password
+            .map(|password| password.to_vec())
+            .unwrap_or_default();
+        let reply = match self.authenticate(&mut cmd_ctx, username, password) {
+            Ok(()) => Resp::Simple(OK_REPLY.to_string().into_bytes()),
+            Err(err) => err,
+        };
+        cmd_ctx.set_resp_result(Ok(reply));
+    }
+
+    // The username is the database name. Without the username,
+    // the password is checked against the current database such as the auto selected one,
+    // and then used as the database name for the databases without passwords.
+    fn authenticate(
+        &self,
+        cmd_ctx: &mut CmdCtx,
+        username: Option<BinSafeStr>,
+        password: BinSafeStr,
+    ) -> Result<(), RespVec> {
+        let wrong_pass = || Resp::Error(b"WRONGPASS invalid username-password pair".to_vec());
+        // "default" is the username used by the clients by default.
+        let username = username.filter(|name| !name.eq_ignore_ascii_case(b"default"));
+
+        if username.is_none() && self.is_admin_password(&password) {
+            cmd_ctx
+                .get_client()
+                .write()
+                .expect("ForwardHandler::authenticate")
+                .set_admin();
+            return Ok(());
+        }
+
+        // Without the username, the password is used as the database name
+        // so it can't be used to authenticate the database.
+        let (db_name, db_password) = match username {
+            Some(username) => (
+                Self::parse_db_name(&username).map_err(|_| wrong_pass())?,
+                Some(password),
+            ),
+            None => {
+                let current_db = cmd_ctx.get_db_name();
+                if let Some(readonly) = self.manager.check_password(&current_db, &password) {
+                    Self::set_client_auth(cmd_ctx, current_db, readonly);
+                    return Ok(());
+                }
+                let db_name = Self::parse_db_name(&password);
+                let db_name = if self.manager.requires_auth(&current_db) {
+                    db_name.map_err(|_| wrong_pass())?
+                } else {
+                    db_name?
+                };
+                (db_name, None)
+            }
+        };
+
+        if !self.manager.requires_auth(&db_name) {
+            self.check_max_clients(cmd_ctx, &db_name)?;
+            cmd_ctx.set_db_name(db_name);
+            return Ok(());
+        }
+        let readonly = db_password
+            .and_then(|password| self.manager.check_password(&db_name, &password))
+            .ok_or_else(wrong_pass)?;
+        self.check_max_clients(cmd_ctx, &db_name)?;
+        Self::set_client_auth(cmd_ctx, db_name, readonly);
+        Ok(())
+    }
+
+    fn is_admin_password(&self, password: &[u8]) -> bool {
+        match self.config.admin_password.as_ref() {
+            Some(admin_password) => admin_password.matches(password),
+            None => false,
+        }
+    }
+
+    // The clients switching to another database are limited by `max_clients` of the cluster.
+    fn check_max_clients(&self, cmd_ctx: &CmdCtx, db_name: &DBName) -> Result<(), RespVec> {
+        if cmd_ctx.get_db_name() == *db_name {
+            return Ok(());
+        }
+        let max_clients = self.manager.get_max_clients(db_name);
+        if max_clients == 0
+            || (self.client_registry.get_db_client_num(db_name) as u64) < max_clients
+        {
+            return Ok(());
+        }
+        self.client_registry.on_cluster_client_rejected();
+        Err(Resp::Error(
+            b"ERR max number of clients of the cluster reached".to_vec(),
+        ))
+    }
+
+    fn set_client_auth(cmd_ctx: &mut CmdCtx, db_name: DBName, readonly: bool) {
+        cmd_ctx
+            .get_client()
+            .write()
+            .expect("ForwardHandler::set_client_auth")
+            .set_auth(ClientAuth {
+                db: db_name.clone(),
+                readonly,
+            });
+        cmd_ctx.set_db_name(db_name);
+    }
+
+    fn parse_db_name(db_name: &[u8]) -> Result<DBName, RespVec> {
+        let db_name = str::from_utf8(db_name)
+            .map_err(|_| Resp::Error(String::from("Invalid database name").into_bytes()))?;
+        DBName::from(db_name)
+            .map_err(|_| Resp::Error(String::from("Database name is too long").into_bytes()))
+    }
+
+    // Returns None if the command is disabled.
+    fn resolve_command(&self, cmd_ctx: CmdCtx) -> Option<CmdCtx> {
+        if cmd_ctx.get_cmd_type() == CmdType::UmCtl {
+            return Some(cmd_ctx);
+        }
+        let resolution = match cmd_ctx.get_cmd().get_command_element(0) {
+            Some(cmd_name) => self
+                .manager
+                .resolve_command(&cmd_ctx.get_db_name(), cmd_name),
+            None => return Some(cmd_ctx),
+        };
+        match resolution {
+            CommandResolution::Original => Some(cmd_ctx),
+            CommandResolution::Renamed(original) => {
+                Some(cmd_ctx.rename_cmd(original.into_bytes(), &self.config.command_table))
+            }
+            CommandResolution::Disabled => {
+                let err = format!(
+                    "ERR command '{}' is disabled",
+                    cmd_ctx.get_cmd().get_command_name().unwrap_or("")
+                );
+                cmd_ctx.set_resp_result(Ok(Resp::Error(err.into_bytes())));
+                None
+            }
+        }
+    }
+
+    // Returns None if the command is rejected.
+    fn check_permission(&self, cmd_ctx: CmdCtx) -> Option<CmdCtx> {
+        match cmd_ctx.get_cmd_type() {
+            CmdType::Auth | CmdType::Hello | CmdType::Quit => return Some(cmd_ctx),
+            _ => (),
+        }
+
+        let db_name = cmd_ctx.get_db_name();
+        let (readonly, is_admin) = {
+            let client = cmd_ctx.get_client();
+            let client = client.read().expect("ForwardHandler::check_permission");
+            let readonly = match client.get_auth() {
+                Some(auth) if auth.db == db_name => Some(auth.readonly),
+                _ => None,
+            };
+            (readonly, client.is_admin())
+        };
+
+        // UMCTL changes the metadata of all the clusters including their passwords,
+        // so it's only allowed after authenticated by the admin password.
+        if cmd_ctx.get_cmd_type() == CmdType::UmCtl {
+            if is_admin {
+                return Some(cmd_ctx);
+            }
+            let err: &[u8] = if self.config.admin_password.is_none() {
+                b"NOPERM UMCTL is disabled since admin_password is not set"
+            } else {
+                b"NOPERM this user has no permissions to run the 'UMCTL' command"
+            };
+            cmd_ctx.set_resp_result(Ok(Resp::Error(err.to_vec())));
+            return None;
+        }
+
+        match readonly {
+            None if self.manager.requires_auth(&db_name) => {
+                cmd_ctx
+                    .set_resp_result(Ok(Resp::Error(b"NOAUTH Authentication required.".to_vec())));
+                None
+            }
+            Some(true) if !is_readonly_cmd(cmd_ctx.get_cmd()) => {
+                let err = format!(
+                    "NOPERM this user has no permissions to run the '{}' command",
+                    cmd_ctx.get_cmd().get_command_name().unwrap_or("")
+                );
+                cmd_ctx.set_resp_result(Ok(Resp::Error(err.into_bytes())));
+                None
+            }
+            _ => Some(cmd_ctx),
+        }
+    }
+
+    // Returns None if the command is rejected,
+    // otherwise returns the time the command needs to wait until.
+    fn check_rate_limit(&self, cmd_ctx: CmdCtx) -> Option<(CmdCtx, Option<Instant>)> {
+        let now = Instant::now();
+        let wait = if cmd_ctx.get_cmd_type() == CmdType::Others {
+            let bytes = cmd_ctx.get_cmd().get_data_size() as u64;
+            match self.manager.check_rate_limit(&cmd_ctx.get_db_name(), bytes) {
+                Ok(wait) => wait,
+                Err(RateLimitExceeded) => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(
+                        b"ERR max request rate of the cluster exceeded".to_vec(),
+                    )));
+                    return None;
+                }
+            }
+        } else {
+            Duration::from_secs(0)
+        };
+        let deadline = cmd_ctx
+            .get_client()
+            .write()
+            .expect("ForwardHandler::check_rate_limit")
+            .throttle(now + wait, now);
+        Some((cmd_ctx, deadline))
+    }
+
+    fn handle_hello(&self, mut cmd_ctx: CmdCtx) {
+        let mut resp_version = cmd_ctx.get_resp_version();
+
+        if let Some(protover) = cmd_ctx.get_cmd().get_command_element(1) {
+            resp_version = match RespVersion::from_protover(protover) {
+                Some(v) => v,
+                None => {
                     return cmd_ctx.set_resp_result(Ok(Resp::Error(
-                        String::from("Invalid database name").into_bytes(),
+                        b"NOPROTO unsupported protocol version".to_vec(),
                     )))
                 }
-            },
-        };
-        let dbname = match DBName::from(&db) {
-            Ok(dbname) => dbname,
-            _err => {
+            };
+        }
+
+        let mut auth = None;
+        let mut client_name = None;
+        let mut i = 2;
+        while let Some(option) = cmd_ctx.get_cmd().get_command_element(i) {
+            let option = String::from_utf8_lossy(option).to_string();
+            let cmd = cmd_ctx.get_cmd();
+            if str_ascii_case_insensitive_eq(&option, "AUTH") {
+                match (
+                    cmd.get_command_element(i + 1),
+                    cmd.get_command_element(i + 2),
+                ) {
+                    (Some(username), Some(password)) => {
+                        auth = Some((username.to_vec(), password.to_vec()))
+                    }
+                    _ => {
+                        return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                            b"ERR Syntax error in HELLO option 'AUTH'".to_vec(),
+                        )))
+                    }
+                }
+                i += 3;
+            } else if str_ascii_case_insensitive_eq(&option, "SETNAME")
+                && cmd.get_command_element(i + 1).is_some()
+            {
+                let name = cmd.get_command_element(i + 1).unwrap_or_default();
+                if !is_valid_client_name(name) {
+                    return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                        b"ERR Client names cannot contain spaces, newlines or special characters."
+                            .to_vec(),
+                    )));
+                }
+                client_name = Some(name.to_vec());
+                i += 2;
+            } else {
                 return cmd_ctx.set_resp_result(Ok(Resp::Error(
-                    String::from("Database name is too long").into_bytes(),
-                )))
+                    format!("ERR Syntax error in HELLO option '{}'", option).into_bytes(),
+                )));
             }
-        };
-        cmd_ctx.set_db_name(dbname);
-        cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())));
+        }
+
+        if let Some((username, password)) = auth {
+            if let Err(err) = self.authenticate(&mut cmd_ctx, Some(username), password) {
+                return cmd_ctx.set_resp_result(Ok(err));
+            }
+        }
+        if let Some(name) = client_name {
+            let name = if name.is_empty() { None } else { Some(name) };
+            cmd_ctx
+                .get_client()
+                .write()
+                .expect("ForwardHandler::handle_hello")
+                .set_name(name);
+        }
+        cmd_ctx.set_resp_version(resp_version);
+
+        let bulk = |s: &str| Resp::Bulk(BulkStr::Str(s.to_string().into_bytes()));
+        // The session will convert it to an array for RESP2.
+        let reply = Resp::Map(vec![
+            bulk("server"),
+            bulk("undermoon"),
+            bulk("version"),
+            bulk(UNDERMOON_VERSION),
+            bulk("proto"),
+            Resp::Integer(resp_version.to_protover().to_string().into_bytes()),
+            bulk("id"),
+            Resp::Integer(cmd_ctx.get_session_id().to_string().into_bytes()),
+            bulk("mode"),
+            bulk("cluster"),
+            bulk("role"),
+            bulk("master"),
+            bulk("modules"),
+            Resp::Arr(Array::Arr(vec![])),
+        ]);
+        cmd_ctx.set_resp_result(Ok(reply));
     }
 
     fn handle_cluster(&self, cmd_ctx: CmdCtx) {
@@ -200,6 +538,10 @@ impl<F: RedisClientFactory> ForwardHandler<F> {
             self.handle_umctl_slowlog(cmd_ctx);
         } else if sub_cmd.eq("DEBUG") {
             self.handle_umctl_debug(cmd_ctx);
+        } else if sub_cmd.eq("LISTCLIENT") {
+            self.handle_umctl_list_client(cmd_ctx);
+        } else if sub_cmd.eq("KILLCLIENT") {
+            self.handle_umctl_kill_client(cmd_ctx);
         } else {
             cmd_ctx.set_resp_result(Ok(Resp::Error(
                 String::from("Invalid sub command").into_bytes(),
@@ -231,12 +573,7 @@ impl<F: RedisClientFactory> ForwardHandler<F> {
                     )));
                 }
             },
-            Err(err) => match err {
-                DBError::OldEpoch => cmd_ctx
-                    .set_resp_result(Ok(Resp::Error(OLD_EPOCH_REPLY.to_string().into_bytes()))),
-                DBError::TryAgain => cmd_ctx
-                    .set_resp_result(Ok(Resp::Error(TRY_AGAIN_REPLY.to_string().into_bytes()))),
-            },
+            Err(err) => cmd_ctx.set_resp_result(Ok(gen_db_error_reply(err))),
         }
     }
 
@@ -258,12 +595,7 @@ impl<F: RedisClientFactory> ForwardHandler<F> {
             }
             Err(e) => {
                 //                debug!("Failed to update replicator meta data {:?}", e);
-                match e {
-                    DBError::OldEpoch => cmd_ctx
-                        .set_resp_result(Ok(Resp::Error(OLD_EPOCH_REPLY.to_string().into_bytes()))),
-                    DBError::TryAgain => cmd_ctx
-                        .set_resp_result(Ok(Resp::Error(TRY_AGAIN_REPLY.to_string().into_bytes()))),
-                }
+                cmd_ctx.set_resp_result(Ok(gen_db_error_reply(e)))
             }
         }
     }
@@ -338,6 +670,44 @@ impl<F: RedisClientFactory> ForwardHandler<F> {
         }
     }
 
+    // UMCTL LISTCLIENT [<filter> <value> ...]
+    fn handle_umctl_list_client(&self, cmd_ctx: CmdCtx) {
+        let elements = Self::get_cmd_elements(&cmd_ctx);
+        let args: Vec<&[u8]> = elements.iter().skip(2).map(|e| e.as_slice()).collect();
+        let reply = match ClientFilter::parse(&args, None) {
+            Ok(filter) => {
+                Self::gen_client_list_reply(self.client_registry.get_client_list(&filter))
+            }
+            Err(err) => Resp::Error(err.into_bytes()),
+        };
+        cmd_ctx.set_resp_result(Ok(reply))
+    }
+
+    // UMCTL KILLCLIENT <filter> <value> [<filter> <value> ...]
+    // Killing all the clients is not allowed so at least one filter is required.
+    fn handle_umctl_kill_client(&self, cmd_ctx: CmdCtx) {
+        let elements = Self::get_cmd_elements(&cmd_ctx);
+        let args: Vec<&[u8]> = elements.iter().skip(2).map(|e| e.as_slice()).collect();
+        let reply = if args.is_empty() {
+            Resp::Error(b"ERR missing client filter".to_vec())
+        } else {
+            match ClientFilter::parse(&args, None) {
+                Ok(filter) => {
+                    let killed = self.client_registry.kill(&filter);
+                    info!("killed {} clients by UMCTL", killed);
+                    Resp::Integer(killed.to_string().into_bytes())
+                }
+                Err(err) => Resp::Error(err.into_bytes()),
+            }
+        };
+        cmd_ctx.set_resp_result(Ok(reply))
+    }
+
+    fn gen_client_list_reply(lines: Vec<String>) -> RespVec {
+        let list: String = lines.into_iter().map(|line| line + "\n").collect();
+        Resp::Bulk(BulkStr::Str(list.into_bytes()))
+    }
+
     fn handle_umctl_debug(&self, cmd_ctx: CmdCtx) {
         let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 2) {
             Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd),
@@ -408,161 +778,1107 @@ impl<F: RedisClientFactory> ForwardHandler<F> {
         }
     }
 
-    fn handle_data_cmd(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
-        match cmd_ctx.get_data_cmd_type() {
-            DataCmdType::MGET => {
-                CmdReplyFuture::Right(Box::pin(self.handle_mget(cmd_ctx, reply_receiver)))
+    fn handle_command(&self, cmd_ctx: CmdCtx) {
+        let table = &self.config.command_table;
+        if cmd_ctx.get_cmd().get_command_element(1).is_none() {
+            let infos = table
+                .get_all()
+                .into_iter()
+                .map(|info| info.to_resp())
+                .collect();
+            return cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(infos))));
+        }
+
+        let (cmd_ctx, sub_cmd) = match Self::get_sub_command(cmd_ctx, 1) {
+            Some((cmd_ctx, sub_cmd)) => (cmd_ctx, sub_cmd.to_uppercase()),
+            None => return,
+        };
+
+        let cmd = cmd_ctx.get_cmd();
+        let reply = if sub_cmd.eq("COUNT") {
+            Resp::Integer(table.get_all().len().to_string().into_bytes())
+        } else if sub_cmd.eq("INFO") {
+            let infos = (2..cmd.get_argc())
+                .map(|i| {
+                    cmd.get_command_element(i)
+                        .and_then(|cmd_name| table.get(cmd_name))
+                        .map(|info| info.to_resp())
+                        .unwrap_or(Resp::Bulk(BulkStr::Nil))
+                })
+                .collect();
+            Resp::Arr(Array::Arr(infos))
+        } else if sub_cmd.eq("GETKEYS") {
+            // The command to get keys from starts at index 2.
+            let argc = cmd.get_argc().saturating_sub(2);
+            match cmd
+                .get_command_element(2)
+                .and_then(|cmd_name| table.get(cmd_name))
+            {
+                None => Resp::Error(b"Invalid command specified".to_vec()),
+                Some(info) if !info.check_arity(argc) => {
+                    Resp::Error(b"Invalid number of arguments specified for command".to_vec())
+                }
+                Some(info) => {
+                    let keys: Vec<RespVec> = info
+                        .get_key_indexes(argc, |i| cmd.get_command_element(i + 2))
+                        .into_iter()
+                        .filter_map(|i| cmd.get_command_element(i + 2))
+                        .map(|key| Resp::Bulk(BulkStr::Str(key.to_vec())))
+                        .collect();
+                    if keys.is_empty() {
+                        Resp::Error(b"The command has no key arguments".to_vec())
+                    } else {
+                        Resp::Arr(Array::Arr(keys))
+                    }
+                }
             }
-            DataCmdType::MSET => {
-                CmdReplyFuture::Right(Box::pin(self.handle_mset(cmd_ctx, reply_receiver)))
+        } else {
+            Resp::Error(b"invalid command sub-command".to_vec())
+        };
+        cmd_ctx.set_resp_result(Ok(reply));
+    }
+
+    fn locate_local_slot(&self, cmd_ctx: &CmdCtx, slot: usize) -> Result<String, RespVec> {
+        let db_name = cmd_ctx.get_db_name();
+        let err_msg = match self.manager.locate_slot(&db_name, slot) {
+            SlotLocation::Local(address) => return Ok(address),
+            SlotLocation::Remote(address) => gen_moved(slot, address),
+            SlotLocation::Migrating => TRY_AGAIN_REPLY.to_string(),
+            SlotLocation::NotCovered => format!("slot not found: {}", db_name),
+            SlotLocation::DBNotFound => format!("db not found: {}", db_name),
+        };
+        Err(Resp::Error(err_msg.into_bytes()))
+    }
+
+    // All the keys of a transaction, including the watched keys, should be in the same slot
+    // so that they can be sent to a single backend redis.
+    fn bind_transaction_slot(&self, cmd_ctx: &CmdCtx) -> Result<Option<String>, RespVec> {
+        let cmd = cmd_ctx.get_cmd();
+        if cmd.is_cross_slot() {
+            return Err(Resp::Error(
+                b"CROSSSLOT Keys in request don't hash to the same slot".to_vec(),
+            ));
+        }
+        let slot = match cmd.get_keys().first() {
+            Some(key) => get_slot(key),
+            None => return Ok(None),
+        };
+        let bound = cmd_ctx
+            .get_transaction()
+            .lock()
+            .expect("ForwardHandler::bind_transaction_slot")
+            .bind_slot(slot);
+        if !bound {
+            return Err(Resp::Error(
+                b"CROSSSLOT Keys in request don't hash to the same slot".to_vec(),
+            ));
+        }
+        self.locate_local_slot(cmd_ctx, slot).map(Some)
+    }
+
+    fn get_cmd_elements(cmd_ctx: &CmdCtx) -> Vec<BinSafeStr> {
+        let cmd = cmd_ctx.get_cmd();
+        (0..cmd.get_argc())
+            .filter_map(|i| cmd.get_command_element(i).map(|e| e.to_vec()))
+            .collect()
+    }
+
+    // Same as `get_cmd_elements` but with the key prefix of the cluster.
+    fn get_backend_cmd_elements(cmd_ctx: &CmdCtx) -> Vec<BinSafeStr> {
+        let packet = cmd_ctx.get_packet();
+        (0..packet.get_array_len().unwrap_or(0))
+            .filter_map(|i| packet.get_array_element(i).map(|e| e.to_vec()))
+            .collect()
+    }
+
+    // READONLY lets the read-only commands of this session be served by the replicas.
+    fn handle_readonly(&self, cmd_ctx: CmdCtx, readonly: bool) {
+        cmd_ctx
+            .get_client()
+            .write()
+            .expect("ForwardHandler::handle_readonly")
+            .set_readonly(readonly);
+        cmd_ctx.set_resp_result(Ok(Resp::Simple(OK_REPLY.to_string().into_bytes())))
+    }
+
+    fn handle_client(&self, cmd_ctx: CmdCtx) {
+        let reply = self.run_client_subcmd(&cmd_ctx);
+        cmd_ctx.set_resp_result(Ok(reply))
+    }
+
+    // The clients of other databases are invisible to CLIENT LIST and CLIENT KILL.
+    fn run_client_subcmd(&self, cmd_ctx: &CmdCtx) -> RespVec {
+        let elements = Self::get_cmd_elements(cmd_ctx);
+        let sub_cmd = elements
+            .get(1)
+            .map(|e| e.to_ascii_uppercase())
+            .unwrap_or_default();
+        let db_name = cmd_ctx.get_db_name();
+        let client = cmd_ctx.get_client();
+        let id = cmd_ctx.get_session_id();
+        let db_filter = ClientFilter {
+            db: Some(db_name.clone()),
+            ..Default::default()
+        };
+
+        match (sub_cmd.as_slice(), elements.len()) {
+            (b"ID", 2) => Resp::Integer(id.to_string().into_bytes()),
+            (b"GETNAME", 2) => {
+                let client = client.read().expect("ForwardHandler::run_client_subcmd");
+                match client.get_name() {
+                    Some(name) => Resp::Bulk(BulkStr::Str(name.to_vec())),
+                    None => Resp::Bulk(BulkStr::Nil),
+                }
             }
-            DataCmdType::DEL if cmd_ctx.get_cmd().get_command_element(2).is_some() => {
-                CmdReplyFuture::Right(Box::pin(self.handle_multi_int_cmd(
-                    cmd_ctx,
-                    reply_receiver,
-                    "DEL",
-                )))
+            (b"SETNAME", 3) => {
+                let name = &elements[2];
+                if !is_valid_client_name(name) {
+                    return Resp::Error(
+                        b"ERR Client names cannot contain spaces, newlines or special characters."
+                            .to_vec(),
+                    );
+                }
+                // An empty name removes the name.
+                let name = if name.is_empty() {
+                    None
+                } else {
+                    Some(name.clone())
+                };
+                client
+                    .write()
+                    .expect("ForwardHandler::run_client_subcmd")
+                    .set_name(name);
+                Resp::Simple(OK_REPLY.to_string().into_bytes())
             }
-            DataCmdType::EXISTS if cmd_ctx.get_cmd().get_command_element(2).is_some() => {
-                CmdReplyFuture::Right(Box::pin(self.handle_multi_int_cmd(
-                    cmd_ctx,
-                    reply_receiver,
-                    "EXISTS",
-                )))
+            (b"INFO", 2) => {
+                let client = client.read().expect("ForwardHandler::run_client_subcmd");
+                let info = format_client_info(&db_name, &client) + "\n";
+                Resp::Bulk(BulkStr::Str(info.into_bytes()))
             }
-            _ => {
-                self.handle_single_key_data_cmd(cmd_ctx);
-                CmdReplyFuture::Left(reply_receiver)
+            (b"LIST", 2) => {
+                Self::gen_client_list_reply(self.client_registry.get_client_list(&db_filter))
+            }
+            // The old form: CLIENT KILL addr
+            (b"KILL", 3) => {
+                let filter = ClientFilter {
+                    address: Some(String::from_utf8_lossy(&elements[2]).to_string()),
+                    ..db_filter
+                };
+                if self.client_registry.kill(&filter) == 0 {
+                    Resp::Error(b"ERR No such client".to_vec())
+                } else {
+                    Resp::Simple(OK_REPLY.to_string().into_bytes())
+                }
+            }
+            (b"KILL", _) => {
+                let args: Vec<&[u8]> = elements.iter().skip(2).map(|e| e.as_slice()).collect();
+                let filter = match ClientFilter::parse(&args, Some(id)) {
+                    Ok(filter) => filter,
+                    Err(err) => return Resp::Error(err.into_bytes()),
+                };
+                if matches!(filter.db, Some(ref db) if *db != db_name) {
+                    return Resp::Integer(b"0".to_vec());
+                }
+                let filter = ClientFilter {
+                    db: Some(db_name),
+                    ..filter
+                };
+                let killed = self.client_registry.kill(&filter);
+                Resp::Integer(killed.to_string().into_bytes())
             }
+            _ => Resp::Error(b"ERR unsupported CLIENT subcommand".to_vec()),
         }
     }
 
-    async fn handle_mget(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
-        let factory = CmdCtxFactory::default();
-        let mut futs = vec![];
-        for i in 1.. {
-            let key = match cmd_ctx.get_cmd().get_command_element(i) {
-                Some(key) => key,
-                None => break,
-            };
-            let resp = Resp::Arr(Array::Arr(vec![
-                Resp::Bulk(BulkStr::Str(b"GET".to_vec())),
-                Resp::Bulk(BulkStr::Str(key.to_vec())),
-            ]));
-            let (sub_cmd_ctx, fut) = factory.create_with(&cmd_ctx, resp);
-            futs.push(fut);
-            self.handle_single_key_data_cmd(sub_cmd_ctx);
-        }
+    fn handle_multi(&self, cmd_ctx: CmdCtx) {
+        let started = cmd_ctx
+            .get_transaction()
+            .lock()
+            .expect("ForwardHandler::handle_multi")
+            .start_multi();
+        let reply = if started {
+            Resp::Simple(OK_REPLY.to_string().into_bytes())
+        } else {
+            Resp::Error(b"ERR MULTI calls can not be nested".to_vec())
+        };
+        cmd_ctx.set_resp_result(Ok(reply))
+    }
 
-        if futs.is_empty() {
-            cmd_ctx.set_resp_result(Ok(Resp::Error(
-                b"ERR wrong number of arguments for 'mget' command".to_vec(),
-            )));
-            return reply_receiver.await;
-        }
+    fn queue_transaction_cmd(&self, cmd_ctx: CmdCtx) {
+        let res = match cmd_ctx.get_cmd().get_type() {
+            CmdType::Others | CmdType::Ping | CmdType::Echo | CmdType::Unwatch => {
+                check_transaction_cmd(cmd_ctx.get_cmd(), cmd_ctx.get_key_prefix().is_some())
+                    .and_then(|()| self.bind_transaction_slot(&cmd_ctx).map(|_| ()))
+            }
+            _ => Err(Resp::Error(
+                b"ERR Command not allowed inside a transaction".to_vec(),
+            )),
+        };
 
-        let mut values = vec![];
-        let res = future::join_all(futs).await;
-        for sub_result in res.into_iter() {
-            let reply = match sub_result {
-                Ok(reply) => reply,
-                Err(err) => return Err(err),
-            };
-            if let Resp::Error(err) = &reply {
-                cmd_ctx.set_resp_result(Ok(Resp::Error(err.clone())));
-                return reply_receiver.await;
+        let transaction = cmd_ctx.get_transaction();
+        let mut transaction = transaction
+            .lock()
+            .expect("ForwardHandler::queue_transaction_cmd");
+        match res {
+            Ok(()) => {
+                transaction.queue(Self::get_backend_cmd_elements(&cmd_ctx));
+                cmd_ctx.set_resp_result(Ok(Resp::Simple(b"QUEUED".to_vec())))
+            }
+            Err(err) => {
+                transaction.abort();
+                cmd_ctx.set_resp_result(Ok(err))
             }
-            values.push(reply);
         }
+    }
 
-        let resp = Resp::Arr(Array::Arr(values));
-        cmd_ctx.set_resp_result(Ok(resp));
-        reply_receiver.await
+    fn handle_watch(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
+        let in_multi = cmd_ctx
+            .get_transaction()
+            .lock()
+            .expect("ForwardHandler::handle_watch")
+            .is_in_multi();
+        let res = if in_multi {
+            Err(Resp::Error(
+                b"ERR WATCH inside MULTI is not allowed".to_vec(),
+            ))
+        } else if !cmd_ctx.get_cmd().check_arity() {
+            Err(Resp::Error(
+                b"ERR wrong number of arguments for 'watch' command".to_vec(),
+            ))
+        } else {
+            self.bind_transaction_slot(&cmd_ctx)
+        };
+        let address = match res {
+            Ok(Some(address)) => address,
+            Ok(None) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR missing key".to_vec())));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            Err(err) => {
+                cmd_ctx.set_resp_result(Ok(err));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+        };
+        cmd_ctx
+            .get_transaction()
+            .lock()
+            .expect("ForwardHandler::handle_watch")
+            .set_watching();
+        CmdReplyFuture::Right(Box::pin(self.watch_keys(cmd_ctx, reply_receiver, address)))
     }
 
-    async fn handle_mset(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
-        let factory = CmdCtxFactory::default();
-        let mut futs = vec![];
-        for i in 0.. {
-            let key = match cmd_ctx.get_cmd().get_command_element(2 * i + 1) {
-                Some(key) => key,
-                None => break,
-            };
-            let value = match cmd_ctx.get_cmd().get_command_element(2 * i + 2) {
-                Some(value) => value,
-                None => {
-                    // The existing sub commands will be set with Canceled.
-                    cmd_ctx.set_resp_result(Ok(Resp::Error(
-                        b"ERR wrong number of arguments for 'mset' command".to_vec(),
-                    )));
-                    return reply_receiver.await;
+    async fn watch_keys(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        address: String,
+    ) -> TaskResult {
+        let transaction = cmd_ctx.get_transaction();
+        let watch_conn = transaction
+            .lock()
+            .expect("ForwardHandler::watch_keys")
+            .take_watch_conn();
+        let mut client = match watch_conn {
+            Some((addr, client)) if addr == address => client,
+            watch_conn => {
+                release_watch_conn(watch_conn).await;
+                match self.client_factory.create_client(address.clone()).await {
+                    Ok(client) => Box::new(client) as Box<dyn RedisClient>,
+                    Err(err) => {
+                        cmd_ctx.set_resp_result(Ok(Resp::Error(
+                            format!("ERR failed to connect to backend: {:?}", err).into_bytes(),
+                        )));
+                        return reply_receiver.await;
+                    }
                 }
-            };
-            let resp = Resp::Arr(Array::Arr(vec![
-                Resp::Bulk(BulkStr::Str(b"SET".to_vec())),
-                Resp::Bulk(BulkStr::Str(key.to_vec())),
-                Resp::Bulk(BulkStr::Str(value.to_vec())),
-            ]));
-            let (sub_cmd_ctx, fut) = factory.create_with(&cmd_ctx, resp);
-            futs.push(fut);
-            self.handle_single_key_data_cmd(sub_cmd_ctx);
-        }
+            }
+        };
 
-        if futs.is_empty() {
-            cmd_ctx.set_resp_result(Ok(Resp::Error(
-                b"ERR wrong number of arguments for 'mset' command".to_vec(),
-            )));
-            return reply_receiver.await;
+        match client
+            .execute_single(Self::get_backend_cmd_elements(&cmd_ctx))
+            .await
+        {
+            Ok(reply) => {
+                transaction
+                    .lock()
+                    .expect("ForwardHandler::watch_keys")
+                    .set_watch_conn(address, client);
+                cmd_ctx.set_resp_result(Ok(reply));
+            }
+            Err(err) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    format!("ERR failed to watch keys: {:?}", err).into_bytes(),
+                )));
+            }
         }
+        reply_receiver.await
+    }
 
-        let res = future::join_all(futs).await;
-        for sub_result in res.into_iter() {
-            let reply = match sub_result {
-                Ok(reply) => reply,
-                Err(err) => return Err(err),
-            };
-            if let Resp::Error(err) = reply {
-                cmd_ctx.set_resp_result(Ok(Resp::Error(err)));
-                return reply_receiver.await;
+    fn handle_unwatch(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
+        cmd_ctx
+            .get_transaction()
+            .lock()
+            .expect("ForwardHandler::handle_unwatch")
+            .unwatch();
+        CmdReplyFuture::Right(Box::pin(async move {
+            let watch_conn = cmd_ctx
+                .get_transaction()
+                .lock()
+                .expect("ForwardHandler::handle_unwatch")
+                .take_watch_conn();
+            release_watch_conn(watch_conn).await;
+            cmd_ctx.set_resp_result(Ok(Resp::Simple(OK_REPLY.to_string().into_bytes())));
+            reply_receiver.await
+        }))
+    }
+
+    fn handle_discard(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
+        let in_multi = {
+            let transaction = cmd_ctx.get_transaction();
+            let mut transaction = transaction.lock().expect("ForwardHandler::handle_discard");
+            let in_multi = transaction.is_in_multi();
+            if in_multi {
+                transaction.finish();
             }
+            in_multi
+        };
+        if !in_multi {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR DISCARD without MULTI".to_vec())));
+            return CmdReplyFuture::Left(reply_receiver);
         }
+        // The watched keys also need to be released.
+        self.handle_unwatch(cmd_ctx, reply_receiver)
+    }
 
-        let resp = Resp::Simple(OK_REPLY.to_string().into_bytes());
-        cmd_ctx.set_resp_result(Ok(resp));
-        reply_receiver.await
+    fn handle_exec(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
+        let queued = {
+            let transaction = cmd_ctx.get_transaction();
+            let mut transaction = transaction.lock().expect("ForwardHandler::handle_exec");
+            if !transaction.is_in_multi() {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(b"ERR EXEC without MULTI".to_vec())));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            transaction.finish()
+        };
+        CmdReplyFuture::Right(Box::pin(self.exec_transaction(
+            cmd_ctx,
+            reply_receiver,
+            queued,
+        )))
     }
 
-    async fn handle_multi_int_cmd(
+    async fn exec_transaction(
         &self,
         cmd_ctx: CmdCtx,
         reply_receiver: CmdReplyReceiver,
-        cmd_name: &'static str,
+        queued: QueuedTransaction,
     ) -> TaskResult {
-        let factory = CmdCtxFactory::default();
-        let mut futs = vec![];
-        for i in 1.. {
-            let key = match cmd_ctx.get_cmd().get_command_element(i) {
-                Some(key) => key,
-                None => break,
-            };
-            let resp = Resp::Arr(Array::Arr(vec![
-                Resp::Bulk(BulkStr::Str(cmd_name.to_string().into_bytes())),
-                Resp::Bulk(BulkStr::Str(key.to_vec())),
-            ]));
-            let (sub_cmd_ctx, fut) = factory.create_with(&cmd_ctx, resp);
-            futs.push(fut);
-            self.handle_single_key_data_cmd(sub_cmd_ctx);
-        }
+        let watch_conn = cmd_ctx
+            .get_transaction()
+            .lock()
+            .expect("ForwardHandler::exec_transaction")
+            .take_watch_conn();
 
-        if futs.is_empty() {
+        let QueuedTransaction {
+            aborted,
+            cmds,
+            slot,
+            watching,
+        } = queued;
+
+        // The WATCH failed to get sent so the transaction can't be guaranteed.
+        if aborted || (watching && watch_conn.is_none()) {
+            release_watch_conn(watch_conn).await;
             cmd_ctx.set_resp_result(Ok(Resp::Error(
-                format!("ERR wrong number of arguments for '{}' command", cmd_name).into_bytes(),
+                b"EXECABORT Transaction discarded because of previous errors.".to_vec(),
             )));
             return reply_receiver.await;
         }
 
-        let mut count: usize = 0;
-        let res = future::join_all(futs).await;
+        if cmds.is_empty() {
+            release_watch_conn(watch_conn).await;
+            cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(vec![]))));
+            return reply_receiver.await;
+        }
+
+        let address = match slot.map(|slot| self.locate_local_slot(&cmd_ctx, slot)) {
+            Some(Ok(address)) => address,
+            Some(Err(err)) => {
+                release_watch_conn(watch_conn).await;
+                cmd_ctx.set_resp_result(Ok(err));
+                return reply_receiver.await;
+            }
+            None => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    b"ERR transaction without keys is not supported".to_vec(),
+                )));
+                return reply_receiver.await;
+            }
+        };
+
+        let mut client = match watch_conn {
+            Some((addr, client)) if addr == address => client,
+            // The slot has been moved to another node since WATCH.
+            Some(watch_conn) => {
+                release_watch_conn(Some(watch_conn)).await;
+                cmd_ctx.set_resp_result(Ok(Resp::Error(TRY_AGAIN_REPLY.to_string().into_bytes())));
+                return reply_receiver.await;
+            }
+            None => match self.client_factory.create_client(address).await {
+                Ok(client) => Box::new(client),
+                Err(err) => {
+                    cmd_ctx.set_resp_result(Ok(Resp::Error(
+                        format!("ERR failed to connect to backend: {:?}", err).into_bytes(),
+                    )));
+                    return reply_receiver.await;
+                }
+            },
+        };
+
+        let mut tx_cmds = Vec::with_capacity(cmds.len() + 2);
+        tx_cmds.push(vec![b"MULTI".to_vec()]);
+        tx_cmds.extend(cmds);
+        tx_cmds.push(vec![b"EXEC".to_vec()]);
+        let reply = match client.execute_multi(tx_cmds).await {
+            Ok(mut replies) => replies
+                .pop()
+                .unwrap_or_else(|| Resp::Error(b"ERR missing EXEC reply".to_vec())),
+            Err(err) => {
+                Resp::Error(format!("ERR failed to execute transaction: {:?}", err).into_bytes())
+            }
+        };
+        cmd_ctx.set_resp_result(Ok(reply));
+        reply_receiver.await
+    }
+
+    // Only the subscribe commands, PING and QUIT are allowed in the subscriber mode.
+    fn handle_subscribed_cmd(&self, cmd_ctx: CmdCtx) {
+        let cmd = cmd_ctx.get_cmd();
+        let reply = match cmd.get_type() {
+            CmdType::Ping => {
+                let msg = cmd.get_command_element(1).unwrap_or(b"").to_vec();
+                Resp::Arr(Array::Arr(vec![
+                    Resp::Bulk(BulkStr::Str(b"pong".to_vec())),
+                    Resp::Bulk(BulkStr::Str(msg)),
+                ]))
+            }
+            _ => Resp::Error(
+                format!(
+                    "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context",
+                    cmd.get_command_name().unwrap_or("").to_lowercase()
+                )
+                .into_bytes(),
+            ),
+        };
+        cmd_ctx.set_resp_result(Ok(reply))
+    }
+
+    fn get_cmd_args(cmd_ctx: &CmdCtx) -> Vec<BinSafeStr> {
+        let mut args = Self::get_cmd_elements(cmd_ctx);
+        args.drain(..1.min(args.len()));
+        args
+    }
+
+    fn handle_subscribe(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> CmdReplyFuture {
+        if !cmd_ctx.get_cmd().check_arity() {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR wrong number of arguments for 'subscribe' command".to_vec(),
+            )));
+            return CmdReplyFuture::Left(reply_receiver);
+        }
+        // Channels are located in the same way as PUBLISH.
+        let mut channels = vec![];
+        for channel in Self::get_cmd_args(&cmd_ctx) {
+            match self.locate_local_slot(&cmd_ctx, get_slot(&channel)) {
+                Ok(address) => channels.push((channel, address)),
+                Err(err) => {
+                    cmd_ctx.set_resp_result(Ok(err));
+                    return CmdReplyFuture::Left(reply_receiver);
+                }
+            }
+        }
+        CmdReplyFuture::Right(Box::pin(self.subscribe_channels(
+            cmd_ctx,
+            reply_receiver,
+            channels,
+        )))
+    }
+
+    async fn subscribe_channels(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        channels: Vec<(BinSafeStr, String)>,
+    ) -> TaskResult {
+        let subscription = cmd_ctx.get_subscription();
+        let mut groups: HashMap<String, Vec<BinSafeStr>> = HashMap::new();
+        {
+            let subscription = subscription
+                .lock()
+                .expect("ForwardHandler::subscribe_channels");
+            for (channel, address) in channels.iter() {
+                if !subscription.has_channel(channel) {
+                    groups
+                        .entry(address.clone())
+                        .or_default()
+                        .push(channel.clone());
+                }
+            }
+        }
+
+        if let Err(err) = self
+            .send_subscriber_cmd(
+                &subscription,
+                "SUBSCRIBE",
+                groups,
+                true,
+                cmd_ctx.get_key_prefix(),
+            )
+            .await
+        {
+            cmd_ctx.set_resp_result(Ok(err));
+            return reply_receiver.await;
+        }
+
+        let acks = {
+            let mut subscription = subscription
+                .lock()
+                .expect("ForwardHandler::subscribe_channels");
+            channels
+                .into_iter()
+                .map(|(channel, address)| subscription.add_channel(channel, address))
+                .collect()
+        };
+        cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(acks))));
+        reply_receiver.await
+    }
+
+    async fn unsubscribe_channels(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> TaskResult {
+        let subscription = cmd_ctx.get_subscription();
+        let mut groups: HashMap<String, Vec<BinSafeStr>> = HashMap::new();
+        let mut acks = vec![];
+        {
+            let mut subscription = subscription
+                .lock()
+                .expect("ForwardHandler::unsubscribe_channels");
+            let mut channels = Self::get_cmd_args(&cmd_ctx);
+            if channels.is_empty() {
+                channels = subscription.get_channels();
+            }
+            if channels.is_empty() {
+                acks.push(subscription.gen_ack(b"unsubscribe", None));
+            }
+            for channel in channels.into_iter() {
+                let (address, ack) = subscription.remove_channel(channel.clone());
+                if let Some(address) = address {
+                    groups.entry(address).or_default().push(channel);
+                }
+                acks.push(ack);
+            }
+        }
+
+        if let Err(err) = self
+            .send_subscriber_cmd(
+                &subscription,
+                "UNSUBSCRIBE",
+                groups,
+                false,
+                cmd_ctx.get_key_prefix(),
+            )
+            .await
+        {
+            warn!("failed to unsubscribe: {:?}", err);
+        }
+        cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(acks))));
+        reply_receiver.await
+    }
+
+    async fn subscribe_patterns(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> TaskResult {
+        let patterns = Self::get_cmd_args(&cmd_ctx);
+        if patterns.is_empty() {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR wrong number of arguments for 'psubscribe' command".to_vec(),
+            )));
+            return reply_receiver.await;
+        }
+        let db_name = cmd_ctx.get_db_name();
+        let addresses = self.manager.get_local_nodes(&db_name);
+        if addresses.is_empty() {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                format!("db not found: {}", db_name).into_bytes(),
+            )));
+            return reply_receiver.await;
+        }
+
+        let subscription = cmd_ctx.get_subscription();
+        let mut groups: HashMap<String, Vec<BinSafeStr>> = HashMap::new();
+        {
+            let subscription = subscription
+                .lock()
+                .expect("ForwardHandler::subscribe_patterns");
+            for pattern in patterns.iter().filter(|p| !subscription.has_pattern(p)) {
+                for address in addresses.iter() {
+                    groups
+                        .entry(address.clone())
+                        .or_default()
+                        .push(pattern.clone());
+                }
+            }
+        }
+
+        if let Err(err) = self
+            .send_subscriber_cmd(
+                &subscription,
+                "PSUBSCRIBE",
+                groups,
+                true,
+                cmd_ctx.get_key_prefix(),
+            )
+            .await
+        {
+            cmd_ctx.set_resp_result(Ok(err));
+            return reply_receiver.await;
+        }
+
+        let acks = {
+            let mut subscription = subscription
+                .lock()
+                .expect("ForwardHandler::subscribe_patterns");
+            patterns
+                .into_iter()
+                .map(|pattern| subscription.add_pattern(pattern, addresses.clone()))
+                .collect()
+        };
+        cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(acks))));
+        reply_receiver.await
+    }
+
+    async fn unsubscribe_patterns(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> TaskResult {
+        let subscription = cmd_ctx.get_subscription();
+        let mut groups: HashMap<String, Vec<BinSafeStr>> = HashMap::new();
+        let mut acks = vec![];
+        {
+            let mut subscription = subscription
+                .lock()
+                .expect("ForwardHandler::unsubscribe_patterns");
+            let mut patterns = Self::get_cmd_args(&cmd_ctx);
+            if patterns.is_empty() {
+                patterns = subscription.get_patterns();
+            }
+            if patterns.is_empty() {
+                acks.push(subscription.gen_ack(b"punsubscribe", None));
+            }
+            for pattern in patterns.into_iter() {
+                let (addresses, ack) = subscription.remove_pattern(pattern.clone());
+                for address in addresses.into_iter() {
+                    groups.entry(address).or_default().push(pattern.clone());
+                }
+                acks.push(ack);
+            }
+        }
+
+        if let Err(err) = self
+            .send_subscriber_cmd(
+                &subscription,
+                "PUNSUBSCRIBE",
+                groups,
+                false,
+                cmd_ctx.get_key_prefix(),
+            )
+            .await
+        {
+            warn!("failed to unsubscribe patterns: {:?}", err);
+        }
+        cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(acks))));
+        reply_receiver.await
+    }
+
+    // Each session has its own subscriber connection to each backend redis.
+    async fn send_subscriber_cmd(
+        &self,
+        subscription: &Arc<sync::Mutex<Subscription>>,
+        cmd_name: &str,
+        groups: HashMap<String, Vec<BinSafeStr>>,
+        create_conn: bool,
+        key_prefix: Option<&[u8]>,
+    ) -> Result<(), RespVec> {
+        for (address, names) in groups.into_iter() {
+            let (conn, push_sender) = {
+                let mut subscription = subscription
+                    .lock()
+                    .expect("ForwardHandler::send_subscriber_cmd");
+                (
+                    subscription.take_conn(&address),
+                    subscription.get_push_sender(),
+                )
+            };
+            let mut conn = match conn {
+                Some(conn) => conn,
+                None if !create_conn => continue,
+                None => SubscriberConn::connect(
+                    address.clone(),
+                    self.config.backend_credentials.get(&address),
+                    self.config.backend_tls.as_ref(),
+                    push_sender,
+                    key_prefix.map(|key_prefix| key_prefix.to_vec()),
+                )
+                .await
+                .map_err(|err| {
+                    Resp::Error(format!("ERR failed to connect to backend: {:?}", err).into_bytes())
+                })?,
+            };
+
+            // Keep using the prefix the connection subscribed with.
+            let names = match conn.get_key_prefix() {
+                Some(key_prefix) => add_subscriber_prefix(cmd_name, key_prefix, names),
+                None => names,
+            };
+            let ack_num = names.len();
+            let mut cmd = vec![cmd_name.as_bytes().to_vec()];
+            cmd.extend(names);
+            let res = conn.execute(cmd, ack_num).await;
+            subscription
+                .lock()
+                .expect("ForwardHandler::send_subscriber_cmd")
+                .put_conn(address, conn);
+            res.map_err(|err| {
+                Resp::Error(format!("ERR failed to run {}: {:?}", cmd_name, err).into_bytes())
+            })?;
+        }
+        Ok(())
+    }
+
+    async fn handle_scan(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
+        let reply = match self.scan_local_nodes(&cmd_ctx).await {
+            Ok(reply) => reply,
+            Err(err) => err,
+        };
+        cmd_ctx.set_resp_result(Ok(reply));
+        reply_receiver.await
+    }
+
+    async fn scan_local_nodes(&self, cmd_ctx: &CmdCtx) -> Result<RespVec, RespVec> {
+        let cursor = match cmd_ctx
+            .get_cmd()
+            .get_command_element(1)
+            .and_then(ScanCursor::parse)
+        {
+            Some(cursor) => cursor,
+            None => return Err(Resp::Error(b"ERR invalid cursor".to_vec())),
+        };
+
+        let db_name = cmd_ctx.get_db_name();
+        let mut nodes = self.manager.get_local_nodes(&db_name);
+        if nodes.is_empty() {
+            return Err(Resp::Error(
+                format!("db not found: {}", db_name).into_bytes(),
+            ));
+        }
+        if nodes.len() > MAX_SCAN_NODE_NUM {
+            return Err(Resp::Error(b"ERR too many nodes to scan".to_vec()));
+        }
+        // The index in the cursor relies on the order of the nodes.
+        nodes.sort();
+        let address = match nodes.get(cursor.node_index) {
+            Some(address) => address.clone(),
+            None => return Ok(gen_scan_reply(0, vec![])),
+        };
+
+        let mut cmd = Self::get_cmd_elements(cmd_ctx);
+        if let Some(node_cursor) = cmd.get_mut(1) {
+            *node_cursor = cursor.node_cursor.to_string().into_bytes();
+        }
+        let key_prefix = cmd_ctx.get_key_prefix();
+        if let Some(key_prefix) = key_prefix {
+            // The options are in pairs after the cursor.
+            let match_index = (2..cmd.len())
+                .step_by(2)
+                .find(|i| cmd[*i].eq_ignore_ascii_case(b"MATCH"));
+            match match_index.and_then(|i| cmd.get_mut(i + 1)) {
+                Some(pattern) => *pattern = gen_prefix_pattern(key_prefix, pattern),
+                None => {
+                    cmd.push(b"MATCH".to_vec());
+                    cmd.push(gen_prefix_pattern(key_prefix, b"*"));
+                }
+            }
+        }
+        let mut client = self
+            .client_factory
+            .create_client(address.clone())
+            .await
+            .map_err(|err| {
+                Resp::Error(format!("ERR failed to connect to backend: {:?}", err).into_bytes())
+            })?;
+        let reply = client.execute_single(cmd).await.map_err(|err| {
+            Resp::Error(format!("ERR failed to scan backend: {:?}", err).into_bytes())
+        })?;
+        let (next_node_cursor, keys) = parse_scan_reply(reply)?;
+
+        let keys = keys
+            .into_iter()
+            .filter_map(|key| match key_prefix {
+                Some(key_prefix) => strip_key_prefix(key_prefix, &key),
+                None => Some(key),
+            })
+            .filter(|key| self.is_owned_key(&db_name, &address, key))
+            .collect();
+
+        let next_cursor = match cursor.next(nodes.len(), next_node_cursor) {
+            None => 0,
+            Some(next_cursor) => match next_cursor.encode() {
+                Some(next_cursor) => next_cursor,
+                None => return Err(Resp::Error(b"ERR backend cursor overflow".to_vec())),
+            },
+        };
+        Ok(gen_scan_reply(next_cursor, keys))
+    }
+
+    fn handle_blocking_cmd(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> CmdReplyFuture {
+        let res = if cmd_ctx.get_cmd().is_cross_slot() {
+            Err(Resp::Error(
+                b"CROSSSLOT Keys in request don't hash to the same slot".to_vec(),
+            ))
+        } else {
+            match cmd_ctx
+                .get_cmd()
+                .get_keys()
+                .first()
+                .map(|key| get_slot(key))
+            {
+                Some(slot) => self.locate_local_slot(&cmd_ctx, slot),
+                None => Err(Resp::Error(b"missing key".to_vec())),
+            }
+        };
+        match res {
+            Ok(address) => CmdReplyFuture::Right(Box::pin(self.run_blocking_cmd(
+                cmd_ctx,
+                reply_receiver,
+                address,
+            ))),
+            Err(err) => {
+                cmd_ctx.set_resp_result(Ok(err));
+                CmdReplyFuture::Left(reply_receiver)
+            }
+        }
+    }
+
+    // The session drops this future to cancel the command when the client disconnects,
+    // which will also close the backend connection.
+    async fn run_blocking_cmd(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        address: String,
+    ) -> TaskResult {
+        let mut client = match self.blocking_client_factory.create_client(address).await {
+            Ok(client) => client,
+            Err(err) => {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(
+                    format!("ERR failed to connect to backend: {:?}", err).into_bytes(),
+                )));
+                return reply_receiver.await;
+            }
+        };
+
+        let exec_fut = client.execute_single(Self::get_backend_cmd_elements(&cmd_ctx));
+        let timeout = cmd_ctx
+            .get_cmd()
+            .get_blocking_timeout()
+            .and_then(|timeout| timeout.checked_add(BLOCKING_CMD_TIMEOUT_MARGIN));
+        let res = match timeout {
+            None => exec_fut.await,
+            Some(timeout) => time::timeout(timeout, exec_fut)
+                .await
+                .unwrap_or(Err(RedisClientError::Timeout)),
+        };
+        let reply = res.unwrap_or_else(|err| {
+            Resp::Error(format!("ERR failed to run blocking command: {:?}", err).into_bytes())
+        });
+        cmd_ctx.set_resp_result(Ok(reply));
+        reply_receiver.await
+    }
+
+    fn handle_data_cmd(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
+        if !cmd_ctx.get_cmd().check_arity() {
+            let cmd_name = cmd_ctx
+                .get_cmd()
+                .get_command_name()
+                .unwrap_or("")
+                .to_lowercase();
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                format!("ERR wrong number of arguments for '{}' command", cmd_name).into_bytes(),
+            )));
+            return CmdReplyFuture::Left(reply_receiver);
+        }
+        if !cmd_ctx.get_cmd().check_numkeys() {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(INVALID_NUMKEYS_REPLY.to_vec())));
+            return CmdReplyFuture::Left(reply_receiver);
+        }
+
+        if cmd_ctx.get_key_prefix().is_some() {
+            if let Err(err) = check_prefixed_cmd(cmd_ctx.get_cmd()) {
+                cmd_ctx.set_resp_result(Ok(err));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+        }
+
+        if cmd_ctx.get_cmd().is_blocking() {
+            if !cmd_ctx.get_cmd().check_blocking_timeout() {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(TIMEOUT_OUT_OF_RANGE_REPLY.to_vec())));
+                return CmdReplyFuture::Left(reply_receiver);
+            }
+            return self.handle_blocking_cmd(cmd_ctx, reply_receiver);
+        }
+
+        match cmd_ctx.get_data_cmd_type() {
+            DataCmdType::MGET => {
+                CmdReplyFuture::Right(Box::pin(self.handle_mget(cmd_ctx, reply_receiver)))
+            }
+            DataCmdType::SCAN => {
+                CmdReplyFuture::Right(Box::pin(self.handle_scan(cmd_ctx, reply_receiver)))
+            }
+            DataCmdType::MSET => {
+                CmdReplyFuture::Right(Box::pin(self.handle_mset(cmd_ctx, reply_receiver)))
+            }
+            DataCmdType::DEL if cmd_ctx.get_cmd().get_command_element(2).is_some() => {
+                CmdReplyFuture::Right(Box::pin(self.handle_multi_int_cmd(
+                    cmd_ctx,
+                    reply_receiver,
+                    "DEL",
+                )))
+            }
+            DataCmdType::EXISTS if cmd_ctx.get_cmd().get_command_element(2).is_some() => {
+                CmdReplyFuture::Right(Box::pin(self.handle_multi_int_cmd(
+                    cmd_ctx,
+                    reply_receiver,
+                    "EXISTS",
+                )))
+            }
+            DataCmdType::EVALSHA => {
+                CmdReplyFuture::Right(Box::pin(self.handle_eval(cmd_ctx, reply_receiver)))
+            }
+            DataCmdType::EVAL if cmd_ctx.get_cmd().get_key().is_none() => {
+                CmdReplyFuture::Right(Box::pin(self.handle_eval(cmd_ctx, reply_receiver)))
+            }
+            DataCmdType::SCRIPT => {
+                CmdReplyFuture::Right(Box::pin(self.handle_script(cmd_ctx, reply_receiver)))
+            }
+            DataCmdType::DBSIZE
+            | DataCmdType::KEYS
+            | DataCmdType::FLUSHDB
+            | DataCmdType::RANDOMKEY => {
+                CmdReplyFuture::Right(Box::pin(self.handle_keyspace_cmd(cmd_ctx, reply_receiver)))
+            }
+            _ => {
+                self.handle_single_key_data_cmd(cmd_ctx);
+                CmdReplyFuture::Left(reply_receiver)
+            }
+        }
+    }
+
+    async fn handle_mget(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
+        let batched = self.is_batching_enabled(&cmd_ctx);
+        let cmd_name = if batched { "MGET" } else { "GET" };
+        let (key_num, groups) = self.send_key_groups(&cmd_ctx, cmd_name, 1, batched);
+
+        if groups.is_empty() {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR wrong number of arguments for 'mget' command".to_vec(),
+            )));
+            return reply_receiver.await;
+        }
+
+        let (indexes_list, futs): (Vec<_>, Vec<_>) = groups.into_iter().unzip();
+        let res = future::join_all(futs).await;
+        let mut group_replies = vec![];
+        for (indexes, sub_result) in indexes_list.into_iter().zip(res) {
+            let reply = match sub_result {
+                Ok(reply) => reply,
+                Err(err) => return Err(err),
+            };
+            group_replies.push((indexes, reply));
+        }
+
+        let resp = match merge_group_replies(key_num, batched, group_replies) {
+            Ok(values) => Resp::Arr(Array::Arr(values)),
+            Err(err) => err,
+        };
+        cmd_ctx.set_resp_result(Ok(resp));
+        reply_receiver.await
+    }
+
+    async fn handle_mset(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
+        // The arguments of MSET should be key-value pairs.
+        if cmd_ctx.get_cmd().get_command_element(1).is_none()
+            || cmd_ctx.get_cmd().get_argc() & 1 == 0
+        {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"ERR wrong number of arguments for 'mset' command".to_vec(),
+            )));
+            return reply_receiver.await;
+        }
+
+        let batched = self.is_batching_enabled(&cmd_ctx);
+        let cmd_name = if batched { "MSET" } else { "SET" };
+        let (_, groups) = self.send_key_groups(&cmd_ctx, cmd_name, 2, batched);
+
+        let futs = groups.into_iter().map(|(_, fut)| fut);
+        let res = future::join_all(futs).await;
+        for sub_result in res.into_iter() {
+            let reply = match sub_result {
+                Ok(reply) => reply,
+                Err(err) => return Err(err),
+            };
+            if let Resp::Error(err) = reply {
+                cmd_ctx.set_resp_result(Ok(Resp::Error(err)));
+                return reply_receiver.await;
+            }
+        }
+
+        let resp = Resp::Simple(OK_REPLY.to_string().into_bytes());
+        cmd_ctx.set_resp_result(Ok(resp));
+        reply_receiver.await
+    }
+
+    async fn handle_multi_int_cmd(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+        cmd_name: &'static str,
+    ) -> TaskResult {
+        let batched = self.is_batching_enabled(&cmd_ctx);
+        let (_, groups) = self.send_key_groups(&cmd_ctx, cmd_name, 1, batched);
+
+        if groups.is_empty() {
+            cmd_ctx.set_resp_result(Ok(Resp::Error(
+                format!("ERR wrong number of arguments for '{}' command", cmd_name).into_bytes(),
+            )));
+            return reply_receiver.await;
+        }
+
+        let mut count: usize = 0;
+        let futs = groups.into_iter().map(|(_, fut)| fut);
+        let res = future::join_all(futs).await;
         for sub_result in res.into_iter() {
             let reply = match sub_result {
                 Ok(reply) => reply,
@@ -598,8 +1914,311 @@ impl<F: RedisClientFactory> ForwardHandler<F> {
         reply_receiver.await
     }
 
+    // The compressed values need to be handled key by key.
+    fn is_batching_enabled(&self, cmd_ctx: &CmdCtx) -> bool {
+        !self.compressor.is_enabled(&cmd_ctx.get_db_name())
+    }
+
+    // Splits a multi-key command into sub-commands and sends them.
+    // Each key takes `step` arguments.
+    // Returns the number of keys and the indexes of the keys in each sub-command.
+    fn send_key_groups(
+        &self,
+        cmd_ctx: &CmdCtx,
+        cmd_name: &'static str,
+        step: usize,
+        batched: bool,
+    ) -> (usize, Vec<(Vec<usize>, SubCmdFuture)>) {
+        let args = Self::get_cmd_elements(cmd_ctx);
+        let key_args: Vec<&[BinSafeStr]> = args.get(1..).unwrap_or(&[]).chunks(step).collect();
+        let groups = if batched {
+            let keys: Vec<&[u8]> = key_args.iter().map(|arg| arg[0].as_slice()).collect();
+            self.group_keys(&cmd_ctx.get_db_name(), &keys)
+        } else {
+            (0..key_args.len()).map(|i| vec![i]).collect()
+        };
+
+        let factory = CmdCtxFactory::new(self.config.command_table.clone());
+        let mut sub_cmds = vec![];
+        for indexes in groups.into_iter() {
+            let sub_cmd = gen_group_cmd(cmd_name, &key_args, &indexes);
+            let (sub_cmd_ctx, fut) = factory.create_with(cmd_ctx, sub_cmd);
+            if batched {
+                // The keys in the same node could be in different slots.
+                self.manager.send(sub_cmd_ctx);
+            } else {
+                self.handle_single_key_data_cmd(sub_cmd_ctx);
+            }
+            sub_cmds.push((indexes, fut));
+        }
+        (key_args.len(), sub_cmds)
+    }
+
+    // Only the keys of the stable local slots are grouped by backend node.
+    // The others are grouped by slot so that they still get migrated or redirected
+    // in the same way as the commands from the clients.
+    fn group_keys(&self, db_name: &DBName, keys: &[&[u8]]) -> Vec<Vec<usize>> {
+        group_keys_by_location(keys, |slot| self.manager.locate_slot(db_name, slot))
+    }
+
+    async fn handle_eval(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
+        let mut cmd = Self::get_cmd_elements(&cmd_ctx);
+        let reply = self.send_script_cmd(&cmd_ctx, cmd.clone()).await?;
+
+        let reply = match reply {
+            Resp::Error(ref err)
+                if cmd_ctx.get_data_cmd_type() == DataCmdType::EVALSHA
+                    && err.starts_with(b"NOSCRIPT") =>
+            {
+                let db_name = cmd_ctx.get_db_name();
+                let script = cmd
+                    .get(1)
+                    .and_then(|sha| self.script_cache.get(&db_name, sha));
+                match script {
+                    Some(script) => {
+                        // EVAL will also load the script to the backend.
+                        cmd[0] = b"EVAL".to_vec();
+                        cmd[1] = script;
+                        self.send_script_cmd(&cmd_ctx, cmd).await?
+                    }
+                    None => reply,
+                }
+            }
+            reply => reply,
+        };
+        cmd_ctx.set_resp_result(Ok(reply));
+        reply_receiver.await
+    }
+
+    // The scripts without any key could run on any local node.
+    async fn send_script_cmd(
+        &self,
+        cmd_ctx: &CmdCtx,
+        cmd: Vec<BinSafeStr>,
+    ) -> Result<RespVec, CommandError> {
+        if cmd_ctx.get_cmd().get_key().is_some() {
+            let resp = Resp::Arr(Array::Arr(
+                cmd.into_iter()
+                    .map(|e| Resp::Bulk(BulkStr::Str(e)))
+                    .collect(),
+            ));
+            let (sub_cmd_ctx, fut) =
+                CmdCtxFactory::new(self.config.command_table.clone()).create_with(cmd_ctx, resp);
+            self.handle_single_key_data_cmd(sub_cmd_ctx);
+            return fut.await;
+        }
+
+        let db_name = cmd_ctx.get_db_name();
+        let nodes = self.manager.get_local_nodes(&db_name);
+        if nodes.is_empty() {
+            return Ok(Resp::Error(
+                format!("db not found: {}", db_name).into_bytes(),
+            ));
+        }
+        let address = nodes[random_index(nodes.len())].clone();
+        let mut client = match self.client_factory.create_client(address).await {
+            Ok(client) => client,
+            Err(err) => {
+                return Ok(Resp::Error(
+                    format!("ERR failed to connect to backend: {:?}", err).into_bytes(),
+                ))
+            }
+        };
+        let reply = client.execute_single(cmd).await.unwrap_or_else(|err| {
+            Resp::Error(format!("ERR failed to run script: {:?}", err).into_bytes())
+        });
+        Ok(reply)
+    }
+
+    async fn handle_script(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> TaskResult {
+        let reply = match self.run_script_subcmd(&cmd_ctx).await {
+            Ok(reply) => reply,
+            Err(err) => err,
+        };
+        cmd_ctx.set_resp_result(Ok(reply));
+        reply_receiver.await
+    }
+
+    // The scripts are loaded to all the local nodes and cached in the proxy.
+    async fn run_script_subcmd(&self, cmd_ctx: &CmdCtx) -> Result<RespVec, RespVec> {
+        let db_name = cmd_ctx.get_db_name();
+        let cmd = cmd_ctx.get_cmd();
+        let sub_cmd = cmd
+            .get_command_element(1)
+            .map(|e| e.to_ascii_uppercase())
+            .unwrap_or_default();
+        match sub_cmd.as_slice() {
+            b"LOAD" => {
+                let script = match cmd.get_command_element(2) {
+                    Some(script) if cmd.get_argc() == 3 => script.to_vec(),
+                    _ => {
+                        return Err(Resp::Error(
+                            b"ERR wrong number of arguments for SCRIPT LOAD".to_vec(),
+                        ))
+                    }
+                };
+                let replies = self
+                    .broadcast_to_local_nodes(&db_name, Self::get_cmd_elements(cmd_ctx))
+                    .await?;
+                let reply = match replies.into_iter().next() {
+                    Some((_, reply)) => reply,
+                    None => return Err(Resp::Error(b"ERR no backend node".to_vec())),
+                };
+                match reply {
+                    Resp::Bulk(BulkStr::Str(ref sha)) => {
+                        self.script_cache.insert(db_name, sha, script);
+                    }
+                    ref others => {
+                        return Err(Resp::Error(
+                            format!("ERR unexpected reply from SCRIPT LOAD: {:?}", others)
+                                .into_bytes(),
+                        ))
+                    }
+                }
+                Ok(reply)
+            }
+            // A cached script could always be loaded by EVALSHA.
+            b"EXISTS" => {
+                let shas: Vec<BinSafeStr> = (2..cmd.get_argc())
+                    .filter_map(|i| cmd.get_command_element(i).map(|e| e.to_vec()))
+                    .collect();
+                let mut existing: Vec<bool> = shas
+                    .iter()
+                    .map(|sha| self.script_cache.get(&db_name, sha).is_some())
+                    .collect();
+                let replies = self
+                    .broadcast_to_local_nodes(&db_name, Self::get_cmd_elements(cmd_ctx))
+                    .await?;
+                let mut loaded = vec![true; shas.len()];
+                for (_, reply) in replies.into_iter() {
+                    let flags = match reply {
+                        Resp::Arr(Array::Arr(flags)) if flags.len() == shas.len() => flags,
+                        others => {
+                            return Err(Resp::Error(
+                                format!("ERR unexpected reply from SCRIPT EXISTS: {:?}", others)
+                                    .into_bytes(),
+                            ))
+                        }
+                    };
+                    for (i, flag) in flags.into_iter().enumerate() {
+                        if flag != Resp::Integer(b"1".to_vec()) {
+                            loaded[i] = false;
+                        }
+                    }
+                }
+                for (exists, loaded) in existing.iter_mut().zip(loaded) {
+                    *exists = *exists || loaded;
+                }
+                let flags = existing
+                    .into_iter()
+                    .map(|exists| Resp::Integer(if exists { b"1" } else { b"0" }.to_vec()))
+                    .collect();
+                Ok(Resp::Arr(Array::Arr(flags)))
+            }
+            b"FLUSH" => {
+                self.script_cache.flush(&db_name);
+                self.broadcast_to_local_nodes(&db_name, Self::get_cmd_elements(cmd_ctx))
+                    .await?;
+                Ok(Resp::Simple(OK_REPLY.to_string().into_bytes()))
+            }
+            _ => Err(Resp::Error(
+                format!(
+                    "ERR unsupported SCRIPT subcommand: {}",
+                    String::from_utf8_lossy(&sub_cmd)
+                )
+                .into_bytes(),
+            )),
+        }
+    }
+
+    // DBSIZE, KEYS, FLUSHDB and RANDOMKEY do not have any key,
+    // so they are sent to all the local nodes of the database and the replies get merged.
+    async fn handle_keyspace_cmd(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> TaskResult {
+        let reply = match self.scatter_gather_keyspace_cmd(&cmd_ctx).await {
+            Ok(reply) => reply,
+            Err(err) => err,
+        };
+        cmd_ctx.set_resp_result(Ok(reply));
+        reply_receiver.await
+    }
+
+    async fn scatter_gather_keyspace_cmd(&self, cmd_ctx: &CmdCtx) -> Result<RespVec, RespVec> {
+        let db_name = cmd_ctx.get_db_name();
+        let data_cmd_type = cmd_ctx.get_data_cmd_type();
+        check_flushdb_enabled(data_cmd_type, self.manager.is_flushdb_enabled(&db_name))?;
+
+        let mut cmd = Self::get_cmd_elements(cmd_ctx);
+        let key_prefix = cmd_ctx.get_key_prefix();
+        // The other keyspace commands are rejected by check_prefixed_cmd
+        // as the keys of the other clusters on the same nodes could not be skipped.
+        if let (Some(key_prefix), DataCmdType::KEYS) = (key_prefix, data_cmd_type) {
+            if let Some(pattern) = cmd.get_mut(1) {
+                *pattern = gen_prefix_pattern(key_prefix, pattern);
+            }
+        }
+
+        let replies = self.broadcast_to_local_nodes(&db_name, cmd).await?;
+        merge_keyspace_replies(data_cmd_type, replies, key_prefix, |address, key| {
+            self.is_owned_key(&db_name, address, key)
+        })
+    }
+
+    // Returns the replies along with the node addresses. Error replies are returned as Err.
+    async fn broadcast_to_local_nodes(
+        &self,
+        db_name: &DBName,
+        cmd: Vec<BinSafeStr>,
+    ) -> Result<Vec<(String, RespVec)>, RespVec> {
+        let nodes = self.manager.get_local_nodes(db_name);
+        if nodes.is_empty() {
+            return Err(Resp::Error(
+                format!("db not found: {}", db_name).into_bytes(),
+            ));
+        }
+
+        let futs = nodes.into_iter().map(|address| {
+            let cmd = cmd.clone();
+            async move {
+                let mut client = self
+                    .client_factory
+                    .create_client(address.clone())
+                    .await
+                    .map_err(|err| {
+                        Resp::Error(
+                            format!("ERR failed to connect to backend: {:?}", err).into_bytes(),
+                        )
+                    })?;
+                let reply = client.execute_single(cmd).await.map_err(|err| {
+                    Resp::Error(format!("ERR failed to run on backend: {:?}", err).into_bytes())
+                })?;
+                match reply {
+                    Resp::Error(err) => Err(Resp::Error(err)),
+                    reply => Ok((address, reply)),
+                }
+            }
+        });
+        future::try_join_all(futs).await
+    }
+
+    // The keys of the slots being migrated out or not committed yet should be skipped.
+    fn is_owned_key(&self, db_name: &DBName, address: &str, key: &[u8]) -> bool {
+        self.manager
+            .get_local_slot_owner(db_name, get_slot(key))
+            .as_deref()
+            == Some(address)
+    }
+
     fn handle_single_key_data_cmd(&self, cmd_ctx: CmdCtx) {
         let mut cmd_ctx = cmd_ctx;
+        if cmd_ctx.get_cmd().is_cross_slot() {
+            return cmd_ctx.set_resp_result(Ok(Resp::Error(
+                b"CROSSSLOT Keys in request don't hash to the same slot".to_vec(),
+            )));
+        }
         match self.compressor.try_compressing_cmd_ctx(&mut cmd_ctx) {
             Ok(())
             | Err(CompressionError::UnsupportedCmdType)
@@ -622,23 +2241,372 @@ impl<F: RedisClientFactory> ForwardHandler<F> {
     }
 }
 
+fn gen_db_error_reply(err: DBError) -> RespVec {
+    let err_str = match err {
+        DBError::OldEpoch => OLD_EPOCH_REPLY.to_string(),
+        DBError::TryAgain => TRY_AGAIN_REPLY.to_string(),
+        DBError::SharedNodeMigration(db_name) => format!(
+            "ERR slot migration is not supported for the cluster {} with key prefix or sharing nodes",
+            db_name
+        ),
+    };
+    Resp::Error(err_str.into_bytes())
+}
+
+// The keyspace commands need to be sent to all the local nodes
+// so they can't be queued in a transaction bound to a single node.
+fn is_keyspace_cmd(data_cmd_type: DataCmdType) -> bool {
+    matches!(
+        data_cmd_type,
+        DataCmdType::SCAN
+            | DataCmdType::DBSIZE
+            | DataCmdType::KEYS
+            | DataCmdType::FLUSHDB
+            | DataCmdType::RANDOMKEY
+    )
+}
+
+fn check_transaction_cmd(cmd: &Command, has_key_prefix: bool) -> Result<(), RespVec> {
+    let cmd_name = cmd.get_command_name().unwrap_or("").to_lowercase();
+    if !cmd.check_arity() {
+        return Err(Resp::Error(
+            format!("ERR wrong number of arguments for '{}' command", cmd_name).into_bytes(),
+        ));
+    }
+    if !cmd.check_numkeys() {
+        return Err(Resp::Error(INVALID_NUMKEYS_REPLY.to_vec()));
+    }
+    if is_keyspace_cmd(cmd.get_data_cmd_type()) {
+        return Err(Resp::Error(
+            format!("ERR '{}' is not allowed inside a transaction", cmd_name).into_bytes(),
+        ));
+    }
+    if has_key_prefix {
+        check_prefixed_cmd(cmd)?;
+        // The keyless commands would run on the data of all the clusters sharing the node.
+        if cmd.get_type() == CmdType::Others && cmd.get_key().is_none() {
+            return Err(Resp::Error(
+                format!(
+                    "ERR '{}' without keys is not allowed inside a transaction for the cluster with key prefix",
+                    cmd_name
+                )
+                .into_bytes(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The commands allowed for the read-only credential of a cluster.
+fn is_readonly_cmd(cmd: &Command) -> bool {
+    let sub_cmd = cmd
+        .get_command_element(1)
+        .map(|e| e.to_ascii_uppercase())
+        .unwrap_or_default();
+    match cmd.get_type() {
+        CmdType::Others => cmd.is_readonly(),
+        CmdType::Config => sub_cmd == b"GET",
+        CmdType::Client => sub_cmd != b"KILL",
+        CmdType::UmCtl => false,
+        _ => true,
+    }
+}
+
+fn gen_scan_reply(cursor: u64, keys: Vec<BinSafeStr>) -> RespVec {
+    let keys = keys
+        .into_iter()
+        .map(|key| Resp::Bulk(BulkStr::Str(key)))
+        .collect();
+    Resp::Arr(Array::Arr(vec![
+        Resp::Bulk(BulkStr::Str(cursor.to_string().into_bytes())),
+        Resp::Arr(Array::Arr(keys)),
+    ]))
+}
+
+fn parse_scan_reply(reply: RespVec) -> Result<(u64, Vec<BinSafeStr>), RespVec> {
+    let invalid_reply = || Resp::Error(b"ERR invalid SCAN reply from backend".to_vec());
+    let mut resps = match reply {
+        Resp::Arr(Array::Arr(resps)) => resps.into_iter(),
+        Resp::Error(err) => return Err(Resp::Error(err)),
+        _ => return Err(invalid_reply()),
+    };
+    let cursor = match resps.next() {
+        Some(Resp::Bulk(BulkStr::Str(cursor))) => {
+            btou::<u64>(&cursor).map_err(|_| invalid_reply())?
+        }
+        _ => return Err(invalid_reply()),
+    };
+    let keys = match resps.next() {
+        Some(Resp::Arr(Array::Arr(keys))) => keys
+            .into_iter()
+            .filter_map(|key| match key {
+                Resp::Bulk(BulkStr::Str(key)) => Some(key),
+                _ => None,
+            })
+            .collect(),
+        _ => return Err(invalid_reply()),
+    };
+    Ok((cursor, keys))
+}
+
+fn group_keys_by_location<L>(keys: &[&[u8]], locate_slot: L) -> Vec<Vec<usize>>
+where
+    L: Fn(usize) -> SlotLocation,
+{
+    let mut group_indexes: HashMap<KeyGroup, usize> = HashMap::new();
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for (i, key) in keys.iter().enumerate() {
+        let slot = get_slot(key);
+        let group = match locate_slot(slot) {
+            SlotLocation::Local(address) => KeyGroup::Node(address),
+            _ => KeyGroup::Slot(slot),
+        };
+        match group_indexes.entry(group) {
+            Entry::Occupied(entry) => groups[*entry.get()].push(i),
+            Entry::Vacant(entry) => {
+                entry.insert(groups.len());
+                groups.push(vec![i]);
+            }
+        }
+    }
+    groups
+}
+
+fn gen_group_cmd(cmd_name: &str, key_args: &[&[BinSafeStr]], indexes: &[usize]) -> RespVec {
+    let mut sub_cmd = vec![Resp::Bulk(BulkStr::Str(cmd_name.as_bytes().to_vec()))];
+    for index in indexes.iter() {
+        for arg in key_args[*index].iter() {
+            sub_cmd.push(Resp::Bulk(BulkStr::Str(arg.clone())));
+        }
+    }
+    Resp::Arr(Array::Arr(sub_cmd))
+}
+
+// Puts the values of the sub-commands of MGET back in the order of the keys.
+fn merge_group_replies(
+    key_num: usize,
+    batched: bool,
+    group_replies: Vec<(Vec<usize>, RespVec)>,
+) -> Result<Vec<RespVec>, RespVec> {
+    let mut values = vec![Resp::Bulk(BulkStr::Nil); key_num];
+    for (indexes, reply) in group_replies.into_iter() {
+        let group_values = split_group_reply(reply, batched, indexes.len())?;
+        for (index, value) in indexes.into_iter().zip(group_values) {
+            values[index] = value;
+        }
+    }
+    Ok(values)
+}
+
+// Returns the values of all the keys in a sub-command of MGET.
+fn split_group_reply(
+    reply: RespVec,
+    batched: bool,
+    key_num: usize,
+) -> Result<Vec<RespVec>, RespVec> {
+    match reply {
+        Resp::Error(err) => Err(Resp::Error(err)),
+        Resp::Arr(Array::Arr(values)) if batched && values.len() == key_num => Ok(values),
+        reply if !batched => Ok(vec![reply]),
+        others => Err(Resp::Error(
+            format!("unexpected reply from MGET: {:?}", others).into_bytes(),
+        )),
+    }
+}
+
+fn check_flushdb_enabled(data_cmd_type: DataCmdType, enabled: bool) -> Result<(), RespVec> {
+    if data_cmd_type == DataCmdType::FLUSHDB && !enabled {
+        return Err(Resp::Error(
+            b"ERR FLUSHDB is disabled for this cluster".to_vec(),
+        ));
+    }
+    Ok(())
+}
+
+// Merges the replies of a keyspace command from all the local nodes.
+fn merge_keyspace_replies<O>(
+    data_cmd_type: DataCmdType,
+    replies: Vec<(String, RespVec)>,
+    key_prefix: Option<&[u8]>,
+    is_owned_key: O,
+) -> Result<RespVec, RespVec>
+where
+    O: Fn(&str, &[u8]) -> bool,
+{
+    match data_cmd_type {
+        // The keys of the slots being migrated out are still counted until they get deleted,
+        // since they can't be filtered without iterating all the keys.
+        DataCmdType::DBSIZE => {
+            let mut count: u64 = 0;
+            for (_, reply) in replies.into_iter() {
+                count += parse_int_reply(reply, "DBSIZE")?;
+            }
+            Ok(Resp::Integer(count.to_string().into_bytes()))
+        }
+        DataCmdType::KEYS => {
+            let mut keys = vec![];
+            for (address, reply) in replies.into_iter() {
+                let node_keys = parse_keys_reply(reply)?;
+                keys.extend(
+                    node_keys
+                        .into_iter()
+                        .filter_map(|key| match key_prefix {
+                            Some(key_prefix) => strip_key_prefix(key_prefix, &key),
+                            None => Some(key),
+                        })
+                        .filter(|key| is_owned_key(&address, key)),
+                );
+            }
+            let keys = keys
+                .into_iter()
+                .map(|key| Resp::Bulk(BulkStr::Str(key)))
+                .collect();
+            Ok(Resp::Arr(Array::Arr(keys)))
+        }
+        DataCmdType::RANDOMKEY => {
+            let mut keys = vec![];
+            for (address, reply) in replies.into_iter() {
+                match reply {
+                    Resp::Bulk(BulkStr::Str(key)) => {
+                        if is_owned_key(&address, &key) {
+                            keys.push(key);
+                        }
+                    }
+                    // RESP3 has its own null type.
+                    Resp::Bulk(BulkStr::Nil) | Resp::Null => (),
+                    others => {
+                        return Err(Resp::Error(
+                            format!("ERR unexpected reply from RANDOMKEY: {:?}", others)
+                                .into_bytes(),
+                        ))
+                    }
+                }
+            }
+            if keys.is_empty() {
+                return Ok(Resp::Bulk(BulkStr::Nil));
+            }
+            let index = random_index(keys.len());
+            Ok(Resp::Bulk(BulkStr::Str(keys.swap_remove(index))))
+        }
+        _ => Ok(Resp::Simple(OK_REPLY.to_string().into_bytes())),
+    }
+}
+
+fn parse_int_reply(reply: RespVec, cmd_name: &str) -> Result<u64, RespVec> {
+    match reply {
+        Resp::Integer(data) => btou::<u64>(&data).map_err(|err| {
+            Resp::Error(
+                format!("unexpected reply from {}: {:?} {:?}", cmd_name, data, err).into_bytes(),
+            )
+        }),
+        others => Err(Resp::Error(
+            format!("unexpected reply from {}: {:?}", cmd_name, others).into_bytes(),
+        )),
+    }
+}
+
+fn parse_keys_reply(reply: RespVec) -> Result<Vec<BinSafeStr>, RespVec> {
+    match reply {
+        Resp::Arr(Array::Arr(keys)) => Ok(keys
+            .into_iter()
+            .filter_map(|key| match key {
+                Resp::Bulk(BulkStr::Str(key)) => Some(key),
+                _ => None,
+            })
+            .collect()),
+        others => Err(Resp::Error(
+            format!("unexpected reply from KEYS: {:?}", others).into_bytes(),
+        )),
+    }
+}
+
+// Redis will not release the watched keys of a connection until EXEC, DISCARD or UNWATCH.
+async fn release_watch_conn(watch_conn: Option<(String, Box<dyn RedisClient>)>) {
+    if let Some((address, mut client)) = watch_conn {
+        if let Err(err) = client.execute_single(vec![b"UNWATCH".to_vec()]).await {
+            warn!("failed to unwatch {}: {:?}", address, err);
+        }
+    }
+}
+
 impl<F: RedisClientFactory> CmdCtxHandler for ForwardHandler<F> {
     fn handle_cmd_ctx(&self, cmd_ctx: CmdCtx, reply_receiver: CmdReplyReceiver) -> CmdReplyFuture {
         let mut cmd_ctx = cmd_ctx;
         if self.config.auto_select_db {
             cmd_ctx = self.manager.try_select_db(cmd_ctx);
         }
+        let mut cmd_ctx = match self.resolve_command(cmd_ctx) {
+            Some(cmd_ctx) => cmd_ctx,
+            None => return CmdReplyFuture::Left(reply_receiver),
+        };
+        cmd_ctx.set_key_prefix(self.manager.get_key_prefix(&cmd_ctx.get_db_name()));
+        let cmd_ctx = match self.check_permission(cmd_ctx) {
+            Some(cmd_ctx) => cmd_ctx,
+            None => return CmdReplyFuture::Left(reply_receiver),
+        };
+        match self.check_rate_limit(cmd_ctx) {
+            None => CmdReplyFuture::Left(reply_receiver),
+            Some((cmd_ctx, None)) => self.dispatch_cmd_ctx(cmd_ctx, reply_receiver),
+            // The session will not poll the later commands before this one finishes.
+            Some((cmd_ctx, Some(deadline))) => CmdReplyFuture::Right(Box::pin(async move {
+                time::delay_until(time::Instant::from_std(deadline)).await;
+                self.dispatch_cmd_ctx(cmd_ctx, reply_receiver).await
+            })),
+        }
+    }
+}
 
+impl<F: RedisClientFactory> ForwardHandler<F> {
+    fn dispatch_cmd_ctx(
+        &self,
+        cmd_ctx: CmdCtx,
+        reply_receiver: CmdReplyReceiver,
+    ) -> CmdReplyFuture {
         let cmd_type = cmd_ctx.get_cmd().get_type();
+        let in_multi = cmd_ctx
+            .get_transaction()
+            .lock()
+            .expect("ForwardHandler::handle_cmd_ctx")
+            .is_in_multi();
+        if in_multi {
+            match cmd_type {
+                CmdType::Multi | CmdType::Exec | CmdType::Discard | CmdType::Watch => (),
+                _ => {
+                    self.queue_transaction_cmd(cmd_ctx);
+                    return CmdReplyFuture::Left(reply_receiver);
+                }
+            }
+        }
+
+        let subscribed = cmd_ctx
+            .get_subscription()
+            .lock()
+            .expect("ForwardHandler::handle_cmd_ctx")
+            .is_subscribed();
+        if subscribed {
+            match cmd_type {
+                CmdType::Subscribe
+                | CmdType::PSubscribe
+                | CmdType::Unsubscribe
+                | CmdType::PUnsubscribe
+                | CmdType::Quit => (),
+                _ => {
+                    self.handle_subscribed_cmd(cmd_ctx);
+                    return CmdReplyFuture::Left(reply_receiver);
+                }
+            }
+        }
+
         match cmd_type {
             CmdType::Ping => {
                 cmd_ctx.set_resp_result(Ok(Resp::Simple(String::from("OK").into_bytes())))
             }
             CmdType::Info => cmd_ctx.set_resp_result(Ok(Resp::Bulk(BulkStr::Str(
                 format!(
-                    "version:{}\r\n\r\n{}",
+                    "version:{}\r\n\r\n{}# Clients\r\n{}\r\n",
                     UNDERMOON_VERSION,
-                    self.manager.info()
+                    self.manager.info(),
+                    self.client_registry.info()
                 )
                 .into_bytes(),
             )))),
@@ -665,11 +2633,337 @@ impl<F: RedisClientFactory> CmdCtxHandler for ForwardHandler<F> {
             CmdType::UmCtl => self.handle_umctl(cmd_ctx),
             CmdType::Cluster => self.handle_cluster(cmd_ctx),
             CmdType::Config => self.handle_config(cmd_ctx),
-            CmdType::Command => {
-                cmd_ctx.set_resp_result(Ok(Resp::Arr(Array::Arr(vec![]))));
-            }
+            CmdType::Command => self.handle_command(cmd_ctx),
+            CmdType::Hello => self.handle_hello(cmd_ctx),
+            CmdType::ReadOnly => self.handle_readonly(cmd_ctx, true),
+            CmdType::ReadWrite => self.handle_readonly(cmd_ctx, false),
+            CmdType::Client => self.handle_client(cmd_ctx),
             CmdType::Others => return self.handle_data_cmd(cmd_ctx, reply_receiver),
+            CmdType::Multi => self.handle_multi(cmd_ctx),
+            CmdType::Exec => return self.handle_exec(cmd_ctx, reply_receiver),
+            CmdType::Discard => return self.handle_discard(cmd_ctx, reply_receiver),
+            CmdType::Watch => return self.handle_watch(cmd_ctx, reply_receiver),
+            CmdType::Unwatch => return self.handle_unwatch(cmd_ctx, reply_receiver),
+            CmdType::Subscribe => return self.handle_subscribe(cmd_ctx, reply_receiver),
+            CmdType::PSubscribe => {
+                return CmdReplyFuture::Right(Box::pin(
+                    self.subscribe_patterns(cmd_ctx, reply_receiver),
+                ))
+            }
+            CmdType::Unsubscribe => {
+                return CmdReplyFuture::Right(Box::pin(
+                    self.unsubscribe_channels(cmd_ctx, reply_receiver),
+                ))
+            }
+            CmdType::PUnsubscribe => {
+                return CmdReplyFuture::Right(Box::pin(
+                    self.unsubscribe_patterns(cmd_ctx, reply_receiver),
+                ))
+            }
         };
         CmdReplyFuture::Left(reply_receiver)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::RespPacket;
+
+    fn gen_cmd(elements: &[&str]) -> Command {
+        let resp = Resp::Arr(Array::Arr(
+            elements
+                .iter()
+                .map(|e| Resp::Bulk(BulkStr::Str(e.as_bytes().to_vec())))
+                .collect(),
+        ));
+        Command::new(Box::new(RespPacket::from_resp_vec(resp)))
+    }
+
+    #[test]
+    fn test_check_transaction_cmd() {
+        assert!(check_transaction_cmd(&gen_cmd(&["SET", "k", "v"]), false).is_ok());
+        assert!(check_transaction_cmd(&gen_cmd(&["PING"]), false).is_ok());
+        assert_eq!(
+            check_transaction_cmd(&gen_cmd(&["EVAL", "script", "2", "k"]), false),
+            Err(Resp::Error(INVALID_NUMKEYS_REPLY.to_vec()))
+        );
+        assert_eq!(
+            check_transaction_cmd(&gen_cmd(&["SET", "k"]), false),
+            Err(Resp::Error(
+                b"ERR wrong number of arguments for 'set' command".to_vec()
+            ))
+        );
+        for cmd in &[
+            vec!["FLUSHDB"],
+            vec!["KEYS", "*"],
+            vec!["DBSIZE"],
+            vec!["RANDOMKEY"],
+            vec!["SCAN", "0"],
+        ] {
+            let err = format!(
+                "ERR '{}' is not allowed inside a transaction",
+                cmd[0].to_lowercase()
+            );
+            assert_eq!(
+                check_transaction_cmd(&gen_cmd(cmd), false),
+                Err(Resp::Error(err.into_bytes()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_readonly_cmd() {
+        for cmd in &[
+            vec!["GET", "a"],
+            vec!["PING"],
+            vec!["INFO"],
+            vec!["MULTI"],
+            vec!["SUBSCRIBE", "ch"],
+            vec!["CONFIG", "get", "max_clients"],
+            vec!["CLIENT", "LIST"],
+            vec!["CLIENT", "SETNAME", "a"],
+        ] {
+            assert!(is_readonly_cmd(&gen_cmd(cmd)), "{:?}", cmd);
+        }
+        for cmd in &[
+            vec!["SET", "a", "b"],
+            vec!["PUBLISH", "ch", "msg"],
+            vec!["CONFIG", "SET", "max_clients", "0"],
+            vec!["CLIENT", "kill", "ID", "1"],
+            vec!["UMCTL", "LISTDB"],
+        ] {
+            assert!(!is_readonly_cmd(&gen_cmd(cmd)), "{:?}", cmd);
+        }
+    }
+
+    #[test]
+    fn test_check_transaction_cmd_with_key_prefix() {
+        assert!(check_transaction_cmd(&gen_cmd(&["SET", "k", "v"]), true).is_ok());
+        assert!(check_transaction_cmd(&gen_cmd(&["PUBLISH", "ch", "msg"]), true).is_ok());
+        assert!(check_transaction_cmd(&gen_cmd(&["PING"]), true).is_ok());
+        assert!(check_transaction_cmd(&gen_cmd(&["SCRIPT", "FLUSH"]), false).is_ok());
+
+        assert_eq!(
+            check_transaction_cmd(&gen_cmd(&["FLUSHALL"]), true),
+            Err(Resp::Error(
+                b"ERR 'flushall' without keys is not allowed inside a transaction for the cluster with key prefix"
+                    .to_vec()
+            ))
+        );
+        assert_eq!(
+            check_transaction_cmd(&gen_cmd(&["SCRIPT", "FLUSH"]), true),
+            Err(Resp::Error(
+                b"ERR SCRIPT FLUSH is not supported for the cluster with key prefix".to_vec()
+            ))
+        );
+        assert!(check_transaction_cmd(&gen_cmd(&["SORT", "k", "BY", "w_*"]), true).is_err());
+        assert!(check_transaction_cmd(&gen_cmd(&["KEYS", "*"]), true).is_err());
+    }
+
+    fn bulk(s: &str) -> RespVec {
+        Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn test_group_keys_by_node_and_migrating_slot() {
+        let keys: Vec<&[u8]> = vec![b"{a}1", b"{c}1", b"{b}1", b"{d}1", b"{c}2", b"{a}2"];
+        let (slot_a, slot_b, slot_c) = (get_slot(b"{a}"), get_slot(b"{b}"), get_slot(b"{c}"));
+        let groups = group_keys_by_location(&keys, |slot| {
+            if slot == slot_a || slot == slot_b {
+                SlotLocation::Local("node1".to_string())
+            } else if slot == slot_c {
+                SlotLocation::Migrating
+            } else {
+                SlotLocation::Remote("peer".to_string())
+            }
+        });
+        // The keys of different stable slots on the same node are batched together
+        // while the others are only batched within the same slot.
+        assert_eq!(groups, vec![vec![0, 2, 5], vec![1, 4], vec![3]]);
+    }
+
+    #[test]
+    fn test_gen_group_cmd() {
+        let args: Vec<BinSafeStr> = ["k1", "v1", "k2", "v2", "k3", "v3"]
+            .iter()
+            .map(|e| e.as_bytes().to_vec())
+            .collect();
+        let key_args: Vec<&[BinSafeStr]> = args.chunks(2).collect();
+        assert_eq!(
+            gen_group_cmd("MSET", &key_args, &[2, 0]),
+            Resp::Arr(Array::Arr(vec![
+                bulk("MSET"),
+                bulk("k3"),
+                bulk("v3"),
+                bulk("k1"),
+                bulk("v1"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_merge_group_replies() {
+        let group_replies = vec![
+            (
+                vec![0, 2, 5],
+                Resp::Arr(Array::Arr(vec![
+                    bulk("v0"),
+                    Resp::Bulk(BulkStr::Nil),
+                    bulk("v5"),
+                ])),
+            ),
+            (
+                vec![1, 4],
+                Resp::Arr(Array::Arr(vec![bulk("v1"), bulk("v4")])),
+            ),
+            (vec![3], Resp::Arr(Array::Arr(vec![bulk("v3")]))),
+        ];
+        assert_eq!(
+            merge_group_replies(6, true, group_replies),
+            Ok(vec![
+                bulk("v0"),
+                bulk("v1"),
+                Resp::Bulk(BulkStr::Nil),
+                bulk("v3"),
+                bulk("v4"),
+                bulk("v5"),
+            ])
+        );
+
+        // Without batching, each key is sent in its own GET.
+        let group_replies = vec![(vec![1], bulk("v1")), (vec![0], bulk("v0"))];
+        assert_eq!(
+            merge_group_replies(2, false, group_replies),
+            Ok(vec![bulk("v0"), bulk("v1")])
+        );
+    }
+
+    #[test]
+    fn test_merge_group_replies_with_error() {
+        let err = Resp::Error(b"MOVED 1 127.0.0.1:6000".to_vec());
+        let group_replies = vec![
+            (vec![0], Resp::Arr(Array::Arr(vec![bulk("v0")]))),
+            (vec![1, 2], err.clone()),
+        ];
+        assert_eq!(merge_group_replies(3, true, group_replies), Err(err));
+
+        // The reply of a group should have exactly one value for each key.
+        let group_replies = vec![(vec![0, 1], Resp::Arr(Array::Arr(vec![bulk("v0")])))];
+        assert!(merge_group_replies(2, true, group_replies).is_err());
+    }
+
+    #[test]
+    fn test_split_group_reply() {
+        let values = vec![bulk("v0"), Resp::Bulk(BulkStr::Nil)];
+        assert_eq!(
+            split_group_reply(Resp::Arr(Array::Arr(values.clone())), true, 2),
+            Ok(values)
+        );
+        assert_eq!(
+            split_group_reply(Resp::Bulk(BulkStr::Nil), false, 1),
+            Ok(vec![Resp::Bulk(BulkStr::Nil)])
+        );
+        let err = Resp::Error(b"ERR failed".to_vec());
+        assert_eq!(split_group_reply(err.clone(), true, 2), Err(err.clone()));
+        assert_eq!(split_group_reply(err.clone(), false, 1), Err(err));
+        assert!(split_group_reply(bulk("v0"), true, 1).is_err());
+    }
+
+    fn gen_owned_key_checker(
+        slot_owners: Vec<(&'static str, usize)>,
+    ) -> impl Fn(&str, &[u8]) -> bool {
+        move |address, key| slot_owners.contains(&(address, get_slot(key)))
+    }
+
+    #[test]
+    fn test_merge_dbsize_replies() {
+        let replies = vec![
+            ("node1".to_string(), Resp::Integer(b"3".to_vec())),
+            ("node2".to_string(), Resp::Integer(b"4".to_vec())),
+        ];
+        let reply = merge_keyspace_replies(DataCmdType::DBSIZE, replies, None, |_, _| false);
+        assert_eq!(reply, Ok(Resp::Integer(b"7".to_vec())));
+
+        let replies = vec![
+            ("node1".to_string(), Resp::Integer(b"3".to_vec())),
+            ("node2".to_string(), bulk("4")),
+        ];
+        let reply = merge_keyspace_replies(DataCmdType::DBSIZE, replies, None, |_, _| false);
+        assert!(reply.is_err());
+    }
+
+    #[test]
+    fn test_merge_keys_replies() {
+        // The key `{b}` is left on node1 after being migrated to node2.
+        let is_owned_key = gen_owned_key_checker(vec![
+            ("node1", get_slot(b"{a}")),
+            ("node2", get_slot(b"{b}")),
+        ]);
+        let replies = vec![
+            (
+                "node1".to_string(),
+                Resp::Arr(Array::Arr(vec![bulk("{a}1"), bulk("{b}1")])),
+            ),
+            (
+                "node2".to_string(),
+                Resp::Arr(Array::Arr(vec![bulk("{b}1"), bulk("{b}2")])),
+            ),
+        ];
+        let reply = merge_keyspace_replies(DataCmdType::KEYS, replies, None, &is_owned_key);
+        assert_eq!(
+            reply,
+            Ok(Resp::Arr(Array::Arr(vec![
+                bulk("{a}1"),
+                bulk("{b}1"),
+                bulk("{b}2")
+            ])))
+        );
+
+        let replies = vec![(
+            "node1".to_string(),
+            Resp::Arr(Array::Arr(vec![bulk("t1:{a}1"), bulk("t2:{a}2")])),
+        )];
+        let reply = merge_keyspace_replies(DataCmdType::KEYS, replies, Some(b"t1:"), &is_owned_key);
+        assert_eq!(reply, Ok(Resp::Arr(Array::Arr(vec![bulk("{a}1")]))));
+    }
+
+    #[test]
+    fn test_merge_randomkey_replies() {
+        let is_owned_key = gen_owned_key_checker(vec![("node2", get_slot(b"{b}"))]);
+        let replies = vec![
+            ("node1".to_string(), Resp::Bulk(BulkStr::Nil)),
+            ("node2".to_string(), Resp::Null),
+        ];
+        let reply = merge_keyspace_replies(DataCmdType::RANDOMKEY, replies, None, &is_owned_key);
+        assert_eq!(reply, Ok(Resp::Bulk(BulkStr::Nil)));
+
+        // The key not owned by the node is skipped.
+        let replies = vec![
+            ("node1".to_string(), bulk("{b}1")),
+            ("node2".to_string(), bulk("{b}2")),
+        ];
+        let reply = merge_keyspace_replies(DataCmdType::RANDOMKEY, replies, None, &is_owned_key);
+        assert_eq!(reply, Ok(bulk("{b}2")));
+
+        let replies = vec![("node1".to_string(), bulk("{a}1"))];
+        let reply = merge_keyspace_replies(DataCmdType::RANDOMKEY, replies, None, &is_owned_key);
+        assert_eq!(reply, Ok(Resp::Bulk(BulkStr::Nil)));
+    }
+
+    #[test]
+    fn test_flushdb_flag() {
+        assert_eq!(
+            check_flushdb_enabled(DataCmdType::FLUSHDB, false),
+            Err(Resp::Error(
+                b"ERR FLUSHDB is disabled for this cluster".to_vec()
+            ))
+        );
+        assert!(check_flushdb_enabled(DataCmdType::FLUSHDB, true).is_ok());
+        assert!(check_flushdb_enabled(DataCmdType::DBSIZE, false).is_ok());
+
+        let replies = vec![("node1".to_string(), Resp::Simple(b"OK".to_vec()))];
+        let reply = merge_keyspace_replies(DataCmdType::FLUSHDB, replies, None, |_, _| false);
+        assert_eq!(reply, Ok(Resp::Simple(b"OK".to_vec())));
+    }
+}
@@ -0,0 +1,338 @@
+use super::command::Command;
+use crate::protocol::{Array, BinSafeStr, BulkStr, Resp, RespVec};
+
+// With the `key_prefix` of a cluster, several clusters could share the same backend redis.
+// The keys inside the proxy are still the ones the clients see so that they are routed
+// by the same slots and the hash tags keep working.
+// They only get prefixed right before being sent to the backend.
+
+pub fn add_key_prefix(key_prefix: &[u8], key: &[u8]) -> BinSafeStr {
+    let mut prefixed = Vec::with_capacity(key_prefix.len() + key.len());
+    prefixed.extend_from_slice(key_prefix);
+    prefixed.extend_from_slice(key);
+    prefixed
+}
+
+// Returns None if the key does not belong to this prefix.
+pub fn strip_key_prefix(key_prefix: &[u8], key: &[u8]) -> Option<BinSafeStr> {
+    if key.starts_with(key_prefix) {
+        Some(key[key_prefix.len()..].to_vec())
+    } else {
+        None
+    }
+}
+
+// The special characters of the glob-style pattern in the prefix need to be escaped.
+pub fn gen_prefix_pattern(key_prefix: &[u8], pattern: &[u8]) -> BinSafeStr {
+    let mut prefixed = Vec::with_capacity(key_prefix.len() * 2 + pattern.len());
+    for b in key_prefix {
+        if let b'*' | b'?' | b'[' | b']' | b'\\' = b {
+            prefixed.push(b'\\');
+        }
+        prefixed.push(*b);
+    }
+    prefixed.extend_from_slice(pattern);
+    prefixed
+}
+
+enum ReplyKeys {
+    // The first element of the array, e.g. BLPOP.
+    First,
+    // The key of each stream, e.g. XREAD.
+    Streams,
+}
+
+fn get_reply_keys(cmd: &Command) -> Option<ReplyKeys> {
+    match cmd.get_command_info()?.name {
+        "BLPOP" | "BRPOP" | "BZPOPMIN" | "BZPOPMAX" => Some(ReplyKeys::First),
+        "XREAD" | "XREADGROUP" => Some(ReplyKeys::Streams),
+        _ => None,
+    }
+}
+
+pub fn has_reply_keys(cmd: &Command) -> bool {
+    get_reply_keys(cmd).is_some()
+}
+
+// Removes the prefix of the keys in the replies of the commands above.
+// SCAN and KEYS are handled separately since they need to be sent to multiple nodes.
+pub fn strip_reply_keys(cmd: &Command, reply: RespVec, key_prefix: &[u8]) -> RespVec {
+    let strip = |resp: &mut RespVec| {
+        if let Resp::Bulk(BulkStr::Str(key)) = resp {
+            if let Some(stripped) = strip_key_prefix(key_prefix, key) {
+                *key = stripped;
+            }
+        }
+    };
+
+    match (get_reply_keys(cmd), reply) {
+        (Some(ReplyKeys::First), Resp::Arr(Array::Arr(mut resps))) => {
+            if let Some(key) = resps.first_mut() {
+                strip(key);
+            }
+            Resp::Arr(Array::Arr(resps))
+        }
+        (Some(ReplyKeys::Streams), Resp::Arr(Array::Arr(mut streams))) => {
+            for stream in streams.iter_mut() {
+                if let Resp::Arr(Array::Arr(ref mut resps)) = stream {
+                    if let Some(key) = resps.first_mut() {
+                        strip(key);
+                    }
+                }
+            }
+            Resp::Arr(Array::Arr(streams))
+        }
+        // RESP3 replies the streams in a map.
+        (Some(ReplyKeys::Streams), Resp::Map(mut resps)) => {
+            for key in resps.iter_mut().step_by(2) {
+                strip(key);
+            }
+            Resp::Map(resps)
+        }
+        (_, reply) => reply,
+    }
+}
+
+// The channels of Pub/Sub are also namespaced.
+pub fn add_subscriber_prefix(
+    cmd_name: &str,
+    key_prefix: &[u8],
+    names: Vec<BinSafeStr>,
+) -> Vec<BinSafeStr> {
+    let is_pattern = cmd_name.eq_ignore_ascii_case("PSUBSCRIBE")
+        || cmd_name.eq_ignore_ascii_case("PUNSUBSCRIBE");
+    names
+        .into_iter()
+        .map(|name| {
+            if is_pattern {
+                gen_prefix_pattern(key_prefix, &name)
+            } else {
+                add_key_prefix(key_prefix, &name)
+            }
+        })
+        .collect()
+}
+
+// Removes the prefix of the channel and pattern in the pushed `message` and `pmessage`.
+pub fn strip_message_prefix(msg: RespVec, key_prefix: &[u8]) -> RespVec {
+    let mut resps = match msg {
+        Resp::Arr(Array::Arr(resps)) => resps,
+        others => return others,
+    };
+    let is_pmessage = match resps.first() {
+        Some(Resp::Bulk(BulkStr::Str(kind))) => kind.as_slice() == b"pmessage",
+        _ => false,
+    };
+    let pattern_prefix = gen_prefix_pattern(key_prefix, b"");
+    for (i, resp) in resps.iter_mut().enumerate().skip(1).take(2) {
+        let prefix = if is_pmessage && i == 1 {
+            pattern_prefix.as_slice()
+        } else if is_pmessage || i == 1 {
+            key_prefix
+        } else {
+            // The payload of `message`.
+            break;
+        };
+        if let Resp::Bulk(BulkStr::Str(name)) = resp {
+            if let Some(stripped) = strip_key_prefix(prefix, name) {
+                *name = stripped;
+            }
+        }
+    }
+    Resp::Arr(Array::Arr(resps))
+}
+
+// Rejects the commands which could access the data of the other clusters
+// on the same backend redis.
+pub fn check_prefixed_cmd(cmd: &Command) -> Result<(), RespVec> {
+    let name = match cmd.get_command_info() {
+        Some(info) => info.name,
+        None => return Ok(()),
+    };
+    let rejected = match name {
+        "DBSIZE" | "FLUSHDB" | "RANDOMKEY" | "PUBSUB" => Some(name.to_string()),
+        "SCRIPT" => cmd
+            .get_command_element(1)
+            .filter(|sub_cmd| sub_cmd.eq_ignore_ascii_case(b"FLUSH"))
+            .map(|_| "SCRIPT FLUSH".to_string()),
+        "SORT" if has_sort_pattern(cmd) => Some("SORT with BY or GET pattern".to_string()),
+        _ => None,
+    };
+    match rejected {
+        Some(rejected) => Err(Resp::Error(
+            format!(
+                "ERR {} is not supported for the cluster with key prefix",
+                rejected
+            )
+            .into_bytes(),
+        )),
+        None => Ok(()),
+    }
+}
+
+// The patterns of BY and GET containing `*` look up other keys.
+fn has_sort_pattern(cmd: &Command) -> bool {
+    let mut i = 2;
+    while let Some(option) = cmd.get_command_element(i) {
+        if option.eq_ignore_ascii_case(b"BY") || option.eq_ignore_ascii_case(b"GET") {
+            let pattern = cmd.get_command_element(i + 1).unwrap_or(b"");
+            if pattern.contains(&b'*') {
+                return true;
+            }
+            i += 2;
+        } else if option.eq_ignore_ascii_case(b"LIMIT") {
+            i += 3;
+        } else if option.eq_ignore_ascii_case(b"STORE") {
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::RespPacket;
+
+    fn gen_cmd(elements: &[&str]) -> Command {
+        let resp = Resp::Arr(Array::Arr(
+            elements
+                .iter()
+                .map(|e| Resp::Bulk(BulkStr::Str(e.as_bytes().to_vec())))
+                .collect(),
+        ));
+        Command::new(Box::new(RespPacket::from_resp_vec(resp)))
+    }
+
+    fn bulk(s: &str) -> RespVec {
+        Resp::Bulk(BulkStr::Str(s.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn test_key_prefix() {
+        assert_eq!(add_key_prefix(b"t1:", b"key"), b"t1:key".to_vec());
+        assert_eq!(strip_key_prefix(b"t1:", b"t1:key"), Some(b"key".to_vec()));
+        assert_eq!(strip_key_prefix(b"t1:", b"t2:key"), None);
+        assert_eq!(gen_prefix_pattern(b"t1:", b"*"), b"t1:*".to_vec());
+        assert_eq!(
+            gen_prefix_pattern(b"t[1]*:", b"a?"),
+            b"t\\[1\\]\\*:a?".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_strip_reply_keys() {
+        let cmd = gen_cmd(&["BLPOP", "a", "b", "0"]);
+        let reply = Resp::Arr(Array::Arr(vec![bulk("t1:b"), bulk("t1:value")]));
+        assert_eq!(
+            strip_reply_keys(&cmd, reply, b"t1:"),
+            Resp::Arr(Array::Arr(vec![bulk("b"), bulk("t1:value")]))
+        );
+        let reply = Resp::Arr(Array::Nil);
+        assert_eq!(strip_reply_keys(&cmd, reply.clone(), b"t1:"), reply);
+
+        let cmd = gen_cmd(&["XREAD", "STREAMS", "a", "b", "0", "0"]);
+        let reply = Resp::Arr(Array::Arr(vec![
+            Resp::Arr(Array::Arr(vec![
+                bulk("t1:a"),
+                Resp::Arr(Array::Arr(vec![])),
+            ])),
+            Resp::Arr(Array::Arr(vec![
+                bulk("t1:b"),
+                Resp::Arr(Array::Arr(vec![])),
+            ])),
+        ]));
+        assert_eq!(
+            strip_reply_keys(&cmd, reply, b"t1:"),
+            Resp::Arr(Array::Arr(vec![
+                Resp::Arr(Array::Arr(vec![bulk("a"), Resp::Arr(Array::Arr(vec![]))])),
+                Resp::Arr(Array::Arr(vec![bulk("b"), Resp::Arr(Array::Arr(vec![]))])),
+            ]))
+        );
+        let reply = Resp::Map(vec![bulk("t1:a"), Resp::Arr(Array::Arr(vec![]))]);
+        assert_eq!(
+            strip_reply_keys(&cmd, reply, b"t1:"),
+            Resp::Map(vec![bulk("a"), Resp::Arr(Array::Arr(vec![]))])
+        );
+
+        let cmd = gen_cmd(&["LRANGE", "a", "0", "-1"]);
+        assert!(!has_reply_keys(&cmd));
+        let reply = Resp::Arr(Array::Arr(vec![bulk("t1:a")]));
+        assert_eq!(strip_reply_keys(&cmd, reply.clone(), b"t1:"), reply);
+    }
+
+    #[test]
+    fn test_subscriber_prefix() {
+        let names = vec![b"ch".to_vec(), b"news.*".to_vec()];
+        assert_eq!(
+            add_subscriber_prefix("SUBSCRIBE", b"t*:", names.clone()),
+            vec![b"t*:ch".to_vec(), b"t*:news.*".to_vec()]
+        );
+        assert_eq!(
+            add_subscriber_prefix("psubscribe", b"t*:", names),
+            vec![b"t\\*:ch".to_vec(), b"t\\*:news.*".to_vec()]
+        );
+
+        let msg = Resp::Arr(Array::Arr(vec![
+            bulk("message"),
+            bulk("t*:ch"),
+            bulk("t*:payload"),
+        ]));
+        assert_eq!(
+            strip_message_prefix(msg, b"t*:"),
+            Resp::Arr(Array::Arr(vec![
+                bulk("message"),
+                bulk("ch"),
+                bulk("t*:payload")
+            ]))
+        );
+        let msg = Resp::Arr(Array::Arr(vec![
+            bulk("pmessage"),
+            bulk("t\\*:news.*"),
+            bulk("t*:news.a"),
+            bulk("t*:payload"),
+        ]));
+        assert_eq!(
+            strip_message_prefix(msg, b"t*:"),
+            Resp::Arr(Array::Arr(vec![
+                bulk("pmessage"),
+                bulk("news.*"),
+                bulk("news.a"),
+                bulk("t*:payload")
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_check_prefixed_cmd() {
+        assert!(check_prefixed_cmd(&gen_cmd(&["GET", "a"])).is_ok());
+        assert!(check_prefixed_cmd(&gen_cmd(&["SORT", "a", "LIMIT", "0", "10", "ALPHA"])).is_ok());
+        assert!(check_prefixed_cmd(&gen_cmd(&["SORT", "a", "BY", "nosort", "GET", "#"])).is_ok());
+        assert!(check_prefixed_cmd(&gen_cmd(&["SCRIPT", "LOAD", "return 1"])).is_ok());
+
+        assert_eq!(
+            check_prefixed_cmd(&gen_cmd(&["SORT", "a", "BY", "w_*"])),
+            Err(Resp::Error(
+                b"ERR SORT with BY or GET pattern is not supported for the cluster with key prefix"
+                    .to_vec()
+            ))
+        );
+        assert!(check_prefixed_cmd(&gen_cmd(&["SORT", "a", "GET", "#", "GET", "o_*->f"])).is_err());
+        assert_eq!(
+            check_prefixed_cmd(&gen_cmd(&["SCRIPT", "flush"])),
+            Err(Resp::Error(
+                b"ERR SCRIPT FLUSH is not supported for the cluster with key prefix".to_vec()
+            ))
+        );
+        for cmd in &[
+            vec!["DBSIZE"],
+            vec!["FLUSHDB"],
+            vec!["RANDOMKEY"],
+            vec!["PUBSUB", "CHANNELS"],
+        ] {
+            assert!(check_prefixed_cmd(&gen_cmd(cmd)).is_err());
+        }
+    }
+}
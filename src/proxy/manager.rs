@@ -4,9 +4,13 @@ use super::backend::{
 };
 use super::blocking::{
     gen_basic_blocking_sender_factory, gen_blocking_sender_factory, BasicBlockingSenderFactory,
-    BlockingBackendSenderFactory, BlockingCmdTaskSender, BlockingMap, CounterTask,
+    BlockingBackendSenderFactory, BlockingCmdTaskSender, BlockingHintTask, BlockingMap,
+    CounterTask,
 };
-use super::database::{DBError, DBSendError, DBTag, DatabaseMap, DEFAULT_DB};
+use super::command_table::{resolve_command, CommandResolution};
+use super::database::{DBError, DBSendError, DBTag, DatabaseMap, SlotLocation, DEFAULT_DB};
+use super::rate_limit::{RateLimitExceeded, RateLimiter};
+use super::replica::ReplicaRouter;
 use super::reply::{DecompressCommitHandlerFactory, ReplyCommitHandlerFactory};
 use super::service::ServerProxyConfig;
 use super::session::{CmdCtx, CmdCtxFactory};
@@ -15,6 +19,7 @@ use crate::common::cluster::{DBName, MigrationTaskMeta, SlotRangeTag};
 use crate::common::config::AtomicMigrationConfig;
 use crate::common::db::ProxyDBMeta;
 use crate::common::track::TrackedFutureRegistry;
+use crate::common::utils::get_slot;
 use crate::migration::delete_keys::DeleteKeysTaskMap;
 use crate::migration::manager::{MigrationManager, MigrationMap, SwitchError};
 use crate::migration::task::MgrSubCmd;
@@ -26,6 +31,7 @@ use crate::replication::replicator::ReplicatorMeta;
 use arc_swap::ArcSwap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 pub struct MetaMap<S: CmdTaskSender, T>
 where
@@ -82,9 +88,11 @@ pub struct MetaManager<F: RedisClientFactory> {
     epoch: AtomicU64,
     lock: Mutex<()>, // This is the write lock for `epoch`, `db`, and `task`.
     replicator_manager: ReplicatorManager<F>,
+    replica_router: Arc<ReplicaRouter<F, <SenderFactory as CmdTaskSenderFactory>::Sender>>,
     migration_manager: MigrationManager<F, MigrationSenderFactory, CmdCtxFactory>,
     sender_factory: SenderFactory,
     blocking_map: Arc<BlockingMap<BasicSenderFactory, BlockingTaskRetrySender>>,
+    rate_limiter: RateLimiter,
 }
 
 impl<F: RedisClientFactory> MetaManager<F> {
@@ -95,7 +103,9 @@ impl<F: RedisClientFactory> MetaManager<F> {
         future_registry: Arc<TrackedFutureRegistry>,
     ) -> Self {
         let reply_handler_factory = Arc::new(DecompressCommitHandlerFactory::new(meta_map.clone()));
-        let conn_factory = Arc::new(DefaultConnFactory::default());
+        let conn_factory = Arc::new(
+            DefaultConnFactory::new(config.backend_tls.clone()).with_resp3(config.backend_resp3),
+        );
         let blocking_task_sender = Arc::new(BlockingTaskRetrySender::new(meta_map.clone()));
         let basic_sender_factory = gen_basic_blocking_sender_factory(
             config.clone(),
@@ -108,10 +118,11 @@ impl<F: RedisClientFactory> MetaManager<F> {
         let migration_sender_factory = Arc::new(gen_migration_sender_factory(
             config.clone(),
             Arc::new(ReplyCommitHandlerFactory::default()),
-            conn_factory,
+            // The migration checks the RESP2 replies such as the nil of DUMP.
+            Arc::new(DefaultConnFactory::new(config.backend_tls.clone())),
             future_registry.clone(),
         ));
-        let cmd_ctx_factory = Arc::new(CmdCtxFactory::default());
+        let cmd_ctx_factory = Arc::new(CmdCtxFactory::new(config.command_table.clone()));
         let migration_config = Arc::new(AtomicMigrationConfig::default());
         let config_clone = config.clone();
         Self {
@@ -123,6 +134,10 @@ impl<F: RedisClientFactory> MetaManager<F> {
                 client_factory.clone(),
                 future_registry.clone(),
             ),
+            replica_router: Arc::new(ReplicaRouter::new(
+                client_factory.clone(),
+                future_registry.clone(),
+            )),
             migration_manager: MigrationManager::new(
                 config_clone,
                 migration_config,
@@ -133,6 +148,7 @@ impl<F: RedisClientFactory> MetaManager<F> {
             ),
             sender_factory,
             blocking_map,
+            rate_limiter: RateLimiter::default(),
         }
     }
 
@@ -169,6 +185,9 @@ impl<F: RedisClientFactory> MetaManager<F> {
         if db_meta.get_epoch() <= self.epoch.load(Ordering::SeqCst) && !db_meta.get_flags().force {
             return Err(DBError::OldEpoch);
         }
+        if let Some(db_name) = db_meta.find_shared_node_migration() {
+            return Err(DBError::SharedNodeMigration(db_name));
+        }
 
         let old_meta_map = self.meta_map.load();
         let db_map = DatabaseMap::from_db_map(&db_meta, sender_factory);
@@ -186,6 +205,12 @@ impl<F: RedisClientFactory> MetaManager<F> {
                 db_meta.get_local(),
                 left_slots_after_change,
             );
+        self.rate_limiter.retain(|db_name| {
+            db_map
+                .get_config(db_name)
+                .map(|config| !config.rate_limit.is_unlimited())
+                .unwrap_or(false)
+        });
         self.meta_map.store(Arc::new(MetaMap {
             db_map,
             migration_map,
@@ -202,7 +227,12 @@ impl<F: RedisClientFactory> MetaManager<F> {
     }
 
     pub fn update_replicators(&self, meta: ReplicatorMeta) -> Result<(), DBError> {
-        self.replicator_manager.update_replicators(meta)
+        let masters = meta.masters.clone();
+        self.replicator_manager.update_replicators(meta)?;
+        let sender_factory = &self.sender_factory;
+        self.replica_router
+            .update_replicas(&masters, |address| sender_factory.create(address));
+        Ok(())
     }
 
     pub fn get_replication_info(&self) -> String {
@@ -214,9 +244,10 @@ impl<F: RedisClientFactory> MetaManager<F> {
         let db_info = meta_map.db_map.info();
         let mgr_info = meta_map.migration_map.info();
         let del_info = meta_map.deleting_task_map.info();
+        let rate_limit_info = self.rate_limiter.info();
         format!(
-            "# DB\r\n{}\r\n# Migration\r\n{}\r\n{}\r\n",
-            db_info, mgr_info, del_info
+            "# DB\r\n{}\r\n# Migration\r\n{}\r\n{}\r\n# RateLimit\r\n{}\r\n",
+            db_info, mgr_info, del_info, rate_limit_info
         )
     }
 
@@ -257,9 +288,140 @@ impl<F: RedisClientFactory> MetaManager<F> {
     }
 
     pub fn send(&self, cmd_ctx: CmdCtx) {
+        let cmd_ctx = match self.try_sending_to_replica(cmd_ctx) {
+            None => return,
+            Some(cmd_ctx) => cmd_ctx,
+        };
         send_cmd_ctx(&self.meta_map, cmd_ctx);
     }
 
+    // Only the read-only commands on the stable slots could be sent to the replicas
+    // and they will fall back to the masters if there's no available replica,
+    // in which case the command is returned.
+    fn try_sending_to_replica(&self, cmd_ctx: CmdCtx) -> Option<CmdCtx> {
+        if !cmd_ctx.get_cmd().is_readonly() {
+            return Some(cmd_ctx);
+        }
+        let db_name = cmd_ctx.get_db_name();
+        let meta_map = self.meta_map.load();
+        let config = match meta_map.db_map.get_config(&db_name) {
+            Some(config) => config,
+            None => return Some(cmd_ctx),
+        };
+        if !cmd_ctx.get_readonly().unwrap_or(config.default_readonly) {
+            return Some(cmd_ctx);
+        }
+        let slot = match cmd_ctx.get_key() {
+            Some(key) => get_slot(key),
+            None => return Some(cmd_ctx),
+        };
+        if meta_map.migration_map.contains_slot(&db_name, slot) {
+            return Some(cmd_ctx);
+        }
+        let master = match meta_map.db_map.locate_slot(&db_name, slot) {
+            SlotLocation::Local(address) => address,
+            _ => return Some(cmd_ctx),
+        };
+        let sender =
+            match self
+                .replica_router
+                .select_replica(&db_name, &master, config.replica_read_policy)
+            {
+                Some(sender) => sender,
+                None => return Some(cmd_ctx),
+            };
+
+        // The replicas are not involved in migration so they never need blocking.
+        if let Err(err) = sender.send(BlockingHintTask::new(cmd_ctx, false)) {
+            warn!("Failed to forward cmd_ctx to replica: {:?}", err);
+        }
+        None
+    }
+
+    pub fn locate_slot(&self, db_name: &DBName, slot: usize) -> SlotLocation {
+        let meta_map = self.meta_map.load();
+        if meta_map.migration_map.contains_slot(db_name, slot) {
+            return SlotLocation::Migrating;
+        }
+        meta_map.db_map.locate_slot(db_name, slot)
+    }
+
+    // Unlike `locate_slot`, the slots being migrated are still owned by the source node.
+    pub fn get_local_slot_owner(&self, db_name: &DBName, slot: usize) -> Option<String> {
+        match self.meta_map.load().db_map.locate_slot(db_name, slot) {
+            SlotLocation::Local(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    pub fn get_local_nodes(&self, db_name: &DBName) -> Vec<String> {
+        self.meta_map.load().db_map.get_local_nodes(db_name)
+    }
+
+    pub fn is_flushdb_enabled(&self, db_name: &DBName) -> bool {
+        self.meta_map
+            .load()
+            .db_map
+            .get_config(db_name)
+            .map(|config| config.enable_flushdb)
+            .unwrap_or(false)
+    }
+
+    pub fn get_max_clients(&self, db_name: &DBName) -> u64 {
+        self.meta_map
+            .load()
+            .db_map
+            .get_config(db_name)
+            .map(|config| config.max_clients)
+            .unwrap_or(0)
+    }
+
+    pub fn get_key_prefix(&self, db_name: &DBName) -> Option<Vec<u8>> {
+        self.meta_map
+            .load()
+            .db_map
+            .get_config(db_name)
+            .and_then(|config| config.key_prefix.as_ref())
+            .map(|key_prefix| key_prefix.as_bytes().to_vec())
+    }
+
+    pub fn resolve_command(&self, db_name: &DBName, cmd_name: &[u8]) -> CommandResolution {
+        let meta_map = self.meta_map.load();
+        resolve_command(cmd_name, meta_map.db_map.get_config(db_name))
+    }
+
+    // Returns how long the request should be delayed.
+    pub fn check_rate_limit(
+        &self,
+        db_name: &DBName,
+        bytes: u64,
+    ) -> Result<Duration, RateLimitExceeded> {
+        let rate_limit = match self.meta_map.load().db_map.get_config(db_name) {
+            Some(config) => config.rate_limit,
+            None => return Ok(Duration::from_secs(0)),
+        };
+        self.rate_limiter.acquire(db_name, &rate_limit, bytes)
+    }
+
+    pub fn requires_auth(&self, db_name: &DBName) -> bool {
+        self.meta_map
+            .load()
+            .db_map
+            .get_config(db_name)
+            .map(|config| config.requires_auth())
+            .unwrap_or(false)
+    }
+
+    // Returns None if the password is wrong,
+    // otherwise returns whether it only has the read-only access.
+    pub fn check_password(&self, db_name: &DBName, password: &[u8]) -> Option<bool> {
+        self.meta_map
+            .load()
+            .db_map
+            .get_config(db_name)
+            .and_then(|config| config.check_password(password))
+    }
+
     pub fn try_select_db(&self, mut cmd_ctx: CmdCtx) -> CmdCtx {
         if cmd_ctx.get_db_name().as_str() != DEFAULT_DB {
             return cmd_ctx;
@@ -409,15 +409,17 @@ impl<F: CmdTaskFactory, S: CmdTaskSender<Task = ReqTask<F::Task>>> RestoreDataCm
 mod tests {
     use super::super::backend::BackendError;
     use super::super::command::{new_command_pair, CmdReplyReceiver, Command};
-    use super::super::session::{CmdCtx, CmdCtxFactory};
+    use super::super::pubsub::{new_push_pair, Subscription};
+    use super::super::session::{ClientState, CmdCtx, CmdCtxFactory};
+    use super::super::transaction::Transaction;
     use super::*;
     use crate::common::cluster::DBName;
-    use crate::protocol::RespPacket;
     use crate::protocol::{BulkStr, Resp};
+    use crate::protocol::{RespPacket, RespVersion};
     use dashmap::DashMap;
     use std::collections::HashMap;
     use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-    use std::sync::RwLock;
+    use std::sync::{Mutex, RwLock};
     use tokio;
 
     #[derive(Debug, Clone, Copy)]
@@ -561,7 +563,19 @@ mod tests {
         let packet = Box::new(RespPacket::from_resp_vec(resp));
         let cmd = Command::new(packet);
         let (reply_sender, reply_receiver) = new_command_pair();
-        let cmd_ctx = CmdCtx::new(db, cmd, reply_sender, 0);
+        let resp_version = Arc::new(RwLock::new(RespVersion::Resp2));
+        let transaction = Arc::new(Mutex::new(Transaction::default()));
+        let subscription = Arc::new(Mutex::new(Subscription::new(new_push_pair().0)));
+        let client = Arc::new(RwLock::new(ClientState::new(0, String::new())));
+        let cmd_ctx = CmdCtx::new(
+            db,
+            resp_version,
+            transaction,
+            subscription,
+            client,
+            cmd,
+            reply_sender,
+        );
         (cmd_ctx, reply_receiver)
     }
 
@@ -1,13 +1,22 @@
 pub mod backend;
 pub mod blocking;
+pub mod client;
 mod command;
+pub mod command_table;
 mod compress;
 pub mod database;
 pub mod executor;
+mod key_prefix;
 pub mod manager;
 pub mod migration_backend;
+mod pubsub;
+pub mod rate_limit;
+mod replica;
 pub mod reply;
+mod scan;
+mod script;
 pub mod service;
 pub mod session;
 mod slot;
 pub mod slowlog;
+mod transaction;
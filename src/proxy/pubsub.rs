@@ -0,0 +1,394 @@
+use super::key_prefix::strip_message_prefix;
+use crate::common::utils::resolve_first_address;
+use crate::protocol::{
+    auth_conn, connect_stream, get_resp_size, new_simple_packet_codec, Array, BinSafeStr, BulkStr,
+    Credential, RedisClientError, Resp, RespCodec, RespVec, RespVersion, TlsClientConfig,
+};
+use futures::channel::mpsc;
+use futures::task::{Context, Poll};
+use futures::{future, Stream, StreamExt};
+use std::collections::HashMap;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio_util::codec::Decoder;
+
+// The messages pushed to the client session.
+// The size of the messages not sent yet is counted for the output buffer limit.
+#[derive(Clone)]
+pub struct PushSender {
+    sender: mpsc::UnboundedSender<(RespVec, usize)>,
+    pending_bytes: Arc<AtomicUsize>,
+}
+
+impl PushSender {
+    pub fn send(&self, msg: RespVec) -> Result<(), RespVec> {
+        let size = get_resp_size(&msg);
+        self.pending_bytes.fetch_add(size, Ordering::SeqCst);
+        self.sender
+            .unbounded_send((msg, size))
+            .map_err(|err| err.into_inner().0)
+    }
+}
+
+pub struct PushReceiver {
+    receiver: mpsc::UnboundedReceiver<(RespVec, usize)>,
+    pending_bytes: Arc<AtomicUsize>,
+}
+
+impl PushReceiver {
+    pub fn get_pending_bytes(&self) -> usize {
+        self.pending_bytes.load(Ordering::SeqCst)
+    }
+
+    pub fn get_pending_bytes_counter(&self) -> Arc<AtomicUsize> {
+        self.pending_bytes.clone()
+    }
+}
+
+impl Stream for PushReceiver {
+    type Item = RespVec;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let pending_bytes = self.pending_bytes.clone();
+        Pin::new(&mut self.receiver).poll_next(cx).map(|item| {
+            item.map(|(msg, size)| {
+                pending_bytes.fetch_sub(size, Ordering::SeqCst);
+                msg
+            })
+        })
+    }
+}
+
+pub fn new_push_pair() -> (PushSender, PushReceiver) {
+    let (sender, receiver) = mpsc::unbounded();
+    let pending_bytes = Arc::new(AtomicUsize::new(0));
+    let push_sender = PushSender {
+        sender,
+        pending_bytes: pending_bytes.clone(),
+    };
+    let push_receiver = PushReceiver {
+        receiver,
+        pending_bytes,
+    };
+    (push_sender, push_receiver)
+}
+
+// A connection in the subscriber mode to a backend redis.
+// It gets closed once it's dropped.
+pub struct SubscriberConn {
+    cmd_sender: mpsc::UnboundedSender<Vec<BinSafeStr>>,
+    // The replies of SUBSCRIBE, UNSUBSCRIBE, PSUBSCRIBE and PUNSUBSCRIBE.
+    ack_receiver: mpsc::UnboundedReceiver<RespVec>,
+    // The channels and patterns are prefixed on the backend redis.
+    key_prefix: Option<BinSafeStr>,
+}
+
+impl SubscriberConn {
+    pub async fn connect(
+        address: String,
+        credential: Option<&Credential>,
+        tls: Option<&TlsClientConfig>,
+        push_sender: PushSender,
+        key_prefix: Option<BinSafeStr>,
+    ) -> Result<Self, RedisClientError> {
+        let sock_address = match resolve_first_address(&address) {
+            Some(sock_address) => sock_address,
+            None => return Err(RedisClientError::InvalidAddress),
+        };
+        let sock = connect_stream(&address, sock_address, tls)
+            .await
+            .map_err(RedisClientError::Io)?;
+        let sock = match credential {
+            Some(credential) => auth_conn(sock, credential).await?,
+            None => sock,
+        };
+
+        let (encoder, decoder) = new_simple_packet_codec::<Vec<BinSafeStr>, RespVec>();
+        let (writer, mut reader) = RespCodec::new(encoder, decoder).framed(sock).split();
+        let (cmd_sender, cmd_receiver) = mpsc::unbounded();
+        let (ack_sender, ack_receiver) = mpsc::unbounded();
+
+        let send_fut = cmd_receiver.map(Ok).forward(writer);
+        let msg_key_prefix = key_prefix.clone();
+        let recv_fut = async move {
+            while let Some(res) = reader.next().await {
+                let resp = match res {
+                    Ok(resp) => resp,
+                    Err(err) => {
+                        warn!("invalid reply from subscriber connection: {:?}", err);
+                        break;
+                    }
+                };
+                let closed = if is_pushed_message(&resp) {
+                    let resp = match msg_key_prefix.as_ref() {
+                        Some(key_prefix) => strip_message_prefix(resp, key_prefix),
+                        None => resp,
+                    };
+                    push_sender.send(resp).is_err()
+                } else {
+                    ack_sender.unbounded_send(resp).is_err()
+                };
+                if closed {
+                    break;
+                }
+            }
+        };
+
+        tokio::spawn(async move {
+            // Either the session is closed or the backend connection is broken.
+            future::select(Box::pin(send_fut), Box::pin(recv_fut)).await;
+            info!("subscriber connection to {} closed", address);
+        });
+
+        Ok(Self {
+            cmd_sender,
+            ack_receiver,
+            key_prefix,
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.cmd_sender.is_closed()
+    }
+
+    pub fn get_key_prefix(&self) -> Option<&[u8]> {
+        self.key_prefix.as_deref()
+    }
+
+    // Redis replies an acknowledgement for each channel or pattern.
+    pub async fn execute(
+        &mut self,
+        cmd: Vec<BinSafeStr>,
+        ack_num: usize,
+    ) -> Result<(), RedisClientError> {
+        self.cmd_sender
+            .unbounded_send(cmd)
+            .map_err(|_| RedisClientError::Closed)?;
+        for _ in 0..ack_num {
+            match self.ack_receiver.next().await {
+                Some(Resp::Error(err)) => {
+                    warn!("failed to subscribe: {}", String::from_utf8_lossy(&err));
+                    return Err(RedisClientError::InvalidReply);
+                }
+                Some(_) => (),
+                None => return Err(RedisClientError::Closed),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_pushed_message(resp: &RespVec) -> bool {
+    let kind = match resp {
+        Resp::Arr(Array::Arr(resps)) => resps.first(),
+        _ => None,
+    };
+    match kind {
+        Some(Resp::Bulk(BulkStr::Str(kind))) => kind == b"message" || kind == b"pmessage",
+        _ => false,
+    }
+}
+
+// The clients using RESP3 expect the messages and the subscribe replies in the push type.
+pub fn adapt_push_message(msg: RespVec, resp_version: RespVersion) -> RespVec {
+    match (resp_version, msg) {
+        (RespVersion::Resp3, Resp::Arr(Array::Arr(resps))) => Resp::Push(resps),
+        (_, msg) => msg,
+    }
+}
+
+// The subscriber state of a session.
+// The subscribe commands are handled in the reply futures
+// which are polled in the order of the commands in a session,
+// so the connections are taken out instead of holding the lock across await.
+pub struct Subscription {
+    // channel => the backend redis subscribed to.
+    channels: HashMap<BinSafeStr, String>,
+    // A pattern could match channels in any slot,
+    // so it's subscribed to all the local backend redis.
+    patterns: HashMap<BinSafeStr, Vec<String>>,
+    conns: HashMap<String, SubscriberConn>,
+    push_sender: PushSender,
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Subscription {{ channels: {}, patterns: {}, conns: {} }}",
+            self.channels.len(),
+            self.patterns.len(),
+            self.conns.len()
+        )
+    }
+}
+
+impl Subscription {
+    pub fn new(push_sender: PushSender) -> Self {
+        Self {
+            channels: HashMap::new(),
+            patterns: HashMap::new(),
+            conns: HashMap::new(),
+            push_sender,
+        }
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        !self.channels.is_empty() || !self.patterns.is_empty()
+    }
+
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    pub fn get_push_sender(&self) -> PushSender {
+        self.push_sender.clone()
+    }
+
+    pub fn take_conn(&mut self, address: &str) -> Option<SubscriberConn> {
+        self.conns.remove(address).filter(|conn| !conn.is_closed())
+    }
+
+    pub fn put_conn(&mut self, address: String, conn: SubscriberConn) {
+        self.conns.insert(address, conn);
+    }
+
+    pub fn has_channel(&self, channel: &[u8]) -> bool {
+        self.channels.contains_key(channel)
+    }
+
+    pub fn has_pattern(&self, pattern: &[u8]) -> bool {
+        self.patterns.contains_key(pattern)
+    }
+
+    pub fn get_channels(&self) -> Vec<BinSafeStr> {
+        self.channels.keys().cloned().collect()
+    }
+
+    pub fn get_patterns(&self) -> Vec<BinSafeStr> {
+        self.patterns.keys().cloned().collect()
+    }
+
+    pub fn add_channel(&mut self, channel: BinSafeStr, address: String) -> RespVec {
+        self.channels.insert(channel.clone(), address);
+        self.gen_ack(b"subscribe", Some(channel))
+    }
+
+    pub fn remove_channel(&mut self, channel: BinSafeStr) -> (Option<String>, RespVec) {
+        let address = self.channels.remove(&channel);
+        (address, self.gen_ack(b"unsubscribe", Some(channel)))
+    }
+
+    pub fn add_pattern(&mut self, pattern: BinSafeStr, addresses: Vec<String>) -> RespVec {
+        self.patterns.insert(pattern.clone(), addresses);
+        self.gen_ack(b"psubscribe", Some(pattern))
+    }
+
+    pub fn remove_pattern(&mut self, pattern: BinSafeStr) -> (Vec<String>, RespVec) {
+        let addresses = self.patterns.remove(&pattern).unwrap_or_default();
+        (addresses, self.gen_ack(b"punsubscribe", Some(pattern)))
+    }
+
+    // Used for unsubscribing without any subscription.
+    pub fn gen_ack(&self, kind: &[u8], name: Option<BinSafeStr>) -> RespVec {
+        let name = match name {
+            Some(name) => BulkStr::Str(name),
+            None => BulkStr::Nil,
+        };
+        Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(kind.to_vec())),
+            Resp::Bulk(name),
+            Resp::Integer(self.count().to_string().into_bytes()),
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_message(kind: &str) -> RespVec {
+        Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(kind.as_bytes().to_vec())),
+            Resp::Bulk(BulkStr::Str(b"channel".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"payload".to_vec())),
+        ]))
+    }
+
+    #[test]
+    fn test_is_pushed_message() {
+        assert!(is_pushed_message(&gen_message("message")));
+        assert!(is_pushed_message(&gen_message("pmessage")));
+        assert!(!is_pushed_message(&gen_message("subscribe")));
+        assert!(!is_pushed_message(&Resp::Simple(b"OK".to_vec())));
+    }
+
+    #[test]
+    fn test_adapt_push_message() {
+        let msg = gen_message("message");
+        assert_eq!(adapt_push_message(msg.clone(), RespVersion::Resp2), msg);
+        let resps = match msg.clone() {
+            Resp::Arr(Array::Arr(resps)) => resps,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            adapt_push_message(msg, RespVersion::Resp3),
+            Resp::Push(resps)
+        );
+        let err = Resp::Error(b"ERR".to_vec());
+        assert_eq!(adapt_push_message(err.clone(), RespVersion::Resp3), err);
+    }
+
+    #[tokio::test]
+    async fn test_push_pending_bytes() {
+        let (push_sender, mut push_receiver) = new_push_pair();
+        let msg = gen_message("message");
+        let size = get_resp_size(&msg);
+        push_sender
+            .send(msg.clone())
+            .expect("test_push_pending_bytes");
+        push_sender
+            .send(msg.clone())
+            .expect("test_push_pending_bytes");
+        assert_eq!(push_receiver.get_pending_bytes(), 2 * size);
+        assert_eq!(push_receiver.next().await, Some(msg));
+        assert_eq!(push_receiver.get_pending_bytes(), size);
+    }
+
+    #[test]
+    fn test_subscription() {
+        let (push_sender, _push_receiver) = new_push_pair();
+        let mut subscription = Subscription::new(push_sender);
+        assert!(!subscription.is_subscribed());
+
+        let ack = subscription.add_channel(b"a".to_vec(), "127.0.0.1:6379".to_string());
+        assert_eq!(
+            ack,
+            Resp::Arr(Array::Arr(vec![
+                Resp::Bulk(BulkStr::Str(b"subscribe".to_vec())),
+                Resp::Bulk(BulkStr::Str(b"a".to_vec())),
+                Resp::Integer(b"1".to_vec()),
+            ]))
+        );
+        subscription.add_pattern(b"b*".to_vec(), vec!["127.0.0.1:6379".to_string()]);
+        assert!(subscription.is_subscribed());
+        assert!(subscription.has_channel(b"a"));
+        assert!(subscription.has_pattern(b"b*"));
+
+        let (address, ack) = subscription.remove_channel(b"a".to_vec());
+        assert_eq!(address, Some("127.0.0.1:6379".to_string()));
+        assert_eq!(
+            ack,
+            Resp::Arr(Array::Arr(vec![
+                Resp::Bulk(BulkStr::Str(b"unsubscribe".to_vec())),
+                Resp::Bulk(BulkStr::Str(b"a".to_vec())),
+                Resp::Integer(b"1".to_vec()),
+            ]))
+        );
+        let (addresses, _) = subscription.remove_pattern(b"b*".to_vec());
+        assert_eq!(addresses.len(), 1);
+        assert!(!subscription.is_subscribed());
+    }
+}
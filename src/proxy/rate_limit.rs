@@ -0,0 +1,274 @@
+use crate::common::cluster::DBName;
+use crate::common::config::RateLimitConfig;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub struct RateLimitExceeded;
+
+struct TokenBucket {
+    // Could be negative for the delayed requests
+    // so that the later requests need to wait for them.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            tokens: rate as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, rate: u64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate as f64).min(rate as f64);
+        self.last_refill = now;
+    }
+
+    // A request larger than the bucket only waits for a full bucket
+    // and the overdrawn tokens are paid by the later requests.
+    // Otherwise it would always get rejected.
+    fn get_wait_time(&self, rate: u64, n: f64) -> Duration {
+        let n = n.min(rate as f64);
+        if rate == 0 || self.tokens >= n {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((n - self.tokens) / rate as f64)
+        }
+    }
+
+    fn consume(&mut self, rate: u64, n: f64) {
+        if rate != 0 {
+            self.tokens -= n;
+        }
+    }
+}
+
+struct Buckets {
+    ops: TokenBucket,
+    bytes: TokenBucket,
+}
+
+struct DBRateLimiter {
+    buckets: Mutex<Buckets>,
+    delayed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl DBRateLimiter {
+    fn new(config: &RateLimitConfig, now: Instant) -> Self {
+        let buckets = Buckets {
+            ops: TokenBucket::new(config.max_ops_per_sec, now),
+            bytes: TokenBucket::new(config.max_bytes_per_sec, now),
+        };
+        Self {
+            buckets: Mutex::new(buckets),
+            delayed: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    fn acquire(
+        &self,
+        config: &RateLimitConfig,
+        bytes: u64,
+        now: Instant,
+    ) -> Result<Duration, RateLimitExceeded> {
+        let (ops_rate, bytes_rate) = (config.max_ops_per_sec, config.max_bytes_per_sec);
+        let bytes = bytes as f64;
+
+        let mut buckets = self.buckets.lock().expect("DBRateLimiter::acquire");
+        buckets.ops.refill(ops_rate, now);
+        buckets.bytes.refill(bytes_rate, now);
+        let wait = std::cmp::max(
+            buckets.ops.get_wait_time(ops_rate, 1.0),
+            buckets.bytes.get_wait_time(bytes_rate, bytes),
+        );
+        // Nothing is consumed for the rejected requests.
+        if wait > Duration::from_millis(config.max_delay) {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(RateLimitExceeded);
+        }
+        buckets.ops.consume(ops_rate, 1.0);
+        buckets.bytes.consume(bytes_rate, bytes);
+        if wait > Duration::from_secs(0) {
+            self.delayed.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(wait)
+    }
+}
+
+// Token bucket rate limiters of the clusters.
+#[derive(Default)]
+pub struct RateLimiter {
+    limiters: RwLock<HashMap<DBName, Arc<DBRateLimiter>>>,
+}
+
+impl RateLimiter {
+    // Returns how long the request should be delayed.
+    pub fn acquire(
+        &self,
+        db_name: &DBName,
+        config: &RateLimitConfig,
+        bytes: u64,
+    ) -> Result<Duration, RateLimitExceeded> {
+        self.acquire_at(db_name, config, bytes, Instant::now())
+    }
+
+    fn acquire_at(
+        &self,
+        db_name: &DBName,
+        config: &RateLimitConfig,
+        bytes: u64,
+        now: Instant,
+    ) -> Result<Duration, RateLimitExceeded> {
+        if config.is_unlimited() {
+            return Ok(Duration::from_secs(0));
+        }
+
+        let limiter = self
+            .limiters
+            .read()
+            .expect("RateLimiter::acquire")
+            .get(db_name)
+            .cloned();
+        let limiter = match limiter {
+            Some(limiter) => limiter,
+            None => self
+                .limiters
+                .write()
+                .expect("RateLimiter::acquire")
+                .entry(db_name.clone())
+                .or_insert_with(|| Arc::new(DBRateLimiter::new(config, now)))
+                .clone(),
+        };
+        limiter.acquire(config, bytes, now)
+    }
+
+    // Removes the limiters of the deleted clusters or the clusters without rate limit.
+    pub fn retain<F: Fn(&DBName) -> bool>(&self, f: F) {
+        self.limiters
+            .write()
+            .expect("RateLimiter::retain")
+            .retain(|db_name, _| f(db_name));
+    }
+
+    pub fn info(&self) -> String {
+        let limiters = self.limiters.read().expect("RateLimiter::info");
+        let mut lines: Vec<String> = limiters
+            .iter()
+            .map(|(db_name, limiter)| {
+                format!(
+                    "{}:delayed={},rejected={}",
+                    db_name,
+                    limiter.delayed.load(Ordering::Relaxed),
+                    limiter.rejected.load(Ordering::Relaxed),
+                )
+            })
+            .collect();
+        lines.sort();
+        lines.join("\r\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_config(max_ops_per_sec: u64, max_bytes_per_sec: u64, max_delay: u64) -> RateLimitConfig {
+        RateLimitConfig {
+            max_ops_per_sec,
+            max_bytes_per_sec,
+            max_delay,
+        }
+    }
+
+    #[test]
+    fn test_ops_rate_limit() {
+        let limiter = RateLimiter::default();
+        let db_name = DBName::from("mydb").unwrap();
+        let config = gen_config(2, 0, 0);
+        let now = Instant::now();
+
+        assert!(limiter.acquire_at(&db_name, &config, 100, now).is_ok());
+        assert!(limiter.acquire_at(&db_name, &config, 100, now).is_ok());
+        assert_eq!(
+            limiter.acquire_at(&db_name, &config, 100, now),
+            Err(RateLimitExceeded)
+        );
+        let later = now + Duration::from_millis(500);
+        assert_eq!(
+            limiter.acquire_at(&db_name, &config, 100, later),
+            Ok(Duration::from_secs(0))
+        );
+        assert!(limiter.acquire_at(&db_name, &config, 100, later).is_err());
+        assert_eq!(limiter.info(), "mydb:delayed=0,rejected=2");
+
+        limiter.retain(|_| false);
+        assert_eq!(limiter.info(), "");
+    }
+
+    #[test]
+    fn test_delayed_requests() {
+        let limiter = RateLimiter::default();
+        let db_name = DBName::from("mydb").unwrap();
+        let config = gen_config(0, 1000, 1000);
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.acquire_at(&db_name, &config, 1000, now),
+            Ok(Duration::from_secs(0))
+        );
+        let wait = limiter.acquire_at(&db_name, &config, 500, now).unwrap();
+        assert_eq!(wait, Duration::from_millis(500));
+        // Need to wait for the delayed one.
+        let wait = limiter.acquire_at(&db_name, &config, 500, now).unwrap();
+        assert_eq!(wait, Duration::from_millis(1000));
+        assert!(limiter.acquire_at(&db_name, &config, 1, now).is_err());
+        assert_eq!(limiter.info(), "mydb:delayed=2,rejected=1");
+    }
+
+    #[test]
+    fn test_request_larger_than_bucket() {
+        let limiter = RateLimiter::default();
+        let db_name = DBName::from("mydb").unwrap();
+        let config = gen_config(0, 1000, 0);
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.acquire_at(&db_name, &config, 5000, now),
+            Ok(Duration::from_secs(0))
+        );
+        // The later requests pay for the overdrawn tokens.
+        let later = now + Duration::from_secs(4);
+        assert!(limiter.acquire_at(&db_name, &config, 1, later).is_err());
+        let later = now + Duration::from_secs(5);
+        assert!(limiter.acquire_at(&db_name, &config, 1, later).is_ok());
+
+        // Waits for the full bucket instead of the whole request.
+        let config = gen_config(0, 1000, 1000);
+        let db_name = DBName::from("otherdb").unwrap();
+        assert!(limiter.acquire_at(&db_name, &config, 600, now).is_ok());
+        assert_eq!(
+            limiter.acquire_at(&db_name, &config, 5000, now),
+            Ok(Duration::from_millis(600))
+        );
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = RateLimiter::default();
+        let db_name = DBName::from("mydb").unwrap();
+        for _ in 0..100 {
+            assert_eq!(
+                limiter.acquire_at(&db_name, &RateLimitConfig::default(), 1024, Instant::now()),
+                Ok(Duration::from_secs(0))
+            );
+        }
+        assert_eq!(limiter.info(), "");
+    }
+}
@@ -0,0 +1,203 @@
+use super::backend::CmdTaskSender;
+use crate::common::cluster::DBName;
+use crate::common::config::ReplicaReadPolicy;
+use crate::common::track::TrackedFutureRegistry;
+use crate::common::utils::random_index;
+use crate::protocol::{BulkStr, RedisClient, RedisClientFactory, Resp, RespVec};
+use crate::replication::replicator::MasterMeta;
+use futures::future;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+const REPLICA_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+struct ReplicaNode<S: CmdTaskSender> {
+    sender: Arc<S>,
+    // Only the replicas with `master_link_status:up` are used.
+    synced: AtomicBool,
+    latency_us: AtomicU64,
+}
+
+// Routes the read-only commands to the replicas of the local masters.
+// The replicas are from the replication metadata of the masters on this proxy,
+// and get checked periodically to avoid the stale ones.
+pub struct ReplicaRouter<F: RedisClientFactory, S: CmdTaskSender> {
+    client_factory: Arc<F>,
+    future_registry: Arc<TrackedFutureRegistry>,
+    // db => master address => replica addresses
+    replicas: RwLock<HashMap<DBName, HashMap<String, Vec<String>>>>,
+    nodes: RwLock<HashMap<String, Arc<ReplicaNode<S>>>>,
+    round_robin_index: AtomicUsize,
+    checking: AtomicBool,
+}
+
+impl<F: RedisClientFactory, S: CmdTaskSender + Send + Sync + 'static> ReplicaRouter<F, S> {
+    pub fn new(client_factory: Arc<F>, future_registry: Arc<TrackedFutureRegistry>) -> Self {
+        Self {
+            client_factory,
+            future_registry,
+            replicas: RwLock::new(HashMap::new()),
+            nodes: RwLock::new(HashMap::new()),
+            round_robin_index: AtomicUsize::new(0),
+            checking: AtomicBool::new(false),
+        }
+    }
+
+    pub fn update_replicas<C>(self: &Arc<Self>, masters: &[MasterMeta], create_sender: C)
+    where
+        C: Fn(String) -> S,
+    {
+        let mut replicas: HashMap<DBName, HashMap<String, Vec<String>>> = HashMap::new();
+        let mut nodes = HashMap::new();
+        {
+            let old_nodes = self.nodes.read().expect("ReplicaRouter::update_replicas");
+            for meta in masters.iter() {
+                let addresses = meta
+                    .replicas
+                    .iter()
+                    .map(|peer| peer.node_address.clone())
+                    .collect::<Vec<String>>();
+                for address in addresses.iter() {
+                    let node = match old_nodes.get(address) {
+                        Some(node) => node.clone(),
+                        None => Arc::new(ReplicaNode {
+                            sender: Arc::new(create_sender(address.clone())),
+                            synced: AtomicBool::new(false),
+                            latency_us: AtomicU64::new(0),
+                        }),
+                    };
+                    nodes.insert(address.clone(), node);
+                }
+                replicas
+                    .entry(meta.db_name.clone())
+                    .or_default()
+                    .insert(meta.master_node_address.clone(), addresses);
+            }
+        }
+        *self
+            .replicas
+            .write()
+            .expect("ReplicaRouter::update_replicas") = replicas;
+        *self.nodes.write().expect("ReplicaRouter::update_replicas") = nodes;
+
+        if !self.checking.swap(true, Ordering::SeqCst) {
+            let fut = Self::keep_checking(Arc::downgrade(self));
+            let desc = "replica status checker".to_string();
+            let fut = TrackedFutureRegistry::wrap(self.future_registry.clone(), fut, desc);
+            tokio::spawn(fut);
+        }
+    }
+
+    // Returns None if there's no available replica.
+    pub fn select_replica(
+        &self,
+        db_name: &DBName,
+        master: &str,
+        policy: ReplicaReadPolicy,
+    ) -> Option<Arc<S>> {
+        let replicas = self.replicas.read().expect("ReplicaRouter::select_replica");
+        let addresses = replicas.get(db_name)?.get(master)?;
+        let nodes = self.nodes.read().expect("ReplicaRouter::select_replica");
+        let candidates: Vec<&Arc<ReplicaNode<S>>> = addresses
+            .iter()
+            .filter_map(|address| nodes.get(address))
+            .filter(|node| node.synced.load(Ordering::SeqCst))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let node = match policy {
+            ReplicaReadPolicy::Random => candidates[random_index(candidates.len())],
+            ReplicaReadPolicy::RoundRobin => {
+                let index = self.round_robin_index.fetch_add(1, Ordering::Relaxed);
+                candidates[index % candidates.len()]
+            }
+            ReplicaReadPolicy::LowestLatency => candidates
+                .iter()
+                .min_by_key(|node| node.latency_us.load(Ordering::SeqCst))
+                .cloned()?,
+        };
+        Some(node.sender.clone())
+    }
+
+    // Stops once the router is dropped.
+    async fn keep_checking(router: Weak<Self>) {
+        loop {
+            match router.upgrade() {
+                Some(router) => router.check_replicas().await,
+                None => break,
+            }
+            tokio::time::delay_for(REPLICA_CHECK_INTERVAL).await;
+        }
+    }
+
+    async fn check_replicas(&self) {
+        let nodes: Vec<(String, Arc<ReplicaNode<S>>)> = self
+            .nodes
+            .read()
+            .expect("ReplicaRouter::check_replicas")
+            .iter()
+            .map(|(address, node)| (address.clone(), node.clone()))
+            .collect();
+        let futs = nodes.into_iter().map(|(address, node)| async move {
+            match self.check_replica(address.clone()).await {
+                Some(latency) => {
+                    node.latency_us
+                        .store(latency.as_micros() as u64, Ordering::SeqCst);
+                    node.synced.store(true, Ordering::SeqCst);
+                }
+                None => {
+                    if node.synced.swap(false, Ordering::SeqCst) {
+                        warn!("replica {} is not available for reading", address);
+                    }
+                }
+            }
+        });
+        future::join_all(futs).await;
+    }
+
+    // Returns the latency of the replica if it's in sync with its master.
+    async fn check_replica(&self, address: String) -> Option<Duration> {
+        let mut client = self.client_factory.create_client(address).await.ok()?;
+        let start = Instant::now();
+        let reply = client
+            .execute_single(vec![b"INFO".to_vec(), b"replication".to_vec()])
+            .await
+            .ok()?;
+        let latency = start.elapsed();
+        if is_replica_synced(&reply) {
+            Some(latency)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_replica_synced(reply: &RespVec) -> bool {
+    let info = match reply {
+        Resp::Bulk(BulkStr::Str(info)) => info,
+        _ => return false,
+    };
+    info.split(|b| *b == b'\n')
+        .any(|line| line.starts_with(b"master_link_status:up"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_replica_synced() {
+        let info =
+            b"# Replication\r\nrole:slave\r\nmaster_host:127.0.0.1\r\nmaster_link_status:up\r\n";
+        assert!(is_replica_synced(&Resp::Bulk(BulkStr::Str(info.to_vec()))));
+        let info = b"# Replication\r\nrole:slave\r\nmaster_link_status:down\r\n";
+        assert!(!is_replica_synced(&Resp::Bulk(BulkStr::Str(info.to_vec()))));
+        let info = b"# Replication\r\nrole:master\r\nconnected_slaves:0\r\n";
+        assert!(!is_replica_synced(&Resp::Bulk(BulkStr::Str(info.to_vec()))));
+        assert!(!is_replica_synced(&Resp::Error(b"ERR".to_vec())));
+    }
+}
@@ -0,0 +1,106 @@
+use btoi::btou;
+
+// SCAN iterates all the local backend redis of a database one by one.
+// The cursor replied to the client encodes the index of the current node in the lower bits
+// and the cursor of that node in the higher bits,
+// so it's still an integer for the clients.
+const NODE_INDEX_BITS: u32 = 10;
+const NODE_INDEX_MASK: u64 = (1 << NODE_INDEX_BITS) - 1;
+pub const MAX_SCAN_NODE_NUM: usize = 1 << NODE_INDEX_BITS;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanCursor {
+    pub node_index: usize,
+    pub node_cursor: u64,
+}
+
+impl ScanCursor {
+    pub fn parse(cursor: &[u8]) -> Option<Self> {
+        let cursor = btou::<u64>(cursor).ok()?;
+        Some(Self {
+            node_index: (cursor & NODE_INDEX_MASK) as usize,
+            node_cursor: cursor >> NODE_INDEX_BITS,
+        })
+    }
+
+    // Returns None if the node cursor is too large to be encoded.
+    pub fn encode(&self) -> Option<u64> {
+        if self.node_cursor.leading_zeros() < NODE_INDEX_BITS {
+            return None;
+        }
+        Some((self.node_cursor << NODE_INDEX_BITS) | (self.node_index as u64 & NODE_INDEX_MASK))
+    }
+
+    // Moves to the next node once the current one is done.
+    // Returns None when all the nodes are done.
+    pub fn next(&self, node_num: usize, next_node_cursor: u64) -> Option<Self> {
+        if next_node_cursor != 0 {
+            return Some(Self {
+                node_index: self.node_index,
+                node_cursor: next_node_cursor,
+            });
+        }
+        let node_index = self.node_index + 1;
+        if node_index >= node_num {
+            return None;
+        }
+        Some(Self {
+            node_index,
+            node_cursor: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_cursor() {
+        let cursor = ScanCursor::parse(b"0").unwrap();
+        assert_eq!(
+            cursor,
+            ScanCursor {
+                node_index: 0,
+                node_cursor: 0
+            }
+        );
+        assert_eq!(cursor.encode(), Some(0));
+
+        let cursor = ScanCursor {
+            node_index: 3,
+            node_cursor: 233,
+        };
+        let encoded = cursor.encode().unwrap();
+        assert_eq!(
+            ScanCursor::parse(encoded.to_string().as_bytes()),
+            Some(cursor)
+        );
+
+        let cursor = ScanCursor {
+            node_index: 0,
+            node_cursor: u64::MAX,
+        };
+        assert!(cursor.encode().is_none());
+
+        assert!(ScanCursor::parse(b"invalid").is_none());
+    }
+
+    #[test]
+    fn test_next_scan_cursor() {
+        let cursor = ScanCursor {
+            node_index: 1,
+            node_cursor: 0,
+        };
+        let next = cursor.next(3, 7).unwrap();
+        assert_eq!(next.node_index, 1);
+        assert_eq!(next.node_cursor, 7);
+
+        let next = cursor.next(3, 0).unwrap();
+        assert_eq!(next.node_index, 2);
+        assert_eq!(next.node_cursor, 0);
+        assert!(next.encode().unwrap() != 0);
+
+        assert!(next.next(3, 0).is_none());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::common::cluster::DBName;
+use crate::protocol::BinSafeStr;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+// The scripts loaded by SCRIPT LOAD.
+// The backend redis could lose the scripts after failover
+// or not have them at all when the slots are migrated to them,
+// so EVALSHA falls back to EVAL with the cached script on NOSCRIPT errors.
+#[derive(Default)]
+pub struct ScriptCache {
+    scripts: RwLock<HashMap<DBName, HashMap<BinSafeStr, BinSafeStr>>>,
+}
+
+impl ScriptCache {
+    pub fn insert(&self, db_name: DBName, sha: &[u8], script: BinSafeStr) {
+        self.scripts
+            .write()
+            .expect("ScriptCache::insert")
+            .entry(db_name)
+            .or_default()
+            .insert(sha.to_ascii_lowercase(), script);
+    }
+
+    pub fn get(&self, db_name: &DBName, sha: &[u8]) -> Option<BinSafeStr> {
+        self.scripts
+            .read()
+            .expect("ScriptCache::get")
+            .get(db_name)
+            .and_then(|scripts| scripts.get(&sha.to_ascii_lowercase()).cloned())
+    }
+
+    pub fn flush(&self, db_name: &DBName) {
+        self.scripts
+            .write()
+            .expect("ScriptCache::flush")
+            .remove(db_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_cache() {
+        let cache = ScriptCache::default();
+        let db_name = DBName::from("mydb").unwrap();
+        let other_db = DBName::from("otherdb").unwrap();
+        let sha = b"e0e1f9fabfc9d4800c877a703b823ac0578ff8db";
+
+        cache.insert(db_name.clone(), sha, b"return 1".to_vec());
+        assert_eq!(cache.get(&db_name, sha), Some(b"return 1".to_vec()));
+        assert_eq!(
+            cache.get(&db_name, &sha.to_ascii_uppercase()),
+            Some(b"return 1".to_vec())
+        );
+        assert!(cache.get(&other_db, sha).is_none());
+
+        cache.flush(&db_name);
+        assert!(cache.get(&db_name, sha).is_none());
+    }
+}
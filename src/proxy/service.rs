@@ -1,16 +1,46 @@
+use super::client::ClientRegistry;
+use super::command_table::CommandTable;
 use super::session::CmdCtxHandler;
-use super::session::{handle_session, Session};
+use super::session::{handle_session, OutputBufferLimit, Session, SessionError};
 use super::slowlog::SlowRequestLogger;
 use crate::common::config::ConfigError;
 use crate::common::track::TrackedFutureRegistry;
 use crate::common::utils::{resolve_first_address, ThreadSafe};
-use futures::{FutureExt, StreamExt};
+use crate::protocol::{
+    CredentialTable, MaybeTlsStream, ParseLimits, TlsClientConfig, TlsServerConfig,
+};
+use futures::{future, FutureExt, StreamExt};
 use std::error::Error;
+use std::fmt;
+use std::io;
 use std::num::NonZeroUsize;
 use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use string_error::into_err;
-use tokio::net::TcpListener;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+// Should not print the password to the log.
+#[derive(Clone)]
+pub struct AdminPassword(String);
+
+impl AdminPassword {
+    pub fn new(password: String) -> Self {
+        Self(password)
+    }
+
+    pub fn matches(&self, password: &[u8]) -> bool {
+        self.0.as_bytes() == password
+    }
+}
+
+impl fmt::Debug for AdminPassword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "**")
+    }
+}
 
 #[derive(Debug)]
 pub struct ServerProxyConfig {
@@ -23,12 +53,35 @@ pub struct ServerProxyConfig {
     pub session_channel_size: usize,
     pub backend_channel_size: usize,
     pub backend_conn_num: NonZeroUsize,
+    // The idle connections kept for the blocking commands to each backend redis.
+    pub blocking_pool_size: usize,
     pub backend_batch_min_time: usize,
     pub backend_batch_max_time: usize,
     pub backend_batch_buf: NonZeroUsize,
     pub session_batch_min_time: usize,
     pub session_batch_max_time: usize,
     pub session_batch_buf: NonZeroUsize,
+    pub proto_max_bulk_len: usize,
+    pub proto_max_array_len: usize,
+    pub proto_max_depth: usize,
+    pub backend_credentials: CredentialTable,
+    // Required by UMCTL once it's set.
+    pub admin_password: Option<AdminPassword>,
+    // Enables TLS on the client port.
+    pub tls_server: Option<TlsServerConfig>,
+    // Enables TLS toward the backend redis nodes and the peer server proxies.
+    pub backend_tls: Option<TlsClientConfig>,
+    // Negotiates RESP3 on the data connections to the backend redis.
+    pub backend_resp3: bool,
+    // Built-in commands and the extra ones from config.
+    pub command_table: Arc<CommandTable>,
+    // 0 means unlimited.
+    pub max_clients: AtomicUsize,
+    // In seconds. 0 means never closing the idle sessions.
+    pub client_timeout: u64,
+    pub output_buffer_hard_limit: usize,
+    pub output_buffer_soft_limit: usize,
+    pub output_buffer_soft_seconds: u64,
 }
 
 impl ServerProxyConfig {
@@ -42,6 +95,7 @@ impl ServerProxyConfig {
             "session_channel_size" => Ok(self.session_channel_size.to_string()),
             "backend_channel_size" => Ok(self.backend_channel_size.to_string()),
             "backend_conn_num" => Ok(self.backend_conn_num.to_string()),
+            "blocking_pool_size" => Ok(self.blocking_pool_size.to_string()),
             "slowlog_log_slower_than" => Ok(self
                 .slowlog_log_slower_than
                 .load(Ordering::SeqCst)
@@ -52,6 +106,17 @@ impl ServerProxyConfig {
             "session_batch_min_time" => Ok(self.session_batch_min_time.to_string()),
             "session_batch_max_time" => Ok(self.session_batch_max_time.to_string()),
             "session_batch_buf" => Ok(self.session_batch_buf.to_string()),
+            "proto_max_bulk_len" => Ok(self.proto_max_bulk_len.to_string()),
+            "proto_max_array_len" => Ok(self.proto_max_array_len.to_string()),
+            "proto_max_depth" => Ok(self.proto_max_depth.to_string()),
+            "tls" => Ok(self.tls_server.is_some().to_string()),
+            "backend_tls" => Ok(self.backend_tls.is_some().to_string()),
+            "backend_resp3" => Ok(self.backend_resp3.to_string()),
+            "max_clients" => Ok(self.max_clients.load(Ordering::SeqCst).to_string()),
+            "client_timeout" => Ok(self.client_timeout.to_string()),
+            "output_buffer_hard_limit" => Ok(self.output_buffer_hard_limit.to_string()),
+            "output_buffer_soft_limit" => Ok(self.output_buffer_soft_limit.to_string()),
+            "output_buffer_soft_seconds" => Ok(self.output_buffer_soft_seconds.to_string()),
             _ => Err(ConfigError::FieldNotFound),
         }
     }
@@ -66,6 +131,7 @@ impl ServerProxyConfig {
             "session_channel_size" => Err(ConfigError::ReadonlyField),
             "backend_channel_size" => Err(ConfigError::ReadonlyField),
             "backend_conn_num" => Err(ConfigError::ReadonlyField),
+            "blocking_pool_size" => Err(ConfigError::ReadonlyField),
             "slowlog_log_slower_than" => {
                 let int_value = value
                     .parse::<i64>()
@@ -80,9 +146,50 @@ impl ServerProxyConfig {
             "session_batch_min_time" => Err(ConfigError::ReadonlyField),
             "session_batch_max_time" => Err(ConfigError::ReadonlyField),
             "session_batch_buf" => Err(ConfigError::ReadonlyField),
+            "proto_max_bulk_len" => Err(ConfigError::ReadonlyField),
+            "proto_max_array_len" => Err(ConfigError::ReadonlyField),
+            "proto_max_depth" => Err(ConfigError::ReadonlyField),
+            "tls" => Err(ConfigError::ReadonlyField),
+            "backend_tls" => Err(ConfigError::ReadonlyField),
+            "max_clients" => {
+                let int_value = value
+                    .parse::<usize>()
+                    .map_err(|_| ConfigError::InvalidValue)?;
+                self.max_clients.store(int_value, Ordering::SeqCst);
+                Ok(())
+            }
+            "client_timeout" => Err(ConfigError::ReadonlyField),
+            "output_buffer_hard_limit" => Err(ConfigError::ReadonlyField),
+            "output_buffer_soft_limit" => Err(ConfigError::ReadonlyField),
+            "output_buffer_soft_seconds" => Err(ConfigError::ReadonlyField),
             _ => Err(ConfigError::FieldNotFound),
         }
     }
+
+    pub fn get_parse_limits(&self) -> ParseLimits {
+        ParseLimits {
+            max_bulk_len: self.proto_max_bulk_len,
+            max_array_len: self.proto_max_array_len,
+            max_depth: self.proto_max_depth,
+            inline_commands: true,
+        }
+    }
+
+    pub fn get_output_buffer_limit(&self) -> OutputBufferLimit {
+        OutputBufferLimit {
+            hard_limit: self.output_buffer_hard_limit,
+            soft_limit: self.output_buffer_soft_limit,
+            soft_seconds: self.output_buffer_soft_seconds,
+        }
+    }
+
+    pub fn get_client_timeout(&self) -> Option<Duration> {
+        if self.client_timeout == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.client_timeout))
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -91,6 +198,8 @@ pub struct ServerProxyService<H: CmdCtxHandler + ThreadSafe + Clone> {
     cmd_ctx_handler: H,
     slow_request_logger: Arc<SlowRequestLogger>,
     future_registry: Arc<TrackedFutureRegistry>,
+    // All the alive sessions.
+    client_registry: Arc<ClientRegistry>,
 }
 
 impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
@@ -99,12 +208,14 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
         cmd_ctx_handler: H,
         slow_request_logger: Arc<SlowRequestLogger>,
         future_registry: Arc<TrackedFutureRegistry>,
+        client_registry: Arc<ClientRegistry>,
     ) -> Self {
         Self {
             config,
             cmd_ctx_handler,
             slow_request_logger,
             future_registry,
+            client_registry,
         }
     }
 
@@ -130,6 +241,7 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
         let config = self.config.clone();
 
         let future_registry = self.future_registry.clone();
+        let client_registry = self.client_registry.clone();
 
         let mut s = listener.incoming();
         while let Some(sock) = s.next().await {
@@ -147,21 +259,63 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
             };
             info!("accept conn: {}", peer);
 
+            let max_clients = config.max_clients.load(Ordering::SeqCst);
+            if max_clients != 0 && client_registry.get_client_num() >= max_clients {
+                warn!("reject conn {}: max number of clients reached", peer);
+                client_registry.on_connection_rejected();
+                tokio::spawn(reply_max_clients_error(sock, config.tls_server.clone()));
+                continue;
+            }
+
             let curr_session_id = session_id.fetch_add(1, Ordering::SeqCst);
 
             let handle_clone = forward_handler.clone();
-            let session_handler = handle_session(
-                Arc::new(Session::new(
-                    curr_session_id,
-                    handle_clone,
-                    slow_request_logger.clone(),
-                )),
-                sock,
-                config.session_channel_size,
-                config.session_batch_min_time,
-                config.session_batch_max_time,
-                config.session_batch_buf,
-            );
+            let session = Arc::new(Session::new(
+                curr_session_id,
+                peer.clone(),
+                handle_clone,
+                slow_request_logger.clone(),
+            ));
+            let kill_receiver = client_registry.register(session.get_db(), session.get_client());
+            let tls_server = config.tls_server.clone();
+            let config = config.clone();
+            let session_handler = async move {
+                let sock = match tls_server {
+                    Some(tls_server) => accept_tls(&tls_server, sock)
+                        .await
+                        .map_err(SessionError::Io)?,
+                    None => MaybeTlsStream::Plain(sock),
+                };
+                handle_session(
+                    session,
+                    sock,
+                    config.session_channel_size,
+                    config.session_batch_min_time,
+                    config.session_batch_max_time,
+                    config.session_batch_buf,
+                    config.get_parse_limits(),
+                    config.get_client_timeout(),
+                    config.get_output_buffer_limit(),
+                    config.command_table.clone(),
+                )
+                .await
+            };
+            let registry = client_registry.clone();
+            // Dropping the session future closes the connection.
+            let session_handler = async move {
+                let res = match future::select(Box::pin(session_handler), kill_receiver).await {
+                    future::Either::Left((res, _)) => res,
+                    future::Either::Right(_) => {
+                        info!("session killed: session_id={}", curr_session_id);
+                        Ok(())
+                    }
+                };
+                registry.unregister(curr_session_id);
+                if let Err(SessionError::IdleTimeout) = res {
+                    registry.on_connection_timeout();
+                }
+                res
+            };
 
             let desc = format!("session: session_id={} peer={}", curr_session_id, peer);
             let fut = session_handler.map(move |res| match res {
@@ -174,3 +328,36 @@ impl<H: CmdCtxHandler + ThreadSafe + Clone> ServerProxyService<H> {
         Ok(())
     }
 }
+
+// The handshake is limited even if `client_timeout` is disabled
+// so that the clients can't hold the connections without finishing it.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+async fn accept_tls(tls_server: &TlsServerConfig, sock: TcpStream) -> io::Result<MaybeTlsStream> {
+    time::timeout(TLS_HANDSHAKE_TIMEOUT, tls_server.accept(sock))
+        .await
+        .unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "TLS handshake timeout",
+            ))
+        })
+}
+
+// Like redis, reply the error before closing the connection.
+async fn reply_max_clients_error(sock: TcpStream, tls_server: Option<TlsServerConfig>) {
+    let sock = match tls_server {
+        Some(tls_server) => accept_tls(&tls_server, sock).await,
+        None => Ok(MaybeTlsStream::Plain(sock)),
+    };
+    let res = match sock {
+        Ok(mut sock) => {
+            sock.write_all(b"-ERR max number of clients reached\r\n")
+                .await
+        }
+        Err(err) => Err(err),
+    };
+    if let Err(err) = res {
+        warn!("failed to reply max clients error: {:?}", err);
+    }
+}
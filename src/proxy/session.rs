@@ -3,24 +3,31 @@ use super::command::{
     new_command_pair, CmdReplyReceiver, CmdReplySender, CmdType, Command, CommandError,
     CommandResult, DataCmdType, TaskReply, TaskResult,
 };
+use super::command_table::CommandTable;
 use super::database::{DBTag, DEFAULT_DB};
+use super::key_prefix::{has_reply_keys, strip_reply_keys};
+use super::pubsub::{adapt_push_message, new_push_pair, PushReceiver, Subscription};
 use super::slowlog::{SlowRequestLogger, Slowlog, TaskEvent};
+use super::transaction::Transaction;
 use crate::common::batch::TryChunksTimeoutStreamExt;
 use crate::common::cluster::DBName;
 use crate::protocol::{
-    new_simple_packet_codec, DecodeError, EncodeError, Resp, RespCodec, RespPacket, RespVec,
+    new_simple_packet_codec, Array, BinSafeStr, DecodeError, EncodeError, MaybeTlsStream,
+    ParseLimits, Resp, RespCodec, RespPacket, RespVec, RespVersion,
 };
-use futures::{future, stream, Future, TryFutureExt};
+use futures::{future, pin_mut, stream, Future, TryFutureExt};
 use futures::{SinkExt, StreamExt, TryStreamExt};
 use std::boxed::Box;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt;
 use std::io;
 use std::num::NonZeroUsize;
 use std::pin::Pin;
 use std::sync;
-use std::time::Duration;
-use tokio::net::TcpStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::time;
 use tokio_util::codec::Decoder;
 
 // CmdReplyReceiver is the fast path without heap allocation.
@@ -30,33 +37,182 @@ pub type CmdReplyFuture<'a> =
 pub trait CmdHandler {
     fn handle_cmd(&self, cmd: Command) -> CmdReplyFuture;
     fn handle_slowlog(&self, request: Box<RespPacket>, slowlog: Slowlog);
+    fn get_resp_version(&self) -> RespVersion;
+    // Called once the reply of a command is ready to be sent.
+    fn handle_reply_done(&self);
+    // The messages of Pub/Sub are not replies of any command.
+    fn take_push_receiver(&self) -> Option<PushReceiver>;
+    fn is_subscribed(&self) -> bool;
 }
 
 pub trait CmdCtxHandler {
     fn handle_cmd_ctx(&self, cmd_ctx: CmdCtx, result_receiver: CmdReplyReceiver) -> CmdReplyFuture;
 }
 
+// The database authenticated by the password of the cluster.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientAuth {
+    pub db: DBName,
+    // Authenticated by the read-only password.
+    pub readonly: bool,
+}
+
+// The settings and statistics of a client connection shared by all of its commands.
+#[derive(Debug)]
+pub struct ClientState {
+    id: usize,
+    address: String,
+    // Set by CLIENT SETNAME.
+    name: Option<BinSafeStr>,
+    // Set by READONLY and READWRITE. None means following the config of the cluster.
+    readonly: Option<bool>,
+    auth: Option<ClientAuth>,
+    // Authenticated by the `admin_password` of the server proxy for UMCTL.
+    admin: bool,
+    created: Instant,
+    last_active: Instant,
+    cmd_count: u64,
+    // The commands whose replies are not ready yet.
+    pending_replies: usize,
+    // Delayed by the rate limit of the cluster.
+    throttled_until: Option<Instant>,
+}
+
+impl ClientState {
+    pub fn new(id: usize, address: String) -> Self {
+        let now = Instant::now();
+        Self {
+            id,
+            address,
+            name: None,
+            readonly: None,
+            auth: None,
+            admin: false,
+            created: now,
+            last_active: now,
+            cmd_count: 0,
+            pending_replies: 0,
+            throttled_until: None,
+        }
+    }
+
+    pub fn get_id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn get_name(&self) -> Option<&[u8]> {
+        self.name.as_deref()
+    }
+
+    pub fn set_name(&mut self, name: Option<BinSafeStr>) {
+        self.name = name;
+    }
+
+    pub fn get_readonly(&self) -> Option<bool> {
+        self.readonly
+    }
+
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = Some(readonly);
+    }
+
+    pub fn get_auth(&self) -> Option<&ClientAuth> {
+        self.auth.as_ref()
+    }
+
+    // Authenticating as another user drops the previous one like redis.
+    pub fn set_auth(&mut self, auth: ClientAuth) {
+        self.auth = Some(auth);
+        self.admin = false;
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    pub fn set_admin(&mut self) {
+        self.auth = None;
+        self.admin = true;
+    }
+
+    pub fn get_age(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    pub fn get_idle(&self) -> Duration {
+        self.last_active.elapsed()
+    }
+
+    pub fn get_cmd_count(&self) -> u64 {
+        self.cmd_count
+    }
+
+    pub fn get_pending_replies(&self) -> usize {
+        self.pending_replies
+    }
+
+    // Returns the time the command needs to wait until.
+    // The commands after a delayed one also need to wait to keep the order.
+    pub fn throttle(&mut self, deadline: Instant, now: Instant) -> Option<Instant> {
+        let deadline = match self.throttled_until {
+            Some(throttled_until) if throttled_until > deadline => throttled_until,
+            _ => deadline,
+        };
+        self.throttled_until = Some(deadline).filter(|deadline| *deadline > now);
+        self.throttled_until
+    }
+
+    fn on_cmd_received(&mut self) {
+        self.last_active = Instant::now();
+        self.cmd_count += 1;
+        self.pending_replies += 1;
+    }
+
+    fn on_reply_done(&mut self) {
+        self.pending_replies = self.pending_replies.saturating_sub(1);
+    }
+}
+
 #[derive(Debug)]
 pub struct CmdCtx {
     db: sync::Arc<sync::RwLock<DBName>>,
+    resp_version: sync::Arc<sync::RwLock<RespVersion>>,
+    transaction: sync::Arc<sync::Mutex<Transaction>>,
+    subscription: sync::Arc<sync::Mutex<Subscription>>,
+    client: sync::Arc<sync::RwLock<ClientState>>,
     cmd: Command,
     reply_sender: CmdReplySender,
     slowlog: Slowlog,
+    // The keys are only prefixed in the packets sent to the backend.
+    key_prefix: Option<BinSafeStr>,
 }
 
 impl CmdCtx {
     pub fn new(
         db: sync::Arc<sync::RwLock<DBName>>,
+        resp_version: sync::Arc<sync::RwLock<RespVersion>>,
+        transaction: sync::Arc<sync::Mutex<Transaction>>,
+        subscription: sync::Arc<sync::Mutex<Subscription>>,
+        client: sync::Arc<sync::RwLock<ClientState>>,
         cmd: Command,
         reply_sender: CmdReplySender,
-        session_id: usize,
     ) -> CmdCtx {
+        let session_id = client.read().expect("CmdCtx::new").get_id();
         let slowlog = Slowlog::new(session_id);
         CmdCtx {
             db,
+            resp_version,
+            transaction,
+            subscription,
+            client,
             cmd,
             reply_sender,
             slowlog,
+            key_prefix: None,
         }
     }
 
@@ -68,10 +224,46 @@ impl CmdCtx {
         self.db.clone()
     }
 
+    pub fn get_resp_version_lock(&self) -> sync::Arc<sync::RwLock<RespVersion>> {
+        self.resp_version.clone()
+    }
+
+    pub fn get_resp_version(&self) -> RespVersion {
+        *self.resp_version.read().expect("CmdCtx::get_resp_version")
+    }
+
+    pub fn set_resp_version(&self, resp_version: RespVersion) {
+        *self.resp_version.write().expect("CmdCtx::set_resp_version") = resp_version
+    }
+
+    pub fn get_transaction(&self) -> sync::Arc<sync::Mutex<Transaction>> {
+        self.transaction.clone()
+    }
+
+    pub fn get_subscription(&self) -> sync::Arc<sync::Mutex<Subscription>> {
+        self.subscription.clone()
+    }
+
+    pub fn get_client(&self) -> sync::Arc<sync::RwLock<ClientState>> {
+        self.client.clone()
+    }
+
+    pub fn get_readonly(&self) -> Option<bool> {
+        self.client
+            .read()
+            .expect("CmdCtx::get_readonly")
+            .get_readonly()
+    }
+
     pub fn get_session_id(&self) -> usize {
         self.slowlog.get_session_id()
     }
 
+    pub fn rename_cmd(mut self, cmd_name: Vec<u8>, table: &CommandTable) -> Self {
+        self.cmd = self.cmd.rename(cmd_name, table);
+        self
+    }
+
     pub fn change_cmd_element(&mut self, index: usize, data: Vec<u8>) -> bool {
         self.cmd.change_element(index, data)
     }
@@ -83,6 +275,14 @@ impl CmdCtx {
     pub fn get_data_cmd_type(&self) -> DataCmdType {
         self.cmd.get_data_cmd_type()
     }
+
+    pub fn get_key_prefix(&self) -> Option<&[u8]> {
+        self.key_prefix.as_deref()
+    }
+
+    pub fn set_key_prefix(&mut self, key_prefix: Option<BinSafeStr>) {
+        self.key_prefix = key_prefix;
+    }
 }
 
 impl CmdTask for CmdCtx {
@@ -97,8 +297,16 @@ impl CmdTask for CmdCtx {
             cmd,
             mut reply_sender,
             slowlog,
+            key_prefix,
             ..
         } = self;
+        let result = match key_prefix {
+            Some(key_prefix) if has_reply_keys(&cmd) => result.map(|packet| {
+                let reply = strip_reply_keys(&cmd, packet.into_resp_vec(), &key_prefix);
+                Box::new(RespPacket::from_resp_vec(reply))
+            }),
+            _ => result,
+        };
         let task_result =
             result.map(|packet| Box::new(TaskReply::new(cmd.into_packet(), packet, slowlog)));
         let res = reply_sender.send(task_result);
@@ -108,7 +316,10 @@ impl CmdTask for CmdCtx {
     }
 
     fn get_packet(&self) -> Self::Pkt {
-        self.cmd.get_packet()
+        match self.key_prefix.as_deref() {
+            Some(key_prefix) => self.cmd.get_packet_with_key_prefix(key_prefix),
+            None => self.cmd.get_packet(),
+        }
     }
 
     fn set_resp_result(self, result: Result<RespVec, CommandError>)
@@ -133,11 +344,20 @@ impl DBTag for CmdCtx {
     }
 }
 
-pub struct CmdCtxFactory;
+// The sub-commands are looked up in the same command table as the original ones.
+pub struct CmdCtxFactory {
+    command_table: sync::Arc<CommandTable>,
+}
+
+impl CmdCtxFactory {
+    pub fn new(command_table: sync::Arc<CommandTable>) -> Self {
+        Self { command_table }
+    }
+}
 
 impl Default for CmdCtxFactory {
     fn default() -> Self {
-        Self
+        Self::new(sync::Arc::new(CommandTable::default()))
     }
 }
 
@@ -153,22 +373,33 @@ impl CmdTaskFactory for CmdCtxFactory {
         Pin<Box<dyn Future<Output = CmdTaskResult> + Send + 'static>>,
     ) {
         let packet = Box::new(RespPacket::from_resp_vec(resp));
-        let cmd = Command::new(packet);
+        let cmd = Command::new_with_table(packet, &self.command_table);
         let (reply_sender, reply_receiver) = new_command_pair();
-        let cmd_ctx = CmdCtx::new(
+        let mut cmd_ctx = CmdCtx::new(
             another_task.get_db(),
+            another_task.get_resp_version_lock(),
+            another_task.get_transaction(),
+            another_task.get_subscription(),
+            another_task.get_client(),
             cmd,
             reply_sender,
-            another_task.get_session_id(),
         );
+        cmd_ctx.set_key_prefix(another_task.key_prefix.clone());
         let fut = reply_receiver.map_ok(|reply| reply.into_resp_vec());
         (cmd_ctx, Box::pin(fut))
     }
 }
 
 pub struct Session<H: CmdCtxHandler> {
-    session_id: usize,
     db: sync::Arc<sync::RwLock<DBName>>,
+    // Negotiated by HELLO.
+    resp_version: sync::Arc<sync::RwLock<RespVersion>>,
+    // MULTI, EXEC and WATCH.
+    transaction: sync::Arc<sync::Mutex<Transaction>>,
+    // SUBSCRIBE and PSUBSCRIBE.
+    subscription: sync::Arc<sync::Mutex<Subscription>>,
+    client: sync::Arc<sync::RwLock<ClientState>>,
+    push_receiver: sync::Mutex<Option<PushReceiver>>,
     cmd_ctx_handler: H,
     slow_request_logger: sync::Arc<SlowRequestLogger>,
 }
@@ -176,23 +407,49 @@ pub struct Session<H: CmdCtxHandler> {
 impl<H: CmdCtxHandler> Session<H> {
     pub fn new(
         session_id: usize,
+        address: String,
         cmd_ctx_handler: H,
         slow_request_logger: sync::Arc<SlowRequestLogger>,
     ) -> Self {
         let dbname = DBName::from(DEFAULT_DB).expect("Session::new");
+        let (push_sender, push_receiver) = new_push_pair();
         Session {
-            session_id,
             db: sync::Arc::new(sync::RwLock::new(dbname)),
+            resp_version: sync::Arc::new(sync::RwLock::new(RespVersion::Resp2)),
+            transaction: sync::Arc::new(sync::Mutex::new(Transaction::default())),
+            subscription: sync::Arc::new(sync::Mutex::new(Subscription::new(push_sender))),
+            client: sync::Arc::new(sync::RwLock::new(ClientState::new(session_id, address))),
+            push_receiver: sync::Mutex::new(Some(push_receiver)),
             cmd_ctx_handler,
             slow_request_logger,
         }
     }
+
+    pub fn get_db(&self) -> sync::Arc<sync::RwLock<DBName>> {
+        self.db.clone()
+    }
+
+    pub fn get_client(&self) -> sync::Arc<sync::RwLock<ClientState>> {
+        self.client.clone()
+    }
 }
 
 impl<H: CmdCtxHandler> CmdHandler for Session<H> {
     fn handle_cmd(&self, cmd: Command) -> CmdReplyFuture {
+        self.client
+            .write()
+            .expect("Session::handle_cmd")
+            .on_cmd_received();
         let (reply_sender, reply_receiver) = new_command_pair();
-        let mut cmd_ctx = CmdCtx::new(self.db.clone(), cmd, reply_sender, self.session_id);
+        let mut cmd_ctx = CmdCtx::new(
+            self.db.clone(),
+            self.resp_version.clone(),
+            self.transaction.clone(),
+            self.subscription.clone(),
+            self.client.clone(),
+            cmd,
+            reply_sender,
+        );
         cmd_ctx.log_event(TaskEvent::Created);
         self.cmd_ctx_handler.handle_cmd_ctx(cmd_ctx, reply_receiver)
     }
@@ -200,25 +457,59 @@ impl<H: CmdCtxHandler> CmdHandler for Session<H> {
     fn handle_slowlog(&self, request: Box<RespPacket>, slowlog: Slowlog) {
         self.slow_request_logger.add_slow_log(request, slowlog)
     }
+
+    fn get_resp_version(&self) -> RespVersion {
+        *self.resp_version.read().expect("Session::get_resp_version")
+    }
+
+    fn handle_reply_done(&self) {
+        self.client
+            .write()
+            .expect("Session::handle_reply_done")
+            .on_reply_done();
+    }
+
+    fn take_push_receiver(&self) -> Option<PushReceiver> {
+        self.push_receiver
+            .lock()
+            .expect("Session::take_push_receiver")
+            .take()
+    }
+
+    fn is_subscribed(&self) -> bool {
+        self.subscription
+            .lock()
+            .expect("Session::is_subscribed")
+            .is_subscribed()
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn handle_session<H>(
     handler: sync::Arc<H>,
-    sock: TcpStream,
+    sock: MaybeTlsStream,
     _channel_size: usize,
     session_batch_min_time: usize,
     session_batch_max_time: usize,
     session_batch_buf: NonZeroUsize,
+    parse_limits: ParseLimits,
+    idle_timeout: Option<Duration>,
+    output_buffer_limit: OutputBufferLimit,
+    cmd_table: sync::Arc<CommandTable>,
 ) -> Result<(), SessionError>
 where
     H: CmdHandler + Send + Sync + 'static,
 {
     let (encoder, decoder) = new_simple_packet_codec::<Box<RespPacket>, Box<RespPacket>>();
-    let (mut writer, reader) = RespCodec::new(encoder, decoder).framed(sock).split();
+    let (mut writer, reader) = RespCodec::with_limits(encoder, decoder, parse_limits)
+        .framed(sock)
+        .split();
     let mut reader = reader
         .map_err(|e| match e {
             DecodeError::Io(e) => SessionError::Io(e),
             DecodeError::InvalidProtocol => SessionError::Canceled,
+            DecodeError::ExceedLimit(reason) => SessionError::ExceedLimit(reason),
+            DecodeError::InvalidRequest(reason) => SessionError::InvalidRequest(reason),
         })
         .try_chunks_timeout(
             session_batch_buf,
@@ -228,24 +519,135 @@ where
 
     let mut reply_receiver_list = Vec::with_capacity(session_batch_buf.get());
     let mut replies = Vec::with_capacity(session_batch_buf.get());
-
-    while let Some(reqs) = reader.next().await {
+    // The size of the replies not sent yet. Only counted with the output buffer limit.
+    let mut replies_size = 0;
+    // Requests read while waiting for blocking commands.
+    let mut pending_reqs = VecDeque::new();
+    let mut pending_reqs_size = 0;
+    let mut push_receiver = handler.take_push_receiver();
+    let mut output_buffer = OutputBuffer::new(
+        output_buffer_limit,
+        push_receiver
+            .as_ref()
+            .map(PushReceiver::get_pending_bytes_counter),
+    );
+
+    loop {
+        let reqs = {
+            // The subscribers are never idle.
+            let idle_timeout = idle_timeout.filter(|_| !handler.is_subscribed());
+            let next_reqs = async {
+                match idle_timeout {
+                    Some(idle_timeout) => time::timeout(idle_timeout, reader.next())
+                        .await
+                        .map_err(|_| SessionError::IdleTimeout),
+                    None => Ok(reader.next().await),
+                }
+            };
+            pin_mut!(next_reqs);
+            match (pending_reqs.pop_front(), push_receiver.as_mut()) {
+                (Some((reqs, size)), _) => {
+                    pending_reqs_size -= size;
+                    Ok(Some(reqs))
+                }
+                (None, None) => next_reqs.await,
+                (None, Some(receiver)) => match future::select(next_reqs, receiver.next()).await {
+                    future::Either::Left((res, _)) => res,
+                    future::Either::Right((Some(msg), _)) => {
+                        let msg = adapt_push_message(msg, handler.get_resp_version());
+                        let packet = Box::new(RespPacket::from_resp_vec(msg));
+                        let size = output_buffer.get_packet_size(&packet);
+                        let res = output_buffer.wait(writer.send(packet), size).await?;
+                        if let Err(err) = res {
+                            error!("writer error: {}", err);
+                            return Err(SessionError::from(err));
+                        }
+                        continue;
+                    }
+                    future::Either::Right((None, _)) => {
+                        push_receiver = None;
+                        continue;
+                    }
+                },
+            }
+        };
+        let reqs = match reqs {
+            Ok(Some(reqs)) => reqs,
+            Ok(None) => break,
+            Err(err) => {
+                info!("session closed for idle timeout");
+                let resp = Resp::Error(b"ERR idle timeout".to_vec());
+                if let Err(err) = writer.send(Box::new(RespPacket::from_resp_vec(resp))).await {
+                    error!("writer error: {}", err);
+                }
+                return Err(err);
+            }
+        };
+        let mut reader_err = None;
         for req in reqs.into_iter() {
             let packet = match req {
                 Ok(packet) => packet,
                 Err(err) => {
                     error!("session reader error {:?}", err);
-                    return Err(err);
+                    reader_err = Some(err);
+                    break;
                 }
             };
-            let cmd = Command::new(packet);
+            let cmd = Command::new_with_table(packet, &cmd_table);
+            let blocking = cmd.is_blocking();
+            // The subscribe commands reply a message for each channel.
+            let multi_replies = matches!(
+                cmd.get_type(),
+                CmdType::Subscribe
+                    | CmdType::PSubscribe
+                    | CmdType::Unsubscribe
+                    | CmdType::PUnsubscribe
+            );
 
             let fut = handler.handle_cmd(cmd);
-            reply_receiver_list.push(fut);
+            // HELLO switches the protocol right after it gets handled,
+            // so the version needs to be recorded for each reply.
+            reply_receiver_list.push((fut, handler.get_resp_version(), blocking, multi_replies));
         }
 
-        for reply_receiver in reply_receiver_list.drain(..) {
-            let res = reply_receiver.await.map_err(SessionError::CmdErr);
+        for (reply_receiver, resp_version, blocking, multi_replies) in reply_receiver_list.drain(..)
+        {
+            let res = if blocking && reader_err.is_none() {
+                // Keep reading so that the blocking command could get canceled
+                // once the client disconnects.
+                // The requests read are buffered and handled after the blocking command.
+                let mut reply_receiver = reply_receiver;
+                loop {
+                    if pending_reqs.len() >= MAX_PENDING_BATCHES
+                        || pending_reqs_size >= MAX_PENDING_REQS_SIZE
+                    {
+                        return Err(SessionError::ExceedLimit(
+                            "too many requests while running blocking command",
+                        ));
+                    }
+                    match future::select(reply_receiver, reader.next()).await {
+                        future::Either::Left((res, _)) => break res,
+                        future::Either::Right((Some(reqs), receiver)) => {
+                            let size: usize = reqs
+                                .iter()
+                                .filter_map(|req| req.as_ref().ok())
+                                .map(|packet| packet.get_size())
+                                .sum();
+                            pending_reqs_size += size;
+                            pending_reqs.push_back((reqs, size));
+                            reply_receiver = receiver;
+                        }
+                        future::Either::Right((None, _)) => {
+                            info!("session closed while running blocking command");
+                            return Ok(());
+                        }
+                    }
+                }
+            } else {
+                reply_receiver.await
+            };
+            handler.handle_reply_done();
+            let res = res.map_err(SessionError::CmdErr);
             let packet = match res {
                 Ok(task_reply) => {
                     let (request, packet, mut slowlog) = (*task_reply).into_inner();
@@ -261,16 +663,47 @@ where
                 }
             };
 
-            replies.push(packet);
+            let packet = match resp_version {
+                RespVersion::Resp2 if !packet.is_resp2() => Box::new(RespPacket::from_resp_vec(
+                    packet.into_resp_vec().into_resp2(),
+                )),
+                _ => packet,
+            };
+            replies_size += output_buffer.get_packet_size(&packet);
+            output_buffer.check(replies_size)?;
+            if !multi_replies {
+                replies.push(packet);
+                continue;
+            }
+            match packet.into_resp_vec() {
+                Resp::Arr(Array::Arr(resps)) => replies.extend(resps.into_iter().map(|resp| {
+                    let resp = adapt_push_message(resp, resp_version);
+                    Box::new(RespPacket::from_resp_vec(resp))
+                })),
+                resp => replies.push(Box::new(RespPacket::from_resp_vec(resp))),
+            }
+        }
+
+        // Like redis, reply the protocol error before closing the session.
+        if let Some(SessionError::ExceedLimit(reason))
+        | Some(SessionError::InvalidRequest(reason)) = reader_err
+        {
+            let err_msg = format!("ERR Protocol error: {}", reason);
+            let resp = Resp::Error(err_msg.into_bytes());
+            replies.push(Box::new(RespPacket::from_resp_vec(resp)));
         }
 
         let mut batch = stream::iter(replies.drain(..)).map(Ok);
-        if let Err(err) = writer.send_all(&mut batch).await {
+        let res = output_buffer
+            .wait(writer.send_all(&mut batch), replies_size)
+            .await?;
+        if let Err(err) = res {
             error!("writer error: {}", err);
-            let err = match err {
-                EncodeError::Io(err) => SessionError::Io(err),
-                EncodeError::NotReady(_) => SessionError::InvalidState,
-            };
+            return Err(SessionError::from(err));
+        }
+        replies_size = 0;
+
+        if let Some(err) = reader_err {
             return Err(err);
         }
     }
@@ -278,13 +711,136 @@ where
     Ok(())
 }
 
+// The batches of requests buffered while running a blocking command.
+const MAX_PENDING_BATCHES: usize = 1024;
+// The bytes of requests buffered while running a blocking command,
+// same as the default `client-query-buffer-limit` of redis.
+const MAX_PENDING_REQS_SIZE: usize = 1024 * 1024 * 1024;
+
+// Like `client-output-buffer-limit` of redis. 0 means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputBufferLimit {
+    // In bytes.
+    pub hard_limit: usize,
+    // In bytes. Exceeding it for `soft_seconds` also closes the session.
+    pub soft_limit: usize,
+    pub soft_seconds: u64,
+}
+
+const OUTPUT_BUFFER_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+// Tracks the size of the replies and the pushed messages not sent to the client yet.
+struct OutputBuffer {
+    limit: OutputBufferLimit,
+    push_pending_bytes: Option<sync::Arc<AtomicUsize>>,
+    soft_limit_reached_time: Option<Instant>,
+}
+
+impl OutputBuffer {
+    fn new(limit: OutputBufferLimit, push_pending_bytes: Option<sync::Arc<AtomicUsize>>) -> Self {
+        Self {
+            limit,
+            push_pending_bytes,
+            soft_limit_reached_time: None,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.limit.hard_limit != 0 || self.limit.soft_limit != 0
+    }
+
+    // Skip encoding the packets to get the size when there's no limit.
+    fn get_packet_size(&self, packet: &RespPacket) -> usize {
+        if self.is_enabled() {
+            packet.get_size()
+        } else {
+            0
+        }
+    }
+
+    fn check(&mut self, replies_size: usize) -> Result<(), SessionError> {
+        self.check_at(replies_size, Instant::now())
+    }
+
+    fn check_at(&mut self, replies_size: usize, now: Instant) -> Result<(), SessionError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let push_pending_bytes = self
+            .push_pending_bytes
+            .as_ref()
+            .map(|pending_bytes| pending_bytes.load(Ordering::SeqCst))
+            .unwrap_or(0);
+        let size = replies_size + push_pending_bytes;
+        let OutputBufferLimit {
+            hard_limit,
+            soft_limit,
+            soft_seconds,
+        } = self.limit;
+
+        if hard_limit != 0 && size > hard_limit {
+            warn!(
+                "close session for exceeding the output buffer hard limit: {} > {}",
+                size, hard_limit
+            );
+            return Err(SessionError::ExceedOutputBufferLimit);
+        }
+        if soft_limit == 0 || size <= soft_limit {
+            self.soft_limit_reached_time = None;
+            return Ok(());
+        }
+        let reached_time = *self.soft_limit_reached_time.get_or_insert(now);
+        if now.saturating_duration_since(reached_time) >= Duration::from_secs(soft_seconds) {
+            warn!(
+                "close session for exceeding the output buffer soft limit for {} seconds: {} > {}",
+                soft_seconds, size, soft_limit
+            );
+            return Err(SessionError::ExceedOutputBufferLimit);
+        }
+        Ok(())
+    }
+
+    // Keeps checking the limit while the client is reading the replies slowly.
+    // The replies are not counted once they are written.
+    async fn wait<F: Future>(
+        &mut self,
+        fut: F,
+        replies_size: usize,
+    ) -> Result<F::Output, SessionError> {
+        if !self.is_enabled() {
+            return Ok(fut.await);
+        }
+        pin_mut!(fut);
+        loop {
+            if let Ok(output) = time::timeout(OUTPUT_BUFFER_CHECK_INTERVAL, &mut fut).await {
+                self.check(0)?;
+                return Ok(output);
+            }
+            self.check(replies_size)?;
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SessionError {
     Io(io::Error),
     CmdErr(CommandError),
     InvalidProtocol,
+    ExceedLimit(&'static str),
+    InvalidRequest(&'static str),
     Canceled,
     InvalidState,
+    IdleTimeout,
+    ExceedOutputBufferLimit,
+}
+
+impl<T> From<EncodeError<T>> for SessionError {
+    fn from(err: EncodeError<T>) -> Self {
+        match err {
+            EncodeError::Io(err) => SessionError::Io(err),
+            EncodeError::NotReady(_) => SessionError::InvalidState,
+        }
+    }
 }
 
 impl fmt::Display for SessionError {
@@ -310,9 +866,9 @@ impl Error for SessionError {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::protocol::{Array, BulkStr, Resp};
+    use crate::protocol::{BulkStr, Resp};
     use matches::assert_matches;
-    use std::sync::{Arc, RwLock};
+    use std::sync::{Arc, Mutex, RwLock};
     use tokio;
 
     #[tokio::test]
@@ -323,7 +879,19 @@ mod tests {
         let db = Arc::new(RwLock::new(DBName::from("mydb").unwrap()));
         let cmd = Command::new(Box::new(request));
         let (sender, receiver) = new_command_pair();
-        let cmd_ctx = CmdCtx::new(db, cmd, sender, 7799);
+        let resp_version = Arc::new(RwLock::new(RespVersion::Resp2));
+        let transaction = Arc::new(Mutex::new(Transaction::default()));
+        let subscription = Arc::new(Mutex::new(Subscription::new(new_push_pair().0)));
+        let client = Arc::new(RwLock::new(ClientState::new(7799, String::new())));
+        let cmd_ctx = CmdCtx::new(
+            db,
+            resp_version,
+            transaction,
+            subscription,
+            client,
+            cmd,
+            sender,
+        );
         drop(cmd_ctx);
         let err = match receiver.await {
             Ok(_) => panic!(),
@@ -331,4 +899,89 @@ mod tests {
         };
         assert_matches!(err, CommandError::Dropped);
     }
+
+    #[test]
+    fn test_cmd_ctx_factory_command_table() {
+        let request = RespPacket::Data(Resp::Arr(Array::Arr(vec![Resp::Bulk(BulkStr::Str(
+            b"PING".to_vec(),
+        ))])));
+        let (sender, _receiver) = new_command_pair();
+        let cmd_ctx = CmdCtx::new(
+            Arc::new(RwLock::new(DBName::from("mydb").unwrap())),
+            Arc::new(RwLock::new(RespVersion::Resp2)),
+            Arc::new(Mutex::new(Transaction::default())),
+            Arc::new(Mutex::new(Subscription::new(new_push_pair().0))),
+            Arc::new(RwLock::new(ClientState::new(7799, String::new()))),
+            Command::new(Box::new(request)),
+            sender,
+        );
+        let table = CommandTable::from_config(vec!["MYMGET -2 readonly 1 -1 1".to_string()])
+            .expect("test_cmd_ctx_factory_command_table");
+        let factory = CmdCtxFactory::new(Arc::new(table));
+        let resp = Resp::Arr(Array::Arr(vec![
+            Resp::Bulk(BulkStr::Str(b"MYMGET".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"a".to_vec())),
+            Resp::Bulk(BulkStr::Str(b"b".to_vec())),
+        ]));
+        let (sub_cmd_ctx, _fut) = factory.create_with(&cmd_ctx, resp);
+        assert_eq!(
+            sub_cmd_ctx.get_cmd().get_keys(),
+            vec![b"a".as_ref(), b"b".as_ref()]
+        );
+    }
+
+    #[test]
+    fn test_client_admin() {
+        let mut client = ClientState::new(7799, String::new());
+        assert!(!client.is_admin());
+        client.set_admin();
+        assert!(client.is_admin());
+
+        // The sessions authenticated to a cluster are no longer the admin.
+        client.set_auth(ClientAuth {
+            db: DBName::from("mydb").unwrap(),
+            readonly: false,
+        });
+        assert!(!client.is_admin());
+        client.set_admin();
+        assert!(client.is_admin());
+        assert!(client.get_auth().is_none());
+    }
+
+    #[test]
+    fn test_output_buffer_limit() {
+        let limit = OutputBufferLimit {
+            hard_limit: 100,
+            soft_limit: 50,
+            soft_seconds: 10,
+        };
+        let push_pending_bytes = Arc::new(AtomicUsize::new(0));
+        let mut output_buffer = OutputBuffer::new(limit, Some(push_pending_bytes.clone()));
+        let now = Instant::now();
+        assert!(output_buffer.check_at(40, now).is_ok());
+        assert!(output_buffer.check_at(60, now).is_ok());
+        assert!(output_buffer
+            .check_at(60, now + Duration::from_secs(5))
+            .is_ok());
+        // Dropping below the soft limit resets the timer.
+        assert!(output_buffer
+            .check_at(40, now + Duration::from_secs(6))
+            .is_ok());
+        assert!(output_buffer
+            .check_at(60, now + Duration::from_secs(12))
+            .is_ok());
+        assert_matches!(
+            output_buffer.check_at(60, now + Duration::from_secs(22)),
+            Err(SessionError::ExceedOutputBufferLimit)
+        );
+
+        push_pending_bytes.store(80, Ordering::SeqCst);
+        assert_matches!(
+            output_buffer.check_at(40, now),
+            Err(SessionError::ExceedOutputBufferLimit)
+        );
+
+        let mut output_buffer = OutputBuffer::new(OutputBufferLimit::default(), None);
+        assert!(output_buffer.check_at(usize::MAX, now).is_ok());
+    }
 }
@@ -0,0 +1,174 @@
+use crate::protocol::{BinSafeStr, RedisClient};
+use std::fmt;
+use std::mem;
+
+// The transaction state of a session.
+//
+// The shared backend connections are multiplexed by all the sessions,
+// so a transaction is queued inside the proxy and sent to the node of its slot
+// on a dedicated connection when EXEC comes.
+// WATCH needs to be sent before EXEC so it pins a dedicated connection.
+//
+// The queue and the slot are updated when the commands are dispatched,
+// while the dedicated connection is only used in the reply futures
+// which are polled in the order of the commands in a session.
+#[derive(Default)]
+pub struct Transaction {
+    in_multi: bool,
+    // Set when a command fails to get queued so that EXEC will be aborted.
+    aborted: bool,
+    queued_cmds: Vec<Vec<BinSafeStr>>,
+    // All the keys of the queued commands and the watched keys should be in this slot.
+    slot: Option<usize>,
+    watching: bool,
+    // The connection pinned by WATCH and the address of its node.
+    watch_conn: Option<(String, Box<dyn RedisClient>)>,
+}
+
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Transaction {{ in_multi: {}, aborted: {}, queued: {}, slot: {:?}, watching: {} }}",
+            self.in_multi,
+            self.aborted,
+            self.queued_cmds.len(),
+            self.slot,
+            self.watching
+        )
+    }
+}
+
+pub struct QueuedTransaction {
+    pub aborted: bool,
+    pub cmds: Vec<Vec<BinSafeStr>>,
+    pub slot: Option<usize>,
+    pub watching: bool,
+}
+
+impl Transaction {
+    pub fn is_in_multi(&self) -> bool {
+        self.in_multi
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watching
+    }
+
+    pub fn get_slot(&self) -> Option<usize> {
+        self.slot
+    }
+
+    // Returns false if MULTI is nested.
+    pub fn start_multi(&mut self) -> bool {
+        if self.in_multi {
+            return false;
+        }
+        self.in_multi = true;
+        true
+    }
+
+    // Returns false if the slot is different from the one of the previous keys.
+    pub fn bind_slot(&mut self, slot: usize) -> bool {
+        match self.slot {
+            Some(s) => s == slot,
+            None => {
+                self.slot = Some(slot);
+                true
+            }
+        }
+    }
+
+    pub fn abort(&mut self) {
+        self.aborted = true;
+    }
+
+    pub fn queue(&mut self, cmd: Vec<BinSafeStr>) {
+        self.queued_cmds.push(cmd);
+    }
+
+    pub fn set_watching(&mut self) {
+        self.watching = true;
+    }
+
+    // For UNWATCH outside MULTI.
+    pub fn unwatch(&mut self) {
+        self.watching = false;
+        if !self.in_multi {
+            self.slot = None;
+        }
+    }
+
+    // For both EXEC and DISCARD. The watched keys are also released.
+    pub fn finish(&mut self) -> QueuedTransaction {
+        let queued = QueuedTransaction {
+            aborted: self.aborted,
+            cmds: mem::take(&mut self.queued_cmds),
+            slot: self.slot,
+            watching: self.watching,
+        };
+        self.in_multi = false;
+        self.aborted = false;
+        self.slot = None;
+        self.watching = false;
+        queued
+    }
+
+    pub fn set_watch_conn(&mut self, address: String, client: Box<dyn RedisClient>) {
+        self.watch_conn = Some((address, client));
+    }
+
+    pub fn take_watch_conn(&mut self) -> Option<(String, Box<dyn RedisClient>)> {
+        self.watch_conn.take()
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        // The pooled connection should not be reused with the watched keys.
+        if let Some((_, mut client)) = self.watch_conn.take() {
+            tokio::spawn(async move {
+                if let Err(err) = client.execute_single(vec![b"UNWATCH".to_vec()]).await {
+                    warn!("failed to unwatch: {:?}", err);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_state() {
+        let mut tx = Transaction::default();
+        assert!(!tx.is_in_multi());
+
+        tx.set_watching();
+        assert!(tx.bind_slot(1));
+        assert!(tx.start_multi());
+        assert!(!tx.start_multi());
+        assert!(tx.bind_slot(1));
+        assert!(!tx.bind_slot(2));
+        tx.queue(vec![b"SET".to_vec(), b"a".to_vec(), b"1".to_vec()]);
+
+        let queued = tx.finish();
+        assert!(!queued.aborted);
+        assert_eq!(queued.cmds.len(), 1);
+        assert_eq!(queued.slot, Some(1));
+        assert!(queued.watching);
+        assert!(!tx.is_in_multi());
+        assert!(!tx.is_watching());
+        assert!(tx.get_slot().is_none());
+
+        assert!(tx.start_multi());
+        tx.abort();
+        assert!(tx.finish().aborted);
+
+        tx.set_watching();
+        assert!(tx.bind_slot(3));
+        tx.unwatch();
+        assert!(tx.get_slot().is_none());
+    }
+}